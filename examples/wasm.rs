@@ -0,0 +1,90 @@
+//! Inspects an exFAT image in the browser.
+//!
+//! Build with `cargo build --example wasm --target wasm32-unknown-unknown` then generate the
+//! JavaScript bindings with `wasm-bindgen --target web`. The image can be obtained from a `File`
+//! selected by the user with `await file.arrayBuffer()` and nothing will be uploaded.
+#![cfg(target_arch = "wasm32")]
+
+use exfat::directory::Item;
+use exfat::disk::DiskPartition;
+use exfat::Root;
+use js_sys::{ArrayBuffer, Uint8Array};
+use std::cmp::min;
+use std::error::Error;
+use std::io::Read;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Lists the items in the directory at `path` of the image. Each item is the name followed by a
+/// `/` for a directory.
+#[wasm_bindgen]
+pub fn list(image: &ArrayBuffer, path: &str) -> Result<Vec<String>, String> {
+    let root = open(image)?;
+    let mut items = Vec::new();
+    let mut push = |item: &Item<_>| match item {
+        Item::Directory(d) => items.push(format!("{}/", d.name())),
+        Item::File(f) => items.push(f.name().to_owned()),
+    };
+
+    if path.is_empty() || path == "/" {
+        root.iter().for_each(&mut push);
+    } else {
+        let dir = match root.open_path(path) {
+            Ok(Item::Directory(v)) => v,
+            Ok(Item::File(_)) => return Err(format!("{path} is not a directory")),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        for item in dir.open().map_err(|e| e.to_string())? {
+            push(&item);
+        }
+    }
+
+    Ok(items)
+}
+
+/// Reads the whole file at `path` of the image.
+#[wasm_bindgen]
+pub fn read(image: &ArrayBuffer, path: &str) -> Result<Vec<u8>, String> {
+    let root = open(image)?;
+    let mut file = match root.open_path(path) {
+        Ok(Item::File(v)) => v,
+        Ok(Item::Directory(_)) => return Err(format!("{path} is a directory")),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut data = Vec::new();
+
+    file.read_to_end(&mut data).map_err(|e| e.to_string())?;
+
+    Ok(data)
+}
+
+fn open(image: &ArrayBuffer) -> Result<Root<ArrayBufferPartition>, String> {
+    Root::open(ArrayBufferPartition(Uint8Array::new(image))).map_err(|e| e.to_string())
+}
+
+/// A read-only [`DiskPartition`] over the `ArrayBuffer`. The data is copied out of the buffer
+/// only when it is read so the image is not duplicated in the WebAssembly memory.
+struct ArrayBufferPartition(Uint8Array);
+
+impl DiskPartition for ArrayBufferPartition {
+    fn len(&self) -> Option<u64> {
+        Some(self.0.length().into())
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let len = u64::from(self.0.length());
+
+        if offset >= len {
+            return Ok(0);
+        }
+
+        let start = offset as u32;
+        let amount = min(buf.len() as u64, len - offset) as u32;
+
+        self.0
+            .subarray(start, start + amount)
+            .copy_to(&mut buf[..(amount as usize)]);
+
+        Ok(amount.into())
+    }
+}
@@ -0,0 +1,82 @@
+/* C API of the exfat crate. Build the library with:
+ *
+ *     cargo rustc --release --features ffi --crate-type staticlib
+ *
+ * Every function that return int returns one of the EXFAT_* codes. */
+#ifndef EXFAT_H
+#define EXFAT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define EXFAT_OK 0       /* The operation was successful. */
+#define EXFAT_END 1      /* There are no more items in the directory. */
+#define EXFAT_EINVAL -1  /* A pointer is null or the path is not a valid UTF-8. */
+#define EXFAT_EOPEN -2   /* The image cannot be opened. */
+#define EXFAT_EVOLUME -3 /* The image is not a valid exFAT volume. */
+#define EXFAT_ENOENT -4  /* The item does not exist. */
+#define EXFAT_ENOTDIR -5 /* The item is not a directory. */
+#define EXFAT_EISDIR -6  /* The item is a directory. */
+#define EXFAT_EIO -7     /* The data cannot be read from the volume. */
+
+typedef struct exfat_volume exfat_volume;
+typedef struct exfat_dir exfat_dir;
+typedef struct exfat_file exfat_file;
+
+/* A timestamp in the local time of the volume that write it. */
+struct exfat_time {
+    uint16_t year;
+    uint8_t month;
+    uint8_t day;
+    uint8_t hour;
+    uint8_t minute;
+    uint8_t second;
+    uint8_t has_utc_offset; /* Non-zero if utc_offset is available. */
+    uint16_t millisecond;
+    int16_t utc_offset; /* The offset from UTC in minutes. */
+};
+
+/* Metadata of a file or a directory. */
+struct exfat_stat {
+    char name[766]; /* UTF-8 and terminated with NUL. */
+    uint16_t attributes; /* The raw FileAttributes. */
+    uint8_t is_directory;
+    uint64_t size;
+    struct exfat_time created;
+    struct exfat_time modified;
+    struct exfat_time accessed;
+};
+
+/* Metadata of a volume. */
+struct exfat_volume_info {
+    char label[34]; /* UTF-8 and terminated with NUL. Empty if the volume has no label. */
+    uint64_t cluster_size;
+    uint64_t total_bytes;
+    uint64_t free_bytes;
+};
+
+int exfat_open(const char *path, exfat_volume **out);
+void exfat_close(exfat_volume *vol);
+int exfat_volume_info(const exfat_volume *vol, struct exfat_volume_info *out);
+int exfat_stat(const exfat_volume *vol, const char *path, struct exfat_stat *out);
+
+int exfat_opendir(const exfat_volume *vol, const char *path, exfat_dir **out);
+int exfat_readdir(exfat_dir *dir, struct exfat_stat *out);
+void exfat_closedir(exfat_dir *dir);
+
+int exfat_file_open(const exfat_volume *vol, const char *path, exfat_file **out);
+int exfat_file_read(exfat_file *file, void *buf, size_t len, size_t *read);
+int exfat_file_seek(exfat_file *file, int64_t offset, int whence, uint64_t *pos);
+void exfat_file_close(exfat_file *file);
+
+const char *exfat_strerror(int code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,674 @@
+use crate::cluster::Clusters;
+use crate::disk::DiskPartition;
+use crate::entries::ClusterAllocation;
+use crate::fat::{ChainError, Fat};
+use crate::param::Params;
+use crate::prelude::*;
+use crate::{Cluster, ExFat};
+use core::cmp::{max, min};
+use core::fmt::Display;
+use core::ops::Range;
+
+/// Manages the Allocation Bitmap of the volume.
+///
+/// All changes are made in memory and will be written to the partition when
+/// [`flush()`][Self::flush()] is called. The methods of [`ExFat`] that allocate or release
+/// clusters flush both the FAT and the Allocation Bitmap before returning. On a volume with two
+/// FATs the changes will be written to the bitmap of the active FAT first then mirrored to the
+/// other one, the same as [`Fat`].
+pub(crate) struct Allocator {
+    bitmap: Vec<u8>,
+    offsets: Vec<Vec<u64>>, // The offset of each cluster of each bitmap. The active one come first.
+    cluster_size: u64,
+    cluster_count: usize,
+    free: usize,
+    next: usize,
+    dirty: Option<Range<usize>>,
+}
+
+impl Allocator {
+    /// Creates an [`Allocator`] that does not have any cluster available.
+    pub fn empty() -> Self {
+        Self {
+            bitmap: Vec::new(),
+            offsets: Vec::new(),
+            cluster_size: 0,
+            cluster_count: 0,
+            free: 0,
+            next: 2,
+            dirty: None,
+        }
+    }
+
+    /// Loads the Allocation Bitmap at `alloc`. The changes will also be written to the bitmap at
+    /// `mirror` if it is not [`None`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn load<P: DiskPartition>(
+        params: &Params,
+        partition: &P,
+        fat: &Fat,
+        alloc: &ClusterAllocation,
+        mirror: Option<&ClusterAllocation>,
+    ) -> Result<Self, LoadError> {
+        // Get the offset of each cluster.
+        let cluster_count = params.cluster_count;
+        let cluster_size = params.cluster_size();
+        let len = cluster_count.div_ceil(8);
+        let mut offsets = vec![Self::offsets(params, partition, fat, alloc, len)?];
+
+        if let Some(v) = mirror {
+            offsets.push(Self::offsets(params, partition, fat, v, len)?);
+        }
+
+        // Load the bitmap.
+        let mut bitmap = vec![0u8; len];
+
+        for (i, chunk) in bitmap.chunks_mut(cluster_size as usize).enumerate() {
+            let offset = offsets[0][i];
+
+            if let Err(e) = partition.read_exact(offset, chunk) {
+                return Err(LoadError::ReadFailed(offset, e));
+            }
+        }
+
+        // Count free clusters.
+        let mut allocator = Self {
+            bitmap,
+            offsets,
+            cluster_size,
+            cluster_count,
+            free: 0,
+            next: 2,
+            dirty: None,
+        };
+
+        allocator.free = (0..cluster_count).filter(|&i| !allocator.is_set(i)).count();
+
+        Ok(allocator)
+    }
+
+    /// Returns the offset of each cluster of the bitmap at `alloc`, which must be at least `len`
+    /// bytes.
+    fn offsets<P: DiskPartition>(
+        params: &Params,
+        partition: &P,
+        fat: &Fat,
+        alloc: &ClusterAllocation,
+        len: usize,
+    ) -> Result<Vec<u64>, LoadError> {
+        // Check if the bitmap is large enough to cover all clusters.
+        if alloc.data_length() < len as u64 {
+            return Err(LoadError::InvalidDataLength);
+        }
+
+        let cluster_size = params.cluster_size();
+        let mut offsets: Vec<u64> = Vec::new();
+        let mut chain = fat.get_cluster_chain(partition, alloc.first_cluster());
+
+        for cluster in chain.by_ref() {
+            match params.cluster_offset(cluster) {
+                Some(v) => offsets.push(v),
+                None => return Err(LoadError::InvalidCluster(cluster)),
+            }
+
+            if offsets.len() as u64 * cluster_size >= len as u64 {
+                break;
+            }
+        }
+
+        if let Err(e) = chain.finish() {
+            return Err(LoadError::ReadChainFailed(e));
+        }
+
+        if (offsets.len() as u64 * cluster_size) < len as u64 {
+            return Err(LoadError::InvalidDataLength);
+        }
+
+        Ok(offsets)
+    }
+
+    /// Returns the number of free clusters.
+    pub fn free_count(&self) -> usize {
+        self.free
+    }
+
+    /// Returns `true` if `cluster` is marked as allocated.
+    pub fn is_allocated(&self, cluster: Cluster) -> bool {
+        cluster.is_valid(self.cluster_count) && self.is_set(cluster.index() - 2)
+    }
+
+    /// Finds a free cluster, starting from `hint`. The search will start from the cluster
+    /// following the last allocation if `hint` is [`None`].
+    pub fn find_free(&self, hint: Option<Cluster>) -> Option<Cluster> {
+        let hint = hint
+            .map_or(self.next, Cluster::index)
+            .saturating_sub(2)
+            .min(self.cluster_count);
+
+        (hint..self.cluster_count)
+            .chain(0..hint)
+            .find(|&i| !self.is_set(i))
+            .map(|i| Cluster(i as u32 + 2))
+    }
+
+    /// Finds `count` contiguous free clusters, starting from `hint`. The return value is the
+    /// first cluster of the run.
+    pub fn find_free_run(&self, count: usize, hint: Option<Cluster>) -> Option<Cluster> {
+        if count == 0 || count > self.free {
+            return None;
+        }
+
+        let hint = hint
+            .map_or(self.next, Cluster::index)
+            .saturating_sub(2)
+            .min(self.cluster_count);
+        let end = self.cluster_count;
+        let search = |start: usize, limit: usize| {
+            let mut first = start;
+
+            while first + count <= limit {
+                match (first..(first + count)).find(|&i| self.is_set(i)) {
+                    Some(i) => first = i + 1,
+                    None => return Some(first),
+                }
+            }
+
+            None
+        };
+
+        search(hint, end)
+            .or_else(|| search(0, min(hint + count, end)))
+            .map(|i| Cluster(i as u32 + 2))
+    }
+
+    /// Allocates a free cluster, starting the search from `hint`.
+    pub fn allocate(&mut self, hint: Option<Cluster>) -> Result<Cluster, AllocError> {
+        let cluster = match self.find_free(hint) {
+            Some(v) => v,
+            None => return Err(AllocError::NoFreeCluster),
+        };
+
+        self.set(cluster, true);
+
+        Ok(cluster)
+    }
+
+    /// Allocates `count` contiguous clusters, starting the search from `hint`. The return value
+    /// is the first cluster of the run.
+    pub fn allocate_run(
+        &mut self,
+        count: usize,
+        hint: Option<Cluster>,
+    ) -> Result<Cluster, AllocError> {
+        let first = match self.find_free_run(count, hint) {
+            Some(v) => v,
+            None => return Err(AllocError::NoFreeCluster),
+        };
+
+        for cluster in first.0..(first.0 + count as u32) {
+            self.set(Cluster(cluster), true);
+        }
+
+        Ok(first)
+    }
+
+    /// Marks `cluster` as allocated.
+    pub fn claim(&mut self, cluster: Cluster) -> Result<(), AllocError> {
+        if !cluster.is_valid(self.cluster_count) {
+            return Err(AllocError::InvalidCluster(cluster));
+        }
+
+        self.set(cluster, true);
+
+        Ok(())
+    }
+
+    /// Marks `cluster` as free.
+    pub fn free(&mut self, cluster: Cluster) -> Result<(), AllocError> {
+        if !cluster.is_valid(self.cluster_count) {
+            return Err(AllocError::InvalidCluster(cluster));
+        }
+
+        self.set(cluster, false);
+
+        Ok(())
+    }
+
+    /// Writes all changes to every bitmap on the partition.
+    pub fn flush<P: DiskPartition>(&mut self, partition: &P) -> Result<(), WriteError> {
+        let dirty = match self.dirty.take() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        // The bitmap may span multiple clusters so we need to split the write.
+        let cluster_size = self.cluster_size as usize;
+
+        for offsets in &self.offsets {
+            let mut start = dirty.start;
+
+            while start < dirty.end {
+                let i = start / cluster_size;
+                let end = min(dirty.end, (i + 1) * cluster_size);
+                let offset = offsets[i] + (start % cluster_size) as u64;
+
+                if let Err(e) = partition.write_exact(offset, &self.bitmap[start..end]) {
+                    self.dirty = Some(dirty);
+                    return Err(WriteError::WriteFailed(offset, e));
+                }
+
+                start = end;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the bit at `index` is set. The bit #0 is for cluster #2.
+    fn is_set(&self, index: usize) -> bool {
+        (self.bitmap[index / 8] & (1 << (index % 8))) != 0
+    }
+
+    fn set(&mut self, cluster: Cluster, allocated: bool) {
+        let index = cluster.index() - 2;
+        let byte = index / 8;
+        let mask = 1 << (index % 8);
+
+        if allocated == ((self.bitmap[byte] & mask) != 0) {
+            return;
+        }
+
+        // Update the bitmap.
+        if allocated {
+            self.bitmap[byte] |= mask;
+            self.free -= 1;
+            self.next = cluster.index() + 1;
+        } else {
+            self.bitmap[byte] &= !mask;
+            self.free += 1;
+        }
+
+        self.dirty = Some(match self.dirty.take() {
+            Some(v) => min(v.start, byte)..max(v.end, byte + 1),
+            None => byte..(byte + 1),
+        });
+    }
+}
+
+impl<P: DiskPartition> ExFat<P> {
+    /// Allocates a new cluster and appends it to the chain specified by `chain` and
+    /// `no_fat_chain`. The chain will be converted to a FAT chain if the new cluster is not
+    /// contiguous with the last cluster.
+    pub(crate) fn append_cluster(
+        &self,
+        chain: &mut Clusters,
+        no_fat_chain: &mut bool,
+    ) -> Result<Cluster, AllocError> {
+        self.append_clusters(chain, no_fat_chain, 1)?;
+
+        Ok(chain.last().unwrap())
+    }
+
+    /// Allocates `count` clusters and appends it to the chain specified by `chain` and
+    /// `no_fat_chain`. A contiguous run will be preferred. The chain will be converted to a FAT
+    /// chain if the new clusters are not contiguous with the last cluster.
+    pub(crate) fn append_clusters(
+        &self,
+        chain: &mut Clusters,
+        no_fat_chain: &mut bool,
+        count: usize,
+    ) -> Result<(), AllocError> {
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut alloc = self.alloc.lock().unwrap();
+        let mut fat = self.fat.lock().unwrap();
+
+        // Find free clusters. We prefer the run that immediately follow the last cluster.
+        let next = chain.last().map(|v| Cluster(v.0 + 1));
+        let clusters: Vec<Cluster> = match alloc.allocate_run(count, next) {
+            Ok(v) => (v.0..(v.0 + count as u32)).map(Cluster).collect(),
+            Err(AllocError::NoFreeCluster) if alloc.free_count() >= count => {
+                let mut clusters = Vec::with_capacity(count);
+                let mut hint = next;
+
+                for _ in 0..count {
+                    let cluster = match alloc.allocate(hint) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            clusters.iter().for_each(|&c| alloc.set(c, false));
+                            return Err(e);
+                        }
+                    };
+
+                    clusters.push(cluster);
+                    hint = Some(Cluster(cluster.0 + 1));
+                }
+
+                clusters
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Link the clusters. The new clusters must be released and the FAT must be restored if
+        // this failed otherwise they will be lost.
+        let original = *no_fat_chain;
+        let mut undo = Vec::new();
+
+        if let Err(e) = link_clusters(
+            &mut fat,
+            &self.partition,
+            chain,
+            no_fat_chain,
+            &clusters,
+            &mut undo,
+        ) {
+            for (c, v) in undo.into_iter().rev() {
+                fat.set(c, v).ok();
+            }
+
+            clusters.iter().for_each(|&c| alloc.set(c, false));
+            *no_fat_chain = original;
+            return Err(e);
+        }
+
+        clusters.into_iter().for_each(|c| chain.push(c));
+
+        if let Err(e) = fat.flush(&self.partition) {
+            return Err(AllocError::WriteFatFailed(e));
+        }
+
+        if let Err(e) = alloc.flush(&self.partition) {
+            return Err(AllocError::WriteBitmapFailed(e));
+        }
+
+        Ok(())
+    }
+
+    /// Releases all clusters in the chain starting from `first_cluster`.
+    pub(crate) fn free_chain(
+        &self,
+        first_cluster: Cluster,
+        data_length: u64,
+        no_fat_chain: bool,
+    ) -> Result<(), AllocError> {
+        let mut alloc = self.alloc.lock().unwrap();
+        let mut fat = self.fat.lock().unwrap();
+        let chain: Vec<Cluster> = if no_fat_chain {
+            let count = data_length.div_ceil(self.params.cluster_size());
+            let end = match u32::try_from(count)
+                .ok()
+                .and_then(|v| first_cluster.0.checked_add(v))
+            {
+                Some(v) => v,
+                None => return Err(AllocError::InvalidCluster(first_cluster)),
+            };
+
+            (first_cluster.0..end).map(Cluster).collect()
+        } else {
+            match fat
+                .get_cluster_chain(&self.partition, first_cluster)
+                .into_vec()
+            {
+                Ok(v) => v,
+                Err(e) => return Err(AllocError::ReadChainFailed(e)),
+            }
+        };
+
+        for &cluster in &chain {
+            if !no_fat_chain {
+                set_fat(&mut fat, cluster, 0)?;
+            }
+
+            alloc.free(cluster)?;
+        }
+
+        if let Err(e) = fat.flush(&self.partition) {
+            return Err(AllocError::WriteFatFailed(e));
+        }
+
+        if let Err(e) = alloc.flush(&self.partition) {
+            return Err(AllocError::WriteBitmapFailed(e));
+        }
+
+        // The locks must be held until the clusters are discarded so they cannot be reused.
+        self.discard_clusters(&chain);
+
+        Ok(())
+    }
+
+    /// Releases all clusters in `chain` after the first `count` clusters.
+    pub(crate) fn truncate_chain(
+        &self,
+        chain: &mut Clusters,
+        count: usize,
+        no_fat_chain: bool,
+    ) -> Result<(), AllocError> {
+        let mut alloc = self.alloc.lock().unwrap();
+        let mut fat = self.fat.lock().unwrap();
+        let mut released = Vec::new();
+
+        while chain.len() > count {
+            let cluster = chain.pop().unwrap();
+
+            if !no_fat_chain {
+                set_fat(&mut fat, cluster, 0)?;
+            }
+
+            alloc.free(cluster)?;
+            released.push(cluster);
+        }
+
+        released.reverse();
+
+        // Mark the new last cluster as the end of chain.
+        if let (Some(last), false) = (chain.last(), no_fat_chain) {
+            set_fat(&mut fat, last, Fat::END_OF_CHAIN)?;
+        }
+
+        if let Err(e) = fat.flush(&self.partition) {
+            return Err(AllocError::WriteFatFailed(e));
+        }
+
+        if let Err(e) = alloc.flush(&self.partition) {
+            return Err(AllocError::WriteBitmapFailed(e));
+        }
+
+        self.discard_clusters(&released);
+
+        Ok(())
+    }
+
+    /// Discards `clusters` if enabled. Each run of contiguous clusters is discarded at once.
+    fn discard_clusters(&self, clusters: &[Cluster]) {
+        if !self.discard {
+            return;
+        }
+
+        let size = self.params.cluster_size();
+        let mut i = 0;
+
+        while i < clusters.len() {
+            let mut n = 1;
+
+            while clusters
+                .get(i + n)
+                .is_some_and(|c| c.index() == clusters[i].index() + n)
+            {
+                n += 1;
+            }
+
+            if let Some(offset) = self.params.cluster_offset(clusters[i]) {
+                self.partition.discard(offset, size * n as u64).ok();
+            }
+
+            i += n;
+        }
+    }
+
+    /// Fills `cluster` with zeros.
+    pub(crate) fn zero_cluster(&self, cluster: Cluster) -> Result<(), AllocError> {
+        let offset = match self.params.cluster_offset(cluster) {
+            Some(v) => v,
+            None => return Err(AllocError::InvalidCluster(cluster)),
+        };
+
+        let zeroes = vec![0u8; self.params.cluster_size() as usize];
+
+        if let Err(e) = self.partition.write_exact(offset, &zeroes) {
+            return Err(AllocError::WriteClusterFailed(cluster, e));
+        }
+
+        Ok(())
+    }
+}
+
+/// Links `clusters` to the end of `chain` and converts `chain` to a FAT chain if required. The
+/// previous value of each FAT entry that was changed is pushed to `undo`.
+fn link_clusters<P: DiskPartition>(
+    fat: &mut Fat,
+    partition: &P,
+    chain: &Clusters,
+    no_fat_chain: &mut bool,
+    clusters: &[Cluster],
+    undo: &mut Vec<(Cluster, u32)>,
+) -> Result<(), AllocError> {
+    let last = chain.last();
+    let contiguous = last.map(|v| v.0 + 1 == clusters[0].0).unwrap_or(true)
+        && clusters.windows(2).all(|w| w[0].0 + 1 == w[1].0);
+    let mut set = |fat: &mut Fat, cluster: Cluster, value: u32| {
+        let old = match fat.get(partition, cluster) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Err(AllocError::InvalidCluster(cluster)),
+            Err(e) => return Err(AllocError::ReadChainFailed(ChainError::ReadFailed(e))),
+        };
+
+        set_fat(fat, cluster, value)?;
+        undo.push((cluster, old));
+
+        Ok(())
+    };
+
+    if *no_fat_chain && !contiguous {
+        // Convert the chain to a FAT chain.
+        for (c, n) in chain.iter().zip(chain.iter().skip(1)) {
+            set(fat, c, n.0)?;
+        }
+
+        *no_fat_chain = false;
+    }
+
+    if !*no_fat_chain {
+        for w in clusters.windows(2) {
+            set(fat, w[0], w[1].0)?;
+        }
+
+        set(fat, clusters[clusters.len() - 1], Fat::END_OF_CHAIN)?;
+
+        if let Some(last) = last {
+            set(fat, last, clusters[0].0)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets the FAT entry of `cluster` to `value`.
+fn set_fat(fat: &mut Fat, cluster: Cluster, value: u32) -> Result<(), AllocError> {
+    if let Err(e) = fat.set(cluster, value) {
+        return Err(AllocError::WriteFatFailed(e));
+    }
+
+    Ok(())
+}
+
+/// Represents an error for [`Allocator::load()`].
+#[derive(Debug)]
+pub enum LoadError {
+    InvalidDataLength,
+    InvalidCluster(Cluster),
+    ReadChainFailed(crate::fat::ChainError),
+
+    ReadFailed(u64, Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidDataLength => f.write_str("invalid DataLength"),
+            Self::InvalidCluster(c) => write!(f, "cluster {c} is not valid"),
+            Self::ReadChainFailed(_) => f.write_str("cannot read the cluster chain"),
+            Self::ReadFailed(offset, _) => write!(f, "cannot read the data at {offset:#018x}"),
+        }
+    }
+}
+
+impl core::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::ReadChainFailed(e) => Some(e),
+            Self::ReadFailed(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Represents an error for [`Allocator::flush()`].
+#[derive(Debug)]
+pub enum WriteError {
+    WriteFailed(u64, Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::WriteFailed(offset, _) => write!(f, "cannot write the data at {offset:#018x}"),
+        }
+    }
+}
+
+impl core::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::WriteFailed(_, e) => Some(e.as_ref()),
+        }
+    }
+}
+
+/// Represents an error when allocating or releasing clusters.
+#[derive(Debug)]
+pub enum AllocError {
+    NoFreeCluster,
+    InvalidCluster(Cluster),
+    ReadChainFailed(crate::fat::ChainError),
+    WriteBitmapFailed(WriteError),
+    WriteFatFailed(crate::fat::WriteError),
+
+    WriteClusterFailed(Cluster, Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl Display for AllocError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoFreeCluster => f.write_str("no free cluster available"),
+            Self::InvalidCluster(c) => write!(f, "cluster {c} is not valid"),
+            Self::ReadChainFailed(_) => f.write_str("cannot read the cluster chain"),
+            Self::WriteBitmapFailed(_) => f.write_str("cannot update the allocation bitmap"),
+            Self::WriteFatFailed(_) => f.write_str("cannot update the FAT"),
+            Self::WriteClusterFailed(c, _) => write!(f, "cannot write cluster {c}"),
+        }
+    }
+}
+
+impl core::error::Error for AllocError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::ReadChainFailed(e) => Some(e),
+            Self::WriteBitmapFailed(e) => Some(e),
+            Self::WriteFatFailed(e) => Some(e),
+            Self::WriteClusterFailed(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
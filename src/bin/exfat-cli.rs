@@ -0,0 +1,278 @@
+//! Command line utility to inspect exFAT images and block devices. It only use the public API of
+//! the crate.
+use exfat::directory::Item;
+use exfat::disk::DiskPartition;
+use exfat::image::Image;
+use exfat::timestamp::Timestamp;
+use exfat::{FileAttributes, Root};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: exfat-cli <COMMAND> <IMAGE> [ARGS]
+
+Commands:
+  ls <IMAGE> [PATH]             List the items in the directory
+  stat <IMAGE> [PATH]           Show the metadata of the item or the volume if PATH is omitted
+  cat <IMAGE> <PATH>            Write the content of the file to stdout
+  extract <IMAGE> <PATH> <DEST> Copy the file or the directory tree to DEST
+  label <IMAGE> [LABEL]         Show or set the volume label, an empty LABEL removes it
+  check <IMAGE>                 Check the consistency of the volume
+
+IMAGE can be either an image file or a block device.
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|v| v.as_str()).collect();
+    let result = match args.as_slice() {
+        ["ls", image] => ls(image, "/"),
+        ["ls", image, path] => ls(image, path),
+        ["stat", image] => stat_volume(image),
+        ["stat", image, path] => stat(image, path),
+        ["cat", image, path] => cat(image, path),
+        ["extract", image, path, dest] => extract(image, path, dest.as_ref()),
+        ["label", image] => label(image, None),
+        ["label", image, v] => label(image, Some(v)),
+        ["check", image] => check(image),
+        _ => {
+            eprint!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    // Print the error with its causes.
+    if let Err(e) = result {
+        let mut cause = e.source();
+
+        eprint!("exfat-cli: {e}");
+
+        while let Some(e) = cause {
+            eprint!(": {e}");
+            cause = e.source();
+        }
+
+        eprintln!();
+
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn ls(image: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    let root = open(image, false)?;
+
+    if is_root(path) {
+        root.iter().for_each(print_item);
+    } else {
+        let dir = match root.open_path(path)? {
+            Item::Directory(v) => v,
+            Item::File(_) => return Err(format!("{path} is not a directory").into()),
+        };
+
+        for item in dir.items()? {
+            print_item(&item?);
+        }
+    }
+
+    Ok(())
+}
+
+fn stat_volume(image: &str) -> Result<(), Box<dyn Error>> {
+    let root = open(image, false)?;
+
+    println!("Label:         {}", root.volume_label().unwrap_or(""));
+
+    if let Some(guid) = root.volume_guid() {
+        let guid: String = guid.iter().map(|b| format!("{b:02x}")).collect();
+        println!("GUID:          {guid}");
+    }
+
+    println!("Sector size:   {}", root.sector_size());
+    println!("Cluster size:  {}", root.cluster_size());
+    println!("Clusters:      {}", root.cluster_count());
+    println!("Total bytes:   {}", root.total_bytes());
+    println!("Free bytes:    {}", root.free_bytes());
+    println!("Dirty:         {}", root.volume_dirty());
+    println!("Media failure: {}", root.media_failure());
+
+    Ok(())
+}
+
+fn stat(image: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    if is_root(path) {
+        return stat_volume(image);
+    }
+
+    let root = open(image, false)?;
+    let item = root.open_path(path)?;
+    let times = item.timestamps();
+
+    println!("Name:       {}", item.name());
+    println!("Attributes: {}", attributes(item.attributes()));
+
+    if let Item::File(f) = &item {
+        println!("Size:       {}", f.len());
+        println!("Valid size: {}", f.valid_data_length());
+
+        match f.first_cluster() {
+            Some(v) => println!("Cluster:    {v}"),
+            None => println!("Cluster:    none"),
+        }
+
+        println!("Contiguous: {}", f.no_fat_chain());
+    }
+
+    println!("Created:    {}", timestamp(times.created()));
+    println!("Modified:   {}", timestamp(times.modified()));
+    println!("Accessed:   {}", timestamp(times.accessed()));
+
+    Ok(())
+}
+
+fn cat(image: &str, path: &str) -> Result<(), Box<dyn Error>> {
+    let root = open(image, false)?;
+    let mut file = match root.open_path(path)? {
+        Item::File(v) => v,
+        Item::Directory(_) => return Err(format!("{path} is a directory").into()),
+    };
+    let mut stdout = std::io::stdout().lock();
+
+    std::io::copy(&mut file, &mut stdout)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn extract(image: &str, path: &str, dest: &Path) -> Result<(), Box<dyn Error>> {
+    let root = open(image, false)?;
+
+    if is_root(path) {
+        root.extract_to(dest)?;
+        return Ok(());
+    }
+
+    match root.open_path(path)? {
+        Item::Directory(d) => d.extract_to(dest)?,
+        Item::File(mut f) => {
+            let mut file = match File::create(dest) {
+                Ok(v) => v,
+                Err(e) => return Err(format!("cannot create {}: {e}", dest.display()).into()),
+            };
+
+            std::io::copy(&mut f, &mut file)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn label(image: &str, label: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let label = match label {
+        Some(v) => v,
+        None => {
+            let root = open(image, false)?;
+            println!("{}", root.volume_label().unwrap_or(""));
+            return Ok(());
+        }
+    };
+
+    let mut root = open(image, true)?;
+
+    root.set_volume_label(Some(label))?;
+    root.sync_all()?;
+
+    Ok(())
+}
+
+fn check(image: &str) -> Result<(), Box<dyn Error>> {
+    let root = open(image, false)?;
+    let report = root.check()?;
+
+    for problem in report.problems() {
+        println!("{problem}");
+    }
+
+    if report.is_partial() {
+        println!("some items cannot be walked so the lost clusters was not checked");
+    }
+
+    if !report.is_clean() {
+        return Err(format!("found {} problem(s)", report.problems().len()).into());
+    }
+
+    Ok(())
+}
+
+fn open(path: &str, writable: bool) -> Result<Root<Image<File>>, Box<dyn Error>> {
+    let file = match OpenOptions::new().read(true).write(writable).open(path) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("cannot open {path}: {e}").into()),
+    };
+
+    let root = if writable {
+        Root::open_rw(Image::open_file_rw(file)?)?
+    } else {
+        Root::open(Image::open(file)?)?
+    };
+
+    Ok(root)
+}
+
+fn print_item<P: DiskPartition>(item: &Item<P>) {
+    let size = match item {
+        Item::Directory(_) => String::from("-"),
+        Item::File(f) => f.len().to_string(),
+    };
+
+    println!(
+        "{} {:>12} {} {}",
+        attributes(item.attributes()),
+        size,
+        timestamp(item.timestamps().modified()),
+        item.name()
+    );
+}
+
+fn attributes(v: FileAttributes) -> String {
+    let flags = [
+        (v.is_directory(), 'd'),
+        (v.is_read_only(), 'r'),
+        (v.is_hidden(), 'h'),
+        (v.is_system(), 's'),
+        (v.is_archive(), 'a'),
+    ];
+
+    flags
+        .into_iter()
+        .map(|(set, c)| if set { c } else { '-' })
+        .collect()
+}
+
+fn timestamp(v: Timestamp) -> String {
+    let mut s = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        v.year(),
+        v.month(),
+        v.day(),
+        v.hour(),
+        v.minute(),
+        v.second()
+    );
+
+    if let Some(offset) = v.utc_offset() {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.unsigned_abs();
+
+        s.push_str(&format!(" {sign}{:02}:{:02}", offset / 60, offset % 60));
+    }
+
+    s
+}
+
+fn is_root(path: &str) -> bool {
+    path.split('/').all(|c| c.is_empty() || c == ".")
+}
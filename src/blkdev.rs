@@ -0,0 +1,282 @@
+use crate::bounce::Bounce;
+use crate::disk::DiskPartition;
+use std::cmp::min;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileExt, FileTypeExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::RwLock;
+use thiserror::Error;
+
+/// An implementation of [`DiskPartition`] on a raw block device like `/dev/sdb1` or
+/// `/dev/nvme0n1p1`.
+///
+/// When the device is opened with `O_DIRECT` all reads and writes must be aligned to the logical
+/// block size of the device. The unaligned reads and writes will go through an aligned bounce
+/// buffer so the callers don't need to care about it. A regular file can also be opened, in which
+/// case the logical block size is assumed to be 512 bytes.
+pub struct BlockDevice {
+    file: File,
+    block_size: u64,
+    len: u64,
+    direct: bool,
+    writable: bool,
+    rmw: RwLock<()>, // Write locked while updating a partial block.
+}
+
+impl BlockDevice {
+    /// Opens the device at `path` as read-only. Specify `direct` to open it with `O_DIRECT`, which
+    /// bypass the page cache.
+    pub fn open<T: AsRef<Path>>(path: T, direct: bool) -> Result<Self, OpenError> {
+        Self::open_with(path.as_ref(), direct, false)
+    }
+
+    /// Same as [`open()`][Self::open] but the resulting [`BlockDevice`] is writable.
+    pub fn open_rw<T: AsRef<Path>>(path: T, direct: bool) -> Result<Self, OpenError> {
+        Self::open_with(path.as_ref(), direct, true)
+    }
+
+    /// Returns the logical block size of the device.
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Returns the size of the device, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the device has no data.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn open_with(path: &Path, direct: bool, writable: bool) -> Result<Self, OpenError> {
+        // Open the device.
+        let mut options = OpenOptions::new();
+
+        options.read(true).write(writable);
+
+        if direct {
+            options.custom_flags(libc::O_DIRECT);
+        }
+
+        let mut file = match options.open(path) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::OpenFailed(e)),
+        };
+
+        // Get the logical block size and the size of the device.
+        let meta = match file.metadata() {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::GetMetadataFailed(e)),
+        };
+
+        let (block_size, len) = if meta.file_type().is_block_device() {
+            let mut block_size: libc::c_int = 0;
+
+            // SAFETY: BLKSSZGET write an int to the pointer.
+            if unsafe { libc::ioctl(file.as_raw_fd(), libc::BLKSSZGET, &mut block_size) } < 0 {
+                let e = std::io::Error::last_os_error();
+                return Err(OpenError::GetBlockSizeFailed(e));
+            }
+
+            let len = match file.seek(SeekFrom::End(0)) {
+                Ok(v) => v,
+                Err(e) => return Err(OpenError::GetMetadataFailed(e)),
+            };
+
+            match u64::try_from(block_size) {
+                Ok(v) if v.is_power_of_two() => (v, len),
+                _ => return Err(OpenError::InvalidBlockSize(block_size)),
+            }
+        } else {
+            (512, meta.len())
+        };
+
+        Ok(Self {
+            file,
+            block_size,
+            len,
+            direct,
+            writable,
+            rmw: RwLock::new(()),
+        })
+    }
+
+    /// Returns `true` if the I/O on `buf` at `offset` can be done without a bounce buffer.
+    fn is_aligned(&self, offset: u64, buf: &[u8]) -> bool {
+        let align = self.block_size;
+
+        !self.direct
+            || (offset.is_multiple_of(align)
+                && (buf.len() as u64).is_multiple_of(align)
+                && (buf.as_ptr() as u64).is_multiple_of(align))
+    }
+}
+
+impl DiskPartition for BlockDevice {
+    fn len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.file.sync_data() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(WriteError::FlushFailed(e).into()),
+        }
+    }
+
+    /// Punches a hole with `fallocate`, which discards the blocks on a block device that supports
+    /// it. The range will be shrunk to the logical blocks that are fully covered.
+    fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.writable {
+            return Err(WriteError::ReadOnly.into());
+        }
+
+        let start = offset.next_multiple_of(self.block_size);
+        let end = offset + len;
+        let end = end - end % self.block_size;
+
+        if start >= end {
+            return Ok(());
+        }
+
+        // SAFETY: fallocate does not access any memory we own.
+        let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        let (offset, len) = (start as libc::off_t, (end - start) as libc::off_t);
+
+        if unsafe { libc::fallocate(self.file.as_raw_fd(), mode, offset, len) } < 0 {
+            let e = std::io::Error::last_os_error();
+            return Err(WriteError::DiscardFailed(e).into());
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if self.is_aligned(offset, buf) {
+            return match self.file.read_at(buf, offset) {
+                Ok(v) => Ok(v as u64),
+                Err(e) => Err(ReadError::ReadFailed(e).into()),
+            };
+        }
+
+        // Read the blocks that cover the requested range.
+        let start = offset - offset % self.block_size;
+        let head = (offset - start) as usize;
+        let mut bounce = Bounce::new(head + buf.len(), self.block_size);
+        let read = match self.file.read_at(bounce.as_mut(), start) {
+            Ok(v) => v,
+            Err(e) => return Err(ReadError::ReadFailed(e).into()),
+        };
+
+        if read <= head {
+            return Ok(0);
+        }
+
+        // Copy the data.
+        let amount = min(read - head, buf.len());
+
+        buf[..amount].copy_from_slice(&bounce.as_mut()[head..(head + amount)]);
+
+        Ok(amount as u64)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if !self.writable {
+            return Err(WriteError::ReadOnly.into());
+        } else if self.is_aligned(offset, buf) {
+            let _lock = self.rmw.read().unwrap();
+
+            return match self.file.write_at(buf, offset) {
+                Ok(v) => Ok(v as u64),
+                Err(e) => Err(WriteError::WriteFailed(e).into()),
+            };
+        }
+
+        // Read the blocks that cover the requested range then update it. The other writes must
+        // wait until the blocks are written back otherwise they would be overwritten with the old
+        // data.
+        let start = offset - offset % self.block_size;
+        let head = (offset - start) as usize;
+        let mut bounce = Bounce::new(head + buf.len(), self.block_size);
+        let data = bounce.as_mut();
+        let _lock = self.rmw.write().unwrap();
+        // Only the data before the end of the device need to be preserved.
+        let end = min(data.len() as u64, self.len.saturating_sub(start)) as usize;
+        let mut read = 0;
+
+        while read < end {
+            match self.file.read_at(&mut data[read..end], start + read as u64) {
+                Ok(0) => return Err(WriteError::EndOfDevice.into()),
+                Ok(v) => read += v,
+                Err(e) => return Err(WriteError::ReadFailed(e).into()),
+            }
+        }
+
+        data[head..(head + buf.len())].copy_from_slice(buf);
+
+        // Write the blocks.
+        if let Err(e) = self.file.write_all_at(data, start) {
+            return Err(WriteError::WriteFailed(e).into());
+        }
+
+        Ok(buf.len() as u64)
+    }
+}
+
+/// Represents an error for [`BlockDevice::open()`].
+#[derive(Debug, Error)]
+pub enum OpenError {
+    #[error("cannot open the device")]
+    OpenFailed(#[source] std::io::Error),
+
+    #[error("cannot get the metadata of the device")]
+    GetMetadataFailed(#[source] std::io::Error),
+
+    #[error("cannot get the logical block size of the device")]
+    GetBlockSizeFailed(#[source] std::io::Error),
+
+    #[error("logical block size {0} is not valid")]
+    InvalidBlockSize(libc::c_int),
+}
+
+/// Represents an error for [`BlockDevice::read()`].
+#[derive(Debug, Error)]
+enum ReadError {
+    #[error("cannot read the device")]
+    ReadFailed(#[source] std::io::Error),
+}
+
+/// Represents an error for [`BlockDevice::write()`].
+#[derive(Debug, Error)]
+enum WriteError {
+    #[error("the device is not opened for writing")]
+    ReadOnly,
+
+    #[error("cannot read the blocks to update")]
+    ReadFailed(#[source] std::io::Error),
+
+    #[error("cannot write the device")]
+    WriteFailed(#[source] std::io::Error),
+
+    #[error("end of the device has been reached")]
+    EndOfDevice,
+
+    #[error("cannot flush the device")]
+    FlushFailed(#[source] std::io::Error),
+
+    #[error("cannot discard the blocks")]
+    DiscardFailed(#[source] std::io::Error),
+}
@@ -0,0 +1,177 @@
+use crate::prelude::*;
+use byteorder::{ByteOrder, LE};
+use thiserror::Error;
+
+/// Contains the Extended Boot Sectors and the OEM Parameters of the main boot region.
+pub struct BootRegion {
+    extended_boot_sectors: Vec<Vec<u8>>,
+    oem_parameters: Vec<OemParameter>,
+}
+
+impl BootRegion {
+    /// Loads the boot region from `region`, which must be the first 12 sectors of the volume.
+    pub(crate) fn load(region: &[u8], sector_size: usize) -> Result<Self, LoadError> {
+        let mut sectors = region.chunks_exact(sector_size).skip(1);
+
+        // Load Extended Boot Sectors.
+        let mut extended_boot_sectors = Vec::with_capacity(8);
+
+        for i in 0..8 {
+            let sector = sectors.next().unwrap();
+            let (code, signature) = sector.split_at(sector_size - 4);
+
+            if LE::read_u32(signature) != 0xaa550000 {
+                return Err(LoadError::InvalidExtendedBootSignature(i));
+            }
+
+            extended_boot_sectors.push(code.to_vec());
+        }
+
+        // Load OEM Parameters.
+        let oem_parameters = sectors.next().unwrap()[..480]
+            .chunks_exact(48)
+            .map(|data| OemParameter {
+                guid: data[..16].try_into().unwrap(),
+                data: data[16..].try_into().unwrap(),
+            })
+            .collect();
+
+        Ok(Self {
+            extended_boot_sectors,
+            oem_parameters,
+        })
+    }
+
+    /// Returns the ExtendedBootCode of all Extended Boot Sectors.
+    pub fn extended_boot_sectors(&self) -> impl Iterator<Item = &[u8]> {
+        self.extended_boot_sectors.iter().map(|v| v.as_slice())
+    }
+
+    /// Returns all 10 parameters in the OEM Parameters sector, including the unused one.
+    pub fn oem_parameters(&self) -> &[OemParameter] {
+        &self.oem_parameters
+    }
+
+    /// Returns the first Flash Parameters in the OEM Parameters sector.
+    pub fn flash_parameters(&self) -> Option<FlashParameters> {
+        self.oem_parameters
+            .iter()
+            .find(|p| p.guid == FlashParameters::GUID)
+            .map(|p| FlashParameters::load(&p.data))
+    }
+}
+
+/// Represents a parameter in the OEM Parameters sector.
+#[derive(Debug, Clone)]
+pub struct OemParameter {
+    guid: [u8; 16],
+    data: [u8; 32],
+}
+
+impl OemParameter {
+    /// Returns ParametersGuid in on-disk byte order.
+    pub fn guid(&self) -> &[u8; 16] {
+        &self.guid
+    }
+
+    /// Returns the data that follows ParametersGuid.
+    pub fn data(&self) -> &[u8; 32] {
+        &self.data
+    }
+
+    /// Returns `true` if ParametersGuid is the null GUID, which means the parameter is not in use.
+    pub fn is_unused(&self) -> bool {
+        self.guid.iter().all(|&b| b == 0)
+    }
+}
+
+/// Represents the Flash Parameters in the OEM Parameters sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlashParameters {
+    erase_block_size: u32,
+    page_size: u32,
+    spare_sectors: u32,
+    random_access_time: u32,
+    programming_time: u32,
+    read_cycle: u32,
+    write_cycle: u32,
+}
+
+impl FlashParameters {
+    /// {0A0C7E46-3399-4021-90C8-FA6D389C4BA2} in on-disk byte order.
+    const GUID: [u8; 16] = [
+        0x46, 0x7e, 0x0c, 0x0a, 0x99, 0x33, 0x21, 0x40, 0x90, 0xc8, 0xfa, 0x6d, 0x38, 0x9c, 0x4b,
+        0xa2,
+    ];
+
+    fn load(data: &[u8; 32]) -> Self {
+        Self {
+            erase_block_size: LE::read_u32(&data[0..]),
+            page_size: LE::read_u32(&data[4..]),
+            spare_sectors: LE::read_u32(&data[8..]),
+            random_access_time: LE::read_u32(&data[12..]),
+            programming_time: LE::read_u32(&data[16..]),
+            read_cycle: LE::read_u32(&data[20..]),
+            write_cycle: LE::read_u32(&data[24..]),
+        }
+    }
+
+    /// Returns the size of erase block, in bytes.
+    pub fn erase_block_size(&self) -> u32 {
+        self.erase_block_size
+    }
+
+    /// Returns the size of page, in bytes.
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Returns the number of sectors that the media reserves for its internal sparing operations.
+    pub fn spare_sectors(&self) -> u32 {
+        self.spare_sectors
+    }
+
+    /// Returns the random access time, in nanoseconds.
+    pub fn random_access_time(&self) -> u32 {
+        self.random_access_time
+    }
+
+    /// Returns the programming time, in nanoseconds.
+    pub fn programming_time(&self) -> u32 {
+        self.programming_time
+    }
+
+    /// Returns the read cycle time, in nanoseconds.
+    pub fn read_cycle(&self) -> u32 {
+        self.read_cycle
+    }
+
+    /// Returns the write cycle time, in nanoseconds.
+    pub fn write_cycle(&self) -> u32 {
+        self.write_cycle
+    }
+}
+
+/// Computes the boot checksum of `sectors`, which must be the first 11 sectors of the boot
+/// region.
+pub(crate) fn checksum(sectors: &[u8]) -> u32 {
+    let mut checksum = 0u32;
+
+    for (i, &b) in sectors.iter().enumerate() {
+        // Skip VolumeFlags and PercentInUse.
+        if i == 106 || i == 107 || i == 112 {
+            continue;
+        }
+
+        checksum = checksum.rotate_right(1).wrapping_add(b.into());
+    }
+
+    checksum
+}
+
+/// Represents an error for [`BootRegion::load()`].
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("Extended Boot Sector #{0} has invalid signature")]
+    InvalidExtendedBootSignature(usize),
+}
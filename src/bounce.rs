@@ -0,0 +1,21 @@
+/// A buffer that is aligned to the logical block size with the size rounded up to it.
+pub(crate) struct Bounce {
+    data: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl Bounce {
+    pub fn new(len: usize, align: u64) -> Self {
+        let align = align as usize;
+        let len = len.div_ceil(align) * align;
+        let data = vec![0u8; len + align];
+        let start = data.as_ptr().align_offset(align);
+
+        Self { data, start, len }
+    }
+
+    pub fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..(self.start + self.len)]
+    }
+}
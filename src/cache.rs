@@ -0,0 +1,411 @@
+//! Caching the clusters that was read from the partition.
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+use crate::sync::{Arc, Mutex};
+use crate::trace::trace;
+use crate::IoStats;
+use alloc_crate::collections::BTreeMap;
+use core::cmp::min;
+use core::error::Error;
+use core::fmt::{Debug, Formatter};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A storage for the clusters that was read from the Cluster Heap. Use
+/// [`OpenOptions::cluster_cache()`][crate::OpenOptions::cluster_cache()] to use a custom cache.
+///
+/// The blocks are identified by the volume and the index of the block, which is the index of the
+/// cluster minus 2. The volume identifier is unique within the process so a single cache can be
+/// shared by multiple volumes. All blocks of the same volume have the same size.
+pub trait ClusterCache: Send + Sync {
+    /// Copies the data at `offset` within the block into `buf`. Returns `false` if the block is
+    /// not in the cache.
+    fn get(&self, volume: u64, block: u64, offset: usize, buf: &mut [u8]) -> bool;
+
+    /// Stores the data of the block, which was just read from the partition.
+    fn insert(&self, volume: u64, block: u64, data: Vec<u8>);
+
+    /// Removes the block from the cache if present.
+    fn remove(&self, volume: u64, block: u64);
+
+    /// Applies the data that was written at `offset` within the block. The default
+    /// implementation removes the block.
+    fn update(&self, volume: u64, block: u64, offset: usize, data: &[u8]) {
+        let _ = (offset, data);
+
+        self.remove(volume, block);
+    }
+}
+
+impl Debug for dyn ClusterCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str("ClusterCache")
+    }
+}
+
+/// An implementation of [`ClusterCache`] that evicts the least recently used block when full.
+/// This is the cache that is used by
+/// [`OpenOptions::cache_size()`][crate::OpenOptions::cache_size()].
+pub struct LruCache {
+    capacity: usize,
+    blocks: Mutex<Blocks>,
+}
+
+impl LruCache {
+    /// Creates a cache that keeps up to `capacity` blocks.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: Mutex::new(Blocks {
+                data: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Returns the number of cached blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.lock().unwrap().data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ClusterCache for LruCache {
+    fn get(&self, volume: u64, block: u64, offset: usize, buf: &mut [u8]) -> bool {
+        let mut blocks = self.blocks.lock().unwrap();
+        let data = match blocks.touch((volume, block)) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        buf.copy_from_slice(&data[offset..(offset + buf.len())]);
+
+        true
+    }
+
+    fn insert(&self, volume: u64, block: u64, data: Vec<u8>) {
+        if self.capacity != 0 {
+            self.blocks
+                .lock()
+                .unwrap()
+                .insert((volume, block), data, self.capacity);
+        }
+    }
+
+    fn remove(&self, volume: u64, block: u64) {
+        self.blocks.lock().unwrap().remove((volume, block));
+    }
+
+    fn update(&self, volume: u64, block: u64, offset: usize, data: &[u8]) {
+        let mut blocks = self.blocks.lock().unwrap();
+
+        if let Some(v) = blocks.data.get_mut(&(volume, block)) {
+            v.0[offset..(offset + data.len())].copy_from_slice(data);
+        }
+    }
+}
+
+/// A [`DiskPartition`] that keeps the recently used blocks of the Cluster Heap in a
+/// [`ClusterCache`].
+///
+/// Each block has the same size as a cluster. The reads on the Cluster Heap are served from the
+/// cached blocks. The writes always go to the underlying partition then update the cached blocks
+/// so the cache never contains stale data. The cache is disabled if there is no [`ClusterCache`].
+pub(crate) struct BlockCache<P: DiskPartition> {
+    partition: P,
+    base: u64,
+    block_size: u64,
+    id: u64,
+    cache: Option<Arc<dyn ClusterCache>>,
+    generation: Mutex<u64>, // Incremented after each write to the partition.
+    bytes_read: AtomicU64,
+    reads: AtomicU64,
+    clusters_read: AtomicU64,
+    cache_hits: AtomicU64,
+}
+
+impl<P: DiskPartition> BlockCache<P> {
+    /// Creates a cache of `capacity` blocks of `block_size` bytes that start at `base`.
+    pub fn new(partition: P, base: u64, block_size: u64, capacity: usize) -> Self {
+        let cache: Option<Arc<dyn ClusterCache>> = if capacity == 0 {
+            None
+        } else {
+            Some(Arc::new(LruCache::new(capacity)))
+        };
+
+        Self::with_cache(partition, base, block_size, cache)
+    }
+
+    /// Creates a cache of `block_size` bytes blocks that start at `base` with `cache` as the
+    /// storage.
+    pub fn with_cache(
+        partition: P,
+        base: u64,
+        block_size: u64,
+        cache: Option<Arc<dyn ClusterCache>>,
+    ) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        Self {
+            partition,
+            base,
+            block_size,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            cache,
+            generation: Mutex::new(0),
+            bytes_read: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+            clusters_read: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the I/O counters since the cache was created or reset.
+    pub fn stats(&self) -> IoStats {
+        IoStats {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            reads: self.reads.load(Ordering::Relaxed),
+            clusters_read: self.clusters_read.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.bytes_read.store(0, Ordering::Relaxed);
+        self.reads.store(0, Ordering::Relaxed);
+        self.clusters_read.store(0, Ordering::Relaxed);
+        self.cache_hits.store(0, Ordering::Relaxed);
+    }
+
+    /// Updates the counters for a read of `len` bytes at `offset` on the partition.
+    fn count_read(&self, offset: u64, len: u64) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(len, Ordering::Relaxed);
+
+        // Count the clusters that overlap with the read.
+        let end = offset + len;
+
+        if end > self.base && len != 0 {
+            let first = offset.saturating_sub(self.base) / self.block_size;
+            let last = (end - 1 - self.base) / self.block_size;
+
+            self.clusters_read
+                .fetch_add(last - first + 1, Ordering::Relaxed);
+        }
+    }
+
+    /// Copies the data at `offset` from the cached block into `buf`. The block will be loaded if it
+    /// is not in the cache. Returns [`None`] if the data should be read from the partition
+    /// directly.
+    fn read_block(&self, offset: u64, buf: &mut [u8]) -> Option<u64> {
+        let cache = self.cache.as_ref()?;
+
+        if offset < self.base || buf.is_empty() {
+            return None;
+        }
+
+        // Get the block.
+        let index = (offset - self.base) / self.block_size;
+        let start = ((offset - self.base) % self.block_size) as usize;
+        let amount = min(buf.len() as u64, self.block_size - start as u64) as usize;
+        let buf = &mut buf[..amount];
+
+        if cache.get(self.id, index, start, buf) {
+            trace!("cache hit on block #{index}");
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            trace!("cache miss on block #{index}");
+
+            // A write that completes after we take the generation may not be in the data we read so
+            // the block will not be cached in that case.
+            let offset = self.base + index * self.block_size;
+            let generation = *self.generation.lock().unwrap();
+            let mut data = vec![0u8; self.block_size as usize];
+
+            self.partition.read_exact(offset, &mut data).ok()?;
+            self.count_read(offset, self.block_size);
+
+            buf.copy_from_slice(&data[start..(start + amount)]);
+
+            // Hold the lock while inserting so no writes can update the cache in between.
+            let current = self.generation.lock().unwrap();
+
+            if *current == generation {
+                cache.insert(self.id, index, data);
+            }
+        }
+
+        Some(amount as u64)
+    }
+
+    /// Applies the data that was written at `offset` to the cached blocks.
+    fn update(&self, offset: u64, buf: &[u8]) {
+        let end = offset + buf.len() as u64;
+        let cache = match &self.cache {
+            Some(v) if end > self.base && !buf.is_empty() => v,
+            _ => return,
+        };
+
+        let first = offset.saturating_sub(self.base) / self.block_size;
+        let last = (end - 1 - self.base) / self.block_size;
+        let mut generation = self.generation.lock().unwrap();
+
+        *generation += 1;
+
+        for index in first..=last {
+            // Get the overlapped range.
+            let block = self.base + index * self.block_size;
+            let from = offset.max(block);
+            let to = end.min(block + self.block_size);
+            let src = &buf[((from - offset) as usize)..((to - offset) as usize)];
+
+            cache.update(self.id, index, (from - block) as usize, src);
+        }
+    }
+
+    /// Removes the cached blocks that overlap with `len` bytes at `offset`.
+    fn invalidate(&self, offset: u64, len: usize) {
+        let end = offset + len as u64;
+        let cache = match &self.cache {
+            Some(v) if end > self.base && len != 0 => v,
+            _ => return,
+        };
+
+        let first = offset.saturating_sub(self.base) / self.block_size;
+        let last = (end - 1 - self.base) / self.block_size;
+        let mut generation = self.generation.lock().unwrap();
+
+        *generation += 1;
+
+        for index in first..=last {
+            cache.remove(self.id, index);
+        }
+    }
+}
+
+impl<P: DiskPartition> DiskPartition for BlockCache<P> {
+    fn len(&self) -> Option<u64> {
+        self.partition.len()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.partition.block_size()
+    }
+
+    fn is_writable(&self) -> bool {
+        self.partition.is_writable()
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.partition.flush()
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.invalidate(offset, len as usize);
+        self.partition.discard(offset, len)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(offset = offset, len = buf.len()))
+    )]
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        match self.read_block(offset, buf) {
+            Some(v) => Ok(v),
+            None => {
+                trace!("reading {} bytes at {offset:#x}", buf.len());
+
+                let n = self.partition.read(offset, buf)?;
+
+                self.count_read(offset, n);
+
+                Ok(n)
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(offset = offset))
+    )]
+    fn read_vectored_at(
+        &self,
+        offset: u64,
+        bufs: &mut [std::io::IoSliceMut<'_>],
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if self.cache.is_some() {
+            return match bufs.iter_mut().find(|b| !b.is_empty()) {
+                Some(b) => self.read(offset, b),
+                None => Ok(0),
+            };
+        }
+
+        let n = self.partition.read_vectored_at(offset, bufs)?;
+
+        self.count_read(offset, n);
+
+        Ok(n)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        match self.partition.write(offset, buf) {
+            Ok(v) => {
+                self.update(offset, &buf[..(v as usize)]);
+                Ok(v)
+            }
+            Err(e) => {
+                self.invalidate(offset, buf.len());
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Cached blocks with their last use.
+struct Blocks {
+    data: BTreeMap<(u64, u64), (Vec<u8>, u64)>,
+    lru: BTreeMap<u64, (u64, u64)>, // Last use to block key.
+    tick: u64,
+}
+
+impl Blocks {
+    fn insert(&mut self, key: (u64, u64), data: Vec<u8>, capacity: usize) {
+        // Replace the existing block, which is possible when two readers missed the same block.
+        self.remove(key);
+
+        // Evict the least recently used blocks.
+        while self.data.len() >= capacity {
+            let (_, key) = match self.lru.pop_first() {
+                Some(v) => v,
+                None => break,
+            };
+
+            self.data.remove(&key);
+        }
+
+        self.tick += 1;
+        self.lru.insert(self.tick, key);
+        self.data.insert(key, (data, self.tick));
+    }
+
+    fn touch(&mut self, key: (u64, u64)) -> Option<&[u8]> {
+        let block = self.data.get_mut(&key)?;
+
+        self.tick += 1;
+        self.lru.remove(&block.1);
+        self.lru.insert(self.tick, key);
+        block.1 = self.tick;
+
+        Some(&block.0)
+    }
+
+    fn remove(&mut self, key: (u64, u64)) {
+        if let Some((_, used)) = self.data.remove(&key) {
+            self.lru.remove(&used);
+        }
+    }
+}
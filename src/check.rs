@@ -0,0 +1,427 @@
+use crate::cluster::ClustersReader;
+use crate::disk::DiskPartition;
+use crate::entries::{ClusterAllocation, EntriesReader, EntrySet, EntryType, FileEntry};
+use crate::fat::Fat;
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{Cluster, ExFat};
+use alloc_crate::collections::VecDeque;
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+/// Contains the result of [`Root::check()`][crate::Root::check()].
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CheckReport {
+    problems: Vec<Problem>,
+    partial: bool,
+}
+
+impl CheckReport {
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns `true` if some items cannot be walked (e.g. invalid entry sets or cluster chains).
+    /// The clusters of these items are unknown so the lost clusters are not reported in this
+    /// case.
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+
+    /// Returns `true` if no problems was found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Returns the clusters that are used by more than one item, along with the path of the
+    /// first item that use it and the path of the other one.
+    pub fn cross_links(&self) -> impl Iterator<Item = (Cluster, &str, &str)> + '_ {
+        self.problems.iter().filter_map(|p| match p {
+            Problem::CrossLinked(c, a, b) => Some((*c, a.as_str(), b.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Returns the clusters that are marked as allocated in the Allocation Bitmap but not used by
+    /// any item. This is always empty if [`is_partial()`][Self::is_partial()] returns `true`.
+    pub fn lost_clusters(&self) -> impl Iterator<Item = Cluster> + '_ {
+        self.problems.iter().filter_map(|p| match p {
+            Problem::LostCluster(c) => Some(*c),
+            _ => None,
+        })
+    }
+}
+
+/// Represents a problem found by [`Root::check()`][crate::Root::check()].
+///
+/// The path of the Allocation Bitmap and the Up-case Table are `<Allocation Bitmap>` and
+/// `<Up-case Table>`, respectively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Problem {
+    /// The cluster is used by both items.
+    CrossLinked(Cluster, String, String),
+
+    /// The cluster is marked as allocated but not used by any item.
+    LostCluster(Cluster),
+
+    /// The cluster is used by the item but not marked as allocated.
+    UnallocatedCluster(Cluster, String),
+
+    /// The number of clusters in the chain does not match DataLength.
+    LengthMismatch(String),
+
+    /// The cluster chain of the item contains an invalid cluster.
+    InvalidCluster(String, Cluster),
+
+    /// The cluster chain of the item contains a loop.
+    CyclicChain(String),
+
+    /// The SetChecksum of the item is not correct.
+    InvalidChecksum(String),
+
+    /// The NameHash of the item does not match with its name.
+    InvalidNameHash(String),
+
+    /// The entry set at the index in the directory cannot be loaded.
+    InvalidEntrySet(String, usize),
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::CrossLinked(c, a, b) => write!(f, "cluster {c} is used by both {a} and {b}"),
+            Self::LostCluster(c) => write!(f, "cluster {c} is allocated but not used"),
+            Self::UnallocatedCluster(c, p) => {
+                write!(f, "cluster {c} is used by {p} but not allocated")
+            }
+            Self::LengthMismatch(p) => write!(f, "cluster chain of {p} does not match DataLength"),
+            Self::InvalidCluster(p, c) => write!(f, "cluster {c} of {p} is not valid"),
+            Self::CyclicChain(p) => write!(f, "cluster chain of {p} contains a loop"),
+            Self::InvalidChecksum(p) => write!(f, "SetChecksum of {p} is not correct"),
+            Self::InvalidNameHash(p) => write!(f, "NameHash of {p} is not correct"),
+            Self::InvalidEntrySet(p, i) => write!(f, "entry set #{i} in {p} is not valid"),
+        }
+    }
+}
+
+/// Walks the FAT, the Allocation Bitmap and the directory tree of the volume.
+pub(crate) struct Checker<'a, P: DiskPartition> {
+    exfat: &'a Arc<ExFat<P>>,
+    owners: Vec<Option<usize>>,
+    paths: Vec<String>,
+    pub problems: Vec<Problem>,
+    pub invalid_sets: Vec<(String, EntrySet)>, // Entry sets with incorrect SetChecksum.
+    pub partial: bool, // true if some items was not walked so the lost clusters may be in use.
+    cancel: Option<&'a AtomicBool>,
+}
+
+impl<'a, P: DiskPartition> Checker<'a, P> {
+    pub fn new(exfat: &'a Arc<ExFat<P>>) -> Self {
+        Self {
+            exfat,
+            owners: vec![None; exfat.params.cluster_count + 2],
+            paths: Vec::new(),
+            problems: Vec::new(),
+            invalid_sets: Vec::new(),
+            partial: false,
+            cancel: None,
+        }
+    }
+
+    /// Stops the check with [`CheckError::Cancelled`] when `v` become `true`.
+    pub fn cancel(mut self, v: &'a AtomicBool) -> Self {
+        self.cancel = Some(v);
+        self
+    }
+
+    pub fn run(mut self) -> Result<CheckReport, CheckError> {
+        self.check()?;
+
+        Ok(CheckReport {
+            problems: self.problems,
+            partial: self.partial,
+        })
+    }
+
+    /// Runs all checks and populates [`problems`][Self::problems] and
+    /// [`invalid_sets`][Self::invalid_sets].
+    pub fn check(&mut self) -> Result<(), CheckError> {
+        // Walk the directory tree.
+        let root = self.exfat.params.first_cluster_of_root_directory;
+        let mut queue: VecDeque<(String, Cluster, Option<u64>, bool)> = VecDeque::new();
+
+        if self.claim("/", root, None, false)? {
+            queue.push_back((String::new(), root, None, false));
+        } else {
+            self.partial = true;
+        }
+
+        while let Some((path, first, len, no_fat_chain)) = queue.pop_front() {
+            // The Allocation Bitmap cannot be checked on a partial walk so report only the
+            // problems that was found.
+            if self.cancel.is_some_and(|v| v.load(Ordering::Relaxed)) {
+                let problems = core::mem::take(&mut self.problems);
+                let report = CheckReport {
+                    problems,
+                    partial: true,
+                };
+
+                return Err(CheckError::Cancelled(report));
+            }
+
+            let reader =
+                match ClustersReader::new(self.exfat.clone(), first, len, Some(no_fat_chain)) {
+                    Ok(v) => EntriesReader::new(v),
+                    Err(_) => {
+                        // The problem is already reported by claim().
+                        self.partial = true;
+                        continue;
+                    }
+                };
+
+            self.scan(reader, &path, &mut queue)?;
+        }
+
+        // Check the Allocation Bitmap. The clusters that look lost may be used by the items that
+        // was not walked.
+        let alloc = self.exfat.alloc.lock().unwrap();
+
+        for (cluster, owner) in (2..=u32::MAX).map(Cluster).zip(&self.owners[2..]) {
+            match (alloc.is_allocated(cluster), owner) {
+                (true, None) if !self.partial => self.problems.push(Problem::LostCluster(cluster)),
+                (false, Some(i)) => self
+                    .problems
+                    .push(Problem::UnallocatedCluster(cluster, self.paths[*i].clone())),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if `cluster` is used by any item. Only valid after
+    /// [`check()`][Self::check()].
+    pub fn is_used(&self, cluster: Cluster) -> bool {
+        self.owners
+            .get(cluster.index())
+            .is_some_and(Option::is_some)
+    }
+
+    /// Checks all entries in the directory. Sub-directories will be added to `queue`.
+    fn scan(
+        &mut self,
+        mut reader: EntriesReader<P>,
+        path: &str,
+        queue: &mut VecDeque<(String, Cluster, Option<u64>, bool)>,
+    ) -> Result<(), CheckError> {
+        let dir = if path.is_empty() { "/" } else { path };
+        let mut index = 0;
+
+        loop {
+            let entry = match reader.read() {
+                Ok(Some(v)) => v,
+                Ok(None) => break,
+                Err(e) => return Err(CheckError::ReadEntryFailed(dir.into(), e)),
+            };
+
+            // Skip unused and secondary entries.
+            let ty = entry.ty();
+
+            if !ty.is_regular() {
+                if entry.data()[0] == 0 {
+                    break;
+                }
+
+                continue;
+            } else if ty.type_category() != EntryType::PRIMARY
+                || ty.type_importance() != EntryType::CRITICAL
+            {
+                continue;
+            }
+
+            index += 1;
+
+            match ty.type_code() {
+                1 | 2 if path.is_empty() => {
+                    let name = if ty.type_code() == 1 {
+                        "<Allocation Bitmap>"
+                    } else {
+                        "<Up-case Table>"
+                    };
+
+                    match ClusterAllocation::load(&entry) {
+                        Ok(v) => {
+                            self.claim(name, v.first_cluster(), Some(v.data_length()), false)?;
+                        }
+                        Err(_) => {
+                            let problem = Problem::InvalidEntrySet(dir.into(), index - 1);
+                            self.problems.push(problem);
+                            self.partial = true;
+                        }
+                    }
+                }
+                5 => {
+                    let file = match FileEntry::load_unchecked(&entry, &mut reader) {
+                        Ok(v) => v,
+                        Err(_) => {
+                            let problem = Problem::InvalidEntrySet(dir.into(), index - 1);
+                            self.problems.push(problem);
+                            self.partial = true;
+                            continue;
+                        }
+                    };
+
+                    // Check the entry set.
+                    let path = format!("{}/{}", path, file.name);
+
+                    if !file.is_name_hash_valid(self.exfat.upcase()) {
+                        self.problems.push(Problem::InvalidNameHash(path.clone()));
+                    }
+
+                    if !file.entries.is_checksum_valid() {
+                        self.problems.push(Problem::InvalidChecksum(path.clone()));
+                        self.invalid_sets.push((path.clone(), file.entries));
+                    }
+
+                    // Check the allocation.
+                    let stream = &file.stream;
+                    let alloc = stream.allocation();
+                    let first = alloc.first_cluster();
+                    let len = alloc.data_length();
+                    let no_fat_chain = stream.no_fat_chain();
+
+                    for (i, v) in file
+                        .vendor_entries
+                        .iter()
+                        .filter(|v| v.is_vendor_allocation())
+                        .enumerate()
+                    {
+                        let path = format!("{path}<Vendor Allocation #{i}>");
+                        let (first, len) = v.allocation().unwrap();
+
+                        self.claim(&path, first, Some(len), v.no_fat_chain())?;
+                    }
+
+                    let usable = self.claim(&path, first, Some(len), no_fat_chain)?;
+
+                    if !file.attributes.is_directory() || first.0 == 0 {
+                        continue;
+                    } else if usable {
+                        queue.push_back((path, first, Some(len), no_fat_chain));
+                    } else {
+                        self.partial = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks the clusters in the chain as owned by `path`. Returns `false` if the chain is not
+    /// usable.
+    fn claim(
+        &mut self,
+        path: &str,
+        first: Cluster,
+        len: Option<u64>,
+        no_fat_chain: bool,
+    ) -> Result<bool, CheckError> {
+        let count = len.map(|v| v.div_ceil(self.exfat.params.cluster_size()) as usize);
+        let owner = self.paths.len();
+
+        self.paths.push(path.into());
+
+        if (first.0 == 0) != (count == Some(0)) {
+            self.problems.push(Problem::LengthMismatch(path.into()));
+            return Ok(false);
+        } else if first.0 == 0 {
+            return Ok(true);
+        }
+
+        // Walk the chain. The chain cannot be longer than the Cluster Heap even if DataLength is
+        // corrupted.
+        let fat = self.exfat.fat.lock().unwrap();
+        let cluster_count = self.exfat.params.cluster_count;
+        let mut cluster = first;
+        let mut claimed = 0;
+        let mut ok = true;
+
+        while claimed < cluster_count {
+            if !cluster.is_valid(cluster_count) {
+                self.problems
+                    .push(Problem::InvalidCluster(path.into(), cluster));
+                self.partial = true;
+                ok = false;
+                break;
+            }
+
+            match self.owners[cluster.index()] {
+                Some(v) if v == owner => {
+                    self.problems.push(Problem::CyclicChain(path.into()));
+                    ok = false;
+                    break;
+                }
+                Some(v) => {
+                    let problem = Problem::CrossLinked(cluster, self.paths[v].clone(), path.into());
+                    self.problems.push(problem);
+                    ok = false;
+
+                    // The remaining of the FAT chain is already walked by the other item.
+                    if !no_fat_chain {
+                        break;
+                    }
+                }
+                None => self.owners[cluster.index()] = Some(owner),
+            }
+
+            claimed += 1;
+
+            // Move to the next cluster.
+            if no_fat_chain {
+                if Some(claimed) == count {
+                    break;
+                }
+
+                cluster = Cluster(cluster.0.wrapping_add(1));
+                continue;
+            }
+
+            let next = match fat.get(&self.exfat.partition, cluster) {
+                Ok(v) => v.unwrap(),
+                Err(e) => return Err(CheckError::ReadFatFailed(path.into(), e)),
+            };
+
+            if next == Fat::END_OF_CHAIN || Some(claimed) == count {
+                if count.is_some_and(|v| v != claimed) || next != Fat::END_OF_CHAIN {
+                    self.problems.push(Problem::LengthMismatch(path.into()));
+                }
+
+                break;
+            }
+
+            cluster = Cluster(next);
+        }
+
+        Ok(ok)
+    }
+}
+
+/// Represents an error for [`Root::check()`][crate::Root::check()].
+#[derive(Debug, Error)]
+pub enum CheckError {
+    #[error("cannot read an entry in {0}")]
+    ReadEntryFailed(String, #[source] crate::entries::ReaderError),
+
+    #[error("cannot read the FAT entry for {0}")]
+    ReadFatFailed(String, #[source] crate::fat::ReadError),
+
+    /// The check was cancelled. Contains the problems that was found before the cancellation,
+    /// which does not include the problems on the Allocation Bitmap.
+    #[error("the check was cancelled")]
+    Cancelled(CheckReport),
+}
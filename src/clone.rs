@@ -0,0 +1,156 @@
+//! Copying a volume to another partition.
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+use crate::sparse::Sparse;
+use crate::sync::Arc;
+use crate::{Cluster, ExFat, SyncError};
+use core::error::Error;
+use thiserror::Error;
+
+/// Options for [`Root::clone_with()`][crate::Root::clone_with()].
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    sparse: bool,
+}
+
+impl CloneOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skips writing the blocks that contain only zeroes so the destination stays sparse. The
+    /// destination must read as zeroes before the clone (e.g. an image that was just created with
+    /// [`ImageBuilder`][crate::image::ImageBuilder]). The default is `false`.
+    pub fn sparse(mut self, v: bool) -> Self {
+        self.sparse = v;
+        self
+    }
+}
+
+/// Copies the Main and Backup Boot Region, the FATs and the allocated clusters of `exfat` to
+/// `dest`. Returns the number of bytes that was copied.
+pub(crate) fn clone<P, D>(
+    exfat: &Arc<ExFat<P>>,
+    dest: D,
+    options: &CloneOptions,
+) -> Result<u64, CloneError>
+where
+    P: DiskPartition,
+    D: DiskPartition,
+{
+    if !dest.is_writable() {
+        return Err(CloneError::DestinationNotWritable);
+    }
+
+    // Check the size of the destination.
+    let params = &exfat.params;
+    let sector_size = params.bytes_per_sector;
+    let volume_size = params.volume_length * sector_size;
+
+    if dest.len().is_some_and(|v| v < volume_size) {
+        return Err(CloneError::DestinationTooSmall);
+    }
+
+    // Get the regions to copy. The clusters are merged into runs to reduce the number of I/O.
+    let mut regions = vec![
+        (0, 24 * sector_size),
+        (
+            params.fat_offset.offset(sector_size),
+            params.fat_length * params.number_of_fats as u64 * sector_size,
+        ),
+    ];
+
+    let cluster_size = params.cluster_size();
+    let alloc = exfat.alloc.lock().unwrap();
+    let mut run: Option<(u64, u64)> = None;
+
+    for cluster in (2..(params.cluster_count as u32 + 2)).map(Cluster) {
+        if !alloc.is_allocated(cluster) {
+            continue;
+        }
+
+        // The remaining clusters cannot be in the Cluster Heap either.
+        let offset = match params.cluster_offset(cluster) {
+            Some(v) => v,
+            None => break,
+        };
+
+        run = match run {
+            Some((o, l)) if o + l == offset => Some((o, l + cluster_size)),
+            Some(v) => {
+                regions.push(v);
+                Some((offset, cluster_size))
+            }
+            None => Some((offset, cluster_size)),
+        };
+    }
+
+    drop(alloc);
+    regions.extend(run);
+
+    // Copy the regions.
+    let copied = if options.sparse {
+        copy(exfat, &regions, &Sparse::new(&dest))?
+    } else {
+        copy(exfat, &regions, &dest)?
+    };
+
+    if let Err(e) = dest.flush() {
+        return Err(CloneError::FlushFailed(e));
+    }
+
+    Ok(copied)
+}
+
+fn copy<P: DiskPartition, D: DiskPartition>(
+    exfat: &ExFat<P>,
+    regions: &[(u64, u64)],
+    dest: &D,
+) -> Result<u64, CloneError> {
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut copied = 0;
+
+    for &(mut offset, len) in regions {
+        let end = offset + len;
+
+        while offset < end {
+            let n = buf.len().min((end - offset) as usize);
+            let buf = &mut buf[..n];
+
+            if let Err(e) = exfat.partition.read_exact(offset, buf) {
+                return Err(CloneError::ReadFailed(offset, e));
+            }
+
+            if let Err(e) = dest.write_exact(offset, buf) {
+                return Err(CloneError::WriteFailed(offset, e));
+            }
+
+            offset += n as u64;
+            copied += n as u64;
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Represents an error for [`Root::clone_to()`][crate::Root::clone_to()].
+#[derive(Debug, Error)]
+pub enum CloneError {
+    #[error("the destination does not support writing")]
+    DestinationNotWritable,
+
+    #[error("the destination is smaller than the volume")]
+    DestinationTooSmall,
+
+    #[error("cannot sync the volume")]
+    SyncFailed(#[source] SyncError),
+
+    #[error("cannot read the data at {0:#018x}")]
+    ReadFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot write the data at {0:#018x}")]
+    WriteFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot flush the destination")]
+    FlushFailed(#[source] Box<dyn Error + Send + Sync>),
+}
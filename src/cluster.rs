@@ -1,33 +1,41 @@
 use crate::disk::DiskPartition;
-use crate::ExFat;
-use std::cmp::min;
-use std::io::{Read, Seek, SeekFrom};
-use std::sync::Arc;
+use crate::fat::ChainError;
+use crate::io::{Read, Seek, SeekFrom};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{BadClusterPolicy, Cluster, ExFat, ReadGranularity};
+use core::cmp::{max, min};
+#[cfg(feature = "std")]
+use std::io::IoSliceMut;
 use thiserror::Error;
 
 /// A cluster reader to read all data in a cluster chain.
 pub(crate) struct ClustersReader<P: DiskPartition> {
     exfat: Arc<ExFat<P>>,
-    chain: Vec<usize>,
+    chain: Clusters,
     data_length: u64,
+    valid_data_length: u64,
     offset: u64,
+    bad: Option<(Cluster, u64)>, // Bad cluster and its offset if the chain end before data_length.
+    ahead: Option<(u64, Vec<u8>)>, // Offset and data of the clusters that was read ahead.
+    sequential: bool,            // false if the current position was changed by seek.
 }
 
 impl<P: DiskPartition> ClustersReader<P> {
     pub fn new(
         exfat: Arc<ExFat<P>>,
-        first_cluster: usize,
+        first_cluster: Cluster,
         data_length: Option<u64>,
         no_fat_chain: Option<bool>,
     ) -> Result<Self, NewError> {
-        if first_cluster < 2 {
+        if first_cluster.0 < 2 {
             return Err(NewError::InvalidFirstCluster);
         }
 
         // Get cluster chain.
         let params = &exfat.params;
-        let fat = &exfat.fat;
         let cluster_size = params.cluster_size();
+        let mut bad = None;
         let (chain, data_length) = if no_fat_chain.unwrap_or(false) {
             // If the NoFatChain bit is 1 then DataLength must not be zero.
             let data_length = match data_length {
@@ -35,48 +43,370 @@ impl<P: DiskPartition> ClustersReader<P> {
                 _ => return Err(NewError::InvalidDataLength),
             };
 
-            // FIXME: Use div_ceil once https://github.com/rust-lang/rust/issues/88581 stabilized.
-            let count = (data_length + cluster_size - 1) / cluster_size;
-            let chain: Vec<usize> = (first_cluster..(first_cluster + count as usize)).collect();
+            // The last cluster must still fit in FirstCluster.
+            let count = data_length.div_ceil(cluster_size);
 
-            (chain, data_length)
+            if u64::from(first_cluster.0) + count - 1 > u64::from(u32::MAX) {
+                return Err(NewError::InvalidDataLength);
+            }
+
+            (
+                Clusters::Contiguous(first_cluster, count as usize),
+                data_length,
+            )
         } else {
-            let chain: Vec<usize> = fat.get_cluster_chain(first_cluster).collect();
+            let fat = exfat.fat.lock().unwrap();
+            let mut chain = fat.get_cluster_chain(&exfat.partition, first_cluster);
+            let clusters: Clusters = chain.by_ref().collect();
+            let bad_cluster = chain.bad_cluster();
+
+            if let Err(e) = chain.finish() {
+                return Err(NewError::ReadChainFailed(e));
+            }
+
+            drop(fat);
 
-            if chain.is_empty() {
+            // The first cluster itself can be bad.
+            if clusters.is_empty() && bad_cluster.is_none() {
                 return Err(NewError::InvalidFirstCluster);
             }
 
             let data_length = match data_length {
                 Some(v) => {
-                    if v > cluster_size * chain.len() as u64 {
-                        return Err(NewError::InvalidDataLength);
-                    } else {
+                    let capacity = cluster_size * clusters.len() as u64;
+
+                    if v <= capacity {
                         v
+                    } else if let Some(c) = bad_cluster {
+                        match exfat.bad_cluster {
+                            BadClusterPolicy::Stop => capacity,
+                            BadClusterPolicy::Fail | BadClusterPolicy::Zeros => {
+                                bad = Some((c, capacity));
+                                v
+                            }
+                        }
+                    } else {
+                        return Err(NewError::InvalidDataLength);
                     }
                 }
-                None => params.bytes_per_sector * (params.sectors_per_cluster * chain.len() as u64),
+                None if clusters.is_empty() => {
+                    return Err(NewError::InvalidFirstCluster);
+                }
+                None => {
+                    params.bytes_per_sector * (params.sectors_per_cluster * clusters.len() as u64)
+                }
             };
 
-            (chain, data_length)
+            (clusters, data_length)
         };
 
         Ok(Self {
             exfat,
             chain,
             data_length,
+            valid_data_length: data_length,
             offset: 0,
+            bad,
+            ahead: None,
+            sequential: true,
         })
     }
 
-    pub fn cluster(&self) -> usize {
-        self.chain[(self.offset / self.exfat.params.cluster_size()) as usize]
+    pub fn exfat(&self) -> &Arc<ExFat<P>> {
+        &self.exfat
+    }
+
+    /// Creates a [`ClustersReader`] from the already resolved cluster chain.
+    pub fn from_chain(exfat: Arc<ExFat<P>>, chain: Clusters, data_length: u64) -> Self {
+        Self {
+            exfat,
+            chain,
+            data_length,
+            valid_data_length: data_length,
+            offset: 0,
+            bad: None,
+            ahead: None,
+            sequential: true,
+        }
+    }
+
+    pub fn clusters(&self) -> &Clusters {
+        &self.chain
+    }
+
+    pub fn clusters_mut(&mut self) -> &mut Clusters {
+        self.ahead = None;
+        &mut self.chain
+    }
+
+    /// Sets the length of the data. This also discards the data that was read ahead so it must be
+    /// called after the data was written.
+    pub fn set_data_length(&mut self, v: u64) {
+        self.ahead = None;
+        self.data_length = v;
+        self.valid_data_length = min(self.valid_data_length, v);
+        self.offset = min(self.offset, v);
+    }
+
+    /// Sets the length of data that was written. The data after it will be read as zeros.
+    pub fn set_valid_data_length(&mut self, v: u64) {
+        self.ahead = None;
+        self.valid_data_length = min(v, self.data_length);
+    }
+
+    /// Gets the current cluster. This will be the bad cluster if the current position is beyond
+    /// it.
+    pub fn cluster(&self) -> Cluster {
+        let index = (self.offset / self.exfat.params.cluster_size()) as usize;
+
+        match self.chain.get(index) {
+            Some(v) => v,
+            None => self.bad.unwrap().0,
+        }
+    }
+
+    /// Gets the offset on the partition of the current position. Returns [`None`] if the current
+    /// cluster is not available.
+    pub fn partition_offset(&self) -> Option<u64> {
+        let cluster_size = self.exfat.params.cluster_size();
+        let cluster = self.chain.get((self.offset / cluster_size) as usize)?;
+
+        self.exfat
+            .params
+            .cluster_offset(cluster)
+            .map(|v| v + self.offset % cluster_size)
+    }
+
+    /// Reads the data at `pos` without changing the current position. Returns the number of bytes
+    /// read, which can be less than `buf` if the read cross the boundary of a cluster that is not
+    /// contiguous with the next one or exceed [`ReadGranularity`]. The contiguous clusters are read
+    /// with a single read on the partition.
+    pub fn read_at(&self, pos: u64, buf: &mut [u8]) -> crate::io::Result<usize> {
+        use crate::io::Error;
+
+        // Check if the actual read is required.
+        if buf.is_empty() || pos >= self.data_length {
+            return Ok(0);
+        }
+
+        // The data after ValidDataLength is undefined so read it as zeros.
+        if pos >= self.valid_data_length {
+            let amount = min(buf.len() as u64, self.data_length - pos) as usize;
+
+            buf[..amount].fill(0);
+
+            return Ok(amount);
+        }
+
+        // Check if the position is beyond the bad cluster.
+        if let Some((cluster, offset)) = self.bad {
+            if pos >= offset {
+                if self.exfat.bad_cluster == BadClusterPolicy::Fail {
+                    // The first cluster itself can be bad.
+                    let first = self.chain.first().unwrap_or(cluster);
+
+                    return Err(Error::other(BadCluster {
+                        first_cluster: first,
+                        cluster,
+                        offset,
+                    }));
+                }
+
+                let amount = min(buf.len() as u64, self.data_length - pos) as usize;
+
+                buf[..amount].fill(0);
+
+                return Ok(amount);
+            }
+        }
+
+        // Read image.
+        let (offset, amount) = self.locate(pos, buf.len())?;
+
+        if let Err(e) = self.exfat.partition.read_exact(offset, &mut buf[..amount]) {
+            return Err(Error::other(e));
+        }
+
+        Ok(amount)
+    }
+
+    /// Same as [`read_at()`][Self::read_at()] but reads into multiple buffers with
+    /// [`DiskPartition::read_vectored_at()`]. The buffers are filled in order.
+    #[cfg(feature = "std")]
+    pub fn read_vectored_at(
+        &self,
+        pos: u64,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> crate::io::Result<usize> {
+        use crate::io::{Error, ErrorKind};
+
+        // Use read_at() if the data does not come from the partition.
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+
+        if len == 0 {
+            return Ok(0);
+        } else if pos >= self.valid_data_length || self.bad.is_some_and(|(_, o)| pos >= o) {
+            let buf = bufs.iter_mut().find(|b| !b.is_empty()).unwrap();
+
+            return self.read_at(pos, buf);
+        }
+
+        // Limit the buffers to the data that can be read at once.
+        let (offset, amount) = self.locate(pos, len)?;
+        let mut remaining = amount;
+        let mut slices = Vec::with_capacity(bufs.len());
+
+        for buf in bufs.iter_mut() {
+            if remaining == 0 {
+                break;
+            }
+
+            let len = min(buf.len(), remaining);
+
+            slices.push(IoSliceMut::new(&mut buf[..len]));
+            remaining -= len;
+        }
+
+        // Read image.
+        let read = match self.exfat.partition.read_vectored_at(offset, &mut slices) {
+            Ok(v) => v as usize,
+            Err(e) => return Err(Error::other(e)),
+        };
+
+        if read == 0 {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+
+        Ok(read)
+    }
+
+    /// Gets the offset on the partition of the data at `pos` and the number of bytes up to `len`
+    /// that can be read from it at once. `pos` must be less than ValidDataLength and must not be
+    /// beyond the bad cluster.
+    fn locate(&self, pos: u64, len: usize) -> crate::io::Result<(u64, usize)> {
+        use crate::io::Error;
+
+        // Get remaining data in the clusters that are contiguous with the current one.
+        let cluster_size = self.exfat.params.cluster_size();
+        let index = (pos / cluster_size) as usize;
+        let needed = (pos % cluster_size + len as u64).div_ceil(cluster_size);
+        let run = self.contiguous(index, needed as usize) as u64 * cluster_size;
+        let remaining = min(run - pos % cluster_size, self.valid_data_length - pos);
+
+        // Get the offset in the partition.
+        let cluster = self.chain.get(index).unwrap();
+        let offset = match self.exfat.params.cluster_offset(cluster) {
+            Some(v) => v + pos % cluster_size,
+            None => return Err(Error::other(format!("cluster {cluster} is not available"))),
+        };
+
+        // Apply the read granularity.
+        let params = &self.exfat.params;
+        let heap = params.cluster_heap_offset.offset(params.bytes_per_sector);
+        let unit = match self.exfat.read_granularity {
+            ReadGranularity::Sector => params.bytes_per_sector,
+            ReadGranularity::Cluster => cluster_size,
+            ReadGranularity::Clusters(n) => cluster_size * max(n, 1) as u64,
+            ReadGranularity::Contiguous => 0,
+        };
+
+        let remaining = match unit {
+            0 => remaining,
+            v => min(remaining, v - (offset - heap) % v),
+        };
+
+        Ok((offset, min(len, remaining as usize)))
+    }
+
+    /// Reads the cluster at the current position and up to the specified number of clusters after
+    /// it. Returns `false` if nothing was read (e.g. the current position is beyond
+    /// ValidDataLength).
+    fn read_ahead(&mut self, count: usize) -> crate::io::Result<bool> {
+        use crate::io::Error;
+
+        // Get the range to read. The data beyond ValidDataLength or the bad cluster is handled by
+        // read_at().
+        let cluster_size = self.exfat.params.cluster_size();
+        let mut end = self.valid_data_length;
+
+        if let Some((_, offset)) = self.bad {
+            end = min(end, offset);
+        }
+
+        if self.offset >= end {
+            return Ok(false);
+        }
+
+        let first = (self.offset / cluster_size) as usize;
+        let start = first as u64 * cluster_size;
+        let end = min(end, (first + 1 + count) as u64 * cluster_size);
+
+        // Read the clusters.
+        let mut data = match self.ahead.take() {
+            Some((_, v)) => v,
+            None => Vec::new(),
+        };
+
+        data.resize((end - start) as usize, 0);
+
+        let mut done = 0;
+
+        while done < data.len() {
+            let (offset, amount) = self.locate(start + done as u64, data.len() - done)?;
+            let buf = &mut data[done..(done + amount)];
+
+            if let Err(e) = self.exfat.partition.read_exact(offset, buf) {
+                return Err(Error::other(e));
+            }
+
+            done += amount;
+        }
+
+        self.ahead = Some((start, data));
+
+        Ok(true)
+    }
+
+    /// Returns the number of clusters that are physically contiguous in the chain starting at
+    /// `index`, including the cluster at `index` itself. The result will not exceed `max` unless
+    /// it is zero.
+    fn contiguous(&self, index: usize, max: usize) -> usize {
+        let params = &self.exfat.params;
+        let mut last = self.chain.get(index).unwrap();
+        let mut count = 1;
+
+        while count < max {
+            let next = match self.chain.get(index + count) {
+                Some(v) if v.0 == last.0 + 1 && params.cluster_offset(v).is_some() => v,
+                _ => break,
+            };
+
+            last = next;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Gets the data that was read ahead from the current position.
+    fn ahead_data(&self) -> Option<&[u8]> {
+        let (start, data) = self.ahead.as_ref()?;
+        let offset = self.offset.checked_sub(*start)?;
+
+        data.get((offset as usize)..).filter(|v| !v.is_empty())
+    }
+
+    /// Returns `true` if the current position is at the end of the data.
+    pub fn is_eof(&self) -> bool {
+        self.offset == self.data_length
     }
 }
 
 impl<P: DiskPartition> Seek for ClustersReader<P> {
-    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        use std::io::{Error, ErrorKind};
+    fn seek(&mut self, pos: SeekFrom) -> crate::io::Result<u64> {
+        use crate::io::{Error, ErrorKind};
+
+        let offset = self.offset;
 
         self.offset = match pos {
             SeekFrom::Start(v) => min(v, self.data_length),
@@ -100,58 +430,179 @@ impl<P: DiskPartition> Seek for ClustersReader<P> {
             }
         };
 
+        if self.offset != offset {
+            self.sequential = false;
+        }
+
         Ok(self.offset)
     }
 
-    fn rewind(&mut self) -> std::io::Result<()> {
+    fn rewind(&mut self) -> crate::io::Result<()> {
+        if self.offset != 0 {
+            self.sequential = false;
+        }
+
         self.offset = 0;
         Ok(())
     }
 
-    fn stream_position(&mut self) -> std::io::Result<u64> {
+    fn stream_position(&mut self) -> crate::io::Result<u64> {
         Ok(self.offset)
     }
 }
 
 impl<P: DiskPartition> Read for ClustersReader<P> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        use std::io::{Error, ErrorKind};
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+        // Read the following clusters in advance if the data is being read sequentially.
+        let count = self.exfat.read_ahead;
 
-        // Check if the actual read is required.
-        if buf.is_empty() || self.offset == self.data_length {
-            return Ok(0);
+        if count != 0 && self.sequential && !buf.is_empty() && self.ahead_data().is_none() {
+            self.read_ahead(count)?;
         }
 
-        // Get remaining data in the current cluster.
-        let cluster_size = self.exfat.params.cluster_size();
-        let cluster_remaining = cluster_size - self.offset % cluster_size;
-        let remaining = min(cluster_remaining, self.data_length - self.offset);
+        self.sequential = true;
 
-        // Get the offset in the partition.
-        let cluster = self.chain[(self.offset / cluster_size) as usize];
-        let offset = match self.exfat.params.cluster_offset(cluster) {
-            Some(v) => v + self.offset % cluster_size,
-            None => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("cluster #{cluster} is not available"),
-                ));
-            }
-        };
+        // Copy the data that was read ahead.
+        if let Some(data) = self.ahead_data() {
+            let amount = min(buf.len(), data.len());
 
-        // Read image.
-        let amount = min(buf.len(), remaining as usize);
+            buf[..amount].copy_from_slice(&data[..amount]);
+            self.offset += amount as u64;
 
-        if let Err(e) = self.exfat.partition.read_exact(offset, &mut buf[..amount]) {
-            return Err(Error::new(ErrorKind::Other, e));
+            return Ok(amount);
+        }
+
+        let amount = self.read_at(self.offset, buf)?;
+
+        self.offset += amount as u64;
+
+        Ok(amount)
+    }
+
+    #[cfg(feature = "std")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> crate::io::Result<usize> {
+        // The data that was read ahead need to be copied anyway.
+        if self.exfat.read_ahead != 0 {
+            return match bufs.iter_mut().find(|b| !b.is_empty()) {
+                Some(b) => self.read(b),
+                None => Ok(0),
+            };
         }
 
+        let amount = self.read_vectored_at(self.offset, bufs)?;
+
         self.offset += amount as u64;
 
         Ok(amount)
     }
 }
 
+/// The clusters of the data in the order of the chain. The contiguous clusters are kept as the
+/// first cluster and the number of clusters so a large file with NoFatChain does not need to keep
+/// each cluster in memory.
+#[derive(Debug, Clone)]
+pub(crate) enum Clusters {
+    /// The first cluster and the number of clusters that follow it, including itself.
+    Contiguous(Cluster, usize),
+    Chain(Vec<Cluster>),
+}
+
+impl Clusters {
+    /// Creates an empty [`Clusters`].
+    pub fn new() -> Self {
+        Self::Contiguous(Cluster(0), 0)
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Contiguous(_, n) => *n,
+            Self::Chain(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the cluster at `index` in the chain.
+    pub fn get(&self, index: usize) -> Option<Cluster> {
+        match self {
+            Self::Contiguous(first, n) if index < *n => Some(Cluster(first.0 + index as u32)),
+            Self::Contiguous(_, _) => None,
+            Self::Chain(v) => v.get(index).copied(),
+        }
+    }
+
+    pub fn first(&self) -> Option<Cluster> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<Cluster> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cluster> + '_ {
+        (0..self.len()).map(|i| self.get(i).unwrap())
+    }
+
+    /// Appends `cluster` to the end of the chain. The clusters will be kept one by one from now
+    /// on if `cluster` is not contiguous with the last cluster.
+    pub fn push(&mut self, cluster: Cluster) {
+        match self {
+            Self::Contiguous(_, 0) => *self = Self::Contiguous(cluster, 1),
+            Self::Contiguous(first, n) if first.index() + *n == cluster.index() => *n += 1,
+            Self::Contiguous(_, _) => {
+                let mut chain: Vec<Cluster> = self.iter().collect();
+
+                chain.push(cluster);
+
+                *self = Self::Chain(chain);
+            }
+            Self::Chain(v) => v.push(cluster),
+        }
+    }
+
+    /// Removes the last cluster from the chain.
+    pub fn pop(&mut self) -> Option<Cluster> {
+        match self {
+            Self::Contiguous(_, 0) => None,
+            Self::Contiguous(first, n) => {
+                *n -= 1;
+                Some(Cluster(first.0 + *n as u32))
+            }
+            Self::Chain(v) => v.pop(),
+        }
+    }
+}
+
+impl FromIterator<Cluster> for Clusters {
+    fn from_iter<T: IntoIterator<Item = Cluster>>(iter: T) -> Self {
+        let mut clusters = Self::new();
+
+        for cluster in iter {
+            clusters.push(cluster);
+        }
+
+        clusters
+    }
+}
+
+/// Represents an error when reading the data after a cluster that was marked as bad with
+/// [`BadClusterPolicy::Fail`]. This will be the inner error of [`crate::io::Error`].
+#[derive(Debug, Error)]
+#[error("cluster {cluster} at offset {offset} of the data at cluster {first_cluster} is bad")]
+pub struct BadCluster {
+    /// The first cluster of the file, which identify the file on the volume. This is the same as
+    /// [`File::first_cluster()`][crate::file::File::first_cluster()].
+    pub first_cluster: Cluster,
+
+    /// The bad cluster.
+    pub cluster: Cluster,
+
+    /// The offset of the bad cluster from the beginning of the data.
+    pub offset: u64,
+}
+
 /// Represents an error for [`new()`][ClustersReader::new()].
 #[derive(Debug, Error)]
 pub enum NewError {
@@ -160,4 +611,7 @@ pub enum NewError {
 
     #[error("data length is not valid")]
     InvalidDataLength,
+
+    #[error("cannot read the cluster chain")]
+    ReadChainFailed(#[source] ChainError),
 }
@@ -0,0 +1,313 @@
+//! Moving the fragmented items into contiguous clusters.
+use crate::alloc::AllocError;
+use crate::cluster::ClustersReader;
+use crate::disk::DiskPartition;
+use crate::entries::{ClusterAllocation, EntriesReader, EntrySetError, EntryType, FileEntry};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{Cluster, ExFat};
+use alloc_crate::collections::VecDeque;
+use core::error::Error;
+use thiserror::Error;
+
+/// Contains the result of [`Root::defragment()`][crate::Root::defragment()].
+#[derive(Debug, Default)]
+pub struct DefragReport {
+    relocated: Vec<String>,
+    converted: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl DefragReport {
+    /// Returns the items that was moved into a contiguous run of clusters.
+    pub fn relocated(&self) -> &[String] {
+        &self.relocated
+    }
+
+    /// Returns the items that was already contiguous but was using the FAT chain. Only their
+    /// NoFatChain flag was set.
+    pub fn converted(&self) -> &[String] {
+        &self.converted
+    }
+
+    /// Returns the fragmented items that cannot be moved because there are not enough contiguous
+    /// free clusters or their cluster chain does not match DataLength.
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+}
+
+/// Moves all fragmented files and directories into contiguous runs of clusters.
+pub(crate) fn defragment<P: DiskPartition>(
+    exfat: &Arc<ExFat<P>>,
+) -> Result<DefragReport, DefragError> {
+    if !exfat.writable {
+        return Err(DefragError::ReadOnly);
+    }
+
+    if let Err(e) = exfat.set_dirty() {
+        return Err(DefragError::SetDirtyFailed(e));
+    }
+
+    // Walk the directory tree. The root directory does not have a stream extension so it cannot
+    // be moved.
+    let root = exfat.params.first_cluster_of_root_directory;
+    let mut queue = VecDeque::from([(String::new(), root, None, false)]);
+    let mut report = DefragReport::default();
+
+    while let Some((path, first, len, no_fat_chain)) = queue.pop_front() {
+        let dir = if path.is_empty() { "/" } else { path.as_str() };
+        let mut reader = match ClustersReader::new(exfat.clone(), first, len, Some(no_fat_chain)) {
+            Ok(v) => EntriesReader::new(v),
+            Err(e) => return Err(DefragError::OpenDirectoryFailed(dir.into(), e)),
+        };
+
+        loop {
+            let entry = match reader.read() {
+                Ok(Some(v)) => v,
+                Ok(None) => break,
+                Err(e) => return Err(DefragError::ReadEntryFailed(dir.into(), e)),
+            };
+
+            // Skip everything except the File Directory Entry.
+            let ty = entry.ty();
+
+            if !ty.is_regular() {
+                if entry.data()[0] == 0 {
+                    break;
+                }
+
+                continue;
+            } else if ty.type_category() != EntryType::PRIMARY
+                || ty.type_importance() != EntryType::CRITICAL
+                || ty.type_code() != 5
+            {
+                continue;
+            }
+
+            let mut file = match FileEntry::load(&entry, &mut reader) {
+                Ok(v) => v,
+                Err(e) => return Err(DefragError::LoadEntryFailed(dir.into(), e)),
+            };
+
+            // Move the item.
+            let path = format!("{}/{}", path, file.name);
+
+            defragment_item(exfat, &path, &mut file, &mut report)?;
+
+            let stream = &file.stream;
+            let alloc = stream.allocation();
+
+            if file.attributes.is_directory() && alloc.first_cluster().0 != 0 {
+                let first = alloc.first_cluster();
+                let len = Some(alloc.data_length());
+
+                queue.push_back((path, first, len, stream.no_fat_chain()));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn defragment_item<P: DiskPartition>(
+    exfat: &ExFat<P>,
+    path: &str,
+    file: &mut FileEntry,
+    report: &mut DefragReport,
+) -> Result<(), DefragError> {
+    let stream = &file.stream;
+    let alloc = stream.allocation().clone();
+    let first = alloc.first_cluster();
+
+    if stream.no_fat_chain() || first.0 == 0 {
+        return Ok(());
+    }
+
+    // Get the cluster chain. The Allocation Bitmap must be locked before the FAT like the other
+    // writers.
+    let mut bitmap = exfat.alloc.lock().unwrap();
+    let mut fat = exfat.fat.lock().unwrap();
+    let chain = match fat.get_cluster_chain(&exfat.partition, first).into_vec() {
+        Ok(v) => v,
+        Err(e) => return Err(DefragError::ReadChainFailed(path.into(), e)),
+    };
+
+    let count = alloc.data_length().div_ceil(exfat.params.cluster_size()) as usize;
+
+    if chain.len() != count {
+        report.skipped.push(path.into());
+        return Ok(());
+    }
+
+    // Get the offset of each cluster. The chain that contains a cluster outside the Cluster Heap
+    // cannot be relocated.
+    let params = &exfat.params;
+    let sources: Option<Vec<u64>> = chain.iter().map(|&c| params.cluster_offset(c)).collect();
+    let sources = match sources {
+        Some(v) => v,
+        None => {
+            report.skipped.push(path.into());
+            return Ok(());
+        }
+    };
+
+    // Check if the chain is already contiguous.
+    let contiguous = chain.windows(2).all(|w| w[1].0 == w[0].0 + 1);
+    let valid_data_length = stream.valid_data_length();
+
+    if contiguous {
+        file.stream.set_allocation(true, valid_data_length, alloc);
+
+        if let Err(e) = file.write(&exfat.partition) {
+            return Err(DefragError::WriteEntriesFailed(path.into(), e));
+        }
+
+        clear_chain(exfat, &mut bitmap, &mut fat, &chain, false)?;
+        report.converted.push(path.into());
+
+        return Ok(());
+    }
+
+    // Allocate the new clusters and write it before the data so the crash will leave only the
+    // lost clusters.
+    let first = match bitmap.allocate_run(count, None) {
+        Ok(v) => v,
+        Err(AllocError::NoFreeCluster) => {
+            report.skipped.push(path.into());
+            return Ok(());
+        }
+        Err(e) => return Err(DefragError::AllocateFailed(path.into(), e)),
+    };
+
+    if let Err(e) = bitmap.flush(&exfat.partition) {
+        return Err(DefragError::WriteBitmapFailed(e));
+    }
+
+    // Copy the data.
+    let mut buf = vec![0u8; params.cluster_size() as usize];
+
+    for (i, &src) in sources.iter().enumerate() {
+        let target = Cluster(first.0 + i as u32);
+        let dst = match params.cluster_offset(target) {
+            Some(v) => v,
+            None => {
+                let e = AllocError::InvalidCluster(target);
+                return Err(DefragError::AllocateFailed(path.into(), e));
+            }
+        };
+
+        if let Err(e) = exfat.partition.read_exact(src, &mut buf) {
+            return Err(DefragError::ReadClusterFailed(path.into(), e));
+        }
+
+        if let Err(e) = exfat.partition.write_exact(dst, &buf) {
+            return Err(DefragError::WriteClusterFailed(path.into(), e));
+        }
+    }
+
+    if let Err(e) = exfat.partition.flush() {
+        return Err(DefragError::FlushFailed(e));
+    }
+
+    // Point the entry to the new clusters then release the old ones.
+    let alloc = ClusterAllocation::new(first, alloc.data_length());
+
+    file.stream.set_allocation(true, valid_data_length, alloc);
+
+    if let Err(e) = file.write(&exfat.partition) {
+        return Err(DefragError::WriteEntriesFailed(path.into(), e));
+    }
+
+    if let Err(e) = exfat.partition.flush() {
+        return Err(DefragError::FlushFailed(e));
+    }
+
+    clear_chain(exfat, &mut bitmap, &mut fat, &chain, true)?;
+    report.relocated.push(path.into());
+
+    Ok(())
+}
+
+/// Clears the FAT entries of `chain` and optionally releases the clusters.
+fn clear_chain<P: DiskPartition>(
+    exfat: &ExFat<P>,
+    bitmap: &mut crate::alloc::Allocator,
+    fat: &mut crate::fat::Fat,
+    chain: &[Cluster],
+    release: bool,
+) -> Result<(), DefragError> {
+    for &c in chain {
+        if let Err(e) = fat.set(c, 0) {
+            return Err(DefragError::WriteFatFailed(e));
+        }
+
+        if release {
+            if let Err(e) = bitmap.free(c) {
+                return Err(DefragError::UpdateBitmapFailed(e));
+            }
+        }
+    }
+
+    if let Err(e) = fat.flush(&exfat.partition) {
+        return Err(DefragError::WriteFatFailed(e));
+    }
+
+    if let Err(e) = bitmap.flush(&exfat.partition) {
+        return Err(DefragError::WriteBitmapFailed(e));
+    }
+
+    Ok(())
+}
+
+/// Represents an error for [`Root::defragment()`][crate::Root::defragment()].
+#[derive(Debug, Error)]
+pub enum DefragError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("some items of the volume are still in use")]
+    InUse,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot open directory {0}")]
+    OpenDirectoryFailed(String, #[source] crate::cluster::NewError),
+
+    #[error("cannot read an entry in {0}")]
+    ReadEntryFailed(String, #[source] crate::entries::ReaderError),
+
+    #[error("cannot load an entry set in {0}")]
+    LoadEntryFailed(String, #[source] crate::entries::FileEntryError),
+
+    #[error("cannot read the cluster chain of {0}")]
+    ReadChainFailed(String, #[source] crate::fat::ChainError),
+
+    #[error("cannot allocate the clusters for {0}")]
+    AllocateFailed(String, #[source] AllocError),
+
+    #[error("cannot read the data of {0}")]
+    ReadClusterFailed(String, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot write the data of {0}")]
+    WriteClusterFailed(String, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot flush the partition")]
+    FlushFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot write the entry set of {0}")]
+    WriteEntriesFailed(String, #[source] EntrySetError),
+
+    #[error("cannot update the allocation bitmap")]
+    UpdateBitmapFailed(#[source] AllocError),
+
+    #[error("cannot write the allocation bitmap")]
+    WriteBitmapFailed(#[source] crate::alloc::WriteError),
+
+    #[error("cannot write the FAT")]
+    WriteFatFailed(#[source] crate::fat::WriteError),
+
+    #[error("cannot reload the root directory")]
+    ReloadRootFailed(#[source] crate::directory::FindError),
+}
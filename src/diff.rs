@@ -0,0 +1,316 @@
+//! Comparing two directory trees.
+use crate::cluster::ClustersReader;
+use crate::directory::Item;
+use crate::disk::DiskPartition;
+use crate::file::File;
+use crate::io::{Error, ErrorKind};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::timestamp::Timestamps;
+use crate::walk::{Walk, WalkError};
+use crate::{Cluster, ExFat, FileAttributes};
+use alloc_crate::collections::BTreeMap;
+use core::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// Compares the items of `old` and `new` by their path. Use [`Root::walk()`][crate::Root::walk()]
+/// to compare the whole volumes. The changes are ordered by path.
+pub fn diff<P, Q>(
+    old: Walk<P>,
+    new: Walk<Q>,
+    options: &DiffOptions,
+) -> Result<Vec<Change>, DiffError>
+where
+    P: DiskPartition,
+    Q: DiskPartition,
+{
+    // Load the metadata of the new tree. The items are dropped right away since each file holds
+    // its cluster chain.
+    let mut exfat = None;
+    let mut items = BTreeMap::new();
+
+    for item in new {
+        let (path, item) = match item {
+            Ok(v) => v,
+            Err(e) => return Err(DiffError::WalkNewFailed(e)),
+        };
+
+        if let Item::File(f) = &item {
+            exfat.get_or_insert_with(|| f.exfat().clone());
+        }
+
+        items.insert(path, Metadata::new(&item));
+    }
+
+    // Compare with the old tree.
+    let mut changes = Vec::new();
+
+    for item in old {
+        let (path, old) = match item {
+            Ok(v) => v,
+            Err(e) => return Err(DiffError::WalkOldFailed(e)),
+        };
+
+        let new = match items.remove(&path) {
+            Some(v) => v,
+            None => {
+                changes.push(Change::Removed(path));
+                continue;
+            }
+        };
+
+        let differences = compare(&path, &old, &new, exfat.as_ref(), options)?;
+
+        if !differences.is_empty() {
+            changes.push(Change::Changed(path, differences));
+        }
+    }
+
+    changes.extend(items.into_keys().map(Change::Added));
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(changes)
+}
+
+/// Options for [`diff()`].
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    contents: bool,
+    timestamps: bool,
+}
+
+impl DiffOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to compare the data of the files with the same length. The default is `true`.
+    pub fn contents(mut self, v: bool) -> Self {
+        self.contents = v;
+        self
+    }
+
+    /// Sets whether to compare the timestamps. The default is `true`.
+    pub fn timestamps(mut self, v: bool) -> Self {
+        self.timestamps = v;
+        self
+    }
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            contents: true,
+            timestamps: true,
+        }
+    }
+}
+
+/// A change reported by [`diff()`]. The path is always start with `/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The item exists only in the new tree.
+    Added(String),
+
+    /// The item exists only in the old tree.
+    Removed(String),
+
+    /// The item exists in both trees but some of its properties are different.
+    Changed(String, Vec<Difference>),
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added(p) | Self::Removed(p) | Self::Changed(p, _) => p,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Added(p) => write!(f, "+ {p}"),
+            Self::Removed(p) => write!(f, "- {p}"),
+            Self::Changed(p, _) => write!(f, "~ {p}"),
+        }
+    }
+}
+
+/// A property that is different on an item in both trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    /// One is a file and the other is a directory.
+    Kind,
+    Attributes,
+    Timestamps,
+    Length,
+    Contents,
+}
+
+/// Contains the properties of an item on the new tree that are needed by [`compare()`]. The name
+/// is part of the path used as the key of the map.
+struct Metadata {
+    attributes: FileAttributes,
+    timestamps: Timestamps,
+    file: Option<FileMetadata>, // None if the item is a directory.
+}
+
+impl Metadata {
+    fn new<P: DiskPartition>(item: &Item<P>) -> Self {
+        let file = match item {
+            Item::Directory(_) => None,
+            Item::File(f) => Some(FileMetadata {
+                len: f.len(),
+                valid_data_length: f.valid_data_length(),
+                first_cluster: f.first_cluster(),
+                no_fat_chain: f.no_fat_chain(),
+            }),
+        };
+
+        Self {
+            attributes: item.attributes(),
+            timestamps: *item.timestamps(),
+            file,
+        }
+    }
+}
+
+/// Contains the properties of a file on the new tree to read its data.
+struct FileMetadata {
+    len: u64,
+    valid_data_length: u64,
+    first_cluster: Option<Cluster>,
+    no_fat_chain: bool,
+}
+
+fn compare<P, Q>(
+    path: &str,
+    old: &Item<P>,
+    new: &Metadata,
+    exfat: Option<&Arc<ExFat<Q>>>,
+    options: &DiffOptions,
+) -> Result<Vec<Difference>, DiffError>
+where
+    P: DiskPartition,
+    Q: DiskPartition,
+{
+    let mut differences = Vec::new();
+
+    if old.attributes() != new.attributes {
+        differences.push(Difference::Attributes);
+    }
+
+    if options.timestamps && *old.timestamps() != new.timestamps {
+        differences.push(Difference::Timestamps);
+    }
+
+    match (old, &new.file) {
+        (Item::Directory(_), None) => {}
+        (Item::File(o), Some(n)) => {
+            if o.len() != n.len {
+                differences.push(Difference::Length);
+            } else if options.contents && !same_contents(path, o, n, exfat)? {
+                differences.push(Difference::Contents);
+            }
+        }
+        _ => differences.insert(0, Difference::Kind),
+    }
+
+    Ok(differences)
+}
+
+fn same_contents<P, Q>(
+    path: &str,
+    old: &File<P>,
+    new: &FileMetadata,
+    exfat: Option<&Arc<ExFat<Q>>>,
+) -> Result<bool, DiffError>
+where
+    P: DiskPartition,
+    Q: DiskPartition,
+{
+    // Open the new file.
+    let reader = match (exfat, new.first_cluster) {
+        (Some(exfat), Some(first_cluster)) => {
+            let mut reader = match ClustersReader::new(
+                exfat.clone(),
+                first_cluster,
+                Some(new.len),
+                Some(new.no_fat_chain),
+            ) {
+                Ok(v) => v,
+                Err(e) => return Err(DiffError::ReadNewFailed(path.into(), Error::other(e))),
+            };
+
+            reader.set_valid_data_length(new.valid_data_length);
+
+            Some(reader)
+        }
+        _ => None,
+    };
+
+    // Compare the data.
+    let mut a = vec![0u8; 65536];
+    let mut b = vec![0u8; 65536];
+    let mut offset = 0;
+
+    while offset < old.len() {
+        let n = a.len().min((old.len() - offset) as usize);
+
+        if let Err(e) = read(|o, b| old.read_at(o, b), offset, &mut a[..n]) {
+            return Err(DiffError::ReadOldFailed(path.into(), e));
+        }
+
+        let new = |o, b: &mut [u8]| match &reader {
+            Some(r) => r.read_at(o, b),
+            None => Ok(0),
+        };
+
+        if let Err(e) = read(new, offset, &mut b[..n]) {
+            return Err(DiffError::ReadNewFailed(path.into(), e));
+        }
+
+        if a[..n] != b[..n] {
+            return Ok(false);
+        }
+
+        offset += n as u64;
+    }
+
+    Ok(true)
+}
+
+fn read<F>(read_at: F, mut offset: u64, mut buf: &mut [u8]) -> Result<(), Error>
+where
+    F: Fn(u64, &mut [u8]) -> Result<usize, Error>,
+{
+    while !buf.is_empty() {
+        let n = read_at(offset, buf)?;
+
+        if n == 0 {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+
+        offset += n as u64;
+        buf = &mut buf[n..];
+    }
+
+    Ok(())
+}
+
+/// Represents an error for [`diff()`].
+#[derive(Debug, Error)]
+pub enum DiffError {
+    #[error("cannot walk the old directory tree")]
+    WalkOldFailed(#[source] WalkError),
+
+    #[error("cannot walk the new directory tree")]
+    WalkNewFailed(#[source] WalkError),
+
+    #[error("cannot read {0} on the old directory tree")]
+    ReadOldFailed(String, #[source] Error),
+
+    #[error("cannot read {0} on the new directory tree")]
+    ReadNewFailed(String, #[source] Error),
+}
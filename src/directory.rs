@@ -1,51 +1,328 @@
-use crate::cluster::ClustersReader;
+use crate::alloc::AllocError;
+use crate::cluster::{Clusters, ClustersReader};
 use crate::disk::DiskPartition;
-use crate::entries::{ClusterAllocation, EntriesReader, EntryType, FileEntry, StreamEntry};
+use crate::entries::{
+    name_hash, ClusterAllocation, EntriesReader, EntrySet, EntrySetError, EntryType, FileEntry,
+    RawEntries, StreamEntry,
+};
+#[cfg(feature = "std")]
+use crate::extract::{ExtractError, ExtractOptions};
+use crate::fat::ChainError;
 use crate::file::File;
-use crate::ExFat;
-use std::sync::Arc;
+#[cfg(feature = "std")]
+use crate::import::ImportError;
+use crate::io::{Error, ErrorKind};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::timestamp::Timestamps;
+use crate::trace::debug;
+use crate::undelete::{DeletedItems, ScanError};
+use crate::usage::DiskUsage;
+use crate::{Cluster, ExFat, FileAttributes, Handle, Root, SetVolumeLabelError};
+use alloc_crate::collections::BTreeSet;
+use byteorder::{ByteOrder, LE};
 use thiserror::Error;
 
 /// Represents a directory in the exFAT.
 pub struct Directory<P: DiskPartition> {
     exfat: Arc<ExFat<P>>,
-    name: String,
-    stream: StreamEntry,
+    entry: FileEntry,
+    handle: Option<Arc<Handle>>, // None if the volume is read-only.
 }
 
 impl<P: DiskPartition> Directory<P> {
-    pub(crate) fn new(exfat: Arc<ExFat<P>>, name: String, stream: StreamEntry) -> Self {
+    pub(crate) fn new(exfat: Arc<ExFat<P>>, entry: FileEntry) -> Self {
+        // Track the directory so it will not write to its entries after it was moved or removed.
+        let handle = match entry.entries.offsets().first() {
+            Some(&v) if exfat.writable => Some(exfat.open_handle(v)),
+            _ => None,
+        };
+
         Self {
             exfat,
-            name,
-            stream,
+            entry,
+            handle,
         }
     }
 
     pub fn name(&self) -> &str {
-        self.name.as_ref()
+        self.entry.name.as_ref()
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.entry.attributes
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        &self.entry.timestamps
+    }
+
+    /// Sets the timestamps of the directory and writes it to the directory entry.
+    pub fn set_times(&mut self, timestamps: Timestamps) -> crate::io::Result<()> {
+        self.check_writable()?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(Error::other(e));
+        }
+
+        self.update_entry();
+        self.entry.timestamps = timestamps;
+
+        if let Err(e) = self.entry.write(&self.exfat.partition) {
+            return Err(Error::other(e));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the attributes of the directory and writes it to the directory entry. The directory
+    /// flag of `attributes` will be ignored.
+    pub fn set_attributes(&mut self, mut attributes: FileAttributes) -> crate::io::Result<()> {
+        self.check_writable()?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(Error::other(e));
+        }
+
+        self.update_entry();
+        attributes.0 |= 0x0010;
+
+        self.entry.attributes = attributes;
+
+        if let Err(e) = self.entry.write(&self.exfat.partition) {
+            return Err(Error::other(e));
+        }
+
+        Ok(())
     }
 
+    /// Fails with [`ErrorKind::PermissionDenied`] if the volume is read-only or
+    /// [`ErrorKind::NotFound`] if the directory was removed.
+    fn check_writable(&self) -> crate::io::Result<()> {
+        if !self.exfat.writable {
+            Err(Error::from(ErrorKind::PermissionDenied))
+        } else if self.is_removed() {
+            Err(Error::from(ErrorKind::NotFound))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_removed(&self) -> bool {
+        self.handle.as_ref().is_some_and(|v| v.is_removed())
+    }
+
+    /// Picks up the new entries if this directory was renamed or moved.
+    fn update_entry(&mut self) {
+        if let Some(v) = &self.handle {
+            v.update(&mut self.entry);
+        }
+    }
+
+    /// Returns another handle to this directory.
+    pub(crate) fn handle(&self) -> Self {
+        Self::new(self.exfat.clone(), self.entry.clone())
+    }
+
+    /// Reads all items in this directory. Use [`items()`][Self::items()] to read the items one
+    /// by one instead.
     pub fn open(&self) -> Result<Vec<Item<P>>, OpenError> {
+        self.items()?.collect()
+    }
+
+    /// Finds the item with the specified name in this directory. The name is compared
+    /// case-insensitively.
+    pub fn find(&self, name: &str) -> Result<Option<Item<P>>, FindError> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let entry = match find(&self.exfat, Some(&self.entry), &name)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        match Item::new(self.exfat.clone(), entry) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(FindError::CreateFileObjectFailed(e)),
+        }
+    }
+
+    /// Returns an iterator that reads the items in this directory lazily.
+    pub fn items(&self) -> Result<Items<P>, OpenError> {
         // Create an entries reader.
-        let alloc = self.stream.allocation();
-        let mut reader = match ClustersReader::new(
+        let stream = &self.entry.stream;
+        let alloc = stream.allocation();
+
+        debug!(
+            "reading directory {} at cluster #{}",
+            self.name(),
+            alloc.first_cluster()
+        );
+
+        let reader = match ClustersReader::new(
             self.exfat.clone(),
             alloc.first_cluster(),
             Some(alloc.data_length()),
-            Some(self.stream.no_fat_chain()),
+            Some(stream.no_fat_chain()),
         ) {
             Ok(v) => EntriesReader::new(v),
             Err(e) => return Err(OpenError::CreateClustersReaderFailed(alloc.clone(), e)),
         };
 
-        // Read file entries.
-        let mut items: Vec<Item<P>> = Vec::new();
+        Ok(Items {
+            exfat: self.exfat.clone(),
+            reader: Some(reader),
+        })
+    }
+
+    /// Returns an iterator over the raw entries of this directory.
+    pub fn raw_entries(&self) -> Result<RawEntries<P>, crate::cluster::NewError> {
+        let stream = &self.entry.stream;
+        let alloc = stream.allocation();
 
-        loop {
+        RawEntries::new(
+            self.exfat.clone(),
+            alloc.first_cluster(),
+            Some(alloc.data_length()),
+            Some(stream.no_fat_chain()),
+        )
+    }
+
+    /// Returns an iterator over the deleted items in this directory. See [`crate::undelete`] for
+    /// the limitations.
+    pub fn deleted_items(&self) -> Result<DeletedItems<P>, ScanError> {
+        let stream = &self.entry.stream;
+        let alloc = stream.allocation();
+
+        DeletedItems::new(
+            self.exfat.clone(),
+            alloc.first_cluster(),
+            Some(alloc.data_length()),
+            Some(stream.no_fat_chain()),
+        )
+    }
+
+    /// Computes the number of items and the space used by this directory recursively.
+    pub fn disk_usage(&self) -> Result<DiskUsage, OpenError> {
+        crate::usage::disk_usage(self.exfat.params.cluster_size(), [], vec![self.handle()])
+    }
+
+    /// Copies the items in this directory recursively to `dest` on the local file system. See
+    /// [`Root::extract_with()`] for details.
+    #[cfg(feature = "std")]
+    pub fn extract_to<T: AsRef<std::path::Path>>(&self, dest: T) -> Result<(), ExtractError> {
+        self.extract_with(dest, ExtractOptions::new()).map(|_| ())
+    }
+
+    /// Same as [`extract_to()`][Self::extract_to()] but with the custom options. See
+    /// [`Root::extract_with()`] for details.
+    #[cfg(feature = "std")]
+    pub fn extract_with<T: AsRef<std::path::Path>>(
+        &self,
+        dest: T,
+        options: ExtractOptions,
+    ) -> Result<Vec<ExtractError>, ExtractError> {
+        let items = match self.items() {
+            Ok(v) => v,
+            Err(e) => return Err(ExtractError::ReadDirectoryFailed("/".into(), e)),
+        };
+
+        crate::extract::extract(items, dest.as_ref(), options)
+    }
+
+    /// Copies the directory tree in `src` on the local file system into this directory. See
+    /// [`Root::import_from()`] for details.
+    #[cfg(feature = "std")]
+    pub fn import_from<T: AsRef<std::path::Path>>(&mut self, src: T) -> Result<(), ImportError> {
+        crate::import::import(crate::import::Parent::Directory(self), "", src.as_ref())
+    }
+
+    /// Creates a new empty file in this directory.
+    pub fn create_file(&mut self, name: &str) -> Result<File<P>, CreateError> {
+        self.writer().create_file(name)
+    }
+
+    /// Creates a new empty directory in this directory.
+    pub fn create_dir(&mut self, name: &str) -> Result<Directory<P>, CreateError> {
+        self.writer().create_dir(name)
+    }
+
+    /// Renames an item in this directory.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), RenameError> {
+        self.writer().rename(from, to)
+    }
+
+    /// Moves an item in this directory to `dest` with `new_name`.
+    pub fn move_to(
+        &mut self,
+        name: &str,
+        dest: &mut Directory<P>,
+        new_name: &str,
+    ) -> Result<(), RenameError> {
+        self.writer().move_to(name, dest.writer(), new_name)
+    }
+
+    /// Moves an item in this directory to the root directory with `new_name`.
+    pub fn move_to_root(
+        &mut self,
+        name: &str,
+        root: &mut Root<P>,
+        new_name: &str,
+    ) -> Result<(), RenameError> {
+        self.writer().move_to(name, root.writer(), new_name)
+    }
+
+    /// Removes a file or an empty directory from this directory. See [`Root::remove()`] for
+    /// details.
+    pub fn remove(&mut self, name: &str) -> Result<(), RemoveError> {
+        self.writer().remove(name, false)
+    }
+
+    /// Removes a file or a directory including all of its contents from this directory.
+    pub fn remove_all(&mut self, name: &str) -> Result<(), RemoveError> {
+        self.writer().remove(name, true)
+    }
+
+    pub(crate) fn allocation(&self) -> &ClusterAllocation {
+        self.entry.stream.allocation()
+    }
+
+    pub(crate) fn writer(&mut self) -> DirectoryWriter<'_, P> {
+        self.update_entry();
+
+        DirectoryWriter::new(&self.exfat, Some(&mut self.entry))
+    }
+}
+
+impl<P: DiskPartition> Drop for Directory<P> {
+    fn drop(&mut self) {
+        self.update_entry();
+
+        if let Some(handle) = &self.handle {
+            self.exfat
+                .close_handle(self.entry.entries.offsets()[0], handle);
+        }
+    }
+}
+
+/// An iterator over the items in a [`Directory`]. The iterator will stop after an error is
+/// returned.
+pub struct Items<P: DiskPartition> {
+    exfat: Arc<ExFat<P>>,
+    reader: Option<EntriesReader<P>>,
+}
+
+impl<P: DiskPartition> Items<P> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    fn read(&mut self) -> Result<Option<Item<P>>, OpenError> {
+        let reader = match &mut self.reader {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let item = loop {
             // Read primary entry.
             let entry = match reader.read() {
-                Ok(v) => v,
+                Ok(Some(v)) => v,
+                Ok(None) => return Ok(None),
                 Err(e) => return Err(OpenError::ReadEntryFailed(e)),
             };
 
@@ -53,29 +330,46 @@ impl<P: DiskPartition> Directory<P> {
             let ty = entry.ty();
 
             if !ty.is_regular() {
-                break;
+                // Skip unused entries and stop on the end of directory.
+                if entry.data()[0] == 0 {
+                    return Ok(None);
+                }
+
+                continue;
             } else if ty.type_category() != EntryType::PRIMARY {
+                // Skip the remaining of corrupted entry set.
+                if self.exfat.skip_corrupt {
+                    continue;
+                }
+
                 return Err(OpenError::NotPrimaryEntry(entry.index(), entry.cluster()));
+            } else if ty.type_importance() == EntryType::BENIGN && ty.type_code() == 1 {
+                // Skip TexFAT Padding.
+                continue;
+            } else if ty.type_importance() == EntryType::BENIGN && !self.exfat.strict {
+                // Skip unknown benign primary entry and its secondary entries.
+                match reader.skip(entry.data()[1].into()) {
+                    Ok(_) => continue,
+                    Err(e) => return Err(OpenError::ReadEntryFailed(e)),
+                }
             } else if ty.type_importance() != EntryType::CRITICAL || ty.type_code() != 5 {
                 return Err(OpenError::NotFileEntry(entry.index(), entry.cluster()));
             }
 
             // Parse file entry.
-            let file = match FileEntry::load(&entry, &mut reader) {
+            let file = match FileEntry::load(&entry, reader) {
                 Ok(v) => v,
+                Err(e) if self.exfat.skip_entry_set(&entry, &e) => continue,
                 Err(e) => return Err(OpenError::LoadFileEntryFailed(e)),
             };
 
             // Construct item.
-            let name = file.name;
-            let attrs = file.attributes;
-            let stream = file.stream;
-
-            items.push(if attrs.is_directory() {
-                Item::Directory(Directory::new(self.exfat.clone(), name, stream))
+            let item = if file.attributes.is_directory() {
+                Item::Directory(Directory::new(self.exfat.clone(), file))
             } else {
-                match File::new(self.exfat.clone(), name, stream) {
+                match File::new(self.exfat.clone(), file) {
                     Ok(v) => Item::File(v),
+                    Err(e) if self.exfat.skip_entry_set(&entry, &e) => continue,
                     Err(e) => {
                         return Err(OpenError::CreateFileObjectFailed(
                             entry.index(),
@@ -84,10 +378,30 @@ impl<P: DiskPartition> Directory<P> {
                         ));
                     }
                 }
-            });
-        }
+            };
 
-        Ok(items)
+            break item;
+        };
+
+        Ok(Some(item))
+    }
+}
+
+impl<P: DiskPartition> Iterator for Items<P> {
+    type Item = Result<Item<P>, OpenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read() {
+            Ok(Some(v)) => Some(Ok(v)),
+            Ok(None) => {
+                self.reader = None;
+                None
+            }
+            Err(e) => {
+                self.reader = None;
+                Some(Err(e))
+            }
+        }
     }
 }
 
@@ -97,6 +411,768 @@ pub enum Item<P: DiskPartition> {
     File(File<P>),
 }
 
+impl<P: DiskPartition> Item<P> {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Directory(d) => d.name(),
+            Self::File(f) => f.name(),
+        }
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        match self {
+            Self::Directory(d) => d.attributes(),
+            Self::File(f) => f.attributes(),
+        }
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        match self {
+            Self::Directory(d) => d.timestamps(),
+            Self::File(f) => f.timestamps(),
+        }
+    }
+
+    /// Returns the length of the file or the size of the directory entries for the directory.
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Directory(d) => d.entry.stream.valid_data_length(),
+            Self::File(f) => f.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the offset of the entry set on the partition, which identify the item on the
+    /// volume.
+    #[cfg(all(feature = "fuse", any(target_os = "linux", target_os = "macos")))]
+    pub(crate) fn offset(&self) -> u64 {
+        match self {
+            Self::Directory(d) => d.entry.entries.offsets().first().copied().unwrap_or(0),
+            Self::File(f) => f.offset(),
+        }
+    }
+
+    pub(crate) fn new(
+        exfat: Arc<ExFat<P>>,
+        entry: FileEntry,
+    ) -> Result<Self, crate::file::NewError> {
+        if entry.attributes.is_directory() {
+            Ok(Self::Directory(Directory::new(exfat, entry)))
+        } else {
+            File::new(exfat, entry).map(Self::File)
+        }
+    }
+}
+
+/// Finds the File Directory Entry with the specified name in the directory of `owner` or the root
+/// directory if `owner` is [`None`].
+pub(crate) fn find<P: DiskPartition>(
+    exfat: &Arc<ExFat<P>>,
+    owner: Option<&FileEntry>,
+    name: &[u16],
+) -> Result<Option<FileEntry>, FindError> {
+    let mut reader = reader(exfat, owner)?;
+
+    DirectoryWriter::next_file(&mut reader, Some(name))
+}
+
+/// Loads all items in the directory of `owner` or the root directory if `owner` is [`None`].
+pub(crate) fn load_items<P: DiskPartition>(
+    exfat: &Arc<ExFat<P>>,
+    owner: Option<&FileEntry>,
+) -> Result<Vec<Item<P>>, FindError> {
+    let mut reader = reader(exfat, owner)?;
+    let mut items = Vec::new();
+
+    while let Some(entry) = DirectoryWriter::next_file(&mut reader, None)? {
+        match Item::new(exfat.clone(), entry) {
+            Ok(v) => items.push(v),
+            Err(e) => return Err(FindError::CreateFileObjectFailed(e)),
+        }
+    }
+
+    Ok(items)
+}
+
+/// Creates an [`EntriesReader`] for the directory of `owner` or the root directory if `owner` is
+/// [`None`].
+fn reader<P: DiskPartition>(
+    exfat: &Arc<ExFat<P>>,
+    owner: Option<&FileEntry>,
+) -> Result<EntriesReader<P>, FindError> {
+    let reader = match owner {
+        Some(e) => {
+            let alloc = e.stream.allocation();
+
+            ClustersReader::new(
+                exfat.clone(),
+                alloc.first_cluster(),
+                Some(alloc.data_length()),
+                Some(e.stream.no_fat_chain()),
+            )
+        }
+        None => ClustersReader::new(
+            exfat.clone(),
+            exfat.params.first_cluster_of_root_directory,
+            None,
+            None,
+        ),
+    };
+
+    match reader {
+        Ok(v) => Ok(EntriesReader::new(v)),
+        Err(e) => Err(FindError::CreateClustersReaderFailed(e)),
+    }
+}
+
+/// Provides operations to modify the entries of a directory.
+pub(crate) struct DirectoryWriter<'a, P: DiskPartition> {
+    exfat: &'a Arc<ExFat<P>>,
+    owner: Option<&'a mut FileEntry>, // None for the root directory.
+}
+
+impl<'a, P: DiskPartition> DirectoryWriter<'a, P> {
+    pub fn new(exfat: &'a Arc<ExFat<P>>, owner: Option<&'a mut FileEntry>) -> Self {
+        Self { exfat, owner }
+    }
+
+    pub fn create_file(self, name: &str) -> Result<File<P>, CreateError> {
+        if !self.exfat.writable {
+            return Err(CreateError::ReadOnly);
+        }
+
+        let name16 = self.check_name(name)?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(CreateError::SetDirtyFailed(e));
+        }
+
+        let stream = StreamEntry::new(0, false, 0, ClusterAllocation::new(Cluster(0), 0));
+        let exfat = self.exfat.clone();
+        let entry = self.create(name, &name16, FileAttributes(0x0020), stream)?;
+
+        match File::new(exfat, entry) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(CreateError::CreateFileObjectFailed(e)),
+        }
+    }
+
+    pub fn create_dir(self, name: &str) -> Result<Directory<P>, CreateError> {
+        if !self.exfat.writable {
+            return Err(CreateError::ReadOnly);
+        }
+
+        let name16 = self.check_name(name)?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(CreateError::SetDirtyFailed(e));
+        }
+
+        // Allocate a cluster for the directory.
+        let exfat = self.exfat.clone();
+        let mut chain = Clusters::new();
+        let mut no_fat_chain = true;
+        let cluster = match exfat.append_cluster(&mut chain, &mut no_fat_chain) {
+            Ok(v) => v,
+            Err(e) => return Err(CreateError::AllocateFailed(e)),
+        };
+
+        if let Err(e) = exfat.zero_cluster(cluster) {
+            return Err(CreateError::AllocateFailed(e));
+        }
+
+        // Create the entries.
+        let len = exfat.params.cluster_size();
+        let alloc = ClusterAllocation::new(cluster, len);
+        let stream = StreamEntry::new(0, no_fat_chain, len, alloc);
+        let entry = self.create(name, &name16, FileAttributes(0x0010), stream)?;
+
+        Ok(Directory::new(exfat, entry))
+    }
+
+    /// Creates a File Directory Entry with the specified name. The name must be checked with
+    /// [`check_name()`][Self::check_name()].
+    fn create(
+        mut self,
+        name: &str,
+        name16: &[u16],
+        attributes: FileAttributes,
+        mut stream: StreamEntry,
+    ) -> Result<FileEntry, CreateError> {
+        stream.set_name_length(name16.len());
+
+        // Write the entries.
+        let data = FileEntry::build(name16, attributes, &stream, self.exfat.upcase());
+        let offsets = self.allocate_entries(data.len(), &[])?;
+        let mut entries = EntrySet::new();
+
+        for (offset, data) in offsets.into_iter().zip(data) {
+            entries.push(offset, data);
+        }
+
+        if let Err(e) = entries.write(&self.exfat.partition) {
+            return Err(CreateError::WriteEntriesFailed(e));
+        }
+
+        Ok(FileEntry {
+            name: name.into(),
+            attributes,
+            timestamps: Timestamps::load(entries.get(0)),
+            stream,
+            vendor_entries: Vec::new(),
+            entries,
+        })
+    }
+
+    /// Checks if `name` is a valid file name and available in the directory. Returns `name` as
+    /// UTF-16 if the checks are passed.
+    fn check_name(&self, name: &str) -> Result<Vec<u16>, CreateError> {
+        let name = Self::validate_name(name)?;
+
+        match self.find(&name) {
+            Ok(Some(_)) => return Err(CreateError::AlreadyExists),
+            Ok(None) => {}
+            Err(e) => return Err(CreateError::FindFailed(e)),
+        }
+
+        Ok(name)
+    }
+
+    /// Checks if `name` is a valid file name and returns it as UTF-16.
+    fn validate_name(name: &str) -> Result<Vec<u16>, CreateError> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+
+        // The names that contain only dots (e.g. "." and "..") are reserved.
+        if name.is_empty() || name.len() > 255 || name.iter().all(|&c| c == u16::from(b'.')) {
+            return Err(CreateError::InvalidName);
+        }
+
+        for &c in &name {
+            if c < 0x20 || b"\"*/:<>?\\|".iter().any(|&v| u16::from(v) == c) {
+                return Err(CreateError::InvalidName);
+            }
+        }
+
+        Ok(name)
+    }
+
+    /// Finds the File Directory Entry with the specified name.
+    pub fn find(&self, name: &[u16]) -> Result<Option<FileEntry>, FindError> {
+        find(self.exfat, self.owner.as_deref(), name)
+    }
+
+    /// Creates an [`EntriesReader`] for the directory.
+    fn reader(&self) -> Result<EntriesReader<P>, FindError> {
+        reader(self.exfat, self.owner.as_deref())
+    }
+
+    /// Reads the next File Directory Entry from `reader`, skipping unused and other entries.
+    /// If `name` is specified the entries with a different name will be skipped without
+    /// allocating its file name.
+    fn next_file(
+        reader: &mut EntriesReader<P>,
+        name: Option<&[u16]>,
+    ) -> Result<Option<FileEntry>, FindError> {
+        let hash = name.map(|v| name_hash(v, reader.exfat().upcase()));
+
+        loop {
+            let entry = match reader.read() {
+                Ok(Some(v)) => v,
+                Ok(None) => return Ok(None),
+                Err(e) => return Err(FindError::ReadEntryFailed(e)),
+            };
+
+            // Skip unused entries and stop on the end of directory.
+            let ty = entry.ty();
+
+            if !ty.is_regular() {
+                if entry.data()[0] == 0 {
+                    return Ok(None);
+                }
+
+                continue;
+            } else if ty.type_category() != EntryType::PRIMARY
+                || ty.type_importance() != EntryType::CRITICAL
+                || ty.type_code() != 5
+            {
+                continue;
+            }
+
+            let file = match name.zip(hash) {
+                Some((n, h)) => FileEntry::load_if_name(&entry, reader, n, h),
+                None => FileEntry::load(&entry, reader).map(Some),
+            };
+
+            match file {
+                Ok(Some(v)) => return Ok(Some(v)),
+                Ok(None) => continue,
+                Err(e) => return Err(FindError::LoadFileEntryFailed(e)),
+            }
+        }
+    }
+
+    pub fn remove(self, name: &str, recursive: bool) -> Result<(), RemoveError> {
+        if !self.exfat.writable {
+            return Err(RemoveError::ReadOnly);
+        }
+
+        // Find the entry.
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let mut entry = match self.find(&name) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Err(RemoveError::NotFound),
+            Err(e) => return Err(RemoveError::FindFailed(e)),
+        };
+
+        // Check if the directory is empty.
+        if entry.attributes.is_directory() && !recursive {
+            let dir = DirectoryWriter::new(self.exfat, Some(&mut entry));
+            let mut reader = dir.reader().map_err(RemoveError::FindFailed)?;
+
+            if Self::next_file(&mut reader, None)
+                .map_err(RemoveError::FindFailed)?
+                .is_some()
+            {
+                return Err(RemoveError::NotEmpty);
+            }
+        }
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(RemoveError::SetDirtyFailed(e));
+        }
+
+        // Remove the children.
+        if entry.attributes.is_directory() && recursive {
+            DirectoryWriter::new(self.exfat, Some(&mut entry)).clear()?;
+        }
+
+        self.delete(entry)
+    }
+
+    pub fn rename(self, from: &str, to: &str) -> Result<(), RenameError> {
+        if !self.exfat.writable {
+            return Err(RenameError::ReadOnly);
+        }
+
+        // Find the entry.
+        let from: Vec<u16> = from.encode_utf16().collect();
+        let entry = match self.find(&from) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Err(RenameError::NotFound),
+            Err(e) => return Err(RenameError::FindFailed(e)),
+        };
+
+        // Check if the new name is available. We allow the new name to match the entry itself so
+        // the case of the name can be changed.
+        let to = Self::validate_name(to).map_err(|_| RenameError::InvalidName)?;
+
+        match self.find(&to) {
+            Ok(Some(v)) if v.entries.offsets()[0] != entry.entries.offsets()[0] => {
+                return Err(RenameError::AlreadyExists);
+            }
+            Ok(_) => {}
+            Err(e) => return Err(RenameError::FindFailed(e)),
+        }
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(RenameError::SetDirtyFailed(e));
+        }
+
+        self.relocate(entry, None, &to)
+    }
+
+    pub fn move_to(
+        self,
+        name: &str,
+        dest: DirectoryWriter<'_, P>,
+        new_name: &str,
+    ) -> Result<(), RenameError> {
+        if !self.exfat.writable {
+            return Err(RenameError::ReadOnly);
+        }
+
+        // Check if both directories are on the same volume.
+        if !Arc::ptr_eq(self.exfat, dest.exfat) {
+            return Err(RenameError::DifferentVolume);
+        }
+
+        // Find the entry.
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let mut entry = match self.find(&name) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Err(RenameError::NotFound),
+            Err(e) => return Err(RenameError::FindFailed(e)),
+        };
+
+        // Check if the new name is available.
+        let new_name = Self::validate_name(new_name).map_err(|_| RenameError::InvalidName)?;
+
+        match dest.find(&new_name) {
+            Ok(Some(_)) => return Err(RenameError::AlreadyExists),
+            Ok(None) => {}
+            Err(e) => return Err(RenameError::FindFailed(e)),
+        }
+
+        // Prevent a directory from moving into itself or its sub-directories.
+        if entry.attributes.is_directory() {
+            let target = match &dest.owner {
+                Some(v) => v.stream.allocation().first_cluster(),
+                None => self.exfat.params.first_cluster_of_root_directory,
+            };
+
+            let first = entry.stream.allocation().first_cluster();
+            let dir = DirectoryWriter::new(self.exfat, Some(&mut entry));
+
+            if first == target || dir.contains_dir(target).map_err(RenameError::FindFailed)? {
+                return Err(RenameError::MoveIntoItself);
+            }
+        }
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(RenameError::SetDirtyFailed(e));
+        }
+
+        self.relocate(entry, Some(dest), &new_name)
+    }
+
+    /// Rewrites `entry` with a new name. The entry will be moved to `dest` if it is not [`None`].
+    fn relocate(
+        mut self,
+        entry: FileEntry,
+        dest: Option<DirectoryWriter<'_, P>>,
+        name: &[u16],
+    ) -> Result<(), RenameError> {
+        let data = entry.renamed(name, self.exfat.upcase());
+        let old = entry.entries.offsets();
+
+        // Get the location for the new entries.
+        let offsets = match dest {
+            Some(mut v) => v.allocate_entries(data.len(), &[]),
+            None if data.len() <= old.len() => Ok(old[..data.len()].to_vec()),
+            None => self.allocate_entries(data.len(), old),
+        };
+
+        let offsets = offsets.map_err(RenameError::AllocateEntriesFailed)?;
+
+        // Write the new entries.
+        let mut entries = EntrySet::new();
+
+        for (&offset, data) in offsets.iter().zip(data) {
+            entries.push(offset, data);
+        }
+
+        if let Err(e) = entries.write(&self.exfat.partition) {
+            return Err(RenameError::WriteEntriesFailed(e));
+        }
+
+        // Prevent the open handles from writing the old entries back.
+        self.exfat
+            .move_handles(old[0], &String::from_utf16_lossy(name), &entries);
+
+        // Remove the old entries that are not reused.
+        let mut unused = EntrySet::new();
+
+        for (i, &offset) in old.iter().enumerate() {
+            if !offsets.contains(&offset) {
+                unused.push(offset, *entry.entries.get(i));
+            }
+        }
+
+        if let Err(e) = unused.remove(&self.exfat.partition) {
+            return Err(RenameError::WriteEntriesFailed(e));
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the directory that start at `cluster` is a descendant of this directory.
+    /// The directories are walked with a stack and each one is read only once so a corrupted
+    /// volume with a loop in the directory tree cannot make it run forever.
+    fn contains_dir(&self, cluster: Cluster) -> Result<bool, FindError> {
+        let mut visited = BTreeSet::new();
+        let mut pending = Vec::new();
+        let mut reader = self.reader()?;
+
+        loop {
+            while let Some(entry) = Self::next_file(&mut reader, None)? {
+                if !entry.attributes.is_directory() {
+                    continue;
+                }
+
+                let first_cluster = entry.stream.allocation().first_cluster();
+
+                if first_cluster == cluster {
+                    return Ok(true);
+                } else if visited.insert(first_cluster) {
+                    pending.push(entry);
+                }
+            }
+
+            // Move to the next directory.
+            reader = match pending.pop() {
+                Some(v) => self::reader(self.exfat, Some(&v))?,
+                None => break,
+            };
+        }
+
+        Ok(false)
+    }
+
+    /// Removes all items in the directory recursively.
+    fn clear(&mut self) -> Result<(), RemoveError> {
+        let mut reader = self.reader().map_err(RemoveError::FindFailed)?;
+
+        while let Some(mut entry) =
+            Self::next_file(&mut reader, None).map_err(RemoveError::FindFailed)?
+        {
+            if entry.attributes.is_directory() {
+                DirectoryWriter::new(self.exfat, Some(&mut entry)).clear()?;
+            }
+
+            self.delete(entry)?;
+        }
+
+        Ok(())
+    }
+
+    /// Marks the entries of `entry` as not in use and releases its clusters.
+    fn delete(&self, mut entry: FileEntry) -> Result<(), RemoveError> {
+        // Prevent the open handles from writing the entries back.
+        self.exfat.invalidate_handles(entry.entries.offsets()[0]);
+
+        if let Err(e) = entry.entries.remove(&self.exfat.partition) {
+            return Err(RemoveError::WriteEntriesFailed(e));
+        }
+
+        let alloc = entry.stream.allocation();
+
+        if alloc.first_cluster().0 != 0 {
+            if let Err(e) = self.exfat.free_chain(
+                alloc.first_cluster(),
+                alloc.data_length(),
+                entry.stream.no_fat_chain(),
+            ) {
+                return Err(RemoveError::ReleaseClustersFailed(e));
+            }
+        }
+
+        // Release the clusters of the Vendor Allocation entries.
+        for v in &entry.vendor_entries {
+            let (first, len) = match v.allocation() {
+                Some((c, l)) if c.0 != 0 => (c, l),
+                _ => continue,
+            };
+
+            if let Err(e) = self.exfat.free_chain(first, len, v.no_fat_chain()) {
+                return Err(RemoveError::ReleaseClustersFailed(e));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the Volume Label of the root directory. The entry will be marked as unused if `label`
+    /// is empty.
+    pub fn set_volume_label(mut self, label: &[u16]) -> Result<(), SetVolumeLabelError> {
+        if !self.exfat.writable {
+            return Err(SetVolumeLabelError::ReadOnly);
+        } else if label.len() > 11 {
+            return Err(SetVolumeLabelError::InvalidLabel);
+        }
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(SetVolumeLabelError::SetDirtyFailed(e));
+        }
+
+        // Find the current Volume Label entry.
+        let mut reader = match self.reader() {
+            Ok(v) => v,
+            Err(e) => return Err(SetVolumeLabelError::FindFailed(e)),
+        };
+
+        let mut offset = None;
+
+        loop {
+            let entry = match reader.read() {
+                Ok(Some(v)) => v,
+                Ok(None) => break,
+                Err(e) => {
+                    return Err(SetVolumeLabelError::FindFailed(FindError::ReadEntryFailed(
+                        e,
+                    )))
+                }
+            };
+
+            let ty = entry.ty();
+
+            if entry.data()[0] == 0 {
+                break;
+            } else if ty.is_regular()
+                && ty.type_category() == EntryType::PRIMARY
+                && ty.type_importance() == EntryType::CRITICAL
+                && ty.type_code() == 3
+            {
+                offset = Some(entry.offset());
+                break;
+            }
+        }
+
+        // Allocate a new entry if there is no Volume Label entry.
+        let offset = match offset {
+            Some(v) => v,
+            None if label.is_empty() => return Ok(()),
+            None => match self.allocate_entries(1, &[]) {
+                Ok(v) => v[0],
+                Err(e) => return Err(SetVolumeLabelError::AllocateEntryFailed(e)),
+            },
+        };
+
+        // Write the entry.
+        let mut data = [0u8; 32];
+
+        if label.is_empty() {
+            data[0] = 0x03;
+        } else {
+            data[0] = 0x83;
+            data[1] = label.len() as u8;
+            LE::write_u16_into(label, &mut data[2..(2 + label.len() * 2)]);
+        }
+
+        if let Err(e) = self.exfat.partition.write_exact(offset, &data) {
+            return Err(SetVolumeLabelError::WriteFailed(offset, e));
+        }
+
+        Ok(())
+    }
+
+    /// Finds `count` contiguous unused entries and returns their offset on the partition. The
+    /// directory will be extended if there are no unused entries available. The entries in
+    /// `reuse` will be treated as unused.
+    fn allocate_entries(&mut self, count: usize, reuse: &[u64]) -> Result<Vec<u64>, CreateError> {
+        // Get the cluster chain.
+        let exfat = self.exfat;
+        let params = &exfat.params;
+        let cluster_size = params.cluster_size();
+        let (mut chain, mut no_fat_chain, data_length) = match &self.owner {
+            Some(e) => {
+                let alloc = e.stream.allocation();
+                let first = alloc.first_cluster();
+                let count = alloc.data_length().div_ceil(cluster_size) as usize;
+                let chain = if first.0 == 0 {
+                    Clusters::new()
+                } else if e.stream.no_fat_chain() {
+                    let count = count.min((u32::MAX - first.0) as usize + 1);
+                    Clusters::Contiguous(first, count)
+                } else {
+                    let fat = exfat.fat.lock().unwrap();
+                    let mut chain = fat.get_cluster_chain(&exfat.partition, first);
+                    let clusters: Clusters = chain.by_ref().take(count).collect();
+
+                    if let Err(e) = chain.finish() {
+                        return Err(CreateError::ReadChainFailed(e));
+                    }
+
+                    clusters
+                };
+
+                (chain, e.stream.no_fat_chain(), alloc.data_length())
+            }
+            None => {
+                let fat = exfat.fat.lock().unwrap();
+                let first = params.first_cluster_of_root_directory;
+                let chain = match fat.get_cluster_chain(&exfat.partition, first).into_vec() {
+                    Ok(v) => Clusters::Chain(v),
+                    Err(e) => return Err(CreateError::ReadChainFailed(e)),
+                };
+                let len = chain.len() as u64 * cluster_size;
+
+                (chain, false, len)
+            }
+        };
+
+        // Find unused entries.
+        let mut found: Vec<u64> = Vec::with_capacity(count);
+        let mut data = vec![0u8; cluster_size as usize];
+        let mut remaining = data_length;
+
+        'search: for cluster in chain.iter() {
+            let offset = match params.cluster_offset(cluster) {
+                Some(v) => v,
+                None => return Err(CreateError::InvalidCluster(cluster)),
+            };
+
+            let data = &mut data[..(remaining.min(cluster_size) as usize)];
+
+            if let Err(e) = exfat.partition.read_exact(offset, data) {
+                return Err(CreateError::ReadFailed(offset, e));
+            }
+
+            for (i, entry) in data.chunks_exact(32).enumerate() {
+                let offset = offset + i as u64 * 32;
+
+                if (entry[0] & 0x80) != 0 && !reuse.contains(&offset) {
+                    found.clear();
+                    continue;
+                }
+
+                found.push(offset);
+
+                if found.len() == count {
+                    break 'search;
+                }
+            }
+
+            remaining -= data.len() as u64;
+        }
+
+        if found.len() == count {
+            return Ok(found);
+        }
+
+        // Extend the directory.
+        while found.len() != count {
+            let cluster = match exfat.append_cluster(&mut chain, &mut no_fat_chain) {
+                Ok(v) => v,
+                Err(e) => return Err(CreateError::AllocateFailed(e)),
+            };
+
+            if let Err(e) = exfat.zero_cluster(cluster) {
+                return Err(CreateError::AllocateFailed(e));
+            }
+
+            let offset = match params.cluster_offset(cluster) {
+                Some(v) => v,
+                None => return Err(CreateError::InvalidCluster(cluster)),
+            };
+
+            for i in 0..(cluster_size / 32) {
+                found.push(offset + i * 32);
+
+                if found.len() == count {
+                    break;
+                }
+            }
+        }
+
+        // Update the stream extension of the directory.
+        if let Some(owner) = &mut self.owner {
+            let len = chain.len() as u64 * cluster_size;
+            let alloc = ClusterAllocation::new(chain.first().unwrap(), len);
+
+            owner.stream.set_allocation(no_fat_chain, len, alloc);
+
+            if let Err(e) = owner.write(&exfat.partition) {
+                return Err(CreateError::WriteEntriesFailed(e));
+            }
+        }
+
+        Ok(found)
+    }
+}
+
 /// Represents an error for [`open()`][Directory::open].
 #[derive(Debug, Error)]
 pub enum OpenError {
@@ -106,15 +1182,127 @@ pub enum OpenError {
     #[error("cannot read an entry")]
     ReadEntryFailed(#[source] crate::entries::ReaderError),
 
-    #[error("entry #{0} on cluster #{1} is not a primary entry")]
-    NotPrimaryEntry(usize, usize),
+    #[error("entry #{0} on cluster {1} is not a primary entry")]
+    NotPrimaryEntry(usize, Cluster),
+
+    #[error("entry #{0} on cluster {1} is not a file entry")]
+    NotFileEntry(usize, Cluster),
+
+    #[error("cannot load file entry")]
+    LoadFileEntryFailed(#[source] crate::entries::FileEntryError),
+
+    #[error("cannot create a file object for directory entry #{0} on cluster {1}")]
+    CreateFileObjectFailed(usize, Cluster, #[source] crate::file::NewError),
+}
+
+/// Represents an error when creating an item in a directory.
+#[derive(Debug, Error)]
+pub enum CreateError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn core::error::Error + Send + Sync>),
+
+    #[error("invalid name")]
+    InvalidName,
+
+    #[error("an item with the same name already exists")]
+    AlreadyExists,
+
+    #[error("cannot find the existing item")]
+    FindFailed(#[source] FindError),
+
+    #[error("cluster {0} is not valid")]
+    InvalidCluster(Cluster),
+
+    #[error("cannot read the cluster chain of the directory")]
+    ReadChainFailed(#[source] ChainError),
+
+    #[error("cannot read the data at {0:#018x}")]
+    ReadFailed(u64, #[source] Box<dyn core::error::Error + Send + Sync>),
+
+    #[error("cannot allocate a cluster for the directory")]
+    AllocateFailed(#[source] AllocError),
+
+    #[error("cannot write directory entries")]
+    WriteEntriesFailed(#[source] EntrySetError),
 
-    #[error("entry #{0} on cluster #{1} is not a file entry")]
-    NotFileEntry(usize, usize),
+    #[error("cannot create a file object")]
+    CreateFileObjectFailed(#[source] crate::file::NewError),
+}
+
+/// Represents an error when removing an item from a directory.
+#[derive(Debug, Error)]
+pub enum RemoveError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn core::error::Error + Send + Sync>),
+
+    #[error("the item does not exist")]
+    NotFound,
+
+    #[error("the directory is not empty")]
+    NotEmpty,
+
+    #[error("cannot find the item")]
+    FindFailed(#[source] FindError),
+
+    #[error("cannot write directory entries")]
+    WriteEntriesFailed(#[source] EntrySetError),
+
+    #[error("cannot release the clusters")]
+    ReleaseClustersFailed(#[source] AllocError),
+}
+
+/// Represents an error when finding an item in a directory.
+#[derive(Debug, Error)]
+pub enum FindError {
+    #[error("cannot create a clusters reader for the directory")]
+    CreateClustersReaderFailed(#[source] crate::cluster::NewError),
+
+    #[error("cannot read an entry")]
+    ReadEntryFailed(#[source] crate::entries::ReaderError),
 
     #[error("cannot load file entry")]
     LoadFileEntryFailed(#[source] crate::entries::FileEntryError),
 
-    #[error("cannot create a file object for directory entry #{0} on cluster #{1}")]
-    CreateFileObjectFailed(usize, usize, #[source] crate::file::NewError),
+    #[error("cannot create a file object")]
+    CreateFileObjectFailed(#[source] crate::file::NewError),
+}
+
+/// Represents an error when renaming or moving an item.
+#[derive(Debug, Error)]
+pub enum RenameError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn core::error::Error + Send + Sync>),
+
+    #[error("the item does not exist")]
+    NotFound,
+
+    #[error("invalid name")]
+    InvalidName,
+
+    #[error("an item with the same name already exists")]
+    AlreadyExists,
+
+    #[error("the destination is on a different volume")]
+    DifferentVolume,
+
+    #[error("cannot move a directory into itself")]
+    MoveIntoItself,
+
+    #[error("cannot find the item")]
+    FindFailed(#[source] FindError),
+
+    #[error("cannot allocate directory entries")]
+    AllocateEntriesFailed(#[source] CreateError),
+
+    #[error("cannot write directory entries")]
+    WriteEntriesFailed(#[source] EntrySetError),
 }
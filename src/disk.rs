@@ -1,23 +1,29 @@
+use crate::prelude::*;
+use core::error::Error;
 use core::fmt::Display;
 
 /// Encapsulate a disk partition.
+#[allow(clippy::len_without_is_empty)]
 pub trait DiskPartition {
-    #[cfg(not(feature = "std"))]
-    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Display + Send + Sync>>;
+    /// Returns the size of the partition, in bytes, or [`None`] if it is unknown. The default
+    /// implementation returns [`None`].
+    fn len(&self) -> Option<u64> {
+        None
+    }
 
-    #[cfg(feature = "std")]
-    fn read(
-        &self,
-        offset: u64,
-        buf: &mut [u8],
-    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>>;
+    /// Returns the preferred alignment and granularity of the I/O on the partition, in bytes. This
+    /// must be a power of two. The default implementation returns 512.
+    fn block_size(&self) -> u64 {
+        512
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>>;
 
-    #[cfg(not(feature = "std"))]
     fn read_exact(
         &self,
         mut offset: u64,
         mut buf: &mut [u8],
-    ) -> Result<(), Box<dyn Display + Send + Sync>> {
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
         while !buf.is_empty() {
             let n = self.read(offset, buf)?;
 
@@ -32,27 +38,205 @@ pub trait DiskPartition {
         Ok(())
     }
 
+    /// Reads the data at `offset` into `bufs` in order. Returns the total number of bytes read.
+    /// The default implementation reads only into the first non-empty buffer with
+    /// [`read()`][Self::read()].
     #[cfg(feature = "std")]
-    fn read_exact(
+    fn read_vectored_at(
+        &self,
+        offset: u64,
+        bufs: &mut [std::io::IoSliceMut<'_>],
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        match bufs.iter_mut().find(|b| !b.is_empty()) {
+            Some(b) => self.read(offset, b),
+            None => Ok(0),
+        }
+    }
+
+    /// Makes sure all data that was written reach the underlying storage. The default
+    /// implementation does nothing.
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Tells the underlying storage that `len` bytes at `offset` no longer hold any useful data
+    /// (e.g. TRIM on SSD). The content of the range is undefined after this. The default
+    /// implementation does nothing.
+    fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _ = (offset, len);
+        Ok(())
+    }
+
+    /// Returns `true` if the partition support [`write()`][Self::write()]. A volume can be opened
+    /// as read-write only if this return `true`. The default implementation returns `false`, which
+    /// must be overridden together with [`write()`][Self::write()].
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    /// Writes `buf` at `offset`. The default implementation always returns an error, which mean
+    /// the partition is read-only.
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let _ = (offset, buf);
+        Err(Box::new(ReadOnly))
+    }
+
+    fn write_exact(
         &self,
         mut offset: u64,
-        mut buf: &mut [u8],
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        mut buf: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
         while !buf.is_empty() {
-            let n = self.read(offset, buf)?;
+            let n = self.write(offset, buf)?;
 
             if n == 0 {
                 return Err(Box::new(UnexpectedEop));
             }
 
             offset += n;
-            buf = &mut buf[n.try_into().unwrap()..];
+            buf = &buf[n.try_into().unwrap()..];
         }
 
         Ok(())
     }
 }
 
+/// Forwards all methods to the partition behind the pointer so one partition can be shared.
+macro_rules! forward_partition {
+    ($($ty:ty),*) => {
+        $(impl<P: DiskPartition + ?Sized> DiskPartition for $ty {
+            fn len(&self) -> Option<u64> {
+                (**self).len()
+            }
+
+            fn block_size(&self) -> u64 {
+                (**self).block_size()
+            }
+
+            fn is_writable(&self) -> bool {
+                (**self).is_writable()
+            }
+
+            fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+                (**self).flush()
+            }
+
+            fn discard(
+                &self,
+                offset: u64,
+                len: u64,
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                (**self).discard(offset, len)
+            }
+
+            fn read(
+                &self,
+                offset: u64,
+                buf: &mut [u8],
+            ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+                (**self).read(offset, buf)
+            }
+
+            fn read_exact(
+                &self,
+                offset: u64,
+                buf: &mut [u8],
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                (**self).read_exact(offset, buf)
+            }
+
+            #[cfg(feature = "std")]
+            fn read_vectored_at(
+                &self,
+                offset: u64,
+                bufs: &mut [std::io::IoSliceMut<'_>],
+            ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+                (**self).read_vectored_at(offset, bufs)
+            }
+
+            fn write(
+                &self,
+                offset: u64,
+                buf: &[u8],
+            ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+                (**self).write(offset, buf)
+            }
+
+            fn write_exact(
+                &self,
+                offset: u64,
+                buf: &[u8],
+            ) -> Result<(), Box<dyn Error + Send + Sync>> {
+                (**self).write_exact(offset, buf)
+            }
+        })*
+    };
+}
+
+forward_partition!(&P, Box<P>, crate::sync::Arc<P>);
+
+/// A read-only partition on the bytes in memory. Use [`Image`][crate::image::Image] with
+/// [`std::io::Cursor`] for a writable one.
+impl DiskPartition for [u8] {
+    fn len(&self) -> Option<u64> {
+        Some(<[u8]>::len(self) as u64)
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        Ok(read_slice(self, offset, buf))
+    }
+}
+
+/// A read-only partition on the bytes in memory. Use [`Image`][crate::image::Image] with
+/// [`std::io::Cursor`] for a writable one.
+impl DiskPartition for Vec<u8> {
+    fn len(&self) -> Option<u64> {
+        Some(Vec::len(self) as u64)
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        Ok(read_slice(self, offset, buf))
+    }
+}
+
+/// A read-only partition on the bytes in memory. The position of the cursor is ignored.
+#[cfg(feature = "std")]
+impl DiskPartition for std::io::Cursor<Vec<u8>> {
+    fn len(&self) -> Option<u64> {
+        Some(self.get_ref().len() as u64)
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        Ok(read_slice(self.get_ref(), offset, buf))
+    }
+}
+
+/// Copies the data at `offset` in `data` to `buf`. Returns zero if `offset` is out of range.
+pub(crate) fn read_slice(data: &[u8], offset: u64, buf: &mut [u8]) -> u64 {
+    let data = match usize::try_from(offset).ok().and_then(|v| data.get(v..)) {
+        Some(v) => v,
+        None => return 0,
+    };
+
+    let len = buf.len().min(data.len());
+
+    buf[..len].copy_from_slice(&data[..len]);
+
+    len as u64
+}
+
+/// An error for writing to a read-only partition.
+#[derive(Debug)]
+struct ReadOnly;
+
+impl Display for ReadOnly {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the partition is read-only")
+    }
+}
+
+impl Error for ReadOnly {}
+
 /// An error for unexpected end of partition.
 #[derive(Debug)]
 struct UnexpectedEop;
@@ -63,5 +247,4 @@ impl Display for UnexpectedEop {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for UnexpectedEop {}
+impl Error for UnexpectedEop {}
@@ -1,10 +1,14 @@
-use crate::cluster::ClustersReader;
+use crate::cluster::{ClustersReader, NewError};
 use crate::disk::DiskPartition;
-use crate::FileAttributes;
+use crate::io::Read;
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::timestamp::{Timestamp, Timestamps};
+use crate::upcase::UpcaseTable;
+use crate::{Cluster, ExFat, FileAttributes, Warning};
 use byteorder::{ByteOrder, LE};
-use std::cmp::min;
-use std::fmt::{Display, Formatter};
-use std::io::Read;
+use core::cmp::min;
+use core::fmt::{Display, Formatter};
 use thiserror::Error;
 
 /// A struct to read directory entries.
@@ -21,10 +25,33 @@ impl<P: DiskPartition> EntriesReader<P> {
         }
     }
 
-    pub fn read(&mut self) -> Result<RawEntry, ReaderError> {
+    pub fn exfat(&self) -> &Arc<ExFat<P>> {
+        self.cluster_reader.exfat()
+    }
+
+    /// Skips the next `count` entries.
+    pub fn skip(&mut self, count: usize) -> Result<(), ReaderError> {
+        for _ in 0..count {
+            if self.read()?.is_none() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next entry. Returns [`None`] if the end of the directory has been reached.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn read(&mut self) -> Result<Option<RawEntry>, ReaderError> {
+        // Check if the end of directory.
+        if self.cluster_reader.is_eof() {
+            return Ok(None);
+        }
+
         // Get current cluster and entry index.
         let cluster = self.cluster_reader.cluster();
         let index = self.entry_index;
+        let offset = self.cluster_reader.partition_offset();
 
         // Read directory entry.
         let mut entry = [0u8; 32];
@@ -34,37 +61,95 @@ impl<P: DiskPartition> EntriesReader<P> {
         }
 
         // Update entry index.
-        if self.cluster_reader.cluster() != cluster {
+        if self.cluster_reader.is_eof() || self.cluster_reader.cluster() != cluster {
             self.entry_index = 0;
         } else {
             self.entry_index += 1;
         }
 
-        Ok(RawEntry {
+        // The offset is not available if the entry was read from a bad cluster as zeros, which
+        // indicates the end of directory.
+        let offset = match offset {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        Ok(Some(RawEntry {
             index,
             cluster,
+            offset,
             data: entry,
+        }))
+    }
+}
+
+/// An iterator over the raw 32-byte entries of a directory, including the unused, deleted and
+/// unknown ones. The iteration does not stop at the end of directory marker so the entries after
+/// it can be inspected too.
+pub struct RawEntries<P: DiskPartition> {
+    reader: EntriesReader<P>,
+}
+
+impl<P: DiskPartition> RawEntries<P> {
+    pub(crate) fn new(
+        exfat: Arc<ExFat<P>>,
+        first_cluster: Cluster,
+        data_length: Option<u64>,
+        no_fat_chain: Option<bool>,
+    ) -> Result<Self, NewError> {
+        let reader = ClustersReader::new(exfat, first_cluster, data_length, no_fat_chain)?;
+
+        Ok(Self {
+            reader: EntriesReader::new(reader),
         })
     }
 }
 
+impl<P: DiskPartition> Iterator for RawEntries<P> {
+    type Item = Result<RawEntry, ReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.read().transpose()
+    }
+}
+
 /// Represents a raw directory entry.
-pub(crate) struct RawEntry {
+#[derive(Debug, Clone)]
+pub struct RawEntry {
     index: usize,
-    cluster: usize,
+    cluster: Cluster,
+    offset: u64,
     data: [u8; 32],
 }
 
 impl RawEntry {
-    pub fn ty(&self) -> EntryType {
+    pub(crate) fn ty(&self) -> EntryType {
         EntryType(self.data[0])
     }
 
+    /// Returns the EntryType field, which is the first byte of the entry.
+    pub fn entry_type(&self) -> u8 {
+        self.data[0]
+    }
+
+    /// Returns `true` if the InUse bit is set. An entry with zero EntryType is the end of
+    /// directory marker while the others without the InUse bit are deleted.
+    pub fn is_in_use(&self) -> bool {
+        (self.data[0] & 0x80) != 0
+    }
+
+    /// Returns the index of this entry within its cluster.
     pub fn index(&self) -> usize {
         self.index
     }
 
-    pub fn cluster(&self) -> usize {
+    /// Returns the offset of this entry on the partition.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the cluster that contains this entry.
+    pub fn cluster(&self) -> Cluster {
         self.cluster
     }
 
@@ -74,10 +159,14 @@ impl RawEntry {
 }
 
 /// Represents a File Directory Entry.
+#[derive(Clone)]
 pub(crate) struct FileEntry {
     pub name: String,
     pub attributes: FileAttributes,
+    pub timestamps: Timestamps,
     pub stream: StreamEntry,
+    pub vendor_entries: Vec<VendorEntry>,
+    pub entries: EntrySet,
 }
 
 impl FileEntry {
@@ -85,10 +174,54 @@ impl FileEntry {
     where
         P: DiskPartition,
     {
-        // Load fields.
-        let data = &raw.data;
-        let secondary_count = data[1] as usize;
-        let attributes = FileAttributes(LE::read_u16(&data[4..]));
+        let stream = Self::read_stream(raw, reader)?;
+
+        Self::load_with_stream(raw, stream, reader, true, None).map(Option::unwrap)
+    }
+
+    /// Same as [`load()`][Self::load()] except it does not verify the SetChecksum.
+    pub fn load_unchecked<P>(
+        raw: &RawEntry,
+        reader: &mut EntriesReader<P>,
+    ) -> Result<Self, FileEntryError>
+    where
+        P: DiskPartition,
+    {
+        let stream = Self::read_stream(raw, reader)?;
+
+        Self::load_with_stream(raw, stream, reader, false, None).map(Option::unwrap)
+    }
+
+    /// Same as [`load()`][Self::load()] except it returns [`None`] if the NameHash of the entry is
+    /// not `hash` or the name is not equal to `name` case-insensitively. Nothing will be allocated
+    /// for the entry that does not match.
+    pub fn load_if_name<P>(
+        raw: &RawEntry,
+        reader: &mut EntriesReader<P>,
+        name: &[u16],
+        hash: u16,
+    ) -> Result<Option<Self>, FileEntryError>
+    where
+        P: DiskPartition,
+    {
+        let stream = Self::read_stream(raw, reader)?;
+
+        if stream.ty().is_critical_secondary(0) && LE::read_u16(&stream.data[4..]) != hash {
+            return Ok(None);
+        }
+
+        Self::load_with_stream(raw, stream, reader, true, Some(name))
+    }
+
+    /// Reads the entry that follows the File Directory Entry.
+    fn read_stream<P>(
+        raw: &RawEntry,
+        reader: &mut EntriesReader<P>,
+    ) -> Result<RawEntry, FileEntryError>
+    where
+        P: DiskPartition,
+    {
+        let secondary_count = raw.data[1] as usize;
 
         if secondary_count < 1 {
             return Err(FileEntryError::NoStreamExtension(raw.index, raw.cluster));
@@ -96,11 +229,28 @@ impl FileEntry {
             return Err(FileEntryError::NoFileName(raw.index, raw.cluster));
         }
 
-        // Read stream extension.
-        let stream = match reader.read() {
-            Ok(v) => v,
-            Err(e) => return Err(FileEntryError::ReadStreamFailed(e)),
-        };
+        match reader.read() {
+            Ok(Some(v)) => Ok(v),
+            Ok(None) => Err(FileEntryError::NoStreamExtension(raw.index, raw.cluster)),
+            Err(e) => Err(FileEntryError::ReadStreamFailed(e)),
+        }
+    }
+
+    fn load_with_stream<P>(
+        raw: &RawEntry,
+        stream: RawEntry,
+        reader: &mut EntriesReader<P>,
+        verify: bool,
+        filter: Option<&[u16]>,
+    ) -> Result<Option<Self>, FileEntryError>
+    where
+        P: DiskPartition,
+    {
+        // Load fields.
+        let data = &raw.data;
+        let secondary_count = data[1] as usize;
+        let attributes = FileAttributes(LE::read_u16(&data[4..]));
+        let timestamps = Timestamps::load(data);
 
         // Check if the entry is a stream extension.
         let ty = stream.ty();
@@ -113,74 +263,312 @@ impl FileEntry {
         }
 
         // Load stream extension.
+        let mut entries = EntrySet::new();
+
+        entries.push(raw.offset, raw.data);
+        entries.push(stream.offset, stream.data);
+
         let stream = StreamEntry::load(stream, attributes)?;
 
-        // Read file names.
-        let name_count = secondary_count - 1;
-        let mut names: Vec<RawEntry> = Vec::with_capacity(name_count);
+        // Read file names and vendor entries. The name is decoded on the stack so the entry set
+        // that does not match the filter never allocate the name.
+        let name_count = stream.name_length.div_ceil(15);
+        let mut names = 0;
+        let mut name = [0u16; 255];
+        let mut vendor_entries: Vec<VendorEntry> = Vec::new();
 
-        for i in 0..name_count {
-            // Read file name.
+        for i in 0..(secondary_count - 1) {
+            // Read secondary entry.
             let entry = match reader.read() {
-                Ok(v) => v,
+                Ok(Some(v)) => v,
+                Ok(None) => return Err(FileEntryError::NoFileName(raw.index, raw.cluster)),
                 Err(e) => return Err(FileEntryError::ReadFileNameFailed(i, e)),
             };
 
-            // Check if the entry is a file name.
+            // Check entry type. The file names must come before any other secondary entries.
             let ty = entry.ty();
 
-            if !ty.is_critical_secondary(1) {
-                return Err(FileEntryError::NotFileName(entry.index, entry.cluster));
+            if names < name_count {
+                if !ty.is_critical_secondary(1) {
+                    return Err(FileEntryError::NotFileName(entry.index, entry.cluster));
+                }
+
+                // Load GeneralSecondaryFlags.
+                let general_secondary_flags = SecondaryFlags(entry.data[1]);
+
+                if general_secondary_flags.allocation_possible() {
+                    return Err(FileEntryError::InvalidFileName(entry.index, entry.cluster));
+                }
+
+                // Load FileName.
+                let start = names * 15;
+                let end = min(start + 15, stream.name_length);
+
+                LE::read_u16_into(
+                    &entry.data[2..(2 + (end - start) * 2)],
+                    &mut name[start..end],
+                );
+
+                entries.push(entry.offset, entry.data);
+                names += 1;
+            } else if ty.is_benign_secondary() {
+                entries.push(entry.offset, entry.data);
+                vendor_entries.push(VendorEntry { data: entry.data });
+            } else if ty.is_critical_secondary(1) {
+                return Err(FileEntryError::WrongFileNames(raw.index, raw.cluster));
+            } else {
+                return Err(FileEntryError::UnknownSecondaryEntry(
+                    entry.index,
+                    entry.cluster,
+                ));
             }
-
-            names.push(entry);
         }
 
-        // TODO: Use div_ceil when https://github.com/rust-lang/rust/issues/88581 stabilized.
-        if names.len() != (stream.name_length + 15 - 1) / 15 {
+        if names != name_count {
             return Err(FileEntryError::WrongFileNames(raw.index, raw.cluster));
         }
 
-        // Construct a complete file name.
-        let mut need = stream.name_length * 2;
-        let mut name = String::with_capacity(15 * names.len());
+        // Check if the name matches.
+        let exfat = reader.exfat();
+        let name = &name[..stream.name_length];
 
-        for entry in names {
-            let data = entry.data;
+        if filter.is_some_and(|v| !exfat.upcase().eq(v, name)) {
+            return Ok(None);
+        }
 
-            // Load GeneralSecondaryFlags.
-            let general_secondary_flags = SecondaryFlags(data[1]);
+        // Construct a complete file name.
+        let name = match String::from_utf16(name) {
+            Ok(v) => v,
+            Err(_) => return Err(FileEntryError::InvalidFileName(raw.index, raw.cluster)),
+        };
 
-            if general_secondary_flags.allocation_possible() {
-                return Err(FileEntryError::InvalidFileName(entry.index, entry.cluster));
+        // Verify SetChecksum.
+        if verify && !exfat.ignore_checksums && !entries.is_checksum_valid() {
+            if !exfat.lenient {
+                return Err(FileEntryError::ChecksumMismatch(raw.index, raw.cluster));
             }
 
-            // Load FileName.
-            let raw_name = &data[2..(2 + min(30, need))];
+            let warning = Warning::ChecksumMismatch(raw.index, raw.cluster);
+
+            exfat.warnings.lock().unwrap().push(warning);
+        }
+
+        Ok(Some(Self {
+            name,
+            attributes,
+            timestamps,
+            stream,
+            vendor_entries,
+            entries,
+        }))
+    }
+
+    /// Writes FileAttributes, the timestamps and the stream extension to the partition.
+    pub fn write<P: DiskPartition>(&mut self, partition: &P) -> Result<(), EntrySetError> {
+        LE::write_u16(&mut self.entries.get_mut(0)[4..], self.attributes.0);
+        self.timestamps.write(self.entries.get_mut(0));
+        self.stream.write(self.entries.get_mut(1));
+        self.entries.write(partition)
+    }
+
+    /// Returns `true` if the NameHash of the stream extension matches with the name.
+    pub fn is_name_hash_valid(&self, upcase: &UpcaseTable) -> bool {
+        let name: Vec<u16> = self.name.encode_utf16().collect();
+
+        LE::read_u16(&self.entries.get(1)[4..]) == name_hash(&name, upcase)
+    }
+
+    /// Builds the entries for a new File Directory Entry with all timestamps set to the current
+    /// time. The SetChecksum will not be calculated.
+    pub fn build(
+        name: &[u16],
+        attributes: FileAttributes,
+        stream: &StreamEntry,
+        upcase: &UpcaseTable,
+    ) -> Vec<[u8; 32]> {
+        let now = Timestamp::now();
+        let mut file = [0u8; 32];
+
+        file[0] = 0x85;
+        LE::write_u16(&mut file[4..], attributes.0);
+        Timestamps::new(now, now, now).write(&mut file);
+
+        // Stream extension.
+        let mut data = [0u8; 32];
+
+        data[0] = 0xc0;
+        stream.write(&mut data);
+
+        Self::build_set(file, data, name, &[], upcase)
+    }
+
+    /// Builds the entries of this File Directory Entry with a different name. The SetChecksum
+    /// will not be calculated.
+    pub fn renamed(&self, name: &[u16], upcase: &UpcaseTable) -> Vec<[u8; 32]> {
+        let file = *self.entries.get(0);
+        let stream = *self.entries.get(1);
+        let vendors: Vec<[u8; 32]> = self.vendor_entries.iter().map(|v| v.data).collect();
+
+        Self::build_set(file, stream, name, &vendors, upcase)
+    }
+
+    /// Builds an entry set from the file entry, stream extension, the file name and the vendor
+    /// entries.
+    fn build_set(
+        mut file: [u8; 32],
+        mut stream: [u8; 32],
+        name: &[u16],
+        vendors: &[[u8; 32]],
+        upcase: &UpcaseTable,
+    ) -> Vec<[u8; 32]> {
+        let names = name.chunks(15);
+        let mut entries = Vec::with_capacity(2 + names.len() + vendors.len());
+
+        file[1] = (1 + names.len() + vendors.len()) as u8;
+        stream[3] = name.len() as u8;
+        LE::write_u16(&mut stream[4..], name_hash(name, upcase));
+
+        entries.push(file);
+        entries.push(stream);
+
+        // File names.
+        for name in names {
+            let mut data = [0u8; 32];
+
+            data[0] = 0xc1;
+            LE::write_u16_into(name, &mut data[2..(2 + name.len() * 2)]);
+
+            entries.push(data);
+        }
+
+        entries.extend_from_slice(vendors);
+        entries
+    }
+}
 
-            need -= raw_name.len();
+/// Represents a benign secondary entry in the entry set of a file or a directory, which usually
+/// is a Vendor Extension or a Vendor Allocation entry.
+#[derive(Debug, Clone)]
+pub struct VendorEntry {
+    data: [u8; 32],
+}
 
-            // Convert FileName from little-endian to native endian.
-            let mut file_name = [0u16; 15];
-            let file_name = &mut file_name[..(raw_name.len() / 2)];
+impl VendorEntry {
+    /// Returns `true` if this is a Vendor Extension entry.
+    pub fn is_vendor_extension(&self) -> bool {
+        EntryType(self.data[0]).type_code() == 0
+    }
+
+    /// Returns `true` if this is a Vendor Allocation entry.
+    pub fn is_vendor_allocation(&self) -> bool {
+        EntryType(self.data[0]).type_code() == 1
+    }
+
+    /// Returns the VendorGuid in on-disk byte order or [`None`] if this is neither a Vendor
+    /// Extension nor a Vendor Allocation entry.
+    pub fn vendor_guid(&self) -> Option<&[u8; 16]> {
+        if self.is_vendor_extension() || self.is_vendor_allocation() {
+            Some(self.data[2..18].try_into().unwrap())
+        } else {
+            None
+        }
+    }
 
-            LE::read_u16_into(raw_name, file_name);
+    /// Returns FirstCluster and DataLength of a Vendor Allocation entry.
+    pub fn allocation(&self) -> Option<(Cluster, u64)> {
+        if self.is_vendor_allocation() {
+            let first_cluster = Cluster(LE::read_u32(&self.data[20..]));
+            let data_length = LE::read_u64(&self.data[24..]);
+
+            Some((first_cluster, data_length))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the clusters of a Vendor Allocation entry are contiguous and the FAT is
+    /// not used.
+    pub fn no_fat_chain(&self) -> bool {
+        self.is_vendor_allocation() && SecondaryFlags(self.data[1]).no_fat_chain()
+    }
+
+    /// Returns the raw data of this entry, including EntryType.
+    pub fn data(&self) -> &[u8; 32] {
+        &self.data
+    }
+}
 
-            match String::from_utf16(file_name) {
-                Ok(v) => name.push_str(&v),
-                Err(_) => return Err(FileEntryError::InvalidFileName(entry.index, entry.cluster)),
+/// Represents a set of directory entries that belong to the same primary entry.
+#[derive(Clone)]
+pub(crate) struct EntrySet {
+    offsets: Vec<u64>,
+    entries: Vec<[u8; 32]>,
+}
+
+impl EntrySet {
+    pub fn new() -> Self {
+        Self {
+            offsets: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, offset: u64, data: [u8; 32]) {
+        self.offsets.push(offset);
+        self.entries.push(data);
+    }
+
+    /// Gets the offset on the partition of each entry.
+    pub fn offsets(&self) -> &[u64] {
+        &self.offsets
+    }
+
+    pub fn get(&self, index: usize) -> &[u8; 32] {
+        &self.entries[index]
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut [u8; 32] {
+        &mut self.entries[index]
+    }
+
+    /// Returns `true` if the SetChecksum matches the entries.
+    pub fn is_checksum_valid(&self) -> bool {
+        LE::read_u16(&self.entries[0][2..]) == checksum(&self.entries)
+    }
+
+    /// Clears the InUse bit on all entries and writes them to the partition.
+    pub fn remove<P: DiskPartition>(&mut self, partition: &P) -> Result<(), EntrySetError> {
+        for (&offset, data) in self.offsets.iter().zip(&mut self.entries) {
+            data[0] &= 0x7f;
+
+            if let Err(e) = partition.write_exact(offset, &data[..1]) {
+                return Err(EntrySetError::WriteFailed(offset, e));
             }
         }
 
-        Ok(Self {
-            name,
-            attributes,
-            stream,
-        })
+        Ok(())
+    }
+
+    /// Updates SetChecksum and writes all entries to the partition.
+    pub fn write<P: DiskPartition>(&mut self, partition: &P) -> Result<(), EntrySetError> {
+        // Update checksum.
+        let checksum = checksum(&self.entries);
+
+        LE::write_u16(&mut self.entries[0][2..], checksum);
+
+        // Write entries.
+        for (&offset, data) in self.offsets.iter().zip(&self.entries) {
+            if let Err(e) = partition.write_exact(offset, data) {
+                return Err(EntrySetError::WriteFailed(offset, e));
+            }
+        }
+
+        Ok(())
     }
 }
 
 /// Represents a Stream Extension Directory Entry.
+#[derive(Clone)]
 pub(crate) struct StreamEntry {
     no_fat_chain: bool,
     name_length: usize,
@@ -245,6 +633,20 @@ impl StreamEntry {
         })
     }
 
+    pub fn new(
+        name_length: usize,
+        no_fat_chain: bool,
+        valid_data_length: u64,
+        alloc: ClusterAllocation,
+    ) -> Self {
+        Self {
+            no_fat_chain,
+            name_length,
+            valid_data_length,
+            alloc,
+        }
+    }
+
     pub fn no_fat_chain(&self) -> bool {
         self.no_fat_chain
     }
@@ -256,6 +658,71 @@ impl StreamEntry {
     pub fn allocation(&self) -> &ClusterAllocation {
         &self.alloc
     }
+
+    pub fn set_name_length(&mut self, v: usize) {
+        self.name_length = v;
+    }
+
+    pub fn set_allocation(
+        &mut self,
+        no_fat_chain: bool,
+        valid_data_length: u64,
+        alloc: ClusterAllocation,
+    ) {
+        self.no_fat_chain = no_fat_chain;
+        self.valid_data_length = valid_data_length;
+        self.alloc = alloc;
+    }
+
+    /// Writes GeneralSecondaryFlags, ValidDataLength, FirstCluster and DataLength to `data`.
+    pub fn write(&self, data: &mut [u8; 32]) {
+        data[1] = if self.no_fat_chain { 3 } else { 1 };
+        LE::write_u64(&mut data[8..], self.valid_data_length);
+        LE::write_u32(&mut data[20..], self.alloc.first_cluster.0);
+        LE::write_u64(&mut data[24..], self.alloc.data_length);
+    }
+}
+
+/// Computes the SetChecksum of `entries`.
+pub(crate) fn checksum(entries: &[[u8; 32]]) -> u16 {
+    let mut checksum = 0u16;
+
+    for (i, &b) in entries.iter().flatten().enumerate() {
+        if i == 2 || i == 3 {
+            continue;
+        }
+
+        checksum = checksum.rotate_right(1).wrapping_add(b.into());
+    }
+
+    checksum
+}
+
+/// Computes the NameHash of `name`.
+pub(crate) fn name_hash(name: &[u16], upcase: &UpcaseTable) -> u16 {
+    let mut hash = 0u16;
+
+    for &c in name {
+        for b in upcase.to_upper(c).to_le_bytes() {
+            hash = hash.rotate_right(1).wrapping_add(b.into());
+        }
+    }
+
+    hash
+}
+
+/// Converts `c` to upper case using the Unicode case mapping. This is used to generate the
+/// Up-case Table when formatting the volume.
+pub(crate) fn upcase(c: u16) -> u16 {
+    let mut upper = match char::from_u32(c.into()) {
+        Some(v) => v.to_uppercase(),
+        None => return c,
+    };
+
+    match (upper.next(), upper.next()) {
+        (Some(v), None) => u16::try_from(u32::from(v)).unwrap_or(c),
+        _ => c,
+    }
 }
 
 /// Encapsulate EntryType field of the directory entry.
@@ -267,6 +734,7 @@ impl EntryType {
     pub const PRIMARY: u8 = 0;
     pub const SECONDARY: u8 = 1;
     pub const CRITICAL: u8 = 0;
+    pub const BENIGN: u8 = 1;
 
     pub fn is_regular(self) -> bool {
         self.0 >= 0x81
@@ -284,6 +752,12 @@ impl EntryType {
         (self.0 & 0x40) >> 6
     }
 
+    pub fn is_benign_secondary(self) -> bool {
+        self.is_regular()
+            && self.type_importance() == Self::BENIGN
+            && self.type_category() == Self::SECONDARY
+    }
+
     pub fn is_critical_secondary(self, code: u8) -> bool {
         self.is_regular()
             && self.type_importance() == Self::CRITICAL
@@ -293,7 +767,7 @@ impl EntryType {
 }
 
 impl Display for EntryType {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         if self.is_regular() {
             if self.type_importance() == Self::CRITICAL {
                 f.write_str("critical ")?;
@@ -332,7 +806,7 @@ impl SecondaryFlags {
 /// Represents FirstCluster and DataLength fields in the Directory Entry.
 #[derive(Debug, Clone)]
 pub struct ClusterAllocation {
-    first_cluster: usize,
+    first_cluster: Cluster,
     data_length: u64,
 }
 
@@ -340,15 +814,15 @@ impl ClusterAllocation {
     pub(crate) fn load(entry: &RawEntry) -> Result<Self, ClusterAllocationError> {
         // Load fields.
         let data = &entry.data;
-        let first_cluster = LE::read_u32(&data[20..]) as usize;
+        let first_cluster = Cluster(LE::read_u32(&data[20..]));
         let data_length = LE::read_u64(&data[24..]);
 
         // Check values.
-        if first_cluster == 0 {
+        if first_cluster.0 == 0 {
             if data_length != 0 {
                 return Err(ClusterAllocationError::InvalidDataLength);
             }
-        } else if first_cluster < 2 {
+        } else if first_cluster.0 < 2 {
             return Err(ClusterAllocationError::InvalidFirstCluster);
         }
 
@@ -358,7 +832,14 @@ impl ClusterAllocation {
         })
     }
 
-    pub(crate) fn first_cluster(&self) -> usize {
+    pub(crate) fn new(first_cluster: Cluster, data_length: u64) -> Self {
+        Self {
+            first_cluster,
+            data_length,
+        }
+    }
+
+    pub(crate) fn first_cluster(&self) -> Cluster {
         self.first_cluster
     }
 
@@ -368,47 +849,60 @@ impl ClusterAllocation {
 }
 
 impl Display for ClusterAllocation {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}", self.first_cluster, self.data_length)
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}:{}", self.first_cluster.0, self.data_length)
     }
 }
 
 /// Represents an error for [`read()`][EntriesReader::read()].
 #[derive(Debug, Error)]
 pub enum ReaderError {
-    #[error("cannot read entry #{0} on cluster #{1}")]
-    ReadFailed(usize, usize, #[source] std::io::Error),
+    #[error("cannot read entry #{0} on cluster {1}")]
+    ReadFailed(usize, Cluster, #[source] crate::io::Error),
 }
 
 /// Represents an error for [`load()`][FileEntry::load()].
 #[derive(Debug, Error)]
 pub enum FileEntryError {
-    #[error("no stream extension is followed the entry #{0} on cluster #{1}")]
-    NoStreamExtension(usize, usize),
+    #[error("no stream extension is followed the entry #{0} on cluster {1}")]
+    NoStreamExtension(usize, Cluster),
 
-    #[error("no file name is followed the entry #{0} on cluster #{1}")]
-    NoFileName(usize, usize),
+    #[error("no file name is followed the entry #{0} on cluster {1}")]
+    NoFileName(usize, Cluster),
 
     #[error("cannot read stream extension")]
     ReadStreamFailed(#[source] ReaderError),
 
-    #[error("entry #{0} on cluster #{1} is not a stream extension")]
-    NotStreamExtension(usize, usize),
+    #[error("entry #{0} on cluster {1} is not a stream extension")]
+    NotStreamExtension(usize, Cluster),
 
-    #[error("entry #{0} on cluster #{1} is not a valid stream extension")]
-    InvalidStreamExtension(usize, usize),
+    #[error("entry #{0} on cluster {1} is not a valid stream extension")]
+    InvalidStreamExtension(usize, Cluster),
 
     #[error("cannot read file name #{0}")]
     ReadFileNameFailed(usize, #[source] ReaderError),
 
-    #[error("entry #{0} on cluster #{1} is not a file name")]
-    NotFileName(usize, usize),
+    #[error("entry #{0} on cluster {1} is not a file name")]
+    NotFileName(usize, Cluster),
+
+    #[error("entry #{0} on cluster {1} has wrong number of file names")]
+    WrongFileNames(usize, Cluster),
+
+    #[error("entry #{0} on cluster {1} is not a valid file name")]
+    InvalidFileName(usize, Cluster),
 
-    #[error("entry #{0} on cluster #{1} has wrong number of file names")]
-    WrongFileNames(usize, usize),
+    #[error("entry #{0} on cluster {1} is an unknown critical secondary entry")]
+    UnknownSecondaryEntry(usize, Cluster),
 
-    #[error("entry #{0} on cluster #{1} is not a valid file name")]
-    InvalidFileName(usize, usize),
+    #[error("SetChecksum of entry #{0} on cluster {1} is not correct")]
+    ChecksumMismatch(usize, Cluster),
+}
+
+/// Represents an error for [`write()`][EntrySet::write()].
+#[derive(Debug, Error)]
+pub enum EntrySetError {
+    #[error("cannot write the entry at {0:#018x}")]
+    WriteFailed(u64, #[source] Box<dyn core::error::Error + Send + Sync>),
 }
 
 /// Represents an error for [`load()`][ClusterAllocation::load()].
@@ -0,0 +1,329 @@
+//! Copying the directory tree to the local file system.
+use crate::directory::{Directory, Item, OpenError};
+use crate::disk::DiskPartition;
+use crate::file::File;
+use crate::timestamp::Timestamps;
+use core::borrow::Borrow;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::fs::FileTimes;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Options and callbacks for [`Root::extract_with()`][crate::Root::extract_with()] and
+/// [`Directory::extract_with()`][crate::directory::Directory::extract_with()].
+#[derive(Default)]
+pub struct ExtractOptions<'a> {
+    on_error: ErrorPolicy,
+    progress: Option<ProgressFn<'a>>,
+    cancel: Option<&'a AtomicBool>,
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(&str, u64) + 'a>;
+
+impl<'a> ExtractOptions<'a> {
+    /// Creates a new [`ExtractOptions`] that aborts on the first error with no progress callback
+    /// and no cancellation flag.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what to do when an item cannot be extracted. The default is [`ErrorPolicy::Abort`].
+    pub fn on_error(mut self, v: ErrorPolicy) -> Self {
+        self.on_error = v;
+        self
+    }
+
+    /// Sets a callback to be invoked after each file is extracted with its path and size. The path
+    /// is relative to the extracted directory and always start with `/`.
+    pub fn progress<F: FnMut(&str, u64) + 'a>(mut self, f: F) -> Self {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    /// Stops the extraction with [`ExtractError::Cancelled`] when `v` become `true`. The flag is
+    /// checked before each item and while copying the data. The items that was extracted are
+    /// left as-is but the file that was being extracted will be removed.
+    pub fn cancel(mut self, v: &'a AtomicBool) -> Self {
+        self.cancel = Some(v);
+        self
+    }
+}
+
+/// What to do when an item cannot be extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop the extraction and return the error.
+    #[default]
+    Abort,
+    /// Skip the item and continue with the next one. The skipped items will be returned when the
+    /// extraction is completed. The whole directory will be skipped if it cannot be created.
+    Skip,
+}
+
+/// Extracts the items into `dest`, which will be created if it does not exist.
+pub(crate) fn extract<P, T, I>(
+    items: I,
+    dest: &Path,
+    options: ExtractOptions,
+) -> Result<Vec<ExtractError>, ExtractError>
+where
+    P: DiskPartition,
+    T: Borrow<Item<P>>,
+    I: IntoIterator<Item = Result<T, OpenError>>,
+{
+    let mut extractor = Extractor {
+        options,
+        skipped: Vec::new(),
+    };
+
+    if let Err(e) = std::fs::create_dir_all(dest) {
+        return Err(ExtractError::CreateDirectoryFailed(dest.to_owned(), e));
+    }
+
+    match extractor.items(items, "", dest) {
+        Err(ExtractError::Cancelled(_)) => return Err(ExtractError::Cancelled(extractor.skipped)),
+        Err(e) if extractor.options.on_error == ErrorPolicy::Skip => extractor.skipped.push(e),
+        Err(e) => return Err(e),
+        Ok(_) => {}
+    }
+
+    Ok(extractor.skipped)
+}
+
+/// Keeps the state of an extraction.
+struct Extractor<'a> {
+    options: ExtractOptions<'a>,
+    skipped: Vec<ExtractError>,
+}
+
+impl Extractor<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.options
+            .cancel
+            .is_some_and(|v| v.load(Ordering::Relaxed))
+    }
+
+    fn items<P, T, I>(&mut self, items: I, path: &str, dest: &Path) -> Result<(), ExtractError>
+    where
+        P: DiskPartition,
+        T: Borrow<Item<P>>,
+        I: IntoIterator<Item = Result<T, OpenError>>,
+    {
+        for item in items {
+            let item = match item {
+                Ok(v) => v,
+                Err(e) => {
+                    // The remaining items cannot be read so skip the directory as a whole.
+                    let path = if path.is_empty() { "/" } else { path };
+                    return Err(ExtractError::ReadDirectoryFailed(path.into(), e));
+                }
+            };
+
+            if self.is_cancelled() {
+                return Err(ExtractError::Cancelled(Vec::new()));
+            }
+
+            self.item(item.borrow(), path, dest)?;
+        }
+
+        Ok(())
+    }
+
+    fn item<P: DiskPartition>(
+        &mut self,
+        item: &Item<P>,
+        parent: &str,
+        dest: &Path,
+    ) -> Result<(), ExtractError> {
+        // The name come from the volume so it may be crafted to escape from the destination.
+        let name = item.name();
+        let path = format!("{parent}/{name}");
+        let result = if !is_safe_name(name) {
+            Err(ExtractError::UnsafeName(path.clone()))
+        } else {
+            let dest = dest.join(name);
+
+            match item {
+                Item::Directory(d) => self.directory(d, &path, &dest),
+                Item::File(f) => self.file(f, &path, &dest),
+            }
+        };
+
+        match result {
+            Err(ExtractError::Cancelled(v)) => Err(ExtractError::Cancelled(v)),
+            Err(e) if self.options.on_error == ErrorPolicy::Skip => {
+                self.skipped.push(e);
+                Ok(())
+            }
+            v => v,
+        }
+    }
+
+    fn directory<P: DiskPartition>(
+        &mut self,
+        dir: &Directory<P>,
+        path: &str,
+        dest: &Path,
+    ) -> Result<(), ExtractError> {
+        if let Err(e) = std::fs::create_dir(dest) {
+            return Err(ExtractError::CreateDirectoryFailed(dest.to_owned(), e));
+        }
+
+        // Extract the items.
+        match dir.items() {
+            Ok(v) => self.items(v, path, dest)?,
+            Err(e) => return Err(ExtractError::ReadDirectoryFailed(path.into(), e)),
+        }
+
+        // Set the timestamps after the items was created since it will update the modification
+        // time of the directory.
+        let file = match open_dir(dest) {
+            Ok(v) => v,
+            Err(e) => return Err(ExtractError::SetMetadataFailed(dest.to_owned(), e)),
+        };
+
+        if let Err(e) = file.set_times(file_times(dir.timestamps())) {
+            return Err(ExtractError::SetMetadataFailed(dest.to_owned(), e));
+        }
+
+        Ok(())
+    }
+
+    fn file<P: DiskPartition>(
+        &mut self,
+        file: &File<P>,
+        path: &str,
+        dest: &Path,
+    ) -> Result<(), ExtractError> {
+        // Copy the data. The file is closed at the end of the block when cancelled so it can be
+        // removed.
+        let copied = {
+            let mut out = match std::fs::File::create(dest) {
+                Ok(v) => v,
+                Err(e) => return Err(ExtractError::CreateFileFailed(dest.to_owned(), e)),
+            };
+
+            let mut buf = vec![0; 65536];
+            let mut offset = 0;
+
+            loop {
+                if self.is_cancelled() {
+                    break None;
+                }
+
+                let read = match file.read_at(offset, &mut buf) {
+                    Ok(0) => break Some((out, offset)),
+                    Ok(v) => v,
+                    Err(e) => return Err(ExtractError::ReadFileFailed(path.into(), e)),
+                };
+
+                if let Err(e) = out.write_all(&buf[..read]) {
+                    return Err(ExtractError::WriteFileFailed(dest.to_owned(), e));
+                }
+
+                offset += read as u64;
+            }
+        };
+
+        let (out, offset) = match copied {
+            Some(v) => v,
+            None => {
+                std::fs::remove_file(dest).ok();
+                return Err(ExtractError::Cancelled(Vec::new()));
+            }
+        };
+
+        // Set the metadata.
+        if let Err(e) = out.set_times(file_times(file.timestamps())) {
+            return Err(ExtractError::SetMetadataFailed(dest.to_owned(), e));
+        }
+
+        if file.attributes().is_read_only() {
+            let mut perms = match out.metadata() {
+                Ok(v) => v.permissions(),
+                Err(e) => return Err(ExtractError::SetMetadataFailed(dest.to_owned(), e)),
+            };
+
+            perms.set_readonly(true);
+
+            if let Err(e) = out.set_permissions(perms) {
+                return Err(ExtractError::SetMetadataFailed(dest.to_owned(), e));
+            }
+        }
+
+        if let Some(f) = &mut self.options.progress {
+            f(path, offset);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `true` if `name` is a single normal component on the local file system.
+fn is_safe_name(name: &str) -> bool {
+    if name.is_empty() || name.contains(['/', '\\', ':', '\0']) {
+        return false;
+    }
+
+    let mut components = Path::new(name).components();
+
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+fn file_times(v: &Timestamps) -> FileTimes {
+    FileTimes::new()
+        .set_accessed(v.accessed().to_system_time())
+        .set_modified(v.modified().to_system_time())
+}
+
+#[cfg(windows)]
+fn open_dir(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // FILE_WRITE_ATTRIBUTES with FILE_FLAG_BACKUP_SEMANTICS, which is required for a directory.
+    std::fs::OpenOptions::new()
+        .access_mode(0x100)
+        .custom_flags(0x02000000)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+fn open_dir(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::File::open(path)
+}
+
+/// Represents an error when extracting the items.
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("cannot create {}", .0.display())]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("cannot read directory {0}")]
+    ReadDirectoryFailed(String, #[source] OpenError),
+
+    #[error("cannot create {}", .0.display())]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("cannot read {0}")]
+    ReadFileFailed(String, #[source] std::io::Error),
+
+    #[error("cannot write {}", .0.display())]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("cannot set metadata of {}", .0.display())]
+    SetMetadataFailed(PathBuf, #[source] std::io::Error),
+
+    /// The name of the item cannot be used safely on the local file system (e.g. `..` or contains
+    /// a path separator).
+    #[error("{0} has an unsafe name")]
+    UnsafeName(String),
+
+    /// The extraction was cancelled by [`ExtractOptions::cancel()`]. Contains the items that was
+    /// skipped before the cancellation.
+    #[error("the extraction was cancelled")]
+    Cancelled(Vec<ExtractError>),
+}
@@ -1,34 +1,96 @@
 use crate::disk::DiskPartition;
 use crate::param::Params;
+use crate::prelude::*;
+use crate::trace::trace;
+use crate::Cluster;
+use alloc_crate::collections::BTreeMap;
 use byteorder::{ByteOrder, LE};
+use core::cell::RefCell;
+use core::cmp::{max, min};
 use core::fmt::Display;
+use core::ops::Range;
 
+/// Represents the active FAT of the volume.
+///
+/// All changes are made in memory and will be written to the partition when
+/// [`flush()`][Self::flush()] is called. On a volume with two FATs the changes will be written to
+/// the active FAT first then mirrored to the other one.
+///
+/// The entries can be either loaded entirely when the volume is opened, read on demand with a
+/// cached window of entries or read from the partition every time without caching.
 pub(crate) struct Fat {
-    entries: Vec<u32>,
+    offsets: Vec<u64>,
+    count: usize,
+    entries: Vec<u32>,               // Empty if the entries are read on demand.
+    window: Option<RefCell<Window>>, // None if the entries are loaded entirely.
+    changes: BTreeMap<usize, u32>,   // Unflushed changes when the entries are read on demand.
+    dirty: Option<Range<usize>>,
 }
 
 impl Fat {
+    /// The value of FAT entry to indicate the cluster is the last one in the chain.
+    pub const END_OF_CHAIN: u32 = 0xffffffff;
+
+    /// The value of FAT entry to indicate the cluster is bad.
+    pub const BAD_CLUSTER: u32 = 0xfffffff7;
+
+    /// Loads the FAT at `index`. The entries will be kept in memory according to `cache`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(index = index, clusters = params.cluster_count)
+        )
+    )]
     pub fn load<P: DiskPartition>(
         params: &Params,
         partition: &P,
         index: usize,
+        cache: FatCache,
     ) -> Result<Self, LoadError> {
-        // Get FAT region offset.
-        let sector = match params.fat_length.checked_mul(index as u64) {
-            Some(v) => match params.fat_offset.checked_add(v) {
-                Some(v) => v,
-                None => return Err(LoadError::InvalidFatOffset),
-            },
-            None => return Err(LoadError::InvalidFatLength),
-        };
+        // Get FAT region offsets. The active FAT always come first.
+        let mut offsets = vec![Self::offset(params, index)?];
 
-        let offset = match sector.checked_mul(params.bytes_per_sector) {
-            Some(v) => v,
-            None => return Err(LoadError::InvalidFatOffset),
+        for i in 0..(params.number_of_fats as usize) {
+            if i != index {
+                offsets.push(Self::offset(params, i)?);
+            }
+        }
+
+        // Check if the entries should be read on demand.
+        let count = params.cluster_count + 2;
+
+        let size = match cache {
+            FatCache::Full => None,
+            FatCache::Window(v) => {
+                // Make each read a multiple of the block size of the partition.
+                let align = max(partition.block_size() / 4, 1) as usize;
+
+                Some(v.div_ceil(align) * align)
+            }
+            FatCache::Off => Some(0),
         };
 
+        if let Some(size) = size {
+            let window = Window {
+                start: 0,
+                size,
+                entries: Vec::new(),
+            };
+
+            return Ok(Self {
+                offsets,
+                count,
+                entries: Vec::new(),
+                window: Some(RefCell::new(window)),
+                changes: BTreeMap::new(),
+                dirty: None,
+            });
+        }
+
         // Load entries.
-        let count = params.cluster_count + 2;
+        let offset = offsets[0];
         let mut data = vec![0u8; count * 4];
 
         if let Err(e) = partition.read_exact(offset, &mut data) {
@@ -40,52 +102,402 @@ impl Fat {
 
         LE::read_u32_into(&data, &mut entries);
 
-        Ok(Self { entries })
+        Ok(Self {
+            offsets,
+            count,
+            entries,
+            window: None,
+            changes: BTreeMap::new(),
+            dirty: None,
+        })
+    }
+
+    /// Returns `true` if the entries are read on demand.
+    pub fn is_on_demand(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Returns the clusters that have a different FAT entry in `other`. Both FATs must be loaded
+    /// entirely.
+    pub fn diff(&self, other: &Self) -> Vec<Cluster> {
+        (0..=u32::MAX)
+            .map(Cluster)
+            .zip(self.entries.iter().zip(&other.entries))
+            .filter(|(_, (a, b))| a != b)
+            .map(|(c, _)| c)
+            .collect()
+    }
+
+    /// Gets the FAT entry of `cluster` as [`FatEntry`]. Returns [`None`] if `cluster` is not a
+    /// valid cluster in the Cluster Heap.
+    pub fn entry<P: DiskPartition>(
+        &self,
+        partition: &P,
+        cluster: Cluster,
+    ) -> Result<Option<FatEntry>, ReadError> {
+        if cluster.0 < 2 {
+            return Ok(None);
+        }
+
+        let entry = match self.get(partition, cluster)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let entry = match entry {
+            0 => FatEntry::Free,
+            Self::BAD_CLUSTER => FatEntry::Bad,
+            Self::END_OF_CHAIN => FatEntry::EndOfChain,
+            v if v >= 2 && (v as usize) < self.count => FatEntry::Next(Cluster(v)),
+            v => FatEntry::Invalid(v),
+        };
+
+        Ok(Some(entry))
+    }
+
+    /// Gets the FAT entry of `cluster`.
+    pub fn get<P: DiskPartition>(
+        &self,
+        partition: &P,
+        cluster: Cluster,
+    ) -> Result<Option<u32>, ReadError> {
+        let cluster = cluster.index();
+        let window = match &self.window {
+            Some(v) => v,
+            None => return Ok(self.entries.get(cluster).copied()),
+        };
+
+        if cluster >= self.count {
+            return Ok(None);
+        } else if let Some(&v) = self.changes.get(&cluster) {
+            return Ok(Some(v));
+        }
+
+        // Check if the entry is in the window.
+        let mut window = window.borrow_mut();
+
+        if let Some(v) = window.get(cluster) {
+            return Ok(Some(v));
+        } else if window.size == 0 {
+            // Read only the entry if the window is disabled.
+            let offset = self.offsets[0] + cluster as u64 * 4;
+            let mut data = [0u8; 4];
+
+            if let Err(e) = partition.read_exact(offset, &mut data) {
+                return Err(ReadError(offset, e));
+            }
+
+            return Ok(Some(LE::read_u32(&data)));
+        }
+
+        // Read the window that contains the entry.
+        let start = cluster - cluster % window.size;
+        let len = min(window.size, self.count - start);
+
+        trace!("loading FAT entries {start}..{}", start + len);
+
+        let offset = self.offsets[0] + start as u64 * 4;
+        let mut data = vec![0u8; len * 4];
+
+        if let Err(e) = partition.read_exact(offset, &mut data) {
+            return Err(ReadError(offset, e));
+        }
+
+        window.start = start;
+        window.entries.resize(len, 0);
+
+        LE::read_u32_into(&data, &mut window.entries);
+
+        Ok(window.get(cluster))
     }
 
-    pub fn get_cluster_chain(&self, first: usize) -> ClusterChain<'_> {
+    /// Returns an iterator over the cluster chain that start at `first`. The iterator will stop
+    /// when the chain contains a loop or the FAT cannot be read, which can be checked with
+    /// [`ClusterChain::finish()`].
+    pub fn get_cluster_chain<'a, P: DiskPartition>(
+        &'a self,
+        partition: &'a P,
+        first: Cluster,
+    ) -> ClusterChain<'a, P> {
         ClusterChain {
-            entries: &self.entries,
+            fat: self,
+            partition,
+            first,
             next: first,
+            tortoise: None,
+            power: 1,
+            steps: 0,
+            error: None,
+            bad: None,
+        }
+    }
+
+    /// Sets the FAT entry of `cluster` to `value`.
+    pub fn set(&mut self, cluster: Cluster, value: u32) -> Result<(), WriteError> {
+        if cluster.0 < 2 || cluster.index() >= self.count {
+            return Err(WriteError::InvalidCluster(cluster));
+        }
+
+        let cluster = cluster.index();
+
+        // Keep the change separately if the entries are read on demand.
+        if let Some(window) = &mut self.window {
+            let window = window.get_mut();
+
+            if let Some(i) = cluster.checked_sub(window.start) {
+                if let Some(v) = window.entries.get_mut(i) {
+                    *v = value;
+                }
+            }
+
+            self.changes.insert(cluster, value);
+
+            return Ok(());
         }
+
+        self.entries[cluster] = value;
+        self.dirty = Some(match self.dirty.take() {
+            Some(v) => min(v.start, cluster)..max(v.end, cluster + 1),
+            None => cluster..(cluster + 1),
+        });
+
+        Ok(())
+    }
+
+    /// Writes all changes to every FAT on the partition.
+    pub fn flush<P: DiskPartition>(&mut self, partition: &P) -> Result<(), WriteError> {
+        // Write the changes one by one if the entries are read on demand.
+        while let Some((&cluster, &value)) = self.changes.first_key_value() {
+            for &offset in &self.offsets {
+                let offset = offset + cluster as u64 * 4;
+
+                if let Err(e) = partition.write_exact(offset, &value.to_le_bytes()) {
+                    return Err(WriteError::WriteFailed(offset, e));
+                }
+            }
+
+            self.changes.remove(&cluster);
+        }
+
+        let dirty = match self.dirty.take() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        // Convert the entries to little endian.
+        let mut data = vec![0u8; dirty.len() * 4];
+
+        LE::write_u32_into(&self.entries[dirty.clone()], &mut data);
+
+        // Write the entries.
+        for &offset in &self.offsets {
+            let offset = offset + dirty.start as u64 * 4;
+
+            if let Err(e) = partition.write_exact(offset, &data) {
+                self.dirty = Some(dirty);
+                return Err(WriteError::WriteFailed(offset, e));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn offset(params: &Params, index: usize) -> Result<u64, LoadError> {
+        let sector = match params.fat_length.checked_mul(index as u64) {
+            Some(v) => match params.fat_offset.0.checked_add(v) {
+                Some(v) => v,
+                None => return Err(LoadError::InvalidFatOffset),
+            },
+            None => return Err(LoadError::InvalidFatLength),
+        };
+
+        match sector.checked_mul(params.bytes_per_sector) {
+            Some(v) => Ok(v),
+            None => Err(LoadError::InvalidFatOffset),
+        }
+    }
+}
+
+/// Specifies how to keep the FAT entries in memory.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FatCache {
+    /// Loads all entries when the FAT is loaded.
+    Full,
+
+    /// Reads the entries on demand with a cached window of the specified number of entries.
+    Window(usize),
+
+    /// Reads each entry from the partition every time it is needed.
+    Off,
+}
+
+/// Represents a FAT entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatEntry {
+    /// The cluster is not allocated.
+    Free,
+
+    /// The cluster is followed by the specified cluster.
+    Next(Cluster),
+
+    /// The cluster is bad.
+    Bad,
+
+    /// The cluster is the last one in the chain.
+    EndOfChain,
+
+    /// The entry has a value that is not valid.
+    Invalid(u32),
+}
+
+/// A window of the FAT entries that was read from the partition.
+struct Window {
+    start: usize,
+    size: usize,
+    entries: Vec<u32>,
+}
+
+impl Window {
+    fn get(&self, cluster: usize) -> Option<u32> {
+        self.entries.get(cluster.checked_sub(self.start)?).copied()
     }
 }
 
-pub(crate) struct ClusterChain<'fat> {
-    entries: &'fat [u32],
-    next: usize,
+/// An iterator over the cluster chain. The loop detection is using Brent's algorithm so some
+/// clusters in the loop may be yielded more than once before the loop is detected.
+pub(crate) struct ClusterChain<'a, P: DiskPartition> {
+    fat: &'a Fat,
+    partition: &'a P,
+    first: Cluster,
+    next: Cluster,
+    tortoise: Option<Cluster>,
+    power: usize,
+    steps: usize,
+    error: Option<ChainError>,
+    bad: Option<Cluster>,
 }
 
-impl<'fat> Iterator for ClusterChain<'fat> {
-    type Item = usize;
+impl<P: DiskPartition> ClusterChain<'_, P> {
+    /// Returns the error if the iteration was stopped because the chain contains a loop or the
+    /// FAT cannot be read.
+    pub fn finish(&mut self) -> Result<(), ChainError> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the cluster that was marked as bad if the iteration was stopped because of it.
+    pub fn bad_cluster(&self) -> Option<Cluster> {
+        self.bad
+    }
+
+    /// Collects the remaining clusters in the chain.
+    pub fn into_vec(mut self) -> Result<Vec<Cluster>, ChainError> {
+        let chain: Vec<Cluster> = self.by_ref().collect();
+
+        self.finish()?;
+
+        Ok(chain)
+    }
+}
+
+impl<P: DiskPartition> Iterator for ClusterChain<'_, P> {
+    type Item = Cluster;
 
     fn next(&mut self) -> Option<Self::Item> {
         // Check next entry.
-        let entries = self.entries;
         let next = self.next;
 
-        if next < 2 || next >= entries.len() || entries[next] == 0xfffffff7 {
+        if next.0 < 2 || self.error.is_some() {
             return None;
         }
 
+        let entry = match self.fat.get(self.partition, next) {
+            Ok(Some(v)) => v,
+            Ok(None) => return None,
+            Err(e) => {
+                self.error = Some(ChainError::ReadFailed(e));
+                return None;
+            }
+        };
+
+        if entry == Fat::BAD_CLUSTER {
+            self.bad = Some(next);
+            return None;
+        }
+
+        // Check for a loop.
+        if self.tortoise == Some(next) {
+            self.error = Some(ChainError::Loop(self.first));
+            return None;
+        }
+
+        self.steps += 1;
+
+        if self.steps == self.power {
+            self.tortoise = Some(next);
+            self.power *= 2;
+            self.steps = 0;
+        }
+
         // Move to next entry.
-        self.next = entries[next] as usize;
+        self.next = Cluster(entry);
 
         Some(next)
     }
 }
 
+/// Represents an error when reading a cluster chain.
+#[derive(Debug)]
+pub enum ChainError {
+    /// The chain that start at the cluster contains a loop.
+    Loop(Cluster),
+
+    ReadFailed(ReadError),
+}
+
+impl Display for ChainError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Loop(c) => write!(f, "cluster chain that start at {c} contains a loop"),
+            Self::ReadFailed(_) => f.write_str("cannot read the FAT"),
+        }
+    }
+}
+
+impl core::error::Error for ChainError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Loop(_) => None,
+            Self::ReadFailed(e) => Some(e),
+        }
+    }
+}
+
+/// Represents an error when reading the FAT entries on demand.
+#[derive(Debug)]
+pub struct ReadError(pub u64, pub Box<dyn core::error::Error + Send + Sync>);
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "cannot read the data at {:#018x}", self.0)
+    }
+}
+
+impl core::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(self.1.as_ref())
+    }
+}
+
 /// Represents an error for [`Fat::load()`].
 #[derive(Debug)]
 pub enum LoadError {
     InvalidFatLength,
     InvalidFatOffset,
 
-    #[cfg(not(feature = "std"))]
-    ReadFailed(u64, Box<dyn Display + Send + Sync>),
-
-    #[cfg(feature = "std")]
-    ReadFailed(u64, Box<dyn std::error::Error + Send + Sync>),
+    ReadFailed(u64, Box<dyn core::error::Error + Send + Sync>),
 }
 
 impl Display for LoadError {
@@ -98,12 +510,37 @@ impl Display for LoadError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for LoadError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+impl core::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
             Self::ReadFailed(_, e) => Some(e.as_ref()),
             _ => None,
         }
     }
 }
+
+/// Represents an error for [`Fat::set()`] and [`Fat::flush()`].
+#[derive(Debug)]
+pub enum WriteError {
+    InvalidCluster(Cluster),
+
+    WriteFailed(u64, Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidCluster(c) => write!(f, "cluster {c} is not valid"),
+            Self::WriteFailed(offset, _) => write!(f, "cannot write the data at {offset:#018x}"),
+        }
+    }
+}
+
+impl core::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::WriteFailed(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
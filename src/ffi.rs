@@ -0,0 +1,447 @@
+//! C API for reading exFAT images.
+//!
+//! Build a library with `cargo rustc --release --features ffi --crate-type staticlib` (or
+//! `cdylib`) and include `include/exfat.h`. Every function returns one of the `EXFAT_*` codes.
+//! The values of the codes will never be changed once released so it is safe to compare them
+//! from other languages.
+use crate::directory::{Item, Items};
+use crate::image::Image;
+use crate::timestamp::Timestamp;
+use crate::{OpenPathError, Root};
+use std::ffi::{c_char, c_int, CStr};
+use std::io::{Read, Seek, SeekFrom};
+
+/// The operation was successful.
+pub const EXFAT_OK: c_int = 0;
+
+/// There are no more items in the directory.
+pub const EXFAT_END: c_int = 1;
+
+/// A pointer is null or the path is not a valid UTF-8.
+pub const EXFAT_EINVAL: c_int = -1;
+
+/// The image cannot be opened.
+pub const EXFAT_EOPEN: c_int = -2;
+
+/// The image is not a valid exFAT volume.
+pub const EXFAT_EVOLUME: c_int = -3;
+
+/// The item does not exist.
+pub const EXFAT_ENOENT: c_int = -4;
+
+/// The item is not a directory.
+pub const EXFAT_ENOTDIR: c_int = -5;
+
+/// The item is a directory.
+pub const EXFAT_EISDIR: c_int = -6;
+
+/// The data cannot be read from the volume.
+pub const EXFAT_EIO: c_int = -7;
+
+type Partition = Image<std::fs::File>;
+
+/// An opened exFAT volume.
+pub struct Volume(Root<Partition>);
+
+/// An opened directory for [`exfat_readdir()`].
+pub struct Dir(DirItems);
+
+/// An opened file.
+pub struct File(crate::file::File<Partition>);
+
+enum DirItems {
+    Root(std::vec::IntoIter<Stat>),
+    Directory(Items<Partition>),
+}
+
+/// Metadata of a file or a directory.
+#[repr(C)]
+pub struct Stat {
+    /// The name encoded in UTF-8 and terminated with NUL.
+    pub name: [c_char; 766],
+    /// The raw FileAttributes.
+    pub attributes: u16,
+    /// Non-zero if the item is a directory.
+    pub is_directory: u8,
+    /// The size of the file or the directory entries, in bytes.
+    pub size: u64,
+    pub created: Time,
+    pub modified: Time,
+    pub accessed: Time,
+}
+
+/// A timestamp in the local time of the volume that write it.
+#[repr(C)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Non-zero if `utc_offset` is available.
+    pub has_utc_offset: u8,
+    pub millisecond: u16,
+    /// The offset from UTC in minutes.
+    pub utc_offset: i16,
+}
+
+/// Metadata of a volume.
+#[repr(C)]
+pub struct VolumeInfo {
+    /// The volume label encoded in UTF-8 and terminated with NUL. Empty if the volume has no label.
+    pub label: [c_char; 34],
+    pub cluster_size: u64,
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+}
+
+/// Opens the exFAT image at `path` as read-only.
+///
+/// # Safety
+/// `path` must be a NUL-terminated string and `out` must be valid for writing. The resulting
+/// volume must be closed with [`exfat_close()`].
+#[no_mangle]
+pub unsafe extern "C" fn exfat_open(path: *const c_char, out: *mut *mut Volume) -> c_int {
+    let path = match str_arg(path) {
+        Some(v) if !out.is_null() => v,
+        _ => return EXFAT_EINVAL,
+    };
+
+    let image = match std::fs::File::open(path).map(Image::open) {
+        Ok(Ok(v)) => v,
+        _ => return EXFAT_EOPEN,
+    };
+
+    let root = match Root::open(image) {
+        Ok(v) => v,
+        Err(_) => return EXFAT_EVOLUME,
+    };
+
+    *out = Box::into_raw(Box::new(Volume(root)));
+
+    EXFAT_OK
+}
+
+/// Closes the volume. The directories and files that was opened from it are still valid.
+///
+/// # Safety
+/// `vol` must be null or a volume returned by [`exfat_open()`] that is not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_close(vol: *mut Volume) {
+    if !vol.is_null() {
+        drop(Box::from_raw(vol));
+    }
+}
+
+/// Gets the metadata of the volume.
+///
+/// # Safety
+/// `vol` must be an opened volume and `out` must be valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_volume_info(vol: *const Volume, out: *mut VolumeInfo) -> c_int {
+    let (root, out) = match (vol.as_ref(), out.as_mut()) {
+        (Some(v), Some(o)) => (&v.0, o),
+        _ => return EXFAT_EINVAL,
+    };
+
+    copy_str(root.volume_label().unwrap_or(""), &mut out.label);
+
+    out.cluster_size = root.cluster_size();
+    out.total_bytes = root.total_bytes();
+    out.free_bytes = root.free_bytes();
+
+    EXFAT_OK
+}
+
+/// Gets the metadata of the item at `path`. Each component is separated by `/` and is
+/// case-insensitive.
+///
+/// # Safety
+/// `vol` must be an opened volume, `path` must be a NUL-terminated string and `out` must be valid
+/// for writing.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_stat(
+    vol: *const Volume,
+    path: *const c_char,
+    out: *mut Stat,
+) -> c_int {
+    let (root, path, out) = match (vol.as_ref(), str_arg(path), out.as_mut()) {
+        (Some(v), Some(p), Some(o)) => (&v.0, p, o),
+        _ => return EXFAT_EINVAL,
+    };
+
+    match root.open_path(path) {
+        Ok(v) => {
+            fill_stat(&v, out);
+            EXFAT_OK
+        }
+        Err(e) => path_error(&e),
+    }
+}
+
+/// Opens the directory at `path` for reading its items. An empty path or `/` is the root
+/// directory.
+///
+/// # Safety
+/// `vol` must be an opened volume, `path` must be a NUL-terminated string and `out` must be valid
+/// for writing. The resulting directory must be closed with [`exfat_closedir()`].
+#[no_mangle]
+pub unsafe extern "C" fn exfat_opendir(
+    vol: *const Volume,
+    path: *const c_char,
+    out: *mut *mut Dir,
+) -> c_int {
+    let (root, path) = match (vol.as_ref(), str_arg(path)) {
+        (Some(v), Some(p)) if !out.is_null() => (&v.0, p),
+        _ => return EXFAT_EINVAL,
+    };
+
+    let items = if path.split('/').all(|c| c.is_empty() || c == ".") {
+        let items: Vec<Stat> = root
+            .iter()
+            .map(|i| {
+                let mut stat = Stat::zeroed();
+                fill_stat(i, &mut stat);
+                stat
+            })
+            .collect();
+
+        DirItems::Root(items.into_iter())
+    } else {
+        let dir = match root.open_path(path) {
+            Ok(Item::Directory(v)) => v,
+            Ok(Item::File(_)) => return EXFAT_ENOTDIR,
+            Err(e) => return path_error(&e),
+        };
+
+        match dir.items() {
+            Ok(v) => DirItems::Directory(v),
+            Err(_) => return EXFAT_EIO,
+        }
+    };
+
+    *out = Box::into_raw(Box::new(Dir(items)));
+
+    EXFAT_OK
+}
+
+/// Reads the next item in the directory. Returns [`EXFAT_END`] if there are no more items.
+///
+/// # Safety
+/// `dir` must be an opened directory and `out` must be valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_readdir(dir: *mut Dir, out: *mut Stat) -> c_int {
+    let (dir, out) = match (dir.as_mut(), out.as_mut()) {
+        (Some(d), Some(o)) => (d, o),
+        _ => return EXFAT_EINVAL,
+    };
+
+    match &mut dir.0 {
+        DirItems::Root(items) => match items.next() {
+            Some(v) => *out = v,
+            None => return EXFAT_END,
+        },
+        DirItems::Directory(items) => match items.next() {
+            Some(Ok(v)) => fill_stat(&v, out),
+            Some(Err(_)) => return EXFAT_EIO,
+            None => return EXFAT_END,
+        },
+    }
+
+    EXFAT_OK
+}
+
+/// Closes the directory.
+///
+/// # Safety
+/// `dir` must be null or a directory returned by [`exfat_opendir()`] that is not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_closedir(dir: *mut Dir) {
+    if !dir.is_null() {
+        drop(Box::from_raw(dir));
+    }
+}
+
+/// Opens the file at `path` for reading.
+///
+/// # Safety
+/// `vol` must be an opened volume, `path` must be a NUL-terminated string and `out` must be valid
+/// for writing. The resulting file must be closed with [`exfat_file_close()`].
+#[no_mangle]
+pub unsafe extern "C" fn exfat_file_open(
+    vol: *const Volume,
+    path: *const c_char,
+    out: *mut *mut File,
+) -> c_int {
+    let (root, path) = match (vol.as_ref(), str_arg(path)) {
+        (Some(v), Some(p)) if !out.is_null() => (&v.0, p),
+        _ => return EXFAT_EINVAL,
+    };
+
+    match root.open_path(path) {
+        Ok(Item::File(v)) => *out = Box::into_raw(Box::new(File(v))),
+        Ok(Item::Directory(_)) => return EXFAT_EISDIR,
+        Err(e) => return path_error(&e),
+    }
+
+    EXFAT_OK
+}
+
+/// Reads up to `len` bytes from the current position of the file into `buf`. The number of bytes
+/// read will be written to `read`, which is zero at the end of file.
+///
+/// # Safety
+/// `file` must be an opened file, `buf` must be valid for writing `len` bytes and `read` must be
+/// valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_file_read(
+    file: *mut File,
+    buf: *mut u8,
+    len: usize,
+    read: *mut usize,
+) -> c_int {
+    let (file, read) = match (file.as_mut(), read.as_mut()) {
+        (Some(f), Some(r)) if !buf.is_null() => (f, r),
+        _ => return EXFAT_EINVAL,
+    };
+
+    match file.0.read(std::slice::from_raw_parts_mut(buf, len)) {
+        Ok(v) => *read = v,
+        Err(_) => return EXFAT_EIO,
+    }
+
+    EXFAT_OK
+}
+
+/// Moves the position of the file to `offset` relative to `whence`, which is one of `SEEK_SET`,
+/// `SEEK_CUR` and `SEEK_END`. The new position will be written to `pos` if it is not null.
+///
+/// # Safety
+/// `file` must be an opened file and `pos` must be null or valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_file_seek(
+    file: *mut File,
+    offset: i64,
+    whence: c_int,
+    pos: *mut u64,
+) -> c_int {
+    let file = match file.as_mut() {
+        Some(v) => v,
+        None => return EXFAT_EINVAL,
+    };
+
+    let target = match whence {
+        0 => match u64::try_from(offset) {
+            Ok(v) => SeekFrom::Start(v),
+            Err(_) => return EXFAT_EINVAL,
+        },
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => return EXFAT_EINVAL,
+    };
+
+    let new = match file.0.seek(target) {
+        Ok(v) => v,
+        Err(_) => return EXFAT_EINVAL,
+    };
+
+    if let Some(pos) = pos.as_mut() {
+        *pos = new;
+    }
+
+    EXFAT_OK
+}
+
+/// Closes the file.
+///
+/// # Safety
+/// `file` must be null or a file returned by [`exfat_file_open()`] that is not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn exfat_file_close(file: *mut File) {
+    if !file.is_null() {
+        drop(Box::from_raw(file));
+    }
+}
+
+/// Returns a static NUL-terminated description of `code`.
+#[no_mangle]
+pub extern "C" fn exfat_strerror(code: c_int) -> *const c_char {
+    let msg: &'static CStr = match code {
+        EXFAT_OK => c"success",
+        EXFAT_END => c"no more items",
+        EXFAT_EINVAL => c"invalid argument",
+        EXFAT_EOPEN => c"cannot open the image",
+        EXFAT_EVOLUME => c"not a valid exFAT volume",
+        EXFAT_ENOENT => c"no such file or directory",
+        EXFAT_ENOTDIR => c"not a directory",
+        EXFAT_EISDIR => c"is a directory",
+        EXFAT_EIO => c"cannot read the volume",
+        _ => c"unknown error",
+    };
+
+    msg.as_ptr()
+}
+
+impl Stat {
+    fn zeroed() -> Self {
+        // SAFETY: All fields are integers.
+        unsafe { std::mem::zeroed() }
+    }
+}
+
+impl From<Timestamp> for Time {
+    fn from(v: Timestamp) -> Self {
+        Self {
+            year: v.year(),
+            month: v.month(),
+            day: v.day(),
+            hour: v.hour(),
+            minute: v.minute(),
+            second: v.second(),
+            has_utc_offset: v.utc_offset().is_some().into(),
+            millisecond: v.millisecond(),
+            utc_offset: v.utc_offset().unwrap_or(0),
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(v: *const c_char) -> Option<&'a str> {
+    if v.is_null() {
+        None
+    } else {
+        CStr::from_ptr(v).to_str().ok()
+    }
+}
+
+fn fill_stat(item: &Item<Partition>, out: &mut Stat) {
+    let attrs = item.attributes();
+    let times = item.timestamps();
+
+    copy_str(item.name(), &mut out.name);
+
+    out.attributes = attrs.0;
+    out.is_directory = attrs.is_directory().into();
+    out.size = item.len();
+    out.created = times.created().into();
+    out.modified = times.modified().into();
+    out.accessed = times.accessed().into();
+}
+
+/// Copies `src` into `dst` as a NUL-terminated string. `dst` must be large enough.
+fn copy_str(src: &str, dst: &mut [c_char]) {
+    for (d, &s) in dst.iter_mut().zip(src.as_bytes()) {
+        *d = s as c_char;
+    }
+
+    dst[src.len()] = 0;
+}
+
+fn path_error(e: &OpenPathError) -> c_int {
+    match e {
+        OpenPathError::InvalidPath => EXFAT_EINVAL,
+        OpenPathError::NotFound(_) => EXFAT_ENOENT,
+        OpenPathError::NotADirectory(_) => EXFAT_ENOTDIR,
+        _ => EXFAT_EIO,
+    }
+}
@@ -1,34 +1,39 @@
-use crate::cluster::ClustersReader;
+use crate::cluster::{Clusters, ClustersReader};
 use crate::disk::DiskPartition;
-use crate::entries::StreamEntry;
-use crate::ExFat;
-use std::io::{empty, Empty};
-use std::io::{IoSliceMut, Read, Seek, SeekFrom};
-use std::sync::Arc;
+use crate::entries::{ClusterAllocation, EntrySet, FileEntry, StreamEntry, VendorEntry};
+use crate::io::{empty, Empty, Error, ErrorKind, Write};
+use crate::io::{Read, Seek, SeekFrom};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::timestamp::{Timestamp, Timestamps};
+use crate::{Cluster, ExFat, FileAttributes, Handle};
+use core::cmp::{max, min};
+#[cfg(feature = "std")]
+use std::io::IoSliceMut;
 use thiserror::Error;
 
 /// Represents a file in the exFAT.
 pub struct File<P: DiskPartition> {
-    name: String,
-    len: u64,
+    exfat: Arc<ExFat<P>>,
+    entry: FileEntry,
     reader: Reader<P>, // FIXME: Use trait object once https://github.com/rust-lang/rfcs/issues/2035 is resolved.
+    dirty: bool,
+    detached: bool,              // true if the file does not have a directory entry.
+    handle: Option<Arc<Handle>>, // None if the volume is read-only.
 }
 
 impl<P: DiskPartition> File<P> {
-    pub(crate) fn new(
-        exfat: Arc<ExFat<P>>,
-        name: String,
-        stream: StreamEntry,
-    ) -> Result<Self, NewError> {
+    pub(crate) fn new(exfat: Arc<ExFat<P>>, entry: FileEntry) -> Result<Self, NewError> {
         // Create a cluster reader.
+        let stream = &entry.stream;
         let alloc = stream.allocation();
         let first_cluster = alloc.first_cluster();
-        let len = stream.valid_data_length();
-        let reader = if first_cluster == 0 {
+        let len = alloc.data_length();
+        let reader = if first_cluster.0 == 0 {
             Reader::Empty(empty())
         } else {
-            let reader = match ClustersReader::new(
-                exfat,
+            let mut reader = match ClustersReader::new(
+                exfat.clone(),
                 first_cluster,
                 Some(len),
                 Some(stream.no_fat_chain()),
@@ -37,41 +42,344 @@ impl<P: DiskPartition> File<P> {
                 Err(e) => return Err(NewError::CreateClustersReaderFailed(first_cluster, len, e)),
             };
 
+            reader.set_valid_data_length(stream.valid_data_length());
+
             Reader::Cluster(reader)
         };
 
-        Ok(Self { name, len, reader })
+        // Track the file so it will not write to its entries after it was moved or removed.
+        let handle = match entry.entries.offsets().first() {
+            Some(&v) if exfat.writable => Some(exfat.open_handle(v)),
+            _ => None,
+        };
+
+        Ok(Self {
+            exfat,
+            entry,
+            reader,
+            dirty: false,
+            detached: false,
+            handle,
+        })
+    }
+
+    /// Creates a read-only [`File`] that does not have a directory entry.
+    pub(crate) fn detached(
+        exfat: Arc<ExFat<P>>,
+        first_cluster: Cluster,
+        data_length: u64,
+        no_fat_chain: bool,
+    ) -> Result<Self, NewError> {
+        if first_cluster.0 == 0 && data_length != 0 {
+            let e = crate::cluster::NewError::InvalidFirstCluster;
+            return Err(NewError::CreateClustersReaderFailed(
+                first_cluster,
+                data_length,
+                e,
+            ));
+        }
+
+        let alloc = ClusterAllocation::new(first_cluster, data_length);
+        let epoch = Timestamp::new(1980, 1, 1, 0, 0, 0).unwrap();
+        let entry = FileEntry {
+            name: String::new(),
+            attributes: FileAttributes(0),
+            timestamps: Timestamps::new(epoch, epoch, epoch),
+            stream: StreamEntry::new(0, no_fat_chain, data_length, alloc),
+            vendor_entries: Vec::new(),
+            entries: EntrySet::new(),
+        };
+
+        let mut file = Self::new(exfat, entry)?;
+        file.detached = true;
+        Ok(file)
+    }
+
+    /// Returns the offset of the entry set on the partition or zero if the file is detached.
+    #[cfg(all(feature = "fuse", any(target_os = "linux", target_os = "macos")))]
+    pub(crate) fn offset(&self) -> u64 {
+        self.entry.entries.offsets().first().copied().unwrap_or(0)
+    }
+
+    pub(crate) fn exfat(&self) -> &Arc<ExFat<P>> {
+        &self.exfat
     }
 
     pub fn name(&self) -> &str {
-        self.name.as_ref()
+        self.entry.name.as_ref()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.len() == 0
     }
 
+    /// Returns the length of the file, which is DataLength. The data after ValidDataLength will be
+    /// read as zeros.
     pub fn len(&self) -> u64 {
-        self.len
+        self.entry.stream.allocation().data_length()
+    }
+
+    /// Returns how the clusters of the file are laid out on the volume.
+    pub fn fragmentation(&self) -> Fragmentation {
+        let empty = Clusters::new();
+        let clusters = match &self.reader {
+            Reader::Cluster(r) => r.clusters(),
+            Reader::Empty(_) => &empty,
+        };
+
+        // Split the clusters into contiguous runs.
+        let mut extents = 0;
+        let mut largest = 0;
+        let mut run = 0;
+
+        let mut last: Option<Cluster> = None;
+
+        for c in clusters.iter() {
+            if last.is_none_or(|v| v.0 + 1 != c.0) {
+                extents += 1;
+                run = 0;
+            }
+
+            run += 1;
+            largest = max(largest, run);
+            last = Some(c);
+        }
+
+        Fragmentation {
+            extents,
+            largest_extent: largest * self.exfat.params.cluster_size(),
+        }
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.entry.attributes
+    }
+
+    /// Returns the number of bytes that was actually written to the file.
+    pub fn valid_data_length(&self) -> u64 {
+        self.entry.stream.valid_data_length()
+    }
+
+    /// Returns the first cluster of the file or [`None`] if no cluster is allocated.
+    pub fn first_cluster(&self) -> Option<Cluster> {
+        match self.entry.stream.allocation().first_cluster() {
+            Cluster(0) => None,
+            v => Some(v),
+        }
+    }
+
+    /// Returns `true` if the clusters of the file are contiguous and the FAT is not used.
+    pub fn no_fat_chain(&self) -> bool {
+        self.entry.stream.no_fat_chain()
+    }
+
+    /// Returns the benign secondary entries (e.g. Vendor Extension and Vendor Allocation) of the
+    /// file.
+    pub fn vendor_entries(&self) -> &[VendorEntry] {
+        &self.entry.vendor_entries
+    }
+
+    /// Reads the data at `offset` without changing the current position. This does not require a
+    /// mutable reference so multiple threads can read the same file at the same time. Returns the
+    /// number of bytes read, which can be less than `buf` even if the end of file has not been
+    /// reached.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> crate::io::Result<usize> {
+        match &self.reader {
+            Reader::Cluster(r) => r.read_at(offset, buf),
+            Reader::Empty(_) => Ok(0),
+        }
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        &self.entry.timestamps
+    }
+
+    /// Sets the timestamps of the file and writes it to the directory entry.
+    pub fn set_times(&mut self, timestamps: Timestamps) -> crate::io::Result<()> {
+        self.check_writable()?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(Error::other(e));
+        }
+
+        self.entry.timestamps = timestamps;
+        self.dirty = true;
+        self.flush()
+    }
+
+    /// Sets the attributes of the file and writes it to the directory entry. The directory flag
+    /// of `attributes` will be ignored.
+    pub fn set_attributes(&mut self, mut attributes: FileAttributes) -> crate::io::Result<()> {
+        self.check_writable()?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(Error::other(e));
+        }
+
+        attributes.0 &= !0x0010;
+
+        self.entry.attributes = attributes;
+        self.dirty = true;
+        self.flush()
+    }
+
+    /// Truncates or extends the file to `len` bytes and writes it to the directory entry. The
+    /// extended region will be read as zeros without writing it since only DataLength is raised.
+    /// The current position will be moved to the end of file if it is beyond `len`.
+    pub fn set_len(&mut self, len: u64) -> crate::io::Result<()> {
+        self.check_writable()?;
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(Error::other(e));
+        }
+
+        let current = self.len();
+
+        if len > current {
+            // Allocate the clusters without writing them. The region after ValidDataLength will be
+            // read as zeros.
+            let exfat = self.exfat.clone();
+            let reader = self.reader.cluster(&exfat);
+            let stream = &mut self.entry.stream;
+            let mut no_fat_chain = stream.no_fat_chain() || reader.clusters().is_empty();
+            let count = len.div_ceil(exfat.params.cluster_size()) as usize;
+            let count = count.saturating_sub(reader.clusters().len());
+
+            if let Err(e) = exfat.append_clusters(reader.clusters_mut(), &mut no_fat_chain, count) {
+                return Err(Error::other(e));
+            }
+
+            // Update the stream extension.
+            let valid_data_length = stream.valid_data_length();
+            let alloc = ClusterAllocation::new(reader.clusters().first().unwrap(), len);
+
+            stream.set_allocation(no_fat_chain, valid_data_length, alloc);
+            reader.set_data_length(len);
+            reader.set_valid_data_length(valid_data_length);
+            self.dirty = true;
+        } else if len < current {
+            // Release the clusters that is not used.
+            let exfat = self.exfat.clone();
+            let no_fat_chain = self.entry.stream.no_fat_chain();
+            let count = len.div_ceil(exfat.params.cluster_size()) as usize;
+            let reader = self.reader.cluster(&exfat);
+
+            if let Err(e) = exfat.truncate_chain(reader.clusters_mut(), count, no_fat_chain) {
+                return Err(Error::other(e));
+            }
+
+            // Update the stream extension.
+            let alloc = match reader.clusters().first() {
+                Some(c) => ClusterAllocation::new(c, len),
+                None => ClusterAllocation::new(Cluster(0), 0),
+            };
+
+            let valid_data_length = min(self.entry.stream.valid_data_length(), len);
+
+            reader.set_data_length(len);
+
+            self.entry
+                .stream
+                .set_allocation(no_fat_chain && count != 0, valid_data_length, alloc);
+            self.dirty = true;
+        }
+
+        // Write the new allocation immediately so removing the file will release the clusters.
+        self.flush()
+    }
+
+    /// Fails with [`ErrorKind::PermissionDenied`] if the file cannot be written or
+    /// [`ErrorKind::NotFound`] if it was removed.
+    fn check_writable(&self) -> crate::io::Result<()> {
+        if !self.exfat.writable || self.detached {
+            Err(Error::from(ErrorKind::PermissionDenied))
+        } else if self.is_removed() {
+            Err(Error::from(ErrorKind::NotFound))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_removed(&self) -> bool {
+        self.handle.as_ref().is_some_and(|v| v.is_removed())
+    }
+
+    /// Picks up the new entries if this file was renamed or moved.
+    fn update_entry(&mut self) {
+        if let Some(v) = &self.handle {
+            v.update(&mut self.entry);
+        }
+    }
+
+    /// Writes `buf` to `clusters` at `pos`. The clusters must already be allocated.
+    fn write_clusters(
+        exfat: &ExFat<P>,
+        clusters: &Clusters,
+        pos: u64,
+        buf: &[u8],
+    ) -> crate::io::Result<()> {
+        let params = &exfat.params;
+        let cluster_size = params.cluster_size();
+        let mut written = 0;
+
+        while written < buf.len() {
+            let pos = pos + written as u64;
+            let cluster = clusters.get((pos / cluster_size) as usize).unwrap();
+            let amount = min(
+                buf.len() - written,
+                (cluster_size - pos % cluster_size) as usize,
+            );
+            let offset = match params.cluster_offset(cluster) {
+                Some(v) => v + pos % cluster_size,
+                None => return Err(Error::other(format!("cluster {cluster} is not available"))),
+            };
+
+            if let Err(e) = exfat
+                .partition
+                .write_exact(offset, &buf[written..(written + amount)])
+            {
+                return Err(Error::other(e));
+            }
+
+            written += amount;
+        }
+
+        Ok(())
+    }
+}
+
+impl<P: DiskPartition> Drop for File<P> {
+    fn drop(&mut self) {
+        // There is nothing we can do if the flush failed.
+        self.flush().ok();
+
+        self.update_entry();
+
+        if let Some(handle) = &self.handle {
+            self.exfat
+                .close_handle(self.entry.entries.offsets()[0], handle);
+        }
     }
 }
 
+/// Seeking does not read any data so it can be used to access any part of the file randomly. The
+/// position will be moved to the end of file if the target is beyond it.
 impl<P: DiskPartition> Seek for File<P> {
-    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+    fn seek(&mut self, pos: SeekFrom) -> crate::io::Result<u64> {
         match &mut self.reader {
             Reader::Cluster(r) => r.seek(pos),
             Reader::Empty(r) => r.seek(pos),
         }
     }
 
-    fn rewind(&mut self) -> std::io::Result<()> {
+    fn rewind(&mut self) -> crate::io::Result<()> {
         match &mut self.reader {
             Reader::Cluster(r) => r.rewind(),
             Reader::Empty(r) => r.rewind(),
         }
     }
 
-    fn stream_position(&mut self) -> std::io::Result<u64> {
+    fn stream_position(&mut self) -> crate::io::Result<u64> {
         match &mut self.reader {
             Reader::Cluster(r) => r.stream_position(),
             Reader::Empty(r) => r.stream_position(),
@@ -80,35 +388,36 @@ impl<P: DiskPartition> Seek for File<P> {
 }
 
 impl<P: DiskPartition> Read for File<P> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
         match &mut self.reader {
             Reader::Cluster(r) => r.read(buf),
             Reader::Empty(r) => r.read(buf),
         }
     }
 
-    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+    #[cfg(feature = "std")]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> crate::io::Result<usize> {
         match &mut self.reader {
             Reader::Cluster(r) => r.read_vectored(bufs),
             Reader::Empty(r) => r.read_vectored(bufs),
         }
     }
 
-    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> std::io::Result<usize> {
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> crate::io::Result<usize> {
         match &mut self.reader {
             Reader::Cluster(r) => r.read_to_end(buf),
             Reader::Empty(r) => r.read_to_end(buf),
         }
     }
 
-    fn read_to_string(&mut self, buf: &mut String) -> std::io::Result<usize> {
+    fn read_to_string(&mut self, buf: &mut String) -> crate::io::Result<usize> {
         match &mut self.reader {
             Reader::Cluster(r) => r.read_to_string(buf),
             Reader::Empty(r) => r.read_to_string(buf),
         }
     }
 
-    fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> crate::io::Result<()> {
         match &mut self.reader {
             Reader::Cluster(r) => r.read_exact(buf),
             Reader::Empty(r) => r.read_exact(buf),
@@ -116,15 +425,158 @@ impl<P: DiskPartition> Read for File<P> {
     }
 }
 
+impl<P: DiskPartition> Write for File<P> {
+    /// Writes `buf` at the current position. The file will be extended if required. Writing to a
+    /// file on a read-only volume will fail with [`ErrorKind::PermissionDenied`] and writing to a
+    /// file that was removed will fail with [`ErrorKind::NotFound`].
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        self.check_writable()?;
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(Error::other(e));
+        }
+
+        // Get a cluster reader.
+        let exfat = self.exfat.clone();
+        let reader = self.reader.cluster(&exfat);
+
+        // Allocate clusters.
+        let params = &exfat.params;
+        let cluster_size = params.cluster_size();
+        let offset = reader.stream_position()?;
+        let end = match offset.checked_add(buf.len() as u64) {
+            Some(v) => v,
+            None => return Err(Error::from(ErrorKind::InvalidInput)),
+        };
+
+        let stream = &mut self.entry.stream;
+        let mut no_fat_chain = stream.no_fat_chain() || reader.clusters().is_empty();
+
+        let count = end.div_ceil(cluster_size) as usize;
+        let count = count.saturating_sub(reader.clusters().len());
+
+        if let Err(e) = exfat.append_clusters(reader.clusters_mut(), &mut no_fat_chain, count) {
+            return Err(Error::other(e));
+        }
+
+        // Fill the region between ValidDataLength and the current position with zeros since it is
+        // undefined on the disk.
+        let mut pos = stream.valid_data_length();
+
+        if pos < offset {
+            let zeroes = vec![0u8; min(offset - pos, cluster_size) as usize];
+
+            while pos < offset {
+                let amount = min(offset - pos, zeroes.len() as u64) as usize;
+
+                Self::write_clusters(&exfat, reader.clusters(), pos, &zeroes[..amount])?;
+                pos += amount as u64;
+            }
+        }
+
+        // Write the data.
+        Self::write_clusters(&exfat, reader.clusters(), offset, buf)?;
+
+        // Update the stream extension.
+        let valid_data_length = max(stream.valid_data_length(), end);
+        let data_length = max(stream.allocation().data_length(), end);
+        let alloc = ClusterAllocation::new(reader.clusters().first().unwrap(), data_length);
+
+        stream.set_allocation(no_fat_chain, valid_data_length, alloc);
+        reader.set_data_length(data_length);
+        reader.set_valid_data_length(valid_data_length);
+        reader.seek(SeekFrom::Start(end))?;
+
+        self.dirty = true;
+
+        // Write the new allocation immediately so removing the file will release the clusters.
+        if count != 0 {
+            self.flush()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Writes the updated length of the file to its directory entry. Fails with
+    /// [`ErrorKind::NotFound`] if the file was removed while it has unwritten changes.
+    fn flush(&mut self) -> crate::io::Result<()> {
+        self.update_entry();
+
+        if self.dirty {
+            // Don't bring back the entries that was removed.
+            if self.is_removed() {
+                self.dirty = false;
+                return Err(Error::from(ErrorKind::NotFound));
+            }
+
+            if let Err(e) = self.entry.write(&self.exfat.partition) {
+                return Err(Error::other(e));
+            }
+
+            self.dirty = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Contains the result of [`File::fragmentation()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Fragmentation {
+    extents: usize,
+    largest_extent: u64,
+}
+
+impl Fragmentation {
+    /// Returns the number of contiguous runs of clusters. This is zero for an empty file.
+    pub fn extents(&self) -> usize {
+        self.extents
+    }
+
+    /// Returns the size of the largest extent, in bytes.
+    pub fn largest_extent(&self) -> u64 {
+        self.largest_extent
+    }
+
+    /// Returns `true` if all clusters of the file are contiguous.
+    pub fn is_contiguous(&self) -> bool {
+        self.extents <= 1
+    }
+}
+
 /// Encapsulate the either [`ClustersReader`] or [`Empty`].
 enum Reader<P: DiskPartition> {
     Cluster(ClustersReader<P>),
     Empty(Empty),
 }
 
+impl<P: DiskPartition> Reader<P> {
+    /// Gets the [`ClustersReader`]. The [`Empty`] will be replaced with an empty
+    /// [`ClustersReader`].
+    fn cluster(&mut self, exfat: &Arc<ExFat<P>>) -> &mut ClustersReader<P> {
+        if let Self::Empty(_) = self {
+            *self = Self::Cluster(ClustersReader::from_chain(
+                exfat.clone(),
+                Clusters::new(),
+                0,
+            ));
+        }
+
+        match self {
+            Self::Cluster(r) => r,
+            Self::Empty(_) => unreachable!(),
+        }
+    }
+}
+
 /// Represents an error for [`File::new()`].
 #[derive(Debug, Error)]
 pub enum NewError {
-    #[error("cannot create a clusters reader for allocation {0}:{1}")]
-    CreateClustersReaderFailed(usize, u64, #[source] crate::cluster::NewError),
+    #[error("cannot create a clusters reader for allocation {}:{}", .0.0, .1)]
+    CreateClustersReaderFailed(Cluster, u64, #[source] crate::cluster::NewError),
 }
@@ -0,0 +1,422 @@
+use crate::boot;
+use crate::disk::DiskPartition;
+use crate::entries::upcase;
+use crate::prelude::*;
+use byteorder::{ByteOrder, LE};
+use core::error::Error;
+use thiserror::Error;
+
+/// Formats `partition` as exFAT. `size` is the size of the partition, in bytes.
+///
+/// The resulting volume has a single FAT and can be opened with [`Root::open()`][crate::Root::open]
+/// immediately. Any existing data in the system areas of the partition will be overwritten. The
+/// partition is flushed with [`DiskPartition::flush()`] before returning.
+pub fn format<P: DiskPartition>(
+    partition: &P,
+    size: u64,
+    options: &FormatOptions,
+) -> Result<(), FormatError> {
+    if !partition.is_writable() {
+        return Err(FormatError::PartitionNotWritable);
+    }
+
+    let layout = Layout::new(size, options)?;
+
+    // Write FAT.
+    let mut fat = vec![0u32; layout.cluster_count as usize + 2];
+
+    fat[0] = 0xfffffff8;
+    fat[1] = 0xffffffff;
+
+    for (first, count) in [
+        (layout.bitmap_cluster, layout.bitmap_clusters),
+        (layout.upcase_cluster, layout.upcase_clusters),
+        (layout.root_cluster, 1),
+    ] {
+        let last = first + count - 1;
+
+        for c in first..last {
+            fat[c as usize] = c + 1;
+        }
+
+        fat[last as usize] = 0xffffffff;
+    }
+
+    let mut data = vec![0u8; (layout.fat_length * layout.bytes_per_sector) as usize];
+
+    LE::write_u32_into(&fat, &mut data[..(fat.len() * 4)]);
+    write(
+        partition,
+        layout.fat_offset * layout.bytes_per_sector,
+        &data,
+    )?;
+
+    // Write Allocation Bitmap.
+    let mut data = vec![0u8; (layout.bitmap_clusters as u64 * layout.cluster_size()) as usize];
+    let used = layout.bitmap_clusters + layout.upcase_clusters + 1;
+
+    for i in 0..(used as usize) {
+        data[i / 8] |= 1 << (i % 8);
+    }
+
+    write(
+        partition,
+        layout.cluster_offset(layout.bitmap_cluster),
+        &data,
+    )?;
+
+    // Write Up-case Table.
+    let table = upcase_table();
+    let mut data = vec![0u8; (layout.upcase_clusters as u64 * layout.cluster_size()) as usize];
+
+    data[..table.len()].copy_from_slice(&table);
+
+    write(
+        partition,
+        layout.cluster_offset(layout.upcase_cluster),
+        &data,
+    )?;
+
+    // Write root directory.
+    let mut data = vec![0u8; layout.cluster_size() as usize];
+    let (label, bitmap, table_entry) = {
+        let (label, rest) = data.split_at_mut(32);
+        let (bitmap, rest) = rest.split_at_mut(32);
+
+        (label, bitmap, &mut rest[..32])
+    };
+
+    // Volume Label.
+    match &options.volume_label {
+        Some(v) => {
+            label[0] = 0x83;
+            label[1] = v.len() as u8;
+            LE::write_u16_into(v, &mut label[2..(2 + v.len() * 2)]);
+        }
+        None => label[0] = 0x03,
+    }
+
+    // Allocation Bitmap.
+    bitmap[0] = 0x81;
+    LE::write_u32(&mut bitmap[20..], layout.bitmap_cluster);
+    LE::write_u64(&mut bitmap[24..], layout.cluster_count.div_ceil(8) as u64);
+
+    // Up-case Table.
+    table_entry[0] = 0x82;
+    LE::write_u32(&mut table_entry[4..], table_checksum(&table));
+    LE::write_u32(&mut table_entry[20..], layout.upcase_cluster);
+    LE::write_u64(&mut table_entry[24..], table.len() as u64);
+
+    write(partition, layout.cluster_offset(layout.root_cluster), &data)?;
+
+    // Write main and backup boot region. We write the boot regions last so the partition will
+    // not be recognized as exFAT if any of the above failed.
+    let percent = (used as u64 * 100 / layout.cluster_count as u64) as u8;
+    let region = layout.boot_region(options.volume_serial, percent);
+
+    write(partition, 12 * layout.bytes_per_sector, &region)?;
+    write(partition, 0, &region)?;
+
+    if let Err(e) = partition.flush() {
+        return Err(FormatError::FlushFailed(e));
+    }
+
+    Ok(())
+}
+
+/// Options for [`format()`].
+pub struct FormatOptions {
+    bytes_per_sector: u64,
+    cluster_size: Option<u64>,
+    volume_label: Option<Vec<u16>>,
+    volume_serial: u32,
+}
+
+impl FormatOptions {
+    /// Creates a new [`FormatOptions`] with 512 bytes per sector, a cluster size that depends on
+    /// the size of the volume, no volume label and a volume serial number derived from the
+    /// current time (zero without `std` or on `wasm32-unknown-unknown`, which has no clock).
+    pub fn new() -> Self {
+        Self {
+            bytes_per_sector: 512,
+            cluster_size: None,
+            volume_label: None,
+            volume_serial: Self::current_serial(),
+        }
+    }
+
+    /// Sets the size of the sector, in bytes. Must be a power of two between 512 and 4096.
+    pub fn bytes_per_sector(mut self, v: u64) -> Self {
+        self.bytes_per_sector = v;
+        self
+    }
+
+    /// Sets the size of the cluster, in bytes. Must be a power of two between the size of sector
+    /// and 32 MB.
+    pub fn cluster_size(mut self, v: u64) -> Self {
+        self.cluster_size = Some(v);
+        self
+    }
+
+    /// Sets the volume label. The label cannot be longer than 11 UTF-16 code units.
+    pub fn volume_label(mut self, v: &str) -> Self {
+        self.volume_label = Some(v.encode_utf16().collect());
+        self
+    }
+
+    /// Sets the volume serial number. Use this to produce the same image on every run.
+    pub fn volume_serial(mut self, v: u32) -> Self {
+        self.volume_serial = v;
+        self
+    }
+
+    #[cfg(all(
+        feature = "std",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    ))]
+    fn current_serial() -> u32 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(v) => (v.as_secs() as u32) ^ v.subsec_nanos(),
+            Err(_) => 0,
+        }
+    }
+
+    #[cfg(not(all(
+        feature = "std",
+        not(all(target_arch = "wasm32", target_os = "unknown"))
+    )))]
+    fn current_serial() -> u32 {
+        0
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Contains the location of each region on the volume. All offsets are in sector.
+struct Layout {
+    volume_length: u64,
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    fat_offset: u64,
+    fat_length: u64,
+    cluster_heap_offset: u64,
+    cluster_count: u32,
+    bitmap_cluster: u32,
+    bitmap_clusters: u32,
+    upcase_cluster: u32,
+    upcase_clusters: u32,
+    root_cluster: u32,
+}
+
+impl Layout {
+    fn new(size: u64, options: &FormatOptions) -> Result<Self, FormatError> {
+        // Check sector size.
+        let bytes_per_sector = options.bytes_per_sector;
+
+        if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector) {
+            return Err(FormatError::InvalidBytesPerSector);
+        }
+
+        // Check cluster size.
+        let cluster_size = options
+            .cluster_size
+            .unwrap_or(if size <= 256 * 1024 * 1024 {
+                4096
+            } else if size <= 32 * 1024 * 1024 * 1024 {
+                32768
+            } else {
+                131072
+            });
+
+        if !cluster_size.is_power_of_two()
+            || cluster_size < bytes_per_sector
+            || cluster_size > 32 * 1024 * 1024
+        {
+            return Err(FormatError::InvalidClusterSize);
+        }
+
+        // Check volume label.
+        if options.volume_label.as_ref().is_some_and(|v| v.len() > 11) {
+            return Err(FormatError::InvalidVolumeLabel);
+        }
+
+        // Calculate the size of FAT and the number of clusters. The FAT is depend on the number
+        // of clusters so we need to repeat until it is stable.
+        let volume_length = size / bytes_per_sector;
+        let sectors_per_cluster = cluster_size / bytes_per_sector;
+        let fat_offset = 24u64.next_multiple_of(sectors_per_cluster);
+        let mut cluster_count = volume_length.saturating_sub(fat_offset) / sectors_per_cluster;
+        let (fat_length, cluster_heap_offset) = loop {
+            let fat_length = ((cluster_count + 2) * 4).div_ceil(bytes_per_sector);
+            let heap = (fat_offset + fat_length).next_multiple_of(sectors_per_cluster);
+            let count = volume_length.saturating_sub(heap) / sectors_per_cluster;
+
+            if count >= cluster_count {
+                break (fat_length, heap);
+            }
+
+            cluster_count = count;
+        };
+
+        if cluster_count > 0xfffffff5 - 2 {
+            return Err(FormatError::TooLarge);
+        }
+
+        // Calculate the clusters for the Allocation Bitmap, the Up-case Table and the root.
+        let bitmap_clusters = cluster_count.div_ceil(8).div_ceil(cluster_size) as u32;
+        let upcase_clusters = (upcase_table().len() as u64).div_ceil(cluster_size) as u32;
+
+        if cluster_count < (bitmap_clusters + upcase_clusters + 1) as u64 {
+            return Err(FormatError::TooSmall);
+        }
+
+        Ok(Self {
+            volume_length,
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_offset,
+            fat_length,
+            cluster_heap_offset,
+            cluster_count: cluster_count as u32,
+            bitmap_cluster: 2,
+            bitmap_clusters,
+            upcase_cluster: 2 + bitmap_clusters,
+            upcase_clusters,
+            root_cluster: 2 + bitmap_clusters + upcase_clusters,
+        })
+    }
+
+    fn cluster_size(&self) -> u64 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        let sector = self.cluster_heap_offset + (cluster - 2) as u64 * self.sectors_per_cluster;
+        sector * self.bytes_per_sector
+    }
+
+    /// Builds the 12 sectors of the boot region.
+    fn boot_region(&self, volume_serial: u32, percent_in_use: u8) -> Vec<u8> {
+        let sector_size = self.bytes_per_sector as usize;
+        let mut region = vec![0u8; sector_size * 12];
+
+        // Main Boot Sector.
+        let boot = &mut region[..sector_size];
+
+        boot[..3].copy_from_slice(&[0xeb, 0x76, 0x90]);
+        boot[3..11].copy_from_slice(b"EXFAT   ");
+        LE::write_u64(&mut boot[72..], self.volume_length);
+        LE::write_u32(&mut boot[80..], self.fat_offset as u32);
+        LE::write_u32(&mut boot[84..], self.fat_length as u32);
+        LE::write_u32(&mut boot[88..], self.cluster_heap_offset as u32);
+        LE::write_u32(&mut boot[92..], self.cluster_count);
+        LE::write_u32(&mut boot[96..], self.root_cluster);
+        LE::write_u32(&mut boot[100..], volume_serial);
+        LE::write_u16(&mut boot[104..], 0x0100);
+        boot[108] = self.bytes_per_sector.trailing_zeros() as u8;
+        boot[109] = self.sectors_per_cluster.trailing_zeros() as u8;
+        boot[110] = 1;
+        boot[111] = 0x80;
+        boot[112] = percent_in_use;
+        boot[120..510].fill(0xf4);
+        boot[510] = 0x55;
+        boot[511] = 0xaa;
+
+        // Main Extended Boot Sectors.
+        for i in 1..9 {
+            let sector = &mut region[(i * sector_size)..((i + 1) * sector_size)];
+            LE::write_u32(&mut sector[(sector_size - 4)..], 0xaa550000);
+        }
+
+        // Main Boot Checksum.
+        let checksum = boot::checksum(&region[..(sector_size * 11)]);
+
+        for v in region[(sector_size * 11)..].chunks_exact_mut(4) {
+            LE::write_u32(v, checksum);
+        }
+
+        region
+    }
+}
+
+/// Generates a compressed Up-case Table.
+fn upcase_table() -> Vec<u8> {
+    let mut table: Vec<u16> = Vec::new();
+    let mut c = 0u32;
+
+    while c <= 0xffff {
+        // Compress identity mappings.
+        let start = c;
+
+        while c <= 0xffff && upcase(c as u16) == c as u16 {
+            c += 1;
+        }
+
+        let count = c - start;
+
+        if count > 2 || (count != 0 && c > 0xffff) {
+            table.push(0xffff);
+            table.push(count as u16);
+        } else {
+            table.extend((start..c).map(|v| v as u16));
+        }
+
+        if c <= 0xffff {
+            table.push(upcase(c as u16));
+            c += 1;
+        }
+    }
+
+    let mut data = vec![0u8; table.len() * 2];
+
+    LE::write_u16_into(&table, &mut data);
+
+    data
+}
+
+/// Computes the TableChecksum of the Up-case Table.
+fn table_checksum(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0u32, |c, &b| c.rotate_right(1).wrapping_add(b.into()))
+}
+
+fn write<P: DiskPartition>(partition: &P, offset: u64, data: &[u8]) -> Result<(), FormatError> {
+    match partition.write_exact(offset, data) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(FormatError::WriteFailed(offset, e)),
+    }
+}
+
+/// Represents an error for [`format()`].
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("the partition does not support writing")]
+    PartitionNotWritable,
+
+    #[error("invalid sector size")]
+    InvalidBytesPerSector,
+
+    #[error("invalid cluster size")]
+    InvalidClusterSize,
+
+    #[error("invalid volume label")]
+    InvalidVolumeLabel,
+
+    #[error("the partition is too small")]
+    TooSmall,
+
+    #[error("the partition is too large for the cluster size")]
+    TooLarge,
+
+    #[error("cannot write the data at {0:#018x}")]
+    WriteFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot flush the partition")]
+    FlushFailed(#[source] Box<dyn Error + Send + Sync>),
+}
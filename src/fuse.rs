@@ -0,0 +1,338 @@
+//! Read-only FUSE adapter for Linux and macOS.
+//!
+//! The volume is mounted with [`mount()`] or [`spawn_mount()`] and all items are owned by the
+//! user who access it since exFAT does not store the owner.
+use crate::directory::Item;
+use crate::disk::DiskPartition;
+use crate::Root;
+use fuser::{
+    BackgroundSession, Config, Errno, FileAttr, FileHandle, FileType, Filesystem, FopenFlags,
+    Generation, INodeNo, LockOwner, MountOption, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, Request,
+};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// The volume is read-only so the kernel can cache everything forever.
+const TTL: Duration = Duration::MAX;
+
+/// Mounts `root` on `mountpoint` as read-only and serves it until the volume is unmounted.
+pub fn mount<P, M>(root: Root<P>, mountpoint: M) -> std::io::Result<()>
+where
+    P: DiskPartition + Send + Sync + 'static,
+    M: AsRef<Path>,
+{
+    fuser::mount(Fuse::new(root), mountpoint, &config())
+}
+
+/// Same as [`mount()`] but serves the volume on a background thread. The volume will be unmounted
+/// when the returned [`BackgroundSession`] is dropped.
+pub fn spawn_mount<P, M>(root: Root<P>, mountpoint: M) -> std::io::Result<BackgroundSession>
+where
+    P: DiskPartition + Send + Sync + 'static,
+    M: AsRef<Path>,
+{
+    fuser::spawn_mount(Fuse::new(root), mountpoint, &config())
+}
+
+fn config() -> Config {
+    let mut config = Config::default();
+
+    config.mount_options.extend([
+        MountOption::RO,
+        MountOption::FSName("exfat".into()),
+        MountOption::Subtype("exfat".into()),
+        MountOption::DefaultPermissions,
+    ]);
+
+    config
+}
+
+/// An implementation of [`Filesystem`] on top of [`Root`].
+///
+/// The inode 1 is the root directory and the other inodes are allocated the first time the kernel
+/// look them up. Inodes are identified by the location of their entry set so the same item always
+/// get the same inode regardless of the case used to look it up. Inodes are never freed while the
+/// volume is mounted.
+///
+/// The listing of a directory is loaded when it is opened and kept until it is released. No lock
+/// is held while reading the volume.
+pub struct Fuse<P: DiskPartition> {
+    root: Root<P>,
+    nodes: Mutex<Nodes<P>>,
+    dirs: Mutex<Dirs>,
+}
+
+impl<P: DiskPartition> Fuse<P> {
+    pub fn new(root: Root<P>) -> Self {
+        Self {
+            root,
+            nodes: Mutex::new(Nodes {
+                items: Vec::new(),
+                inodes: HashMap::new(),
+            }),
+            dirs: Mutex::new(Dirs {
+                next: 1,
+                listings: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the parent and the item of `ino`.
+    fn node(&self, ino: INodeNo) -> Option<(INodeNo, Arc<Item<P>>)> {
+        self.nodes.lock().unwrap().entry(ino).cloned()
+    }
+
+    fn attr(&self, req: &Request, ino: INodeNo, item: Option<&Item<P>>) -> FileAttr {
+        let cluster_size = self.root.cluster_size();
+        let (kind, perm, size) = match item {
+            Some(Item::File(f)) => (FileType::RegularFile, 0o444, f.len()),
+            Some(Item::Directory(_)) | None => (FileType::Directory, 0o555, cluster_size),
+        };
+        let (created, modified, accessed) = match item {
+            Some(v) => {
+                let t = v.timestamps();
+                (
+                    t.created().to_system_time(),
+                    t.modified().to_system_time(),
+                    t.accessed().to_system_time(),
+                )
+            }
+            None => (UNIX_EPOCH, UNIX_EPOCH, UNIX_EPOCH),
+        };
+
+        FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: accessed,
+            mtime: modified,
+            ctime: modified,
+            crtime: created,
+            kind,
+            perm,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: req.uid(),
+            gid: req.gid(),
+            rdev: 0,
+            flags: 0,
+            blksize: cluster_size as u32,
+        }
+    }
+}
+
+impl<P: DiskPartition + Send + Sync + 'static> Filesystem for Fuse<P> {
+    fn lookup(&self, req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let name = match name.to_str() {
+            Some(v) => v,
+            None => return reply.error(Errno::ENOENT),
+        };
+
+        let found = if parent == INodeNo::ROOT {
+            self.root.find(name).map_err(|_| Errno::EIO)
+        } else {
+            match self.node(parent).as_ref().map(|v| v.1.as_ref()) {
+                Some(Item::Directory(d)) => d.find(name).map_err(|_| Errno::EIO),
+                Some(Item::File(_)) => Err(Errno::ENOTDIR),
+                None => Err(Errno::ENOENT),
+            }
+        };
+
+        match found {
+            Ok(Some(item)) => {
+                let (ino, item) = self.nodes.lock().unwrap().insert(parent, item);
+                let attr = self.attr(req, ino, Some(&item));
+
+                reply.entry(&TTL, &attr, Generation(0));
+            }
+            Ok(None) => reply.error(Errno::ENOENT),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn getattr(&self, req: &Request, ino: INodeNo, _: Option<FileHandle>, reply: ReplyAttr) {
+        if ino == INodeNo::ROOT {
+            reply.attr(&TTL, &self.attr(req, ino, None));
+        } else {
+            match self.node(ino) {
+                Some((_, v)) => reply.attr(&TTL, &self.attr(req, ino, Some(&v))),
+                None => reply.error(Errno::ENOENT),
+            }
+        }
+    }
+
+    fn open(&self, _: &Request, ino: INodeNo, _: OpenFlags, reply: ReplyOpen) {
+        match self.node(ino).as_ref().map(|v| v.1.as_ref()) {
+            Some(Item::File(_)) => reply.opened(FileHandle(0), FopenFlags::empty()),
+            Some(Item::Directory(_)) => reply.error(Errno::EISDIR),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn read(
+        &self,
+        _: &Request,
+        ino: INodeNo,
+        _: FileHandle,
+        offset: u64,
+        size: u32,
+        _: OpenFlags,
+        _: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let node = self.node(ino);
+        let file = match node.as_ref().map(|v| v.1.as_ref()) {
+            Some(Item::File(v)) => v,
+            Some(Item::Directory(_)) => return reply.error(Errno::EISDIR),
+            None => return reply.error(Errno::ENOENT),
+        };
+
+        // Read until the buffer is full or the end of file.
+        let remaining = file.len().saturating_sub(offset);
+        let mut buf = vec![0; size.min(u32::try_from(remaining).unwrap_or(u32::MAX)) as usize];
+        let mut read = 0;
+
+        while read < buf.len() {
+            match file.read_at(offset + read as u64, &mut buf[read..]) {
+                Ok(0) => break,
+                Ok(v) => read += v,
+                Err(_) => return reply.error(Errno::EIO),
+            }
+        }
+
+        reply.data(&buf[..read]);
+    }
+
+    fn opendir(&self, _: &Request, ino: INodeNo, _: OpenFlags, reply: ReplyOpen) {
+        let (parent, items) = if ino == INodeNo::ROOT {
+            // The items of the root are borrowed so load the owned ones in a single pass.
+            match crate::directory::load_items(&self.root.exfat, None) {
+                Ok(v) => (INodeNo::ROOT, v),
+                Err(_) => return reply.error(Errno::EIO),
+            }
+        } else {
+            match self.node(ino) {
+                Some((p, item)) => match item.as_ref() {
+                    Item::Directory(d) => match d.open() {
+                        Ok(v) => (p, v),
+                        Err(_) => return reply.error(Errno::EIO),
+                    },
+                    Item::File(_) => return reply.error(Errno::ENOTDIR),
+                },
+                None => return reply.error(Errno::ENOENT),
+            }
+        };
+
+        // Build the list of entries.
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_owned()),
+            (parent, FileType::Directory, "..".to_owned()),
+        ];
+        let mut nodes = self.nodes.lock().unwrap();
+
+        for item in items {
+            let kind = match item {
+                Item::Directory(_) => FileType::Directory,
+                Item::File(_) => FileType::RegularFile,
+            };
+            let name = item.name().to_owned();
+
+            entries.push((nodes.insert(ino, item).0, kind, name));
+        }
+
+        drop(nodes);
+
+        // Keep the listing until the directory is released.
+        let mut dirs = self.dirs.lock().unwrap();
+        let fh = dirs.next;
+
+        dirs.next += 1;
+        dirs.listings.insert(fh, Arc::new(entries));
+
+        reply.opened(FileHandle(fh), FopenFlags::empty());
+    }
+
+    fn readdir(
+        &self,
+        _: &Request,
+        _: INodeNo,
+        fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let entries = match self.dirs.lock().unwrap().listings.get(&fh.0) {
+            Some(v) => v.clone(),
+            None => return reply.error(Errno::EBADF),
+        };
+
+        for (i, (ino, kind, name)) in entries.iter().enumerate().skip(offset as usize) {
+            if reply.add(*ino, (i + 1) as u64, *kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+
+    fn releasedir(&self, _: &Request, _: INodeNo, fh: FileHandle, _: OpenFlags, reply: ReplyEmpty) {
+        self.dirs.lock().unwrap().listings.remove(&fh.0);
+        reply.ok();
+    }
+
+    fn statfs(&self, _: &Request, _: INodeNo, reply: ReplyStatfs) {
+        let cluster_size = self.root.cluster_size();
+
+        reply.statfs(
+            self.root.cluster_count() as u64,
+            self.root.free_clusters() as u64,
+            self.root.free_clusters() as u64,
+            0,
+            0,
+            cluster_size as u32,
+            255,
+            cluster_size as u32,
+        );
+    }
+}
+
+/// Allocated inodes other than the root.
+struct Nodes<P: DiskPartition> {
+    items: Vec<(INodeNo, Arc<Item<P>>)>,
+    inodes: HashMap<u64, INodeNo>, // Key is the offset of the entry set.
+}
+
+impl<P: DiskPartition> Nodes<P> {
+    fn entry(&self, ino: INodeNo) -> Option<&(INodeNo, Arc<Item<P>>)> {
+        let index = ino.0.checked_sub(2)?;
+
+        self.items.get(usize::try_from(index).ok()?)
+    }
+
+    /// Returns the inode of `item` in `parent`, allocating a new one if it was not looked up.
+    fn insert(&mut self, parent: INodeNo, item: Item<P>) -> (INodeNo, Arc<Item<P>>) {
+        let key = item.offset();
+
+        if let Some(&v) = self.inodes.get(&key) {
+            return (v, self.entry(v).unwrap().1.clone());
+        }
+
+        let ino = INodeNo(self.items.len() as u64 + 2);
+        let item = Arc::new(item);
+
+        self.items.push((parent, item.clone()));
+        self.inodes.insert(key, ino);
+
+        (ino, item)
+    }
+}
+
+/// Listings of the opened directories.
+struct Dirs {
+    next: u64,
+    listings: HashMap<u64, Arc<Vec<DirEntry>>>,
+}
+
+type DirEntry = (INodeNo, FileType, String);
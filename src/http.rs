@@ -0,0 +1,137 @@
+use crate::cache::BlockCache;
+use crate::disk::DiskPartition;
+use std::cmp::min;
+use std::error::Error;
+use std::io::Read;
+use thiserror::Error;
+use ureq::Agent;
+
+/// An implementation of [`DiskPartition`] that reads a remote exFAT image with HTTP `Range`
+/// requests.
+///
+/// The image is fetched in chunks and the recently used chunks are kept in memory so browsing the
+/// volume does not need a request for every read. The server must support range requests. This
+/// can be used to read the image on object storage without downloading the whole image.
+pub struct HttpPartition {
+    cache: BlockCache<Remote>,
+}
+
+impl HttpPartition {
+    /// Opens the image at `url`. Each request will fetch `chunk_size` bytes and up to
+    /// `cache_size` chunks will be kept in memory.
+    pub fn open(url: &str, chunk_size: u64, cache_size: usize) -> Result<Self, OpenError> {
+        if chunk_size == 0 {
+            return Err(OpenError::InvalidChunkSize);
+        }
+
+        // Get the size of the image.
+        let agent = Agent::new();
+        let resp = match agent.get(url).set("Range", "bytes=0-0").call() {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::RequestFailed(e.into())),
+        };
+
+        if resp.status() != 206 {
+            return Err(OpenError::RangeNotSupported);
+        }
+
+        let len = match resp
+            .header("Content-Range")
+            .and_then(|v| v.rsplit_once('/'))
+            .and_then(|v| v.1.trim().parse().ok())
+        {
+            Some(v) => v,
+            None => return Err(OpenError::UnknownLength),
+        };
+
+        // Set up the cache.
+        let remote = Remote {
+            agent,
+            url: url.to_owned(),
+            len,
+        };
+
+        Ok(Self {
+            cache: BlockCache::new(remote, 0, chunk_size, cache_size),
+        })
+    }
+}
+
+impl DiskPartition for HttpPartition {
+    fn len(&self) -> Option<u64> {
+        self.cache.len()
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        self.cache.read(offset, buf)
+    }
+}
+
+/// The remote image without caching.
+struct Remote {
+    agent: Agent,
+    url: String,
+    len: u64,
+}
+
+impl DiskPartition for Remote {
+    fn len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if offset >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Request the range.
+        let amount = min(buf.len() as u64, self.len - offset);
+        let range = format!("bytes={}-{}", offset, offset + amount - 1);
+        let resp = match self.agent.get(&self.url).set("Range", &range).call() {
+            Ok(v) => v,
+            Err(e) => return Err(ReadError::RequestFailed(e.into()).into()),
+        };
+
+        if resp.status() != 206 {
+            return Err(ReadError::UnexpectedStatus(resp.status()).into());
+        }
+
+        // Read the body.
+        let buf = &mut buf[..(amount as usize)];
+
+        if let Err(e) = resp.into_reader().read_exact(buf) {
+            return Err(ReadError::ReadBodyFailed(e).into());
+        }
+
+        Ok(amount)
+    }
+}
+
+/// Represents an error for [`HttpPartition::open()`].
+#[derive(Debug, Error)]
+pub enum OpenError {
+    #[error("chunk size cannot be zero")]
+    InvalidChunkSize,
+
+    #[error("cannot request the image")]
+    RequestFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("the server does not support range requests")]
+    RangeNotSupported,
+
+    #[error("cannot get the size of the image")]
+    UnknownLength,
+}
+
+/// Represents an error for [`HttpPartition::read()`].
+#[derive(Debug, Error)]
+enum ReadError {
+    #[error("cannot request the range")]
+    RequestFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("the server responded with status {0} instead of 206")]
+    UnexpectedStatus(u16),
+
+    #[error("cannot read the response body")]
+    ReadBodyFailed(#[source] std::io::Error),
+}
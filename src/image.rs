@@ -1,14 +1,23 @@
 use crate::disk::DiskPartition;
+use crate::format::{FormatError, FormatOptions};
+use crate::sparse::Sparse;
 use std::error::Error;
-use std::io::{Read, Seek, SeekFrom};
+use std::fs::File;
+use std::io::{IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::sync::Mutex;
 use thiserror::Error;
 
 /// An implementation of [`DiskPartition`] backed by an exFAT image.
 pub struct Image<F: Read + Seek> {
     file: Mutex<(F, u64)>,
+    write: Option<WriteFn<F>>,
+    flush: Option<FlushFn<F>>,
 }
 
+type WriteFn<F> = fn(&mut F, &[u8]) -> std::io::Result<usize>;
+type FlushFn<F> = fn(&mut F) -> std::io::Result<()>;
+
 impl<F: Read + Seek> Image<F> {
     pub fn open(mut file: F) -> Result<Self, OpenError> {
         let offset = match file.stream_position() {
@@ -18,11 +27,176 @@ impl<F: Read + Seek> Image<F> {
 
         Ok(Self {
             file: Mutex::new((file, offset)),
+            write: None,
+            flush: None,
         })
     }
+
+    /// Same as [`open()`][Self::open] but the resulting [`Image`] is writable.
+    /// [`DiskPartition::flush()`] calls [`Write::flush()`] of `file`.
+    pub fn open_rw(file: F) -> Result<Self, OpenError>
+    where
+        F: Write,
+    {
+        let mut image = Self::open(file)?;
+        image.write = Some(F::write);
+        image.flush = Some(F::flush);
+        Ok(image)
+    }
+}
+
+impl Image<File> {
+    /// Same as [`open_rw()`][Self::open_rw] but [`DiskPartition::flush()`] also calls
+    /// [`File::sync_data()`] so the data reach the storage device instead of the OS cache. Use
+    /// this instead of [`open_rw()`][Self::open_rw] when the image is a file on the local file
+    /// system since [`Write::flush()`] does nothing for [`File`].
+    pub fn open_file_rw(file: File) -> Result<Self, OpenError> {
+        let mut image = Self::open_rw(file)?;
+        image.flush = Some(|f| {
+            f.flush()?;
+            f.sync_data()
+        });
+        Ok(image)
+    }
+}
+
+/// A builder to create a new image file.
+pub struct ImageBuilder {
+    size: u64,
+    format: Option<FormatOptions>,
+    overwrite: bool,
+    sparse: bool,
+}
+
+impl ImageBuilder {
+    /// Creates a new [`ImageBuilder`] for an image of `size` bytes. The image will be filled with
+    /// zeroes, sparse and not formatted by default.
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            format: None,
+            overwrite: false,
+            sparse: true,
+        }
+    }
+
+    /// Formats the image as exFAT with `options` after it was created.
+    pub fn format(mut self, options: FormatOptions) -> Self {
+        self.format = Some(options);
+        self
+    }
+
+    /// Replaces the existing file instead of failing. The default is `false`.
+    pub fn overwrite(mut self, v: bool) -> Self {
+        self.overwrite = v;
+        self
+    }
+
+    /// Sets whether the image should be sparse. When `true` the file is extended with
+    /// [`File::set_len()`] and the blocks that contain only zeroes are never written so the image
+    /// takes only the space of the actual data on the file systems that support it. When `false`
+    /// the whole image is written with zeroes to allocate all of its space. The default is `true`.
+    pub fn sparse(mut self, v: bool) -> Self {
+        self.sparse = v;
+        self
+    }
+
+    /// Creates the image at `path` and opens it for writing.
+    pub fn create<T: AsRef<Path>>(&self, path: T) -> Result<Image<File>, CreateError> {
+        // Create the file.
+        let mut options = std::fs::OpenOptions::new();
+
+        options.read(true).write(true);
+
+        if self.overwrite {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+
+        let file = match options.open(path) {
+            Ok(v) => v,
+            Err(e) => return Err(CreateError::CreateFileFailed(e)),
+        };
+
+        if let Err(e) = file.set_len(self.size) {
+            return Err(CreateError::SetLenFailed(e));
+        }
+
+        let image = match Image::open_file_rw(file) {
+            Ok(v) => v,
+            Err(e) => return Err(CreateError::OpenFailed(e)),
+        };
+
+        // Allocate the space.
+        if !self.sparse {
+            let zeroes = vec![0u8; 1024 * 1024];
+            let mut offset = 0;
+
+            while offset < self.size {
+                let n = zeroes.len().min((self.size - offset) as usize);
+
+                if let Err(e) = image.write_exact(offset, &zeroes[..n]) {
+                    return Err(CreateError::AllocateFailed(e));
+                }
+
+                offset += n as u64;
+            }
+        }
+
+        // Format the image.
+        if let Some(options) = &self.format {
+            let result = if self.sparse {
+                crate::format::format(&Sparse::new(&image), self.size, options)
+            } else {
+                crate::format::format(&image, self.size, options)
+            };
+
+            // The image was flushed by format().
+            if let Err(e) = result {
+                return Err(CreateError::FormatFailed(e));
+            }
+        }
+
+        Ok(image)
+    }
 }
 
 impl<F: Read + Seek> DiskPartition for Image<F> {
+    fn len(&self) -> Option<u64> {
+        let mut file = self
+            .file
+            .lock()
+            .expect("the mutex that protect the inner file is poisoned");
+
+        let end = file.0.seek(SeekFrom::End(0)).ok()?;
+
+        file.1 = end;
+
+        Some(end)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.write.is_some()
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let flush = match self.flush {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let mut file = self
+            .file
+            .lock()
+            .expect("the mutex that protect the inner file is poisoned");
+
+        match flush(&mut file.0) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(WriteError::FlushFailed(e).into()),
+        }
+    }
+
     fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
         let mut file = self
             .file
@@ -54,6 +228,79 @@ impl<F: Read + Seek> DiskPartition for Image<F> {
 
         Ok(read)
     }
+
+    fn read_vectored_at(
+        &self,
+        offset: u64,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut file = self
+            .file
+            .lock()
+            .expect("the mutex that protect the inner file is poisoned");
+
+        // Seek the file.
+        if offset != file.1 {
+            match file.0.seek(SeekFrom::Start(offset)) {
+                Ok(v) => {
+                    // The specified offset is out of range.
+                    if v != offset {
+                        return Ok(0);
+                    }
+                }
+                Err(e) => return Err(ReadError::SeekFailed(e).into()),
+            }
+
+            file.1 = offset;
+        }
+
+        // Read the file.
+        let read = match file.0.read_vectored(bufs) {
+            Ok(v) => v.try_into().unwrap(),
+            Err(e) => return Err(ReadError::ReadFailed(e).into()),
+        };
+
+        file.1 += read;
+
+        Ok(read)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let write = match self.write {
+            Some(v) => v,
+            None => return Err(WriteError::ReadOnly.into()),
+        };
+
+        let mut file = self
+            .file
+            .lock()
+            .expect("the mutex that protect the inner file is poisoned");
+
+        // Seek the file.
+        if offset != file.1 {
+            match file.0.seek(SeekFrom::Start(offset)) {
+                Ok(v) => {
+                    // The specified offset is out of range.
+                    if v != offset {
+                        return Ok(0);
+                    }
+                }
+                Err(e) => return Err(WriteError::SeekFailed(e).into()),
+            }
+
+            file.1 = offset;
+        }
+
+        // Write the file.
+        let written = match write(&mut file.0, buf) {
+            Ok(v) => v.try_into().unwrap(),
+            Err(e) => return Err(WriteError::WriteFailed(e).into()),
+        };
+
+        file.1 += written;
+
+        Ok(written)
+    }
 }
 
 /// Represents an error for [`Image::open()`].
@@ -63,6 +310,25 @@ pub enum OpenError {
     GetStreamPositionFailed(#[source] std::io::Error),
 }
 
+/// Represents an error for [`ImageBuilder::create()`].
+#[derive(Debug, Error)]
+pub enum CreateError {
+    #[error("cannot create the image file")]
+    CreateFileFailed(#[source] std::io::Error),
+
+    #[error("cannot set the size of the image file")]
+    SetLenFailed(#[source] std::io::Error),
+
+    #[error("cannot open the image")]
+    OpenFailed(#[source] OpenError),
+
+    #[error("cannot allocate the space for the image")]
+    AllocateFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot format the image")]
+    FormatFailed(#[source] FormatError),
+}
+
 /// Represents an error for [`Image::read()`].
 #[derive(Debug, Error)]
 enum ReadError {
@@ -72,3 +338,19 @@ enum ReadError {
     #[error("cannot read the image")]
     ReadFailed(#[source] std::io::Error),
 }
+
+/// Represents an error for [`Image::write()`].
+#[derive(Debug, Error)]
+enum WriteError {
+    #[error("the image is not opened for writing")]
+    ReadOnly,
+
+    #[error("cannot seek the image to the target offset")]
+    SeekFailed(#[source] std::io::Error),
+
+    #[error("cannot write the image")]
+    WriteFailed(#[source] std::io::Error),
+
+    #[error("cannot flush the image")]
+    FlushFailed(#[source] std::io::Error),
+}
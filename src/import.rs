@@ -0,0 +1,147 @@
+//! Copying a directory tree from the local file system into the volume.
+use crate::directory::{CreateError, Directory, DirectoryWriter};
+use crate::disk::DiskPartition;
+use crate::timestamp::{Timestamp, Timestamps};
+use crate::Root;
+use std::fs::Metadata;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Imports the items in `src` into `parent`.
+pub(crate) fn import<P: DiskPartition>(
+    mut parent: Parent<P>,
+    path: &str,
+    src: &Path,
+) -> Result<(), ImportError> {
+    let entries = match std::fs::read_dir(src) {
+        Ok(v) => v,
+        Err(e) => return Err(ImportError::ReadDirectoryFailed(src.to_owned(), e)),
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(v) => v,
+            Err(e) => return Err(ImportError::ReadDirectoryFailed(src.to_owned(), e)),
+        };
+
+        // Follow the symbolic link to a file. The link to a directory is skipped since it may
+        // point to its parent, which would never end.
+        let host = entry.path();
+        let meta = match std::fs::symlink_metadata(&host) {
+            Ok(v) if v.is_symlink() => match std::fs::metadata(&host) {
+                Ok(v) if v.is_dir() => continue,
+                Ok(v) => v,
+                Err(e) => return Err(ImportError::GetMetadataFailed(host, e)),
+            },
+            Ok(v) => v,
+            Err(e) => return Err(ImportError::GetMetadataFailed(host, e)),
+        };
+
+        let name = match entry.file_name().into_string() {
+            Ok(v) => v,
+            Err(_) => return Err(ImportError::InvalidName(host)),
+        };
+
+        let path = format!("{path}/{name}");
+
+        if meta.is_dir() {
+            let mut dir = match parent.writer().create_dir(&name) {
+                Ok(v) => v,
+                Err(e) => return Err(ImportError::CreateFailed(path, e)),
+            };
+
+            import(Parent::Directory(&mut dir), &path, &host)?;
+
+            // Set the timestamps after the items was created.
+            if let Err(e) = dir.set_times(timestamps(&meta)) {
+                return Err(ImportError::SetDirectoryMetadataFailed(path, e));
+            }
+        } else if meta.is_file() {
+            let mut file = match parent.writer().create_file(&name) {
+                Ok(v) => v,
+                Err(e) => return Err(ImportError::CreateFailed(path, e)),
+            };
+
+            // Copy the data.
+            let mut src = match std::fs::File::open(&host) {
+                Ok(v) => v,
+                Err(e) => return Err(ImportError::OpenFileFailed(host, e)),
+            };
+
+            if let Err(e) = std::io::copy(&mut src, &mut file).and_then(|_| file.flush()) {
+                return Err(ImportError::CopyFailed(host, path, e));
+            }
+
+            // Set the metadata.
+            let mut attrs = file.attributes();
+
+            attrs.set_read_only(meta.permissions().readonly());
+
+            if let Err(e) = file
+                .set_times(timestamps(&meta))
+                .and_then(|_| file.set_attributes(attrs))
+            {
+                return Err(ImportError::SetFileMetadataFailed(path, e));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory to import into.
+pub(crate) enum Parent<'a, P: DiskPartition> {
+    Root(&'a mut Root<P>),
+    Directory(&'a mut Directory<P>),
+}
+
+impl<P: DiskPartition> Parent<'_, P> {
+    fn writer(&mut self) -> DirectoryWriter<'_, P> {
+        match self {
+            Self::Root(v) => v.writer(),
+            Self::Directory(v) => v.writer(),
+        }
+    }
+}
+
+/// The times that cannot be represented will be replaced with 1980-01-01 00:00:00.
+fn timestamps(meta: &Metadata) -> Timestamps {
+    let convert = |v: std::io::Result<_>| {
+        v.ok()
+            .and_then(Timestamp::from_system_time)
+            .unwrap_or_else(|| Timestamp::new(1980, 1, 1, 0, 0, 0).unwrap())
+    };
+    let modified = convert(meta.modified());
+    let created = meta.created().map_or(modified, |v| convert(Ok(v)));
+
+    Timestamps::new(created, modified, convert(meta.accessed()))
+}
+
+/// Represents an error when importing a directory tree.
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("cannot read {}", .0.display())]
+    ReadDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("cannot get metadata of {}", .0.display())]
+    GetMetadataFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("the name of {} is not a valid UTF-8", .0.display())]
+    InvalidName(PathBuf),
+
+    #[error("cannot create {0}")]
+    CreateFailed(String, #[source] CreateError),
+
+    #[error("cannot open {}", .0.display())]
+    OpenFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("cannot copy {} to {}", .0.display(), .1)]
+    CopyFailed(PathBuf, String, #[source] std::io::Error),
+
+    #[error("cannot set metadata of {0}")]
+    SetFileMetadataFailed(String, #[source] std::io::Error),
+
+    #[error("cannot set metadata of {0}")]
+    SetDirectoryMetadataFailed(String, #[source] std::io::Error),
+}
@@ -0,0 +1,214 @@
+//! I/O traits and types that are used by [`File`][crate::file::File].
+//!
+//! This is a re-export of [`std::io`] when `std` feature is enabled. Otherwise it contains a
+//! minimal subset of [`std::io`] that is enough for reading and writing a file without `std`.
+#[cfg(feature = "std")]
+pub use std::io::{empty, Empty, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::nostd::*;
+
+#[cfg(not(feature = "std"))]
+mod nostd {
+    use crate::prelude::*;
+    use core::fmt::{Debug, Display, Formatter};
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// Same as [`std::io::Error`].
+    pub struct Error {
+        kind: ErrorKind,
+        error: Option<Box<dyn core::error::Error + Send + Sync>>,
+    }
+
+    impl Error {
+        pub fn new<E>(kind: ErrorKind, error: E) -> Self
+        where
+            E: Into<Box<dyn core::error::Error + Send + Sync>>,
+        {
+            Self {
+                kind,
+                error: Some(error.into()),
+            }
+        }
+
+        pub fn other<E>(error: E) -> Self
+        where
+            E: Into<Box<dyn core::error::Error + Send + Sync>>,
+        {
+            Self::new(ErrorKind::Other, error)
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+
+        pub fn get_ref(&self) -> Option<&(dyn core::error::Error + Send + Sync + 'static)> {
+            self.error.as_deref()
+        }
+
+        pub fn into_inner(self) -> Option<Box<dyn core::error::Error + Send + Sync>> {
+            self.error
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self {
+            Self { kind, error: None }
+        }
+    }
+
+    impl Debug for Error {
+        fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+            match &self.error {
+                Some(e) => f
+                    .debug_struct("Error")
+                    .field("kind", &self.kind)
+                    .field("error", e)
+                    .finish(),
+                None => f.debug_tuple("Kind").field(&self.kind).finish(),
+            }
+        }
+    }
+
+    impl Display for Error {
+        fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+            match &self.error {
+                Some(e) => Display::fmt(e, f),
+                None => Display::fmt(&self.kind, f),
+            }
+        }
+    }
+
+    impl core::error::Error for Error {
+        fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+            self.error.as_ref().and_then(|e| e.source())
+        }
+    }
+
+    /// Same as [`std::io::ErrorKind`] but contains only the kinds that are used by this crate.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        InvalidInput,
+        NotFound,
+        PermissionDenied,
+        UnexpectedEof,
+        WriteZero,
+        Other,
+    }
+
+    impl Display for ErrorKind {
+        fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+            f.write_str(match self {
+                Self::InvalidInput => "invalid input parameter",
+                Self::NotFound => "entity not found",
+                Self::PermissionDenied => "permission denied",
+                Self::UnexpectedEof => "unexpected end of file",
+                Self::WriteZero => "write zero",
+                Self::Other => "other error",
+            })
+        }
+    }
+
+    /// Same as [`std::io::SeekFrom`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SeekFrom {
+        Start(u64),
+        End(i64),
+        Current(i64),
+    }
+
+    /// Same as [`std::io::Read`].
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => return Err(Error::from(ErrorKind::UnexpectedEof)),
+                    n => buf = &mut buf[n..],
+                }
+            }
+
+            Ok(())
+        }
+
+        fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+            let start = buf.len();
+            let mut chunk = [0u8; 4096];
+
+            loop {
+                match self.read(&mut chunk)? {
+                    0 => break Ok(buf.len() - start),
+                    n => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+        }
+
+        fn read_to_string(&mut self, buf: &mut String) -> Result<usize> {
+            let mut data = Vec::new();
+            let read = self.read_to_end(&mut data)?;
+
+            match core::str::from_utf8(&data) {
+                Ok(v) => buf.push_str(v),
+                Err(_) => return Err(Error::new(ErrorKind::InvalidInput, "invalid UTF-8")),
+            }
+
+            Ok(read)
+        }
+    }
+
+    /// Same as [`std::io::Seek`].
+    pub trait Seek {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+
+        fn rewind(&mut self) -> Result<()> {
+            self.seek(SeekFrom::Start(0))?;
+            Ok(())
+        }
+
+        fn stream_position(&mut self) -> Result<u64> {
+            self.seek(SeekFrom::Current(0))
+        }
+    }
+
+    /// Same as [`std::io::Write`].
+    pub trait Write {
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        fn flush(&mut self) -> Result<()>;
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(Error::from(ErrorKind::WriteZero)),
+                    n => buf = &buf[n..],
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Same as [`std::io::Empty`].
+    #[derive(Debug, Default)]
+    pub struct Empty;
+
+    impl Read for Empty {
+        fn read(&mut self, _: &mut [u8]) -> Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Seek for Empty {
+        fn seek(&mut self, _: SeekFrom) -> Result<u64> {
+            Ok(0)
+        }
+    }
+
+    /// Same as [`std::io::empty()`].
+    pub fn empty() -> Empty {
+        Empty
+    }
+}
@@ -1,35 +1,139 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use self::alloc::Allocator;
+use self::boot::BootRegion;
+use self::cache::{BlockCache, ClusterCache};
+use self::check::{CheckError, CheckReport, Checker};
+use self::clone::{CloneError, CloneOptions};
 use self::cluster::ClustersReader;
-use self::directory::{Directory, Item};
+use self::defrag::{DefragError, DefragReport};
+use self::directory::{
+    CreateError, Directory, DirectoryWriter, FindError, Item, RemoveError, RenameError,
+};
 use self::disk::DiskPartition;
-use self::entries::{ClusterAllocation, EntriesReader, EntryType, FileEntry};
-use self::fat::Fat;
+use self::entries::{
+    ClusterAllocation, EntriesReader, EntrySet, EntryType, FileEntry, RawEntries, RawEntry,
+};
+#[cfg(feature = "std")]
+use self::extract::{ExtractError, ExtractOptions};
+use self::fat::{ChainError, Fat, FatCache, FatEntry};
 use self::file::File;
+#[cfg(feature = "std")]
+use self::import::ImportError;
 use self::param::Params;
+use self::prelude::*;
+use self::repair::{Fix, RepairError};
+use self::sync::{Arc, Mutex, OnceLock};
+use self::trace::debug;
+use self::undelete::{CarveError, DeletedItem, DeletedItems, ScanError};
+use self::upcase::UpcaseTable;
+use self::usage::DiskUsage;
+use self::walk::Walk;
+use alloc_crate::collections::BTreeMap;
 use byteorder::{ByteOrder, LE};
-use std::error::Error;
-use std::sync::Arc;
+use core::error::Error;
+use core::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
 
+pub mod alloc;
+#[cfg(all(feature = "blkdev", target_os = "linux"))]
+pub mod blkdev;
+pub mod boot;
+pub mod cache;
+pub mod check;
+pub mod clone;
 pub mod cluster;
+pub mod defrag;
+pub mod diff;
 pub mod directory;
 pub mod disk;
 pub mod entries;
+#[cfg(feature = "std")]
+pub mod extract;
 pub mod fat;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod file;
+pub mod format;
+#[cfg(all(feature = "fuse", any(target_os = "linux", target_os = "macos")))]
+pub mod fuse;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "std")]
 pub mod image;
+#[cfg(feature = "std")]
+pub mod import;
+pub mod io;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "async")]
+pub mod nonblocking;
 pub mod param;
+pub mod partition_table;
+pub mod repair;
+pub mod retry;
+pub mod sparse;
+pub mod tar;
+pub mod timestamp;
+pub mod undelete;
+pub mod upcase;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring;
+pub mod usage;
+pub mod walk;
+#[cfg(all(feature = "winvol", windows))]
+pub mod winvol;
+
+#[cfg(any(
+    all(feature = "blkdev", target_os = "linux"),
+    all(feature = "winvol", windows)
+))]
+mod bounce;
+mod sync;
+mod trace;
+
+extern crate alloc as alloc_crate;
+
+/// Items from the `std` prelude that are not in the `core` prelude.
+mod prelude {
+    pub use alloc_crate::boxed::Box;
+    pub use alloc_crate::string::{String, ToString};
+    pub use alloc_crate::vec::Vec;
+    pub use alloc_crate::{format, vec};
+}
 
 /// Represents a root directory in exFAT.
 ///
 /// This implementation follows the official specs
 /// https://learn.microsoft.com/en-us/windows/win32/fileio/exfat-specification.
 pub struct Root<P: DiskPartition> {
+    exfat: Arc<ExFat<P>>,
+    boot: BootRegion,
     volume_label: Option<String>,
+    volume_guid: Option<[u8; 16]>,
     items: Vec<Item<P>>,
 }
 
 impl<P: DiskPartition> Root<P> {
+    /// Opens the exFAT on `partition` as read-only.
     pub fn open(partition: P) -> Result<Self, OpenError> {
+        Self::open_with(partition, &OpenOptions::new())
+    }
+
+    /// Opens the exFAT on `partition` as read-write. The partition must support writing.
+    pub fn open_rw(partition: P) -> Result<Self, OpenError> {
+        Self::open_with(partition, &OpenOptions::new().writable(true))
+    }
+
+    /// Opens the exFAT on `partition` with the specified options.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn open_with(partition: P, options: &OpenOptions) -> Result<Self, OpenError> {
+        let writable = options.writable;
+
+        if writable && !partition.is_writable() {
+            return Err(OpenError::PartitionNotWritable);
+        }
+
         // Read boot sector.
         let mut boot = [0u8; 512];
 
@@ -44,12 +148,14 @@ impl<P: DiskPartition> Root<P> {
 
         // Load fields.
         let params = Params {
-            fat_offset: LE::read_u32(&boot[80..]) as u64,
+            volume_length: LE::read_u64(&boot[72..]),
+            fat_offset: Sector(LE::read_u32(&boot[80..]).into()),
             fat_length: LE::read_u32(&boot[84..]) as u64,
-            cluster_heap_offset: LE::read_u32(&boot[88..]) as u64,
+            cluster_heap_offset: Sector(LE::read_u32(&boot[88..]).into()),
             cluster_count: LE::read_u32(&boot[92..]) as usize,
-            first_cluster_of_root_directory: LE::read_u32(&boot[96..]) as usize,
+            first_cluster_of_root_directory: Cluster(LE::read_u32(&boot[96..])),
             volume_flags: LE::read_u16(&boot[106..]).into(),
+            percent_in_use: Mutex::new(boot[112]),
             bytes_per_sector: {
                 let v = boot[108];
 
@@ -81,10 +187,81 @@ impl<P: DiskPartition> Root<P> {
             },
         };
 
+        // Verify the boot checksum.
+        let sector_size = params.bytes_per_sector as usize;
+        let mut region = vec![0u8; sector_size * 12];
+        let mut warnings: Vec<Warning> = Vec::new();
+
+        if let Err(e) = partition.read_exact(0, &mut region) {
+            return Err(OpenError::ReadMainBootFailed(e));
+        }
+
+        let checksum = boot::checksum(&region[..(sector_size * 11)]);
+
+        if !options.ignore_checksums
+            && !region[(sector_size * 11)..]
+                .chunks_exact(4)
+                .all(|v| LE::read_u32(v) == checksum)
+        {
+            if !options.lenient {
+                return Err(OpenError::BootChecksumMismatch);
+            }
+
+            warnings.push(Warning::BootChecksumMismatch);
+        }
+
+        // Load Extended Boot Sectors and OEM Parameters.
+        let boot = match BootRegion::load(&region, sector_size) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::LoadBootRegionFailed(e)),
+        };
+
+        // Check if the Cluster Heap fit within the partition.
+        if let Some(len) = partition.len() {
+            let heap = params.sectors_per_cluster * params.cluster_count as u64;
+            let end = (params.cluster_heap_offset.0 + heap) * params.bytes_per_sector;
+
+            if end > len {
+                if !options.lenient {
+                    return Err(OpenError::PartitionTooSmall(end, len));
+                }
+
+                warnings.push(Warning::PartitionTooSmall(end, len));
+            }
+        }
+
+        // Check VolumeDirty.
+        if params.volume_flags.volume_dirty() {
+            if options.reject_dirty {
+                return Err(OpenError::VolumeDirty);
+            }
+
+            warnings.push(Warning::VolumeDirty);
+        }
+
+        // Check MediaFailure.
+        if params.volume_flags.media_failure() {
+            match options.media_failure {
+                MediaFailurePolicy::Fail => return Err(OpenError::MediaFailure),
+                MediaFailurePolicy::Warn => warnings.push(Warning::MediaFailure),
+                MediaFailurePolicy::Ignore => {}
+            }
+        }
+
         // Read FAT region.
         let active_fat = params.volume_flags.active_fat();
         let fat = if active_fat == 0 || params.number_of_fats == 2 {
-            match Fat::load(&params, &partition, active_fat) {
+            let cache = if options.minimal_memory {
+                FatCache::Off
+            } else if options.fat_window != 0 {
+                FatCache::Window(options.fat_window)
+            } else if options.metadata_only {
+                FatCache::Off
+            } else {
+                FatCache::Full
+            };
+
+            match Fat::load(&params, &partition, active_fat, cache) {
                 Ok(v) => v,
                 Err(e) => return Err(OpenError::ReadFatRegionFailed(e)),
             }
@@ -92,12 +269,61 @@ impl<P: DiskPartition> Root<P> {
             return Err(OpenError::InvalidNumberOfFats);
         };
 
+        // Compare the active FAT with the other one.
+        if options.compare_fats && params.number_of_fats == 2 {
+            debug!("comparing FAT #{active_fat} with the other one");
+
+            let other = match Fat::load(&params, &partition, active_fat ^ 1, FatCache::Full) {
+                Ok(v) => v,
+                Err(e) => return Err(OpenError::ReadFatRegionFailed(e)),
+            };
+
+            let clusters = if fat.is_on_demand() {
+                match Fat::load(&params, &partition, active_fat, FatCache::Full) {
+                    Ok(v) => v.diff(&other),
+                    Err(e) => return Err(OpenError::ReadFatRegionFailed(e)),
+                }
+            } else {
+                fat.diff(&other)
+            };
+
+            if !clusters.is_empty() {
+                warnings.push(Warning::FatMismatch(clusters));
+            }
+        }
+
         // Create a entries reader for the root directory.
         let root_cluster = params.first_cluster_of_root_directory;
+        let heap = params.cluster_heap_offset.offset(params.bytes_per_sector);
+        let (cache_size, read_ahead) = if options.minimal_memory {
+            (0, 0)
+        } else {
+            (options.cache_size, options.read_ahead)
+        };
+
+        let block_size = params.cluster_size();
+        let partition = match &options.cluster_cache {
+            Some(v) => BlockCache::with_cache(partition, heap, block_size, Some(v.clone())),
+            None => BlockCache::new(partition, heap, block_size, cache_size),
+        };
         let exfat = Arc::new(ExFat {
             partition,
             params,
-            fat,
+            fat: Mutex::new(fat),
+            alloc: Mutex::new(Allocator::empty()),
+            upcase: OnceLock::new(),
+            writable,
+            lenient: options.lenient,
+            strict: options.strict,
+            skip_corrupt: options.skip_corrupt,
+            ignore_checksums: options.ignore_checksums,
+            bad_cluster: options.bad_cluster,
+            read_ahead,
+            read_granularity: options.read_granularity,
+            discard: options.discard,
+            dirty: Mutex::new(false),
+            warnings: Mutex::new(warnings),
+            handles: Mutex::new(BTreeMap::new()),
         });
 
         let mut reader = match ClustersReader::new(exfat.clone(), root_cluster, None, None) {
@@ -106,15 +332,20 @@ impl<P: DiskPartition> Root<P> {
         };
 
         // Load root directory.
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("root_directory", cluster = root_cluster.0).entered();
+
         let mut allocation_bitmaps: [Option<ClusterAllocation>; 2] = [None, None];
-        let mut upcase_table: Option<()> = None;
+        let mut upcase_table: Option<(ClusterAllocation, u32)> = None;
         let mut volume_label: Option<String> = None;
+        let mut volume_guid: Option<[u8; 16]> = None;
         let mut items: Vec<Item<P>> = Vec::new();
 
         loop {
             // Read primary entry.
             let entry = match reader.read() {
-                Ok(v) => v,
+                Ok(Some(v)) => v,
+                Ok(None) => break,
                 Err(e) => return Err(OpenError::ReadEntryFailed(e)),
             };
 
@@ -122,8 +353,17 @@ impl<P: DiskPartition> Root<P> {
             let ty = entry.ty();
 
             if !ty.is_regular() {
-                break;
+                if entry.data()[0] == 0 {
+                    break;
+                }
+
+                continue;
             } else if ty.type_category() != EntryType::PRIMARY {
+                // Skip the remaining of corrupted entry set.
+                if exfat.skip_corrupt {
+                    continue;
+                }
+
                 return Err(OpenError::NotPrimaryEntry(entry.index(), entry.cluster()));
             }
 
@@ -165,15 +405,19 @@ impl<P: DiskPartition> Root<P> {
                     }
 
                     // Load fields.
-                    if let Err(e) = ClusterAllocation::load(&entry) {
-                        return Err(OpenError::ReadClusterAllocationFailed(
-                            entry.index(),
-                            entry.cluster(),
-                            e,
-                        ));
-                    }
+                    let checksum = LE::read_u32(&entry.data()[4..]);
+                    let alloc = match ClusterAllocation::load(&entry) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            return Err(OpenError::ReadClusterAllocationFailed(
+                                entry.index(),
+                                entry.cluster(),
+                                e,
+                            ));
+                        }
+                    };
 
-                    upcase_table = Some(());
+                    upcase_table = Some((alloc, checksum));
                 }
                 (EntryType::CRITICAL, 3) => {
                     // Check if more than one volume label.
@@ -199,23 +443,49 @@ impl<P: DiskPartition> Root<P> {
 
                     volume_label = Some(String::from_utf16_lossy(label));
                 }
+                (EntryType::BENIGN, 0) => {
+                    // Check if more than one volume GUID.
+                    if volume_guid.is_some() {
+                        return Err(OpenError::MultipleVolumeGuid);
+                    }
+
+                    // Load fields.
+                    let data = entry.data();
+
+                    if data[1] != 0 {
+                        return Err(OpenError::InvalidVolumeGuid);
+                    }
+
+                    volume_guid = Some(data[6..22].try_into().unwrap());
+                }
+                (EntryType::BENIGN, 1) => {} // TexFAT Padding.
+                (EntryType::BENIGN, _) if !options.strict => {
+                    // Skip unknown benign primary entry and its secondary entries.
+                    if let Err(e) = reader.skip(entry.data()[1].into()) {
+                        return Err(OpenError::ReadEntryFailed(e));
+                    }
+                }
+                (EntryType::CRITICAL, 5) if options.metadata_only => {
+                    // Skip the file and its secondary entries.
+                    if let Err(e) = reader.skip(entry.data()[1].into()) {
+                        return Err(OpenError::ReadEntryFailed(e));
+                    }
+                }
                 (EntryType::CRITICAL, 5) => {
                     // Load the entry.
                     let file = match FileEntry::load(&entry, &mut reader) {
                         Ok(v) => v,
+                        Err(e) if exfat.skip_entry_set(&entry, &e) => continue,
                         Err(e) => return Err(OpenError::LoadFileEntryFailed(e)),
                     };
 
-                    let name = file.name;
-                    let attrs = file.attributes;
-                    let stream = file.stream;
-
                     // Add to the list.
-                    items.push(if attrs.is_directory() {
-                        Item::Directory(Directory::new(exfat.clone(), name, stream))
+                    items.push(if file.attributes.is_directory() {
+                        Item::Directory(Directory::new(exfat.clone(), file))
                     } else {
-                        match File::new(exfat.clone(), name, stream) {
+                        match File::new(exfat.clone(), file) {
                             Ok(v) => Item::File(v),
+                            Err(e) if exfat.skip_entry_set(&entry, &e) => continue,
                             Err(e) => {
                                 return Err(OpenError::CreateFileObjectFailed(
                                     entry.index(),
@@ -239,125 +509,1434 @@ impl<P: DiskPartition> Root<P> {
             return Err(OpenError::NoAllocationBitmap);
         }
 
-        // Check Up-case Table.
-        if upcase_table.is_none() {
-            return Err(OpenError::NoUpcaseTable);
-        }
+        // Load Allocation Bitmap for the active FAT. The changes will be mirrored to the other one
+        // like the FAT.
+        let bitmap = allocation_bitmaps[active_fat].as_ref().unwrap();
+        let mirror = allocation_bitmaps[active_fat ^ 1].as_ref();
+
+        debug!("root directory has {} items", items.len());
+
+        #[cfg(feature = "tracing")]
+        span.exit();
+
+        // Lock the Allocation Bitmap before the FAT like the other writers.
+        let mut alloc = exfat.alloc.lock().unwrap();
+        let fat = exfat.fat.lock().unwrap();
+
+        *alloc = match Allocator::load(&exfat.params, &exfat.partition, &fat, bitmap, mirror) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::LoadAllocationBitmapFailed(e)),
+        };
+
+        drop(alloc);
+
+        // Load Up-case Table.
+        let (alloc, checksum) = match upcase_table {
+            Some(v) => v,
+            None => return Err(OpenError::NoUpcaseTable),
+        };
+
+        let checksum = (!options.ignore_checksums).then_some(checksum);
+
+        let upcase = match UpcaseTable::load(
+            &exfat.params,
+            &exfat.partition,
+            &fat,
+            &alloc,
+            checksum,
+            options.minimal_memory,
+        ) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::LoadUpcaseTableFailed(e)),
+        };
+
+        drop(fat);
+
+        assert!(exfat.upcase.set(upcase).is_ok());
+        debug!("volume opened");
 
         Ok(Self {
+            exfat,
+            boot,
             volume_label,
+            volume_guid,
             items,
         })
     }
 
+    /// Returns `true` if VolumeDirty was set when the volume was opened, which means the volume
+    /// may be inconsistent.
+    pub fn volume_dirty(&self) -> bool {
+        self.exfat.params.volume_flags.volume_dirty()
+    }
+
+    /// Returns `true` if MediaFailure is set, which means the device has reported unrecoverable
+    /// sectors before.
+    pub fn media_failure(&self) -> bool {
+        self.exfat.params.volume_flags.media_failure()
+    }
+
+    /// Returns the Extended Boot Sectors and the OEM Parameters of the main boot region.
+    pub fn boot_region(&self) -> &BootRegion {
+        &self.boot
+    }
+
     pub fn volume_label(&self) -> Option<&str> {
         self.volume_label.as_deref()
     }
-}
 
-impl<P: DiskPartition> IntoIterator for Root<P> {
-    type Item = Item<P>;
-    type IntoIter = std::vec::IntoIter<Item<P>>;
+    /// Returns a snapshot of the volume metadata.
+    pub fn info(&self) -> VolumeInfo {
+        VolumeInfo {
+            label: self.volume_label.clone(),
+            guid: self.volume_guid,
+            cluster_size: self.cluster_size(),
+            cluster_count: self.cluster_count(),
+            free_clusters: self.free_clusters(),
+            percent_in_use: self.percent_in_use(),
+        }
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.items.into_iter()
+    /// Returns the I/O counters of the volume. The reads of the boot region and the FAT while
+    /// opening the volume are not counted.
+    pub fn io_stats(&self) -> IoStats {
+        self.exfat.partition.stats()
     }
-}
 
-/// Represents FileAttributes in the File Directory Entry.
-#[derive(Clone, Copy)]
-#[repr(transparent)]
-pub struct FileAttributes(u16);
+    /// Resets the counters of [`io_stats()`][Self::io_stats()] to zero.
+    pub fn reset_io_stats(&self) {
+        self.exfat.partition.reset_stats();
+    }
 
-impl FileAttributes {
-    pub fn is_read_only(self) -> bool {
-        (self.0 & 0x0001) != 0
+    /// Returns the problems that was tolerated because the volume was opened in lenient mode.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.exfat.warnings.lock().unwrap().clone()
     }
 
-    pub fn is_hidden(self) -> bool {
-        (self.0 & 0x0002) != 0
+    /// Returns the number of clusters in the Cluster Heap that are not allocated.
+    pub fn free_clusters(&self) -> usize {
+        self.exfat.alloc.lock().unwrap().free_count()
     }
 
-    pub fn is_system(self) -> bool {
-        (self.0 & 0x0004) != 0
+    /// Returns the number of bytes in the Cluster Heap that are not allocated.
+    pub fn free_bytes(&self) -> u64 {
+        self.free_clusters() as u64 * self.exfat.params.cluster_size()
     }
 
-    pub fn is_directory(self) -> bool {
-        (self.0 & 0x0010) != 0
+    /// Returns the size of the Cluster Heap, in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.exfat.params.cluster_count as u64 * self.exfat.params.cluster_size()
     }
 
-    pub fn is_archive(self) -> bool {
-        (self.0 & 0x0020) != 0
+    /// Returns PercentInUse of the volume or [`None`] if it is not available. This value may not
+    /// be accurate since some implementations does not update it. Use
+    /// [`update_percent_in_use()`][Self::update_percent_in_use()] to recompute it.
+    pub fn percent_in_use(&self) -> Option<u8> {
+        let v = *self.exfat.params.percent_in_use.lock().unwrap();
+
+        if v <= 100 {
+            Some(v)
+        } else {
+            None
+        }
     }
-}
 
-/// Contains objects for the opened exFAT.
-pub(crate) struct ExFat<P: DiskPartition> {
-    partition: P,
-    params: Params,
-    fat: Fat,
-}
+    /// Recomputes PercentInUse from the Allocation Bitmap and returns it. The new value is
+    /// written to both the main and the backup boot sector if the volume is writable, otherwise
+    /// [`percent_in_use()`][Self::percent_in_use()] will still return the old value. VolumeDirty
+    /// will be set like the other changes and cleared by [`sync_all()`][Self::sync_all()].
+    pub fn update_percent_in_use(&self) -> Result<u8, PercentInUseError> {
+        let exfat = &self.exfat;
+        let total = exfat.params.cluster_count;
+        let free = exfat.alloc.lock().unwrap().free_count();
+        let percent = ((total - free) as u64 * 100 / total as u64) as u8;
+        let mut current = exfat.params.percent_in_use.lock().unwrap();
 
-/// Represents an error for [`Root::open()`].
-#[derive(Debug, Error)]
-pub enum OpenError {
-    #[error("cannot read main boot region")]
-    ReadMainBootFailed(#[source] Box<dyn Error + Send + Sync>),
+        if exfat.writable && *current != percent {
+            if let Err(e) = exfat.set_dirty() {
+                return Err(PercentInUseError::SetDirtyFailed(e));
+            }
 
-    #[error("image is not exFAT")]
-    NotExFat,
+            // PercentInUse is not included in the boot checksum so we don't need to update it.
+            let backup = 12 * exfat.params.bytes_per_sector;
 
-    #[error("invalid BytesPerSectorShift")]
-    InvalidBytesPerSectorShift,
+            for offset in [112, backup + 112] {
+                if let Err(e) = exfat.partition.write_exact(offset, &[percent]) {
+                    return Err(PercentInUseError::WriteFailed(offset, e));
+                }
+            }
 
-    #[error("invalid SectorsPerClusterShift")]
-    InvalidSectorsPerClusterShift,
+            if let Err(e) = exfat.partition.flush() {
+                return Err(PercentInUseError::FlushFailed(e));
+            }
 
-    #[error("invalid NumberOfFats")]
-    InvalidNumberOfFats,
+            *current = percent;
+        }
 
-    #[error("cannot read FAT region")]
-    ReadFatRegionFailed(#[source] fat::LoadError),
+        Ok(percent)
+    }
 
-    #[error("cannot create a clusters reader")]
-    CreateClustersReaderFailed(#[source] cluster::NewError),
+    /// Returns the size of a cluster, in bytes.
+    pub fn cluster_size(&self) -> u64 {
+        self.exfat.params.cluster_size()
+    }
 
-    #[error("cannot read a directory entry")]
-    ReadEntryFailed(#[source] entries::ReaderError),
+    /// Returns the number of clusters in the Cluster Heap. The first cluster is #2 so the last
+    /// cluster is `cluster_count() + 1`.
+    pub fn cluster_count(&self) -> usize {
+        self.exfat.params.cluster_count
+    }
 
-    #[error("directory entry #{0} on cluster #{1} is not a primary entry")]
-    NotPrimaryEntry(usize, usize),
+    /// Reads the data of `cluster` into `buf`, starting from the beginning of the cluster. `buf`
+    /// cannot be larger than [`cluster_size()`][Self::cluster_size()].
+    pub fn read_cluster(&self, cluster: Cluster, buf: &mut [u8]) -> Result<(), ReadClusterError> {
+        let offset = match self.exfat.params.cluster_offset(cluster) {
+            Some(v) => v,
+            None => return Err(ReadClusterError::InvalidCluster(cluster)),
+        };
 
-    #[error("more than 2 allocation bitmaps exists in the root directory")]
-    TooManyAllocationBitmap,
+        if buf.len() as u64 > self.cluster_size() {
+            return Err(ReadClusterError::BufferTooLarge);
+        }
 
-    #[error("allocation bitmap in the root directory is not for its corresponding FAT")]
-    WrongAllocationBitmap,
+        if let Err(e) = self.exfat.partition.read_exact(offset, buf) {
+            return Err(ReadClusterError::ReadFailed(offset, e));
+        }
 
-    #[error("multiple up-case table exists in the root directory")]
-    MultipleUpcaseTable,
+        Ok(())
+    }
 
-    #[error("multiple volume label exists in the root directory")]
-    MultipleVolumeLabel,
+    /// Returns the size of a sector, in bytes.
+    pub fn sector_size(&self) -> u64 {
+        self.exfat.params.bytes_per_sector
+    }
 
-    #[error("invalid volume label")]
-    InvalidVolumeLabel,
+    /// Returns the number of sectors in the volume.
+    pub fn sector_count(&self) -> u64 {
+        self.exfat.params.volume_length
+    }
 
-    #[error("cannot load file entry in the root directory")]
-    LoadFileEntryFailed(#[source] entries::FileEntryError),
+    /// Reads the data of `sector` into `buf`, starting from the beginning of the sector. `buf`
+    /// cannot be larger than [`sector_size()`][Self::sector_size()].
+    pub fn read_sector(&self, sector: Sector, buf: &mut [u8]) -> Result<(), ReadSectorError> {
+        let offset = match self.exfat.params.sector_offset(sector) {
+            Some(v) => v,
+            None => return Err(ReadSectorError::InvalidSector(sector)),
+        };
 
-    #[error("cannot create a file object for directory entry #{0} on cluster #{1}")]
-    CreateFileObjectFailed(usize, usize, #[source] file::NewError),
+        if buf.len() as u64 > self.sector_size() {
+            return Err(ReadSectorError::BufferTooLarge);
+        }
 
-    #[error("cannot read cluster allocation for entry #{0} on cluster #{1}")]
-    ReadClusterAllocationFailed(usize, usize, #[source] entries::ClusterAllocationError),
+        if let Err(e) = self.exfat.partition.read_exact(offset, buf) {
+            return Err(ReadSectorError::ReadFailed(offset, e));
+        }
 
-    #[error("unknown directory entry #{0} on cluster #{1}")]
-    UnknownEntry(usize, usize),
+        Ok(())
+    }
 
-    #[error("no Allocation Bitmap available for active FAT")]
-    NoAllocationBitmap,
+    /// Writes `buf` to `sector`, starting from the beginning of the sector. `buf` cannot be larger
+    /// than [`sector_size()`][Self::sector_size()]. The volume must be opened as read-write.
+    ///
+    /// The data that was already loaded (e.g. the FAT and the items of this [`Root`]) will not be
+    /// updated so writing to the metadata of the volume may corrupt it.
+    pub fn write_sector(&mut self, sector: Sector, buf: &[u8]) -> Result<(), WriteSectorError> {
+        if !self.exfat.writable {
+            return Err(WriteSectorError::ReadOnly);
+        }
 
-    #[error("no Up-case Table available")]
-    NoUpcaseTable,
+        let offset = match self.exfat.params.sector_offset(sector) {
+            Some(v) => v,
+            None => return Err(WriteSectorError::InvalidSector(sector)),
+        };
+
+        if buf.len() as u64 > self.sector_size() {
+            return Err(WriteSectorError::BufferTooLarge);
+        }
+
+        if let Err(e) = self.exfat.set_dirty() {
+            return Err(WriteSectorError::SetDirtyFailed(e));
+        }
+
+        if let Err(e) = self.exfat.partition.write_exact(offset, buf) {
+            return Err(WriteSectorError::WriteFailed(offset, e));
+        }
+
+        Ok(())
+    }
+
+    /// Writes all pending changes to the partition and makes sure they reach the underlying storage
+    /// with [`DiskPartition::flush()`]. The FAT is written first, then the Allocation Bitmap. The
+    /// directory entries are always written immediately. VolumeDirty that was set by this
+    /// [`Root`] will be cleared only after all of them are flushed so the volume is consistent
+    /// on the storage when this method returns. The next change will set VolumeDirty again.
+    ///
+    /// The changes that are made by other threads while this method is running may not be
+    /// included. This method does nothing on a read-only volume.
+    pub fn sync_all(&self) -> Result<(), SyncError> {
+        let exfat = &self.exfat;
+
+        if !exfat.writable {
+            return Ok(());
+        }
+
+        // Write the metadata.
+        if let Err(e) = exfat.fat.lock().unwrap().flush(&exfat.partition) {
+            return Err(SyncError::WriteFatFailed(e));
+        }
+
+        if let Err(e) = exfat.alloc.lock().unwrap().flush(&exfat.partition) {
+            return Err(SyncError::WriteBitmapFailed(e));
+        }
+
+        if let Err(e) = exfat.partition.flush() {
+            return Err(SyncError::FlushFailed(e));
+        }
+
+        // Clear VolumeDirty.
+        let mut dirty = exfat.dirty.lock().unwrap();
+
+        if !*dirty {
+            return Ok(());
+        }
+
+        let flags = u16::from(exfat.params.volume_flags);
+
+        if let Err(e) = exfat.partition.write_exact(106, &flags.to_le_bytes()) {
+            return Err(SyncError::ClearDirtyFailed(e));
+        }
+
+        *dirty = false;
+
+        if let Err(e) = exfat.partition.flush() {
+            return Err(SyncError::FlushFailed(e));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the FAT entry of `cluster` or [`None`] if `cluster` is not valid. The changes that
+    /// was not flushed are included.
+    pub fn fat_entry(&self, cluster: Cluster) -> Result<Option<FatEntry>, fat::ReadError> {
+        let fat = self.exfat.fat.lock().unwrap();
+
+        fat.entry(&self.exfat.partition, cluster)
+    }
+
+    /// Returns the cluster chain that start at `first` from the FAT. The chain will stop before a
+    /// cluster that is marked as bad.
+    pub fn cluster_chain(&self, first: Cluster) -> Result<Vec<Cluster>, ChainError> {
+        let fat = self.exfat.fat.lock().unwrap();
+
+        fat.get_cluster_chain(&self.exfat.partition, first)
+            .into_vec()
+    }
+
+    /// Returns the Up-case Table of the volume.
+    pub fn upcase_table(&self) -> &UpcaseTable {
+        self.exfat.upcase()
+    }
+
+    /// Returns the VolumeGuid in on-disk byte order or [`None`] if the volume does not have the
+    /// Volume GUID entry.
+    pub fn volume_guid(&self) -> Option<&[u8; 16]> {
+        self.volume_guid.as_ref()
+    }
+
+    /// Sets the volume label. The label will be removed if `label` is [`None`] or empty. The
+    /// label cannot be longer than 11 UTF-16 code units.
+    pub fn set_volume_label(&mut self, label: Option<&str>) -> Result<(), SetVolumeLabelError> {
+        let label = label.unwrap_or("");
+        let label16: Vec<u16> = label.encode_utf16().collect();
+
+        self.writer().set_volume_label(&label16)?;
+        self.volume_label = if label.is_empty() {
+            None
+        } else {
+            Some(label.into())
+        };
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the items that was loaded when the volume was opened.
+    pub fn iter(&self) -> core::slice::Iter<'_, Item<P>> {
+        self.items.iter()
+    }
+
+    /// Computes the number of items and the space used by the whole directory tree, starting from
+    /// the items of this [`Root`]. The clusters of the root directory itself are not included.
+    pub fn disk_usage(&self) -> Result<DiskUsage, directory::OpenError> {
+        usage::disk_usage(self.exfat.params.cluster_size(), &self.items, Vec::new())
+    }
+
+    /// Returns an iterator that walks the whole directory tree, starting from the items of this
+    /// [`Root`].
+    pub fn walk(self) -> Walk<P> {
+        Walk::new(self.items)
+    }
+
+    /// Copies the whole directory tree to `dest` on the local file system. This is a shortcut of
+    /// [`extract_with()`][Self::extract_with()] with the default options.
+    #[cfg(feature = "std")]
+    pub fn extract_to<T: AsRef<std::path::Path>>(&self, dest: T) -> Result<(), ExtractError> {
+        self.extract_with(dest, ExtractOptions::new()).map(|_| ())
+    }
+
+    /// Copies the whole directory tree to `dest` on the local file system, which will be created
+    /// if it does not exist. The modified time, the accessed time and the read-only attribute of
+    /// the files are preserved. The times of the directories are preserved too. Returns the errors
+    /// of the skipped items if [`ErrorPolicy::Skip`][extract::ErrorPolicy::Skip] is used.
+    #[cfg(feature = "std")]
+    pub fn extract_with<T: AsRef<std::path::Path>>(
+        &self,
+        dest: T,
+        options: ExtractOptions,
+    ) -> Result<Vec<ExtractError>, ExtractError> {
+        let items = self.items.iter().map(Ok);
+
+        extract::extract(items, dest.as_ref(), options)
+    }
+
+    /// Finds the item with the specified name in the root directory. The name is compared
+    /// case-insensitively. The item will be read from the volume so the items of this [`Root`] do
+    /// not need to be up-to-date.
+    pub fn find(&self, name: &str) -> Result<Option<Item<P>>, FindError> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let entry = match directory::find(&self.exfat, None, &name)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        match Item::new(self.exfat.clone(), entry) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(FindError::CreateFileObjectFailed(e)),
+        }
+    }
+
+    /// Opens the item at `path`. Each component is separated by `/` and is case-insensitive. The
+    /// leading `/` is optional. The item will be read from the volume so the items of this
+    /// [`Root`] do not need to be up-to-date.
+    pub fn open_path(&self, path: &str) -> Result<Item<P>, OpenPathError> {
+        let mut parents: Vec<FileEntry> = Vec::new();
+        let mut current: Option<FileEntry> = None;
+        let mut walked = String::with_capacity(path.len());
+
+        for name in path.split('/') {
+            // Skip the empty components and the current directory.
+            match name {
+                "" | "." => continue,
+                ".." => {
+                    current = parents.pop();
+                    walked.push_str("/..");
+                    continue;
+                }
+                _ => {}
+            }
+
+            walked.push('/');
+            walked.push_str(name);
+
+            // Check if the current item is a directory.
+            if current
+                .as_ref()
+                .is_some_and(|v| !v.attributes.is_directory())
+            {
+                return Err(OpenPathError::NotADirectory(walked));
+            }
+
+            // Find the item.
+            let name: Vec<u16> = name.encode_utf16().collect();
+            let next = match directory::find(&self.exfat, current.as_ref(), &name) {
+                Ok(Some(v)) => v,
+                Ok(None) => return Err(OpenPathError::NotFound(walked)),
+                Err(e) => return Err(OpenPathError::FindFailed(walked, e)),
+            };
+
+            if let Some(v) = current.replace(next) {
+                parents.push(v);
+            }
+        }
+
+        // Construct the item.
+        let entry = match current {
+            Some(v) => v,
+            None => return Err(OpenPathError::InvalidPath),
+        };
+
+        match Item::new(self.exfat.clone(), entry) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(OpenPathError::CreateFileObjectFailed(walked, e)),
+        }
+    }
+
+    /// Copies the directory tree in `src` on the local file system into the root directory. The
+    /// modified time, the accessed time, the creation time and the read-only attribute are
+    /// preserved. Symbolic links to a file are followed while symbolic links to a directory and
+    /// the other special files are skipped. Use [`sync_all()`][Self::sync_all()] to make sure the
+    /// changes reach the storage.
+    #[cfg(feature = "std")]
+    pub fn import_from<T: AsRef<std::path::Path>>(&mut self, src: T) -> Result<(), ImportError> {
+        import::import(import::Parent::Root(self), "", src.as_ref())
+    }
+
+    /// Creates a new empty file in the root directory. The new file will not be added to the
+    /// items of this [`Root`].
+    pub fn create_file(&mut self, name: &str) -> Result<File<P>, CreateError> {
+        self.writer().create_file(name)
+    }
+
+    /// Creates a new empty directory in the root directory. The new directory will not be added
+    /// to the items of this [`Root`].
+    pub fn create_dir(&mut self, name: &str) -> Result<Directory<P>, CreateError> {
+        self.writer().create_dir(name)
+    }
+
+    /// Removes a file or an empty directory from the root directory. The removed item will not
+    /// be removed from the items of this [`Root`]. The [`File`]s that are still open on the
+    /// removed item will fail to write with [`ErrorKind::NotFound`][io::ErrorKind::NotFound].
+    pub fn remove(&mut self, name: &str) -> Result<(), RemoveError> {
+        self.writer().remove(name, false)
+    }
+
+    /// Removes a file or a directory including all of its contents from the root directory. The
+    /// removed item will not be removed from the items of this [`Root`].
+    pub fn remove_all(&mut self, name: &str) -> Result<(), RemoveError> {
+        self.writer().remove(name, true)
+    }
+
+    /// Renames an item in the root directory. The items of this [`Root`] will not be updated.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), RenameError> {
+        self.writer().rename(from, to)
+    }
+
+    /// Moves an item in the root directory to `dest` with `new_name`. The items of this [`Root`]
+    /// will not be updated.
+    pub fn move_to(
+        &mut self,
+        name: &str,
+        dest: &mut Directory<P>,
+        new_name: &str,
+    ) -> Result<(), RenameError> {
+        self.writer().move_to(name, dest.writer(), new_name)
+    }
+
+    /// Checks the consistency of the FAT, the Allocation Bitmap and the directory tree. The lost
+    /// clusters are not reported if some items cannot be walked, which is indicated by
+    /// [`CheckReport::is_partial()`].
+    pub fn check(&self) -> Result<CheckReport, CheckError> {
+        Checker::new(&self.exfat).run()
+    }
+
+    /// Same as [`check()`][Self::check()] but stops with [`CheckError::Cancelled`] when `cancel`
+    /// become `true`.
+    pub fn check_cancellable(&self, cancel: &AtomicBool) -> Result<CheckReport, CheckError> {
+        Checker::new(&self.exfat).cancel(cancel).run()
+    }
+
+    /// Fixes lost clusters, the Allocation Bitmap and incorrect SetChecksums. Nothing will be
+    /// written if `dry_run` is `true`. Returns the fixes that was applied (or would be applied on
+    /// dry-run). Problems that cannot be fixed without losing data (e.g. cross-linked clusters)
+    /// will be left as-is so run [`check()`][Self::check()] afterward to find them. Lost clusters
+    /// are kept if some items cannot be walked (e.g. invalid entry sets) since they may be in use.
+    pub fn repair(&mut self, dry_run: bool) -> Result<Vec<Fix>, RepairError> {
+        repair::repair(&self.exfat, dry_run)
+    }
+
+    /// Moves the clusters of the fragmented files and directories into contiguous runs and sets
+    /// their NoFatChain flag. Each item is copied to the new clusters before its directory entry
+    /// is updated so an interruption will leave only lost clusters, which can be fixed with
+    /// [`repair()`][Self::repair()]. Fails with [`DefragError::InUse`] if any object that was
+    /// obtained from this [`Root`] (e.g. [`File`] or [`Directory`]) is still alive since it would
+    /// refer to the old clusters. The items of this [`Root`] are reloaded afterward.
+    pub fn defragment(&mut self) -> Result<DefragReport, DefragError> {
+        // Our own items also hold a reference to the volume.
+        self.items.clear();
+
+        let report = if Arc::strong_count(&self.exfat) == 1 {
+            defrag::defragment(&self.exfat)
+        } else {
+            Err(DefragError::InUse)
+        };
+
+        self.items = match directory::load_items(&self.exfat, None) {
+            Ok(v) => v,
+            Err(e) => return Err(DefragError::ReloadRootFailed(e)),
+        };
+
+        report
+    }
+
+    /// Returns an iterator over the raw entries of the root directory.
+    pub fn raw_entries(&self) -> Result<RawEntries<P>, cluster::NewError> {
+        let root = self.exfat.params.first_cluster_of_root_directory;
+
+        RawEntries::new(self.exfat.clone(), root, None, None)
+    }
+
+    /// Returns an iterator over the deleted items in the root directory. See
+    /// [`undelete`] for the limitations.
+    pub fn deleted_items(&self) -> Result<DeletedItems<P>, ScanError> {
+        let root = self.exfat.params.first_cluster_of_root_directory;
+
+        DeletedItems::new(self.exfat.clone(), root, None, None)
+    }
+
+    /// Opens the data at `first_cluster` as a read-only [`File`] without a directory entry (e.g.
+    /// the data of a [`DeletedItem`]). The FAT chain is followed unless `no_fat_chain` is `true`,
+    /// in which case the clusters are assumed to be contiguous. Note that the FAT chain of a
+    /// deleted item is not preserved. The name of the file is empty and the timestamps are
+    /// 1980-01-01 00:00:00.
+    pub fn open_clusters(
+        &self,
+        first_cluster: Cluster,
+        data_length: u64,
+        no_fat_chain: bool,
+    ) -> Result<File<P>, file::NewError> {
+        File::detached(self.exfat.clone(), first_cluster, data_length, no_fat_chain)
+    }
+
+    /// Scans the clusters that are not used by any item for the entry sets of the items that are
+    /// not reachable from the root directory (e.g. the directory that contains them was deleted or
+    /// lost). Only the entry sets with a correct SetChecksum are reported. This reads the whole
+    /// Cluster Heap so it can take a long time on a large volume.
+    pub fn carve(&self) -> Result<Vec<DeletedItem>, CarveError> {
+        undelete::carve(&self.exfat, None)
+    }
+
+    /// Same as [`carve()`][Self::carve()] but stops with [`CarveError::Cancelled`] when `cancel`
+    /// become `true`.
+    pub fn carve_cancellable(&self, cancel: &AtomicBool) -> Result<Vec<DeletedItem>, CarveError> {
+        undelete::carve(&self.exfat, Some(cancel))
+    }
+
+    /// Copies this volume to `dest` at the same offsets. This is a shortcut of
+    /// [`clone_with()`][Self::clone_with()] with the default options.
+    pub fn clone_to<D: DiskPartition>(&self, dest: D) -> Result<u64, CloneError> {
+        self.clone_with(dest, &CloneOptions::new())
+    }
+
+    /// Copies the Main and Backup Boot Region, the FATs and the allocated clusters to `dest` at
+    /// the same offsets, which is much faster than copying the whole partition on a mostly empty
+    /// volume. The other regions of `dest` are left as-is. Pending changes will be written before
+    /// the copy if the volume is writable. Returns the number of bytes that was copied.
+    pub fn clone_with<D: DiskPartition>(
+        &self,
+        dest: D,
+        options: &CloneOptions,
+    ) -> Result<u64, CloneError> {
+        if let Err(e) = self.sync_all() {
+            return Err(CloneError::SyncFailed(e));
+        }
+
+        clone::clone(&self.exfat, dest, options)
+    }
+
+    pub(crate) fn writer(&mut self) -> DirectoryWriter<'_, P> {
+        DirectoryWriter::new(&self.exfat, None)
+    }
+}
+
+impl<'a, P: DiskPartition> IntoIterator for &'a Root<P> {
+    type Item = &'a Item<P>;
+    type IntoIter = core::slice::Iter<'a, Item<P>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<P: DiskPartition> IntoIterator for Root<P> {
+    type Item = Item<P>;
+    type IntoIter = alloc_crate::vec::IntoIter<Item<P>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// Contains the result of [`Root::info()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VolumeInfo {
+    label: Option<String>,
+    guid: Option<[u8; 16]>,
+    cluster_size: u64,
+    cluster_count: usize,
+    free_clusters: usize,
+    percent_in_use: Option<u8>,
+}
+
+impl VolumeInfo {
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns the VolumeGuid in on-disk byte order.
+    pub fn guid(&self) -> Option<&[u8; 16]> {
+        self.guid.as_ref()
+    }
+
+    pub fn cluster_size(&self) -> u64 {
+        self.cluster_size
+    }
+
+    pub fn cluster_count(&self) -> usize {
+        self.cluster_count
+    }
+
+    pub fn free_clusters(&self) -> usize {
+        self.free_clusters
+    }
+
+    /// Returns PercentInUse that was stored on the volume. See [`Root::percent_in_use()`].
+    pub fn percent_in_use(&self) -> Option<u8> {
+        self.percent_in_use
+    }
+}
+
+/// Contains the result of [`Root::io_stats()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IoStats {
+    bytes_read: u64,
+    reads: u64,
+    clusters_read: u64,
+    cache_hits: u64,
+}
+
+impl IoStats {
+    /// Returns the number of bytes that was read from the partition.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the number of reads that was issued to the partition.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Returns the number of clusters in the Cluster Heap that was read from the partition. A
+    /// cluster is counted on every read that overlap with it.
+    pub fn clusters_read(&self) -> u64 {
+        self.clusters_read
+    }
+
+    /// Returns the number of reads that was served from the cache. See
+    /// [`OpenOptions::cache_size()`].
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits
+    }
+}
+
+/// Represents an index of a cluster. The first cluster in the Cluster Heap is #2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Cluster(pub u32);
+
+impl Cluster {
+    /// Returns `true` if the cluster is in the Cluster Heap of a volume with `count` clusters.
+    pub fn is_valid(self, count: usize) -> bool {
+        self.0 >= 2 && (self.0 as usize - 2) < count
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl TryFrom<usize> for Cluster {
+    type Error = core::num::TryFromIntError;
+
+    fn try_from(v: usize) -> Result<Self, Self::Error> {
+        v.try_into().map(Self)
+    }
+}
+
+impl From<Cluster> for u32 {
+    fn from(v: Cluster) -> Self {
+        v.0
+    }
+}
+
+impl core::fmt::Display for Cluster {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Represents an index of a sector, relative to the beginning of the volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sector(pub u64);
+
+impl Sector {
+    /// Returns the offset of the sector, in bytes, on a volume with `sector_size`.
+    pub fn offset(self, sector_size: u64) -> u64 {
+        self.0 * sector_size
+    }
+}
+
+impl From<Sector> for u64 {
+    fn from(v: Sector) -> Self {
+        v.0
+    }
+}
+
+impl core::fmt::Display for Sector {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Represents FileAttributes in the File Directory Entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[repr(transparent)]
+pub struct FileAttributes(u16);
+
+impl FileAttributes {
+    pub fn is_read_only(self) -> bool {
+        (self.0 & 0x0001) != 0
+    }
+
+    pub fn is_hidden(self) -> bool {
+        (self.0 & 0x0002) != 0
+    }
+
+    pub fn is_system(self) -> bool {
+        (self.0 & 0x0004) != 0
+    }
+
+    pub fn is_directory(self) -> bool {
+        (self.0 & 0x0010) != 0
+    }
+
+    pub fn is_archive(self) -> bool {
+        (self.0 & 0x0020) != 0
+    }
+
+    pub fn set_read_only(&mut self, v: bool) {
+        self.set(0x0001, v);
+    }
+
+    pub fn set_hidden(&mut self, v: bool) {
+        self.set(0x0002, v);
+    }
+
+    pub fn set_system(&mut self, v: bool) {
+        self.set(0x0004, v);
+    }
+
+    pub fn set_archive(&mut self, v: bool) {
+        self.set(0x0020, v);
+    }
+
+    fn set(&mut self, mask: u16, v: bool) {
+        if v {
+            self.0 |= mask;
+        } else {
+            self.0 &= !mask;
+        }
+    }
+}
+
+/// Options for [`Root::open_with()`].
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    writable: bool,
+    lenient: bool,
+    strict: bool,
+    skip_corrupt: bool,
+    ignore_checksums: bool,
+    metadata_only: bool,
+    compare_fats: bool,
+    reject_dirty: bool,
+    media_failure: MediaFailurePolicy,
+    bad_cluster: BadClusterPolicy,
+    fat_window: usize,
+    cache_size: usize,
+    cluster_cache: Option<Arc<dyn ClusterCache>>,
+    read_ahead: usize,
+    read_granularity: ReadGranularity,
+    discard: bool,
+    minimal_memory: bool,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the volume as read-write. The partition must support writing, which is indicated by
+    /// [`DiskPartition::is_writable()`]. The default is `false`.
+    pub fn writable(mut self, v: bool) -> Self {
+        self.writable = v;
+        self
+    }
+
+    /// Tolerates the problems that does not prevent the volume from being read. The problems will
+    /// be reported by [`Root::warnings()`] instead of failing the operation. The default is
+    /// `false`.
+    pub fn lenient(mut self, v: bool) -> Self {
+        self.lenient = v;
+        self
+    }
+
+    /// Fails with [`OpenError::UnknownEntry`] or [`directory::OpenError::NotFileEntry`] on benign
+    /// primary entries that are not recognized instead of skipping it as required by the specs.
+    /// The default is `false`.
+    pub fn strict(mut self, v: bool) -> Self {
+        self.strict = v;
+        self
+    }
+
+    /// Skips the file entry sets that cannot be loaded (e.g. invalid stream extension or file
+    /// name) and reports it with [`Warning::CorruptEntrySet`] instead of failing the whole
+    /// directory. The default is `false`.
+    pub fn skip_corrupt(mut self, v: bool) -> Self {
+        self.skip_corrupt = v;
+        self
+    }
+
+    /// Skips the verification of the boot checksum, TableChecksum of the Up-case Table and
+    /// SetChecksum of the entry sets. The default is `false`.
+    pub fn ignore_checksums(mut self, v: bool) -> Self {
+        self.ignore_checksums = v;
+        self
+    }
+
+    /// Loads only the metadata of the volume (e.g. the boot region, volume label, volume GUID and
+    /// Allocation Bitmap) without loading the items in the root directory. The FAT entries will be
+    /// read on demand unless [`fat_window()`][Self::fat_window()] is set. The Up-case Table is
+    /// still loaded since it is required to verify TableChecksum and look up the items. The opened
+    /// [`Root`] will have no items but the other operations (e.g. [`Root::open_path()`]) still
+    /// work. The default is `false`.
+    pub fn metadata_only(mut self, v: bool) -> Self {
+        self.metadata_only = v;
+        self
+    }
+
+    /// Loads both FATs on a volume with two FATs and reports the clusters that have a different
+    /// FAT entry with [`Warning::FatMismatch`]. The default is `false`.
+    pub fn compare_fats(mut self, v: bool) -> Self {
+        self.compare_fats = v;
+        self
+    }
+
+    /// Fails with [`OpenError::VolumeDirty`] if VolumeDirty is set instead of reporting it with
+    /// [`Warning::VolumeDirty`]. The default is `false`.
+    pub fn reject_dirty(mut self, v: bool) -> Self {
+        self.reject_dirty = v;
+        self
+    }
+
+    /// Sets how to handle the volume with MediaFailure set. The default is
+    /// [`MediaFailurePolicy::Warn`].
+    pub fn media_failure(mut self, v: MediaFailurePolicy) -> Self {
+        self.media_failure = v;
+        self
+    }
+
+    /// Sets how to read the data after a cluster that was marked as bad in the FAT. The default is
+    /// [`BadClusterPolicy::Fail`].
+    pub fn bad_cluster(mut self, v: BadClusterPolicy) -> Self {
+        self.bad_cluster = v;
+        self
+    }
+
+    /// Reads the FAT entries on demand with a cached window of `v` entries instead of loading the
+    /// whole FAT into memory when opening. The window will be rounded up to a multiple of
+    /// [`DiskPartition::block_size()`]. Zero loads the whole FAT. The default is `0`.
+    pub fn fat_window(mut self, v: usize) -> Self {
+        self.fat_window = v;
+        self
+    }
+
+    /// Keeps up to `v` recently used clusters in memory so repeated reads on the same clusters
+    /// (e.g. scanning the same directory) does not hit the partition every time. Zero disables the
+    /// cache. The default is `0`.
+    pub fn cache_size(mut self, v: usize) -> Self {
+        self.cache_size = v;
+        self
+    }
+
+    /// Uses `v` to keep the clusters instead of an [`LruCache`][cache::LruCache] with
+    /// [`cache_size()`][Self::cache_size()] blocks. The same cache can be used by multiple
+    /// volumes. This is not affected by [`minimal_memory()`][Self::minimal_memory()].
+    pub fn cluster_cache(mut self, v: Arc<dyn ClusterCache>) -> Self {
+        self.cluster_cache = Some(v);
+        self
+    }
+
+    /// Reads up to `v` clusters after the current one in advance when the data of a file or a
+    /// directory is being read sequentially. The data that was read ahead is kept by each reader
+    /// so the changes made through another [`File`] on the same file may not be visible. Zero
+    /// disables read-ahead. The default is `0`.
+    pub fn read_ahead(mut self, v: usize) -> Self {
+        self.read_ahead = v;
+        self
+    }
+
+    /// Sets the maximum amount of data to read from the partition at once when reading a file or
+    /// a directory. The default is [`ReadGranularity::Contiguous`].
+    pub fn read_granularity(mut self, v: ReadGranularity) -> Self {
+        self.read_granularity = v;
+        self
+    }
+
+    /// Discards the clusters that was released by removing or truncating a file or a directory
+    /// with [`DiskPartition::discard()`] so the storage (e.g. SSD or SD card) knows they are no
+    /// longer in use. The failure to discard will be ignored. The default is `false`.
+    pub fn discard(mut self, v: bool) -> Self {
+        self.discard = v;
+        self
+    }
+
+    /// Minimizes the memory usage at the cost of speed for the targets with a tiny RAM (e.g.
+    /// microcontrollers). The FAT entries will be read from the partition every time they are
+    /// needed and the Up-case Table will be kept compressed. [`fat_window()`][Self::fat_window()],
+    /// [`cache_size()`][Self::cache_size()] and [`read_ahead()`][Self::read_ahead()] will be
+    /// ignored. The Allocation Bitmap is still loaded entirely. The default is `false`.
+    pub fn minimal_memory(mut self, v: bool) -> Self {
+        self.minimal_memory = v;
+        self
+    }
+}
+
+/// Specifies how to handle the volume with MediaFailure set, which indicates the device has
+/// reported unrecoverable sectors before.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MediaFailurePolicy {
+    /// Fails with [`OpenError::MediaFailure`].
+    Fail,
+
+    /// Reports [`Warning::MediaFailure`].
+    #[default]
+    Warn,
+
+    /// Opens the volume as usual.
+    Ignore,
+}
+
+/// Specifies how to read the data of a file when its cluster chain reach a cluster that was marked
+/// as bad before DataLength.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BadClusterPolicy {
+    /// Fails the read with [`cluster::BadCluster`] at the first byte of the bad cluster.
+    #[default]
+    Fail,
+
+    /// Reads the remaining data as zeros.
+    Zeros,
+
+    /// Treats the last byte before the bad cluster as the end of the data.
+    Stop,
+}
+
+/// Specifies the maximum amount of data to read from the partition at once when reading a file or
+/// a directory. A smaller unit use less memory on the partition side while a larger unit reduce the
+/// number of reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadGranularity {
+    /// Reads at most one sector at a time.
+    Sector,
+
+    /// Reads at most one cluster at a time.
+    Cluster,
+
+    /// Reads at most the specified number of clusters at a time.
+    Clusters(usize),
+
+    /// Reads all clusters that are physically contiguous at once.
+    #[default]
+    Contiguous,
+}
+
+/// Represents a problem that was tolerated in lenient mode or found by the checks that was enabled
+/// in [`OpenOptions`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Warning {
+    #[error("boot checksum does not match")]
+    BootChecksumMismatch,
+
+    #[error("the volume was not cleanly unmounted")]
+    VolumeDirty,
+
+    #[error("the device has reported unrecoverable sectors")]
+    MediaFailure,
+
+    #[error("the cluster heap end at {0} but the partition has only {1} bytes")]
+    PartitionTooSmall(u64, u64),
+
+    #[error("SetChecksum of entry #{0} on cluster {1} is not correct")]
+    ChecksumMismatch(usize, Cluster),
+
+    #[error("entry set #{0} on cluster {1} was skipped: {2}")]
+    CorruptEntrySet(usize, Cluster, String),
+
+    #[error("the FAT entries of {} clusters are not the same on both FATs", .0.len())]
+    FatMismatch(Vec<Cluster>),
+}
+
+/// Contains objects for the opened exFAT.
+pub(crate) struct ExFat<P: DiskPartition> {
+    partition: BlockCache<P>,
+    params: Params,
+    fat: Mutex<Fat>,
+    alloc: Mutex<Allocator>,
+    upcase: OnceLock<UpcaseTable>,
+    writable: bool,
+    lenient: bool,
+    strict: bool,
+    skip_corrupt: bool,
+    ignore_checksums: bool,
+    bad_cluster: BadClusterPolicy,
+    read_ahead: usize,
+    read_granularity: ReadGranularity,
+    discard: bool,
+    dirty: Mutex<bool>, // true if VolumeDirty was set by us.
+    warnings: Mutex<Vec<Warning>>,
+    handles: Mutex<BTreeMap<u64, Vec<Arc<Handle>>>>, // Keyed by the offset of the entry set.
+}
+
+impl<P: DiskPartition> ExFat<P> {
+    /// Reports the entry set that start at `entry` with [`Warning::CorruptEntrySet`] if corrupted
+    /// entry sets should be skipped. Returns `false` if the error should be returned instead.
+    fn skip_entry_set(&self, entry: &RawEntry, reason: &dyn Error) -> bool {
+        if !self.skip_corrupt {
+            return false;
+        }
+
+        let warning = Warning::CorruptEntrySet(entry.index(), entry.cluster(), reason.to_string());
+
+        self.warnings.lock().unwrap().push(warning);
+
+        true
+    }
+
+    /// Sets VolumeDirty on the main boot sector if it is not already set. This must be called
+    /// before making any changes to the volume.
+    fn set_dirty(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut dirty = self.dirty.lock().unwrap();
+        let flags = self.params.volume_flags;
+
+        if *dirty || flags.volume_dirty() {
+            return Ok(());
+        }
+
+        let flags = u16::from(flags.with_volume_dirty(true));
+
+        self.partition.write_exact(106, &flags.to_le_bytes())?;
+        *dirty = true;
+
+        Ok(())
+    }
+
+    /// Registers an open file or directory with the entry set at `offset`. The returned handle
+    /// will be updated when the item is renamed, moved or removed so it will not write to the
+    /// entries and clusters that no longer belong to it.
+    fn open_handle(&self, offset: u64) -> Arc<Handle> {
+        let handle = Arc::new(Handle {
+            removed: AtomicBool::new(false),
+            moved: Mutex::new(None),
+        });
+
+        self.handles
+            .lock()
+            .unwrap()
+            .entry(offset)
+            .or_default()
+            .push(handle.clone());
+
+        handle
+    }
+
+    /// Unregisters the item that was registered by [`open_handle()`][Self::open_handle()].
+    fn close_handle(&self, offset: u64, handle: &Arc<Handle>) {
+        let mut handles = self.handles.lock().unwrap();
+
+        if let Some(v) = handles.get_mut(&offset) {
+            v.retain(|v| !Arc::ptr_eq(v, handle));
+
+            if v.is_empty() {
+                handles.remove(&offset);
+            }
+        }
+    }
+
+    /// Hands the new name and `entries` to all open items with the entry set at `offset`.
+    fn move_handles(&self, offset: u64, name: &str, entries: &EntrySet) {
+        let mut handles = self.handles.lock().unwrap();
+        let moved = match handles.remove(&offset) {
+            Some(v) => v,
+            None => return,
+        };
+
+        for handle in &moved {
+            *handle.moved.lock().unwrap() = Some((name.into(), entries.clone()));
+        }
+
+        handles
+            .entry(entries.offsets()[0])
+            .or_default()
+            .extend(moved);
+    }
+
+    /// Marks all open items with the entry set at `offset` as removed.
+    fn invalidate_handles(&self, offset: u64) {
+        if let Some(v) = self.handles.lock().unwrap().remove(&offset) {
+            for handle in v {
+                handle.removed.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    /// Returns the Up-case Table of the volume. This can only be called after the volume has been
+    /// loaded.
+    fn upcase(&self) -> &UpcaseTable {
+        self.upcase.get().unwrap()
+    }
+}
+
+/// State of an open [`File`] or [`Directory`] that is shared with [`ExFat`].
+pub(crate) struct Handle {
+    removed: AtomicBool,
+    moved: Mutex<Option<(String, EntrySet)>>, // The new name and entries after a rename or move.
+}
+
+impl Handle {
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+
+    /// Replaces the name and the entries of `entry` if the item was renamed or moved.
+    pub fn update(&self, entry: &mut FileEntry) {
+        if let Some((name, entries)) = self.moved.lock().unwrap().take() {
+            entry.stream.set_name_length(name.encode_utf16().count());
+            entry.name = name;
+            entry.entries = entries;
+        }
+    }
+}
+
+impl<P: DiskPartition> Drop for ExFat<P> {
+    fn drop(&mut self) {
+        // Clear VolumeDirty that we have set. All changes are already written at this point.
+        if *self.dirty.get_mut().unwrap() {
+            let flags = u16::from(self.params.volume_flags);
+
+            self.partition.write_exact(106, &flags.to_le_bytes()).ok();
+        }
+
+        self.partition.flush().ok();
+    }
+}
+
+/// Represents an error for [`Root::open()`].
+#[derive(Debug, Error)]
+pub enum OpenError {
+    #[error("the partition does not support writing")]
+    PartitionNotWritable,
+
+    #[error("cannot read main boot region")]
+    ReadMainBootFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("image is not exFAT")]
+    NotExFat,
+
+    #[error("boot checksum does not match")]
+    BootChecksumMismatch,
+
+    #[error("the volume was not cleanly unmounted")]
+    VolumeDirty,
+
+    #[error("the device has reported unrecoverable sectors")]
+    MediaFailure,
+
+    #[error("cannot load main boot region")]
+    LoadBootRegionFailed(#[source] boot::LoadError),
+
+    #[error("invalid BytesPerSectorShift")]
+    InvalidBytesPerSectorShift,
+
+    #[error("invalid SectorsPerClusterShift")]
+    InvalidSectorsPerClusterShift,
+
+    #[error("invalid NumberOfFats")]
+    InvalidNumberOfFats,
+
+    #[error("the cluster heap end at {0} but the partition has only {1} bytes")]
+    PartitionTooSmall(u64, u64),
+
+    #[error("cannot read FAT region")]
+    ReadFatRegionFailed(#[source] fat::LoadError),
+
+    #[error("cannot create a clusters reader")]
+    CreateClustersReaderFailed(#[source] cluster::NewError),
+
+    #[error("cannot read a directory entry")]
+    ReadEntryFailed(#[source] entries::ReaderError),
+
+    #[error("directory entry #{0} on cluster {1} is not a primary entry")]
+    NotPrimaryEntry(usize, Cluster),
+
+    #[error("more than 2 allocation bitmaps exists in the root directory")]
+    TooManyAllocationBitmap,
+
+    #[error("allocation bitmap in the root directory is not for its corresponding FAT")]
+    WrongAllocationBitmap,
+
+    #[error("multiple up-case table exists in the root directory")]
+    MultipleUpcaseTable,
+
+    #[error("multiple volume label exists in the root directory")]
+    MultipleVolumeLabel,
+
+    #[error("invalid volume label")]
+    InvalidVolumeLabel,
+
+    #[error("multiple volume GUID exists in the root directory")]
+    MultipleVolumeGuid,
+
+    #[error("invalid volume GUID")]
+    InvalidVolumeGuid,
+
+    #[error("cannot load file entry in the root directory")]
+    LoadFileEntryFailed(#[source] entries::FileEntryError),
+
+    #[error("cannot create a file object for directory entry #{0} on cluster {1}")]
+    CreateFileObjectFailed(usize, Cluster, #[source] file::NewError),
+
+    #[error("cannot read cluster allocation for entry #{0} on cluster {1}")]
+    ReadClusterAllocationFailed(usize, Cluster, #[source] entries::ClusterAllocationError),
+
+    #[error("unknown directory entry #{0} on cluster {1}")]
+    UnknownEntry(usize, Cluster),
+
+    #[error("no Allocation Bitmap available for active FAT")]
+    NoAllocationBitmap,
+
+    #[error("cannot load Allocation Bitmap")]
+    LoadAllocationBitmapFailed(#[source] alloc::LoadError),
+
+    #[error("no Up-case Table available")]
+    NoUpcaseTable,
+
+    #[error("cannot load Up-case Table")]
+    LoadUpcaseTableFailed(#[source] upcase::LoadError),
+}
+
+/// Represents an error for [`Root::open_path()`].
+#[derive(Debug, Error)]
+pub enum OpenPathError {
+    #[error("the path does not refer to a file or a directory")]
+    InvalidPath,
+
+    #[error("{0} does not exist")]
+    NotFound(String),
+
+    #[error("the parent of {0} is not a directory")]
+    NotADirectory(String),
+
+    #[error("cannot find {0}")]
+    FindFailed(String, #[source] directory::FindError),
+
+    #[error("cannot create a file object for {0}")]
+    CreateFileObjectFailed(String, #[source] file::NewError),
+}
+
+/// Represents an error for [`Root::read_cluster()`].
+#[derive(Debug, Error)]
+pub enum ReadClusterError {
+    #[error("cluster {0} is not valid")]
+    InvalidCluster(Cluster),
+
+    #[error("the buffer is larger than a cluster")]
+    BufferTooLarge,
+
+    #[error("cannot read the data at {0:#018x}")]
+    ReadFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+}
+
+/// Represents an error for [`Root::read_sector()`].
+#[derive(Debug, Error)]
+pub enum ReadSectorError {
+    #[error("sector {0} is not valid")]
+    InvalidSector(Sector),
+
+    #[error("the buffer is larger than a sector")]
+    BufferTooLarge,
+
+    #[error("cannot read the data at {0:#018x}")]
+    ReadFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+}
+
+/// Represents an error for [`Root::write_sector()`].
+#[derive(Debug, Error)]
+pub enum WriteSectorError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("sector {0} is not valid")]
+    InvalidSector(Sector),
+
+    #[error("the buffer is larger than a sector")]
+    BufferTooLarge,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot write the data at {0:#018x}")]
+    WriteFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+}
+
+/// Represents an error for [`Root::sync_all()`].
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("cannot write the FAT")]
+    WriteFatFailed(#[source] fat::WriteError),
+
+    #[error("cannot write the Allocation Bitmap")]
+    WriteBitmapFailed(#[source] alloc::WriteError),
+
+    #[error("cannot flush the partition")]
+    FlushFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot clear VolumeDirty")]
+    ClearDirtyFailed(#[source] Box<dyn Error + Send + Sync>),
+}
+
+/// Represents an error for [`Root::update_percent_in_use()`].
+#[derive(Debug, Error)]
+pub enum PercentInUseError {
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot write PercentInUse at {0:#x}")]
+    WriteFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("cannot flush the partition")]
+    FlushFailed(#[source] Box<dyn Error + Send + Sync>),
+}
+
+/// Represents an error for [`Root::set_volume_label()`].
+#[derive(Debug, Error)]
+pub enum SetVolumeLabelError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error("invalid volume label")]
+    InvalidLabel,
+
+    #[error("cannot find the Volume Label entry")]
+    FindFailed(#[source] directory::FindError),
+
+    #[error("cannot allocate a Volume Label entry")]
+    AllocateEntryFailed(#[source] CreateError),
+
+    #[error("cannot write the data at {0:#018x}")]
+    WriteFailed(u64, #[source] Box<dyn Error + Send + Sync>),
 }
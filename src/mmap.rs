@@ -0,0 +1,149 @@
+use crate::disk::{read_slice, DiskPartition};
+use memmap2::{Mmap, MmapMut};
+use std::error::Error;
+use std::fs::File;
+use std::sync::RwLock;
+use thiserror::Error;
+
+/// An implementation of [`DiskPartition`] backed by a memory-mapped exFAT image.
+///
+/// The reads are copied directly from the mapped memory without any system call.
+pub struct MmapPartition {
+    map: Map,
+}
+
+impl MmapPartition {
+    /// Maps the whole `file` as read-only.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while the returned [`MmapPartition`] is alive.
+    /// See [`Mmap`] for more details.
+    pub unsafe fn open(file: &File) -> Result<Self, OpenError> {
+        let map = match Mmap::map(file) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::MapFailed(e)),
+        };
+
+        Ok(Self {
+            map: Map::ReadOnly(map),
+        })
+    }
+
+    /// Same as [`open()`][Self::open] but the resulting [`MmapPartition`] is writable. The file
+    /// must be opened for writing.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while the returned [`MmapPartition`] is alive.
+    /// See [`MmapMut`] for more details.
+    pub unsafe fn open_rw(file: &File) -> Result<Self, OpenError> {
+        let map = match MmapMut::map_mut(file) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::MapFailed(e)),
+        };
+
+        Ok(Self {
+            map: Map::ReadWrite(RwLock::new(map)),
+        })
+    }
+
+    /// Returns the mapped data if the partition is read-only. This can be used to access the data
+    /// without copying.
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        match &self.map {
+            Map::ReadOnly(v) => Some(v),
+            Map::ReadWrite(_) => None,
+        }
+    }
+}
+
+impl DiskPartition for MmapPartition {
+    fn len(&self) -> Option<u64> {
+        let len = match &self.map {
+            Map::ReadOnly(v) => v.len(),
+            Map::ReadWrite(v) => v
+                .read()
+                .expect("the lock that protect the mapped memory is poisoned")
+                .len(),
+        };
+
+        Some(len as u64)
+    }
+
+    fn is_writable(&self) -> bool {
+        matches!(self.map, Map::ReadWrite(_))
+    }
+
+    /// Writes all changes to the file.
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let map = match &self.map {
+            Map::ReadOnly(_) => return Ok(()),
+            Map::ReadWrite(v) => v
+                .read()
+                .expect("the lock that protect the mapped memory is poisoned"),
+        };
+
+        match map.flush() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(WriteError::FlushFailed(e).into()),
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let read = match &self.map {
+            Map::ReadOnly(v) => read_slice(v, offset, buf),
+            Map::ReadWrite(v) => {
+                let map = v
+                    .read()
+                    .expect("the lock that protect the mapped memory is poisoned");
+
+                read_slice(&map, offset, buf)
+            }
+        };
+
+        Ok(read)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut map = match &self.map {
+            Map::ReadOnly(_) => return Err(WriteError::ReadOnly.into()),
+            Map::ReadWrite(v) => v
+                .write()
+                .expect("the lock that protect the mapped memory is poisoned"),
+        };
+
+        // Check if the offset is out of range.
+        let data = match usize::try_from(offset).ok().and_then(|v| map.get_mut(v..)) {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+
+        let len = buf.len().min(data.len());
+
+        data[..len].copy_from_slice(&buf[..len]);
+
+        Ok(len as u64)
+    }
+}
+
+/// The mapped memory of [`MmapPartition`].
+enum Map {
+    ReadOnly(Mmap),
+    ReadWrite(RwLock<MmapMut>),
+}
+
+/// Represents an error for [`MmapPartition::open()`].
+#[derive(Debug, Error)]
+pub enum OpenError {
+    #[error("cannot map the file")]
+    MapFailed(#[source] std::io::Error),
+}
+
+/// Represents an error for [`MmapPartition::write()`].
+#[derive(Debug, Error)]
+enum WriteError {
+    #[error("the partition is not opened for writing")]
+    ReadOnly,
+
+    #[error("cannot flush the mapped memory")]
+    FlushFailed(#[source] std::io::Error),
+}
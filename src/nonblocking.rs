@@ -0,0 +1,656 @@
+//! Reading a volume from the async code.
+//!
+//! The volume is processed by the blocking code, which is run by an [`Executor`]. This module
+//! does not require `std` so it can also be used with the executors that does not have the
+//! blocking threads (e.g. Embassy). See [`Executor`] for how to implement it on those executors.
+//! `AsyncRead` and `AsyncSeek` from `futures-io` are implemented only when `std` feature is
+//! enabled.
+use crate::directory::{self, Directory, Item, Items};
+use crate::disk::DiskPartition;
+use crate::file::File;
+use crate::io::SeekFrom;
+use crate::prelude::*;
+use crate::sync::{Arc, Mutex};
+use crate::timestamp::Timestamps;
+use crate::{FileAttributes, OpenError, OpenOptions, OpenPathError, Root};
+use core::error::Error;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures_core::Stream;
+
+/// Encapsulate a disk partition that is read asynchronously (e.g. over the network).
+#[allow(clippy::len_without_is_empty)]
+pub trait AsyncDiskPartition: Send + Sync + 'static {
+    /// Returns the size of the partition, in bytes, or [`None`] if it is unknown. The default
+    /// implementation returns [`None`].
+    fn len(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the preferred alignment and granularity of the I/O on the partition, in bytes. This
+    /// must be a power of two. The default implementation returns 512.
+    fn block_size(&self) -> u64 {
+        512
+    }
+
+    fn read(
+        &self,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> impl Future<Output = Result<u64, Box<dyn Error + Send + Sync>>> + Send;
+}
+
+/// Runs the blocking parts of the async API.
+///
+/// [`TokioExecutor`] and [`AsyncStdExecutor`] are provided when the corresponding feature is
+/// enabled. Implement this trait to use the async API with the other executors. On the executors
+/// that does not have the blocking threads (e.g. Embassy), [`spawn_blocking()`] can return a
+/// future that runs `task` when it is polled and [`block_on()`] can poll `future` in a loop (e.g.
+/// with `embassy_futures::block_on()`). The other tasks will not be run while `task` is running in
+/// this case.
+///
+/// [`spawn_blocking()`]: Self::spawn_blocking()
+/// [`block_on()`]: Self::block_on()
+pub trait Executor: Send + Sync + 'static {
+    /// Runs `task` on a thread that is allowed to block. The returned future must be completed
+    /// after `task` returns.
+    fn spawn_blocking(
+        &self,
+        task: Box<dyn FnOnce() + Send>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    /// Blocks the current thread until `future` is completed. This will be called only by the
+    /// tasks that was started with [`spawn_blocking()`][Self::spawn_blocking()].
+    fn block_on(&self, future: Pin<&mut (dyn Future<Output = ()> + Send + '_)>);
+}
+
+/// An [`Executor`] on the blocking threads of Tokio.
+#[cfg(feature = "tokio")]
+pub struct TokioExecutor(tokio::runtime::Handle);
+
+#[cfg(feature = "tokio")]
+impl TokioExecutor {
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self(handle)
+    }
+
+    /// Creates a [`TokioExecutor`] for the current runtime. This will panic if called outside
+    /// of a Tokio runtime.
+    pub fn current() -> Self {
+        Self(tokio::runtime::Handle::current())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Executor for TokioExecutor {
+    fn spawn_blocking(
+        &self,
+        task: Box<dyn FnOnce() + Send>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let task = self.0.spawn_blocking(task);
+
+        Box::pin(async move {
+            if let Err(e) = task.await {
+                std::panic::resume_unwind(e.into_panic());
+            }
+        })
+    }
+
+    fn block_on(&self, future: Pin<&mut (dyn Future<Output = ()> + Send + '_)>) {
+        self.0.block_on(future)
+    }
+}
+
+/// An [`Executor`] on the blocking threads of async-std.
+#[cfg(feature = "async-std")]
+pub struct AsyncStdExecutor;
+
+#[cfg(feature = "async-std")]
+impl Executor for AsyncStdExecutor {
+    fn spawn_blocking(
+        &self,
+        task: Box<dyn FnOnce() + Send>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async_std::task::spawn_blocking(task))
+    }
+
+    fn block_on(&self, future: Pin<&mut (dyn Future<Output = ()> + Send + '_)>) {
+        async_std::task::block_on(future)
+    }
+}
+
+/// An asynchronous version of [`Root`].
+///
+/// The volume is processed by the same code as [`Root`] on the blocking threads of the
+/// [`Executor`] so the async tasks will never be blocked.
+pub struct AsyncRoot<P: AsyncDiskPartition> {
+    root: Arc<Root<Blocking<P>>>,
+    items: Vec<AsyncItem<P>>,
+    exec: Arc<dyn Executor>,
+}
+
+impl<P: AsyncDiskPartition> AsyncRoot<P> {
+    /// Opens the exFAT on `partition` and runs the blocking parts on `exec`.
+    pub async fn open<E: Executor>(partition: P, exec: E) -> Result<Self, OpenError> {
+        Self::open_with(partition, exec, &OpenOptions::new()).await
+    }
+
+    /// Opens the exFAT on `partition` with `options`. The volume cannot be opened as writable.
+    pub async fn open_with<E: Executor>(
+        partition: P,
+        exec: E,
+        options: &OpenOptions,
+    ) -> Result<Self, OpenError> {
+        let exec: Arc<dyn Executor> = Arc::new(exec);
+        let partition = Blocking {
+            partition,
+            exec: exec.clone(),
+        };
+
+        let options = options.clone();
+        let mut root = blocking(&exec, move || Root::open_with(partition, &options)).await?;
+        let items = core::mem::take(&mut root.items)
+            .into_iter()
+            .map(|v| AsyncItem::new(v, &exec))
+            .collect();
+
+        Ok(Self {
+            root: Arc::new(root),
+            items,
+            exec,
+        })
+    }
+
+    pub fn volume_label(&self) -> Option<&str> {
+        self.root.volume_label()
+    }
+
+    /// Returns an iterator over the items that was loaded when the volume was opened.
+    pub fn iter(&self) -> core::slice::Iter<'_, AsyncItem<P>> {
+        self.items.iter()
+    }
+
+    /// Same as [`Root::find()`].
+    pub async fn find(&self, name: &str) -> Result<Option<AsyncItem<P>>, crate::FindError> {
+        let root = self.root.clone();
+        let name = name.to_string();
+        let item = blocking(&self.exec, move || root.find(&name)).await?;
+
+        Ok(item.map(|v| AsyncItem::new(v, &self.exec)))
+    }
+
+    /// Same as [`Root::open_path()`].
+    pub async fn open_path(&self, path: &str) -> Result<AsyncItem<P>, OpenPathError> {
+        let root = self.root.clone();
+        let path = path.to_string();
+        let item = blocking(&self.exec, move || root.open_path(&path)).await?;
+
+        Ok(AsyncItem::new(item, &self.exec))
+    }
+}
+
+impl<P: AsyncDiskPartition> IntoIterator for AsyncRoot<P> {
+    type Item = AsyncItem<P>;
+    type IntoIter = alloc_crate::vec::IntoIter<AsyncItem<P>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// An asynchronous version of [`Item`].
+pub enum AsyncItem<P: AsyncDiskPartition> {
+    Directory(AsyncDirectory<P>),
+    File(AsyncFile<P>),
+}
+
+impl<P: AsyncDiskPartition> AsyncItem<P> {
+    fn new(item: Item<Blocking<P>>, exec: &Arc<dyn Executor>) -> Self {
+        match item {
+            Item::Directory(v) => Self::Directory(AsyncDirectory {
+                dir: Arc::new(v),
+                exec: exec.clone(),
+            }),
+            Item::File(v) => Self::File(AsyncFile::new(v, exec.clone())),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Directory(d) => d.name(),
+            Self::File(f) => f.name(),
+        }
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        match self {
+            Self::Directory(d) => d.attributes(),
+            Self::File(f) => f.attributes(),
+        }
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        match self {
+            Self::Directory(d) => d.timestamps(),
+            Self::File(f) => f.timestamps(),
+        }
+    }
+}
+
+/// An asynchronous version of [`Directory`].
+pub struct AsyncDirectory<P: AsyncDiskPartition> {
+    dir: Arc<Directory<Blocking<P>>>,
+    exec: Arc<dyn Executor>,
+}
+
+impl<P: AsyncDiskPartition> AsyncDirectory<P> {
+    pub fn name(&self) -> &str {
+        self.dir.name()
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.dir.attributes()
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        self.dir.timestamps()
+    }
+
+    /// Same as [`Directory::open()`].
+    pub async fn open(&self) -> Result<Vec<AsyncItem<P>>, directory::OpenError> {
+        let dir = self.dir.clone();
+        let items = blocking(&self.exec, move || dir.open()).await?;
+
+        Ok(items
+            .into_iter()
+            .map(|v| AsyncItem::new(v, &self.exec))
+            .collect())
+    }
+
+    /// Same as [`Directory::find()`].
+    pub async fn find(&self, name: &str) -> Result<Option<AsyncItem<P>>, directory::FindError> {
+        let dir = self.dir.clone();
+        let name = name.to_string();
+        let item = blocking(&self.exec, move || dir.find(&name)).await?;
+
+        Ok(item.map(|v| AsyncItem::new(v, &self.exec)))
+    }
+
+    /// Returns a [`Stream`] that reads the items in this directory lazily. The next item will be
+    /// read only when it is polled.
+    pub async fn items(&self) -> Result<AsyncItems<P>, directory::OpenError> {
+        let dir = self.dir.clone();
+        let items = blocking(&self.exec, move || dir.items()).await?;
+
+        Ok(AsyncItems {
+            items: Some(items),
+            pending: None,
+            exec: self.exec.clone(),
+        })
+    }
+}
+
+/// A [`Stream`] over the items in [`AsyncDirectory`].
+pub struct AsyncItems<P: AsyncDiskPartition> {
+    items: Option<Items<Blocking<P>>>,
+    pending: Option<Task<NextItem<P>>>,
+    exec: Arc<dyn Executor>,
+}
+
+impl<P: AsyncDiskPartition> Stream for AsyncItems<P> {
+    type Item = Result<AsyncItem<P>, directory::OpenError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        // Start reading the next item.
+        if this.pending.is_none() {
+            let mut items = match this.items.take() {
+                Some(v) => v,
+                None => return Poll::Ready(None),
+            };
+
+            this.pending = Some(Task::spawn(&this.exec, move || {
+                let next = items.next();
+                (items, next)
+            }));
+        }
+
+        // Wait for the item.
+        let (items, next) = ready!(Pin::new(this.pending.as_mut().unwrap()).poll(cx));
+
+        this.pending = None;
+
+        match next {
+            Some(Ok(v)) => {
+                this.items = Some(items);
+                Poll::Ready(Some(Ok(AsyncItem::new(v, &this.exec))))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// The result of reading the next item on the blocking thread.
+type NextItem<P> = (
+    Items<Blocking<P>>,
+    Option<Result<Item<Blocking<P>>, directory::OpenError>>,
+);
+
+/// An asynchronous version of [`File`].
+///
+/// This implements `AsyncRead` and `AsyncSeek` from `futures-io` when `std` feature is enabled.
+/// The same traits from Tokio are also implemented when `tokio` feature is enabled so it can be
+/// used with `tokio::io::copy()`.
+pub struct AsyncFile<P: AsyncDiskPartition> {
+    file: Arc<File<Blocking<P>>>,
+    exec: Arc<dyn Executor>,
+    pos: u64, // Kept here so seek does not need to wait for the blocking reads.
+    read: Option<Task<(Vec<u8>, crate::io::Result<usize>)>>,
+    #[cfg(feature = "tokio")]
+    seek: Option<SeekFrom>, // Pending seek of tokio::io::AsyncSeek.
+}
+
+impl<P: AsyncDiskPartition> AsyncFile<P> {
+    fn new(file: File<Blocking<P>>, exec: Arc<dyn Executor>) -> Self {
+        Self {
+            file: Arc::new(file),
+            exec,
+            pos: 0,
+            read: None,
+            #[cfg(feature = "tokio")]
+            seek: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.file.name()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.file.len()
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.file.attributes()
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        self.file.timestamps()
+    }
+
+    /// Reads the data at the current position into `buf`. See
+    /// [`Read::read()`][crate::io::Read::read()] for more details.
+    pub async fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+        poll_fn(|cx| self.poll_read_inner(cx, buf)).await
+    }
+
+    /// Same as [`File::read_at()`]. This can run at the same time as the other reads.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> crate::io::Result<usize> {
+        let file = self.file.clone();
+        let mut data = vec![0u8; buf.len()];
+        let (data, read) = blocking(&self.exec, move || {
+            let read = file.read_at(offset, &mut data);
+            (data, read)
+        })
+        .await;
+        let read = read?;
+
+        buf[..read].copy_from_slice(&data[..read]);
+
+        Ok(read)
+    }
+
+    /// Reads all data from the current position until the end of file and appends it to `buf`.
+    pub async fn read_to_end(&mut self, buf: &mut Vec<u8>) -> crate::io::Result<usize> {
+        poll_fn(|cx| self.poll_idle(cx)).await;
+
+        let file = self.file.clone();
+        let pos = self.pos;
+        let (data, read) = blocking(&self.exec, move || {
+            let mut data = Vec::new();
+            let mut chunk = vec![0u8; 0x10000];
+
+            loop {
+                match file.read_at(pos + data.len() as u64, &mut chunk) {
+                    Ok(0) => break,
+                    Ok(v) => data.extend_from_slice(&chunk[..v]),
+                    Err(e) => return (data, Err(e)),
+                }
+            }
+
+            let read = data.len();
+
+            (data, Ok(read))
+        })
+        .await;
+        let read = read?;
+
+        buf.extend_from_slice(&data);
+        self.pos += read as u64;
+
+        Ok(read)
+    }
+
+    /// Moves the current position. This does not read any data so it never blocks. This will fail
+    /// if a read that was started by `AsyncRead::poll_read()` is not completed.
+    pub fn seek(&mut self, pos: SeekFrom) -> crate::io::Result<u64> {
+        use crate::io::{Error, ErrorKind};
+
+        if self.read.is_some() {
+            return Err(Error::other("other operation is pending"));
+        }
+
+        // The position cannot be beyond the end of file, the same as File.
+        let len = self.len();
+        let pos = match pos {
+            SeekFrom::Start(v) => Some(v),
+            SeekFrom::End(v) => len.checked_add_signed(v),
+            SeekFrom::Current(v) => self.pos.checked_add_signed(v),
+        };
+
+        match pos {
+            Some(v) => {
+                self.pos = v.min(len);
+                Ok(self.pos)
+            }
+            None => Err(Error::from(ErrorKind::InvalidInput)),
+        }
+    }
+
+    /// Reads into `buf` on the blocking threads. The read will be started if there is no pending
+    /// read.
+    fn poll_read_inner(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<crate::io::Result<usize>> {
+        if self.read.is_none() {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            let file = self.file.clone();
+            let pos = self.pos;
+            let mut data = vec![0u8; buf.len()];
+
+            self.read = Some(Task::spawn(&self.exec, move || {
+                let read = file.read_at(pos, &mut data);
+                (data, read)
+            }));
+        }
+
+        // Wait for the read. The buffer can be smaller than the one that was used to start.
+        let (data, read) = ready!(self.poll_pending(cx));
+        let amount = read?.min(buf.len());
+
+        buf[..amount].copy_from_slice(&data[..amount]);
+        self.pos += amount as u64;
+
+        Poll::Ready(Ok(amount))
+    }
+
+    /// Waits for the pending read and discards it as if it never happened.
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.read.is_some() {
+            ready!(self.poll_pending(cx)).1.ok();
+        }
+
+        Poll::Ready(())
+    }
+
+    /// Polls the pending read. This must be called only when there is a pending read.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<(Vec<u8>, crate::io::Result<usize>)> {
+        let read = self.read.as_mut().unwrap();
+        let result = ready!(Pin::new(read).poll(cx));
+
+        self.read = None;
+
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<P: AsyncDiskPartition> tokio::io::AsyncRead for AsyncFile<P> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<crate::io::Result<()>> {
+        let amount = ready!(self
+            .get_mut()
+            .poll_read_inner(cx, buf.initialize_unfilled()))?;
+
+        buf.advance(amount);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<P: AsyncDiskPartition> tokio::io::AsyncSeek for AsyncFile<P> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> crate::io::Result<()> {
+        let this = self.get_mut();
+
+        if this.seek.is_some() {
+            return Err(crate::io::Error::other("other seek is pending"));
+        }
+
+        this.seek = Some(position);
+
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<crate::io::Result<u64>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_idle(cx));
+
+        match this.seek.take() {
+            Some(v) => Poll::Ready(this.seek(v)),
+            None => Poll::Ready(Ok(this.pos)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<P: AsyncDiskPartition> futures_io::AsyncRead for AsyncFile<P> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<crate::io::Result<usize>> {
+        self.get_mut().poll_read_inner(cx, buf)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<P: AsyncDiskPartition> futures_io::AsyncSeek for AsyncFile<P> {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<crate::io::Result<u64>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_idle(cx));
+
+        Poll::Ready(this.seek(pos))
+    }
+}
+
+/// A [`DiskPartition`] that blocks the current thread until the read on [`AsyncDiskPartition`] is
+/// completed. This must be used only on the blocking threads of the [`Executor`].
+struct Blocking<P> {
+    partition: P,
+    exec: Arc<dyn Executor>,
+}
+
+impl<P: AsyncDiskPartition> DiskPartition for Blocking<P> {
+    fn len(&self) -> Option<u64> {
+        self.partition.len()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.partition.block_size()
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut result = None;
+
+        self.exec.block_on(core::pin::pin!(async {
+            result = Some(self.partition.read(offset, buf).await);
+        }));
+
+        result.unwrap()
+    }
+}
+
+/// A task that was started with [`Executor::spawn_blocking()`].
+struct Task<R> {
+    done: Pin<Box<dyn Future<Output = ()> + Send>>,
+    result: Arc<Mutex<Option<R>>>,
+}
+
+impl<R: Send + 'static> Task<R> {
+    fn spawn<F>(exec: &Arc<dyn Executor>, f: F) -> Self
+    where
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let result = Arc::new(Mutex::new(None));
+        let output = result.clone();
+        let done = exec.spawn_blocking(Box::new(move || {
+            *output.lock().unwrap() = Some(f());
+        }));
+
+        Self { done, result }
+    }
+}
+
+impl<R> Future for Task<R> {
+    type Output = R;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(self.done.as_mut().poll(cx));
+
+        match self.result.lock().unwrap().take() {
+            Some(v) => Poll::Ready(v),
+            None => panic!("the blocking task was not completed"),
+        }
+    }
+}
+
+/// Runs `f` on the blocking threads of `exec` and waits for it to complete.
+async fn blocking<F, R>(exec: &Arc<dyn Executor>, f: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    Task::spawn(exec, f).await
+}
@@ -1,32 +1,42 @@
+use crate::sync::Mutex;
+use crate::{Cluster, Sector};
+
 pub(crate) struct Params {
-    pub fat_offset: u64,          // in sector
-    pub fat_length: u64,          // in sector
-    pub cluster_heap_offset: u64, // in sector
-    pub cluster_count: usize,     // not including the first 2 pseudo clusters
-    pub first_cluster_of_root_directory: usize,
+    pub volume_length: u64, // in sector
+    pub fat_offset: Sector,
+    pub fat_length: u64, // in sector
+    pub cluster_heap_offset: Sector,
+    pub cluster_count: usize, // not including the first 2 pseudo clusters
+    pub first_cluster_of_root_directory: Cluster,
     pub volume_flags: VolumeFlags,
+    pub percent_in_use: Mutex<u8>, // 0xff if not available
     pub bytes_per_sector: u64,
     pub sectors_per_cluster: u64,
     pub number_of_fats: u8,
 }
 
 impl Params {
-    /// Calculates offset in the image of a specified cluster.
-    pub fn cluster_offset(&self, index: usize) -> Option<u64> {
-        if index < 2 {
+    /// Calculates offset in the image of a specified cluster. Returns [`None`] if the cluster is
+    /// not in the Cluster Heap.
+    pub fn cluster_offset(&self, cluster: Cluster) -> Option<u64> {
+        if !cluster.is_valid(self.cluster_count) {
             return None;
         }
 
-        let index = index - 2;
-
-        if index >= self.cluster_count {
-            return None;
-        }
+        let index = u64::from(cluster.0 - 2);
+        let sector = Sector(self.cluster_heap_offset.0 + self.sectors_per_cluster * index);
 
-        let sector = self.cluster_heap_offset + self.sectors_per_cluster * index as u64;
-        let offset = self.bytes_per_sector * sector;
+        Some(sector.offset(self.bytes_per_sector))
+    }
 
-        Some(offset)
+    /// Calculates offset in the image of a specified sector. Returns [`None`] if the sector is
+    /// beyond the volume.
+    pub fn sector_offset(&self, sector: Sector) -> Option<u64> {
+        if sector.0 < self.volume_length {
+            Some(sector.offset(self.bytes_per_sector))
+        } else {
+            None
+        }
     }
 
     /// Gets the size of cluster, in bytes.
@@ -43,6 +53,28 @@ impl VolumeFlags {
     pub fn active_fat(self) -> usize {
         (self.0 & 1) as usize
     }
+
+    pub fn volume_dirty(self) -> bool {
+        (self.0 & 2) != 0
+    }
+
+    pub fn media_failure(self) -> bool {
+        (self.0 & 4) != 0
+    }
+
+    pub fn with_volume_dirty(self, v: bool) -> Self {
+        if v {
+            Self(self.0 | 2)
+        } else {
+            Self(self.0 & !2)
+        }
+    }
+}
+
+impl From<VolumeFlags> for u16 {
+    fn from(v: VolumeFlags) -> Self {
+        v.0
+    }
 }
 
 impl From<u16> for VolumeFlags {
@@ -0,0 +1,460 @@
+//! Discovering partitions on a whole-disk image or device.
+//!
+//! Both MBR (including the logical partitions in the extended partitions) and GPT are supported.
+//! Each partition can be opened as a [`PartitionView`], which can be passed to
+//! [`Root::open()`][crate::Root::open()] directly.
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+use byteorder::{ByteOrder, LE};
+use core::error::Error;
+use thiserror::Error;
+
+/// Reads the partition table on `disk`. GPT will be used if the disk has a protective MBR. The
+/// logical sector size of GPT is detected automatically while MBR always assume 512 bytes.
+pub fn read<D: DiskPartition + ?Sized>(disk: &D) -> Result<PartitionTable, ReadError> {
+    let mut mbr = [0u8; 512];
+
+    read_at(disk, 0, &mut mbr)?;
+
+    if mbr[510..] != [0x55, 0xaa] || &mbr[3..11] == b"EXFAT   " {
+        return Err(ReadError::NoPartitionTable);
+    }
+
+    // Check for GPT.
+    if (0..4).any(|i| mbr[446 + i * 16 + 4] == 0xee) {
+        for sector_size in [512, 4096] {
+            if let Some(v) = read_gpt(disk, sector_size)? {
+                return Ok(v);
+            }
+        }
+
+        return Err(ReadError::InvalidGptHeader);
+    }
+
+    read_mbr(disk, &mbr)
+}
+
+/// Finds the first partition that contains an exFAT volume and opens it.
+pub fn find_exfat<D: DiskPartition>(disk: D) -> Result<Option<PartitionView<D>>, ReadError> {
+    let table = read(&disk)?;
+
+    for p in table.partitions() {
+        let mut boot = [0u8; 11];
+
+        if p.len() < 512 {
+            continue;
+        }
+
+        read_at(&disk, p.offset(), &mut boot)?;
+
+        if &boot[3..] == b"EXFAT   " {
+            return Ok(Some(p.open(disk)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Represents a partition table that was read by [`read()`].
+pub struct PartitionTable {
+    scheme: Scheme,
+    partitions: Vec<Partition>,
+}
+
+impl PartitionTable {
+    /// Returns whether the table is MBR or GPT. A protective MBR is reported as GPT.
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Returns all non-empty partitions ordered by their number.
+    pub fn partitions(&self) -> &[Partition] {
+        &self.partitions
+    }
+}
+
+/// Type of a partition table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Mbr,
+    Gpt,
+}
+
+/// Represents an entry in the partition table.
+#[derive(Debug, Clone)]
+pub struct Partition {
+    number: usize,
+    offset: u64,
+    len: u64,
+    kind: PartitionType,
+    name: Option<String>,
+}
+
+impl Partition {
+    /// Returns the number of this partition, starting from 1. On MBR the logical partitions in
+    /// the extended partition start from 5.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Returns the offset of this partition on the disk, in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size of this partition, in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the type of this partition from the partition table.
+    pub fn kind(&self) -> PartitionType {
+        self.kind
+    }
+
+    /// Returns the partition name on GPT or [`None`] on MBR.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Opens this partition on `disk`, which must be the same disk that the table was read from.
+    pub fn open<D: DiskPartition>(&self, disk: D) -> PartitionView<D> {
+        PartitionView::new(disk, self.offset, self.len)
+    }
+}
+
+/// Type of a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The system ID on MBR. exFAT uses 0x07, which is shared with NTFS.
+    Mbr(u8),
+    /// The partition type GUID on GPT in on-disk byte order. exFAT uses the Microsoft Basic Data
+    /// Partition.
+    Gpt([u8; 16]),
+}
+
+impl PartitionType {
+    /// The Microsoft Basic Data Partition (EBD0A0A2-B9E5-4433-87C0-68B6B72699C7) in on-disk byte
+    /// order.
+    pub const BASIC_DATA: [u8; 16] = [
+        0xa2, 0xa0, 0xd0, 0xeb, 0xe5, 0xb9, 0x33, 0x44, 0x87, 0xc0, 0x68, 0xb6, 0xb7, 0x26, 0x99,
+        0xc7,
+    ];
+}
+
+/// An implementation of [`DiskPartition`] on a region of another [`DiskPartition`]. All offsets
+/// are relative to the start of the region and the access beyond the region will be truncated.
+pub struct PartitionView<D> {
+    disk: D,
+    offset: u64,
+    len: u64,
+}
+
+impl<D: DiskPartition> PartitionView<D> {
+    /// Creates a view of `len` bytes at `offset` on `disk`.
+    pub fn new(disk: D, offset: u64, len: u64) -> Self {
+        Self { disk, offset, len }
+    }
+
+    /// Returns the offset of this view on the underlying disk, in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the underlying disk.
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+
+    /// Returns the number of bytes that can be accessed at `offset`.
+    fn available(&self, offset: u64, len: usize) -> usize {
+        match self.len.checked_sub(offset) {
+            Some(v) => len.min(v.try_into().unwrap_or(usize::MAX)),
+            None => 0,
+        }
+    }
+}
+
+impl<D: DiskPartition> DiskPartition for PartitionView<D> {
+    fn len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn block_size(&self) -> u64 {
+        self.disk.block_size()
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let len = self.available(offset, buf.len());
+
+        if len == 0 {
+            return Ok(0);
+        }
+
+        self.disk.read(self.offset + offset, &mut buf[..len])
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.disk.flush()
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let len = len.min(self.len.saturating_sub(offset));
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        self.disk.discard(self.offset + offset, len)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.disk.is_writable()
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let len = self.available(offset, buf.len());
+
+        if len == 0 {
+            return Ok(0);
+        }
+
+        self.disk.write(self.offset + offset, &buf[..len])
+    }
+}
+
+fn read_mbr<D: DiskPartition + ?Sized>(
+    disk: &D,
+    mbr: &[u8; 512],
+) -> Result<PartitionTable, ReadError> {
+    let mut partitions = Vec::new();
+    let mut extended = None;
+
+    // Load the primary partitions.
+    for (i, e) in mbr[446..510].chunks_exact(16).enumerate() {
+        let kind = e[4];
+        let start = LE::read_u32(&e[8..]) as u64 * 512;
+        let len = LE::read_u32(&e[12..]) as u64 * 512;
+
+        if kind == 0 || len == 0 {
+            continue;
+        } else if matches!(kind, 0x05 | 0x0f | 0x85) {
+            extended = Some(start);
+            continue;
+        }
+
+        partitions.push(Partition {
+            number: i + 1,
+            offset: start,
+            len,
+            kind: PartitionType::Mbr(kind),
+            name: None,
+        });
+    }
+
+    // Load the logical partitions. The offset of the next EBR is relative to the first EBR.
+    if let Some(first) = extended {
+        let mut ebr = [0u8; 512];
+        let mut next = first;
+
+        for number in 5.. {
+            // Limit the number of logical partitions in case the chain contains a loop.
+            if number > 128 {
+                return Err(ReadError::InvalidExtendedPartition);
+            }
+
+            read_at(disk, next, &mut ebr)?;
+
+            if ebr[510..] != [0x55, 0xaa] {
+                return Err(ReadError::InvalidExtendedPartition);
+            }
+
+            let e = &ebr[446..462];
+            let len = LE::read_u32(&e[12..]) as u64 * 512;
+
+            if e[4] != 0 && len != 0 {
+                partitions.push(Partition {
+                    number,
+                    offset: next + LE::read_u32(&e[8..]) as u64 * 512,
+                    len,
+                    kind: PartitionType::Mbr(e[4]),
+                    name: None,
+                });
+            }
+
+            let link = &ebr[462..478];
+
+            match LE::read_u32(&link[8..]) {
+                0 => break,
+                v => next = first + v as u64 * 512,
+            }
+        }
+    }
+
+    Ok(PartitionTable {
+        scheme: Scheme::Mbr,
+        partitions,
+    })
+}
+
+fn read_gpt<D: DiskPartition + ?Sized>(
+    disk: &D,
+    sector_size: u64,
+) -> Result<Option<PartitionTable>, ReadError> {
+    // Load the primary header then the backup header if the primary one is corrupted.
+    let mut header = vec![0u8; sector_size as usize];
+    let mut found = false;
+
+    for lba in [
+        Some(1),
+        disk.len().and_then(|v| (v / sector_size).checked_sub(1)),
+    ] {
+        let offset = match lba {
+            Some(v) => v * sector_size,
+            None => continue,
+        };
+
+        read_at(disk, offset, &mut header)?;
+
+        if is_gpt_header(&header) {
+            found = true;
+            break;
+        }
+    }
+
+    if !found {
+        return Ok(None);
+    }
+
+    // Load the entries. The CRC does not protect against a crafted header so the values must be
+    // validated before use.
+    let first = match LE::read_u64(&header[72..]).checked_mul(sector_size) {
+        Some(v) => v,
+        None => return Err(ReadError::InvalidGptEntries),
+    };
+
+    let count = LE::read_u32(&header[80..]) as usize;
+    let size = LE::read_u32(&header[84..]) as usize;
+
+    if !size.is_power_of_two() || size < 128 || size as u64 > sector_size || count > 1024 {
+        return Err(ReadError::InvalidGptEntries);
+    }
+
+    let len = match count.checked_mul(size) {
+        Some(v) if v <= 0x100000 => v,
+        _ => return Err(ReadError::InvalidGptEntries),
+    };
+
+    let mut entries = vec![0u8; len];
+
+    read_at(disk, first, &mut entries)?;
+
+    if crc32(&entries) != LE::read_u32(&header[88..]) {
+        return Err(ReadError::InvalidGptEntries);
+    }
+
+    // Parse the entries.
+    let mut partitions = Vec::new();
+
+    for (i, e) in entries.chunks_exact(size).enumerate() {
+        let kind: [u8; 16] = e[..16].try_into().unwrap();
+        let start = LE::read_u64(&e[32..]);
+        let end = LE::read_u64(&e[40..]);
+
+        if kind == [0; 16] || end < start {
+            continue;
+        }
+
+        let offset = start.checked_mul(sector_size);
+        let len = (end - start)
+            .checked_add(1)
+            .and_then(|v| v.checked_mul(sector_size));
+        let (offset, len) = match (offset, len) {
+            (Some(o), Some(l)) if o.checked_add(l).is_some() => (o, l),
+            _ => return Err(ReadError::InvalidGptEntries),
+        };
+
+        let name: Vec<u16> = e[56..128]
+            .chunks_exact(2)
+            .map(LE::read_u16)
+            .take_while(|&c| c != 0)
+            .collect();
+
+        partitions.push(Partition {
+            number: i + 1,
+            offset,
+            len,
+            kind: PartitionType::Gpt(kind),
+            name: Some(String::from_utf16_lossy(&name)),
+        });
+    }
+
+    Ok(Some(PartitionTable {
+        scheme: Scheme::Gpt,
+        partitions,
+    }))
+}
+
+fn is_gpt_header(data: &[u8]) -> bool {
+    if &data[..8] != b"EFI PART" {
+        return false;
+    }
+
+    // The checksum is calculated with the checksum field set to zero.
+    let size = LE::read_u32(&data[12..]) as usize;
+
+    if !(92..=data.len()).contains(&size) {
+        return false;
+    }
+
+    let mut copy = data[..size].to_vec();
+
+    copy[16..20].fill(0);
+
+    crc32(&copy) == LE::read_u32(&data[16..])
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &b in data {
+        crc ^= u32::from(b);
+
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+fn read_at<D: DiskPartition + ?Sized>(
+    disk: &D,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<(), ReadError> {
+    match disk.read_exact(offset, buf) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ReadError::ReadFailed(offset, e)),
+    }
+}
+
+/// Represents an error for [`read()`].
+#[derive(Debug, Error)]
+pub enum ReadError {
+    #[error("cannot read the data at {0:#018x}")]
+    ReadFailed(u64, #[source] Box<dyn Error + Send + Sync>),
+
+    #[error("the disk does not have a partition table")]
+    NoPartitionTable,
+
+    #[error("the GPT header is not valid")]
+    InvalidGptHeader,
+
+    #[error("the GPT partition entries are not valid")]
+    InvalidGptEntries,
+
+    #[error("the extended partition is not valid")]
+    InvalidExtendedPartition,
+}
@@ -0,0 +1,142 @@
+use crate::alloc::AllocError;
+use crate::check::{CheckError, Checker, Problem};
+use crate::disk::DiskPartition;
+use crate::entries::EntrySetError;
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{Cluster, ExFat};
+use core::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// Represents a fix applied (or to be applied on dry-run) by
+/// [`Root::repair()`][crate::Root::repair()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    /// Releases a cluster that is allocated but not used by any item.
+    FreeCluster(Cluster),
+
+    /// Marks a cluster that is used by an item as allocated.
+    AllocateCluster(Cluster),
+
+    /// Rewrites the SetChecksum of the item.
+    RewriteChecksum(String),
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::FreeCluster(c) => write!(f, "release cluster {c}"),
+            Self::AllocateCluster(c) => write!(f, "mark cluster {c} as allocated"),
+            Self::RewriteChecksum(p) => write!(f, "rewrite SetChecksum of {p}"),
+        }
+    }
+}
+
+/// Fixes the problems that can be fixed without losing any data.
+pub(crate) fn repair<P: DiskPartition>(
+    exfat: &Arc<ExFat<P>>,
+    dry_run: bool,
+) -> Result<Vec<Fix>, RepairError> {
+    if !dry_run && !exfat.writable {
+        return Err(RepairError::ReadOnly);
+    }
+
+    // Find the problems.
+    let mut checker = Checker::new(exfat);
+
+    if let Err(e) = checker.check() {
+        return Err(RepairError::CheckFailed(e));
+    }
+
+    let mut fixes: Vec<Fix> = Vec::new();
+
+    for p in &checker.problems {
+        match p {
+            // The lost clusters are not reported if some items was not walked.
+            Problem::LostCluster(c) => fixes.push(Fix::FreeCluster(*c)),
+            Problem::UnallocatedCluster(c, _) => fixes.push(Fix::AllocateCluster(*c)),
+            _ => {}
+        }
+    }
+
+    for (path, _) in &checker.invalid_sets {
+        fixes.push(Fix::RewriteChecksum(path.clone()));
+    }
+
+    if dry_run {
+        return Ok(fixes);
+    }
+
+    if let Err(e) = exfat.set_dirty() {
+        return Err(RepairError::SetDirtyFailed(e));
+    }
+
+    // Fix the Allocation Bitmap and the FAT.
+    let mut alloc = exfat.alloc.lock().unwrap();
+    let mut fat = exfat.fat.lock().unwrap();
+
+    for fix in &fixes {
+        match fix {
+            Fix::FreeCluster(c) => {
+                if let Err(e) = fat.set(*c, 0) {
+                    return Err(RepairError::WriteFatFailed(e));
+                }
+
+                if let Err(e) = alloc.free(*c) {
+                    return Err(RepairError::UpdateBitmapFailed(e));
+                }
+            }
+            Fix::AllocateCluster(c) => {
+                if let Err(e) = alloc.claim(*c) {
+                    return Err(RepairError::UpdateBitmapFailed(e));
+                }
+            }
+            Fix::RewriteChecksum(_) => {}
+        }
+    }
+
+    if let Err(e) = fat.flush(&exfat.partition) {
+        return Err(RepairError::WriteFatFailed(e));
+    }
+
+    if let Err(e) = alloc.flush(&exfat.partition) {
+        return Err(RepairError::WriteBitmapFailed(e));
+    }
+
+    drop(fat);
+    drop(alloc);
+
+    // Fix the SetChecksum.
+    for (_, mut entries) in checker.invalid_sets {
+        if let Err(e) = entries.write(&exfat.partition) {
+            return Err(RepairError::WriteEntriesFailed(e));
+        }
+    }
+
+    Ok(fixes)
+}
+
+/// Represents an error for [`Root::repair()`][crate::Root::repair()].
+#[derive(Debug, Error)]
+pub enum RepairError {
+    #[error("the volume is read-only")]
+    ReadOnly,
+
+    #[error("cannot mark the volume as dirty")]
+    SetDirtyFailed(#[source] Box<dyn core::error::Error + Send + Sync>),
+
+    #[error("cannot check the volume")]
+    CheckFailed(#[source] CheckError),
+
+    #[error("cannot update the allocation bitmap")]
+    UpdateBitmapFailed(#[source] AllocError),
+
+    #[error("cannot write the allocation bitmap")]
+    WriteBitmapFailed(#[source] crate::alloc::WriteError),
+
+    #[error("cannot write the FAT")]
+    WriteFatFailed(#[source] crate::fat::WriteError),
+
+    #[error("cannot write directory entries")]
+    WriteEntriesFailed(#[source] EntrySetError),
+}
@@ -0,0 +1,154 @@
+//! Retrying the failed reads on an unreliable partition.
+//!
+//! Some devices (e.g. a flaky USB card reader) randomly fail a read that will succeed when
+//! retrying, or fail a large read that will succeed when it is split into smaller reads.
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+use core::error::Error;
+use core::time::Duration;
+
+/// Options for [`Retry`].
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Duration,
+    split: bool,
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of retries after the first failure. Zero disables the retry. The default is
+    /// `3`.
+    pub fn attempts(mut self, v: u32) -> Self {
+        self.attempts = v;
+        self
+    }
+
+    /// Sets the delay before the first retry, which will be doubled on each subsequent retry. The
+    /// delay is ignored without `std` since there is no way to sleep. The default is 10
+    /// milliseconds.
+    pub fn backoff(mut self, v: Duration) -> Self {
+        self.backoff = v;
+        self
+    }
+
+    /// Reads one block at a time when a read that is larger than
+    /// [`DiskPartition::block_size()`] still fails after all retries. Each block is retried
+    /// separately. The default is `true`.
+    pub fn split(mut self, v: bool) -> Self {
+        self.split = v;
+        self
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(10),
+            split: true,
+        }
+    }
+}
+
+/// An implementation of [`DiskPartition`] that retries the failed reads according to
+/// [`RetryPolicy`]. The writes are not retried.
+pub struct Retry<D> {
+    disk: D,
+    policy: RetryPolicy,
+}
+
+impl<D: DiskPartition> Retry<D> {
+    pub fn new(disk: D, policy: RetryPolicy) -> Self {
+        Self { disk, policy }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+
+    /// Reads `buf` at `offset` with the retries. Returns the last error if all attempts failed.
+    fn read_with_retry(
+        &self,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let mut delay = self.policy.backoff;
+        let mut retries = 0;
+
+        loop {
+            let e = match self.disk.read(offset, buf) {
+                Ok(v) => return Ok(v),
+                Err(e) => e,
+            };
+
+            if retries == self.policy.attempts {
+                return Err(e);
+            }
+
+            #[cfg(feature = "std")]
+            std::thread::sleep(delay);
+
+            delay = delay.saturating_mul(2);
+            retries += 1;
+        }
+    }
+}
+
+impl<D: DiskPartition> DiskPartition for Retry<D> {
+    fn len(&self) -> Option<u64> {
+        self.disk.len()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.disk.block_size()
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let e = match self.read_with_retry(offset, buf) {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+
+        // Fall back to the smaller reads on the block boundaries.
+        let block = self.disk.block_size();
+
+        if !self.policy.split || buf.len() as u64 <= block {
+            return Err(e);
+        }
+
+        let mut read = 0;
+
+        while read < buf.len() {
+            let offset = offset + read as u64;
+            let n = (block - offset % block).min((buf.len() - read) as u64) as usize;
+            let n = match self.read_with_retry(offset, &mut buf[read..(read + n)])? {
+                0 => break,
+                v => v as usize,
+            };
+
+            read += n;
+        }
+
+        Ok(read as u64)
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.disk.flush()
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.disk.discard(offset, len)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.disk.is_writable()
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        self.disk.write(offset, buf)
+    }
+}
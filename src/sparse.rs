@@ -0,0 +1,74 @@
+//! Keeping the destination sparse when writing to it.
+//!
+//! The destination must read as zeroes before writing (e.g. a file that was just extended with
+//! [`std::fs::File::set_len()`]) since the blocks that contain only zeroes are never written.
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+use core::error::Error;
+
+/// An implementation of [`DiskPartition`] that skips writing the blocks that contain only zeroes.
+/// The size of the block is [`DiskPartition::block_size()`] of the inner partition.
+pub struct Sparse<D> {
+    disk: D,
+}
+
+impl<D: DiskPartition> Sparse<D> {
+    pub fn new(disk: D) -> Self {
+        Self { disk }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.disk
+    }
+}
+
+impl<D: DiskPartition> DiskPartition for Sparse<D> {
+    fn len(&self) -> Option<u64> {
+        self.disk.len()
+    }
+
+    fn block_size(&self) -> u64 {
+        self.disk.block_size()
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        self.disk.read(offset, buf)
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.disk.flush()
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.disk.discard(offset, len)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.disk.is_writable()
+    }
+
+    fn write(&self, mut offset: u64, mut buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let block = self.disk.block_size();
+        let len = buf.len() as u64;
+
+        // Split the data on the block boundaries.
+        while !buf.is_empty() {
+            let n = (block - offset % block).min(buf.len() as u64) as usize;
+            let (data, rem) = buf.split_at(n);
+
+            if !is_zero(data) {
+                self.disk.write_exact(offset, data)?;
+            }
+
+            offset += n as u64;
+            buf = rem;
+        }
+
+        Ok(len)
+    }
+}
+
+/// Returns `true` if `data` contains only zeroes.
+pub fn is_zero(data: &[u8]) -> bool {
+    data.iter().all(|&b| b == 0)
+}
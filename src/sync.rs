@@ -0,0 +1,132 @@
+//! Synchronization primitives that work with and without `std`.
+//!
+//! Without `std` the [`Mutex`] is a spin lock and the [`OnceLock`] can be set only once without
+//! blocking, which is enough for the single-core environments like a bootloader.
+pub use alloc_crate::sync::Arc;
+#[cfg(feature = "std")]
+pub use std::sync::{Mutex, OnceLock};
+
+#[cfg(not(feature = "std"))]
+pub use self::nostd::*;
+
+#[cfg(not(feature = "std"))]
+mod nostd {
+    use core::cell::UnsafeCell;
+    use core::convert::Infallible;
+    use core::hint::spin_loop;
+    use core::ops::{Deref, DerefMut};
+    use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+    /// A spin lock with the same API as [`std::sync::Mutex`]. The lock never get poisoned.
+    pub struct Mutex<T> {
+        locked: AtomicBool,
+        value: UnsafeCell<T>,
+    }
+
+    impl<T> Mutex<T> {
+        pub const fn new(value: T) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        pub fn lock(&self) -> Result<MutexGuard<'_, T>, Infallible> {
+            while self
+                .locked
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                spin_loop();
+            }
+
+            Ok(MutexGuard(self))
+        }
+
+        pub fn get_mut(&mut self) -> Result<&mut T, Infallible> {
+            Ok(self.value.get_mut())
+        }
+    }
+
+    unsafe impl<T: Send> Send for Mutex<T> {}
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    /// An RAII guard of [`Mutex`].
+    pub struct MutexGuard<'a, T>(&'a Mutex<T>);
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &Self::Target {
+            // SAFETY: We hold the lock.
+            unsafe { &*self.0.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            // SAFETY: We hold the lock.
+            unsafe { &mut *self.0.value.get() }
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            self.0.locked.store(false, Ordering::Release);
+        }
+    }
+
+    /// A cell with the same API as [`std::sync::OnceLock`] except there are no blocking
+    /// initialization.
+    pub struct OnceLock<T> {
+        state: AtomicU8,
+        value: UnsafeCell<Option<T>>,
+    }
+
+    impl<T> OnceLock<T> {
+        const EMPTY: u8 = 0;
+        const SETTING: u8 = 1;
+        const READY: u8 = 2;
+
+        pub const fn new() -> Self {
+            Self {
+                state: AtomicU8::new(Self::EMPTY),
+                value: UnsafeCell::new(None),
+            }
+        }
+
+        pub fn get(&self) -> Option<&T> {
+            if self.state.load(Ordering::Acquire) != Self::READY {
+                return None;
+            }
+
+            // SAFETY: The value will never change once it is ready.
+            unsafe { (*self.value.get()).as_ref() }
+        }
+
+        pub fn set(&self, value: T) -> Result<(), T> {
+            if self
+                .state
+                .compare_exchange(
+                    Self::EMPTY,
+                    Self::SETTING,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                return Err(value);
+            }
+
+            // SAFETY: Only one thread can reach here and nobody read the value until it is ready.
+            unsafe { *self.value.get() = Some(value) };
+
+            self.state.store(Self::READY, Ordering::Release);
+
+            Ok(())
+        }
+    }
+
+    unsafe impl<T: Send> Send for OnceLock<T> {}
+    unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+}
@@ -0,0 +1,208 @@
+//! Exporting the directory tree as a tar archive.
+//!
+//! The archive is in the POSIX ustar format. A PAX extended header is written before the items
+//! whose path or size cannot be stored in the ustar header so the archive can be read by any
+//! modern tar implementation.
+use crate::directory::Item;
+use crate::disk::DiskPartition;
+use crate::io::{Error, ErrorKind, Write};
+use crate::prelude::*;
+use crate::walk::{Walk, WalkError};
+use thiserror::Error;
+
+const BLOCK_SIZE: usize = 512;
+
+/// The largest size that can be stored in the 11 octal digits of the ustar header.
+const MAX_SIZE: u64 = 0o77777777777;
+
+/// Writes all items of `walk` as a tar archive to `out`. The data is streamed so the archive is
+/// never held in memory. Use [`Root::walk()`][crate::Root::walk()] to export the whole volume.
+///
+/// The paths in the archive are relative to the root directory. The modified time and the
+/// read-only attribute are stored as mtime and mode of the item.
+pub fn export<P, W>(walk: Walk<P>, out: &mut W) -> Result<(), ExportError>
+where
+    P: DiskPartition,
+    W: Write + ?Sized,
+{
+    let mut buf = vec![0; 65536];
+
+    for item in walk {
+        let (path, item) = match item {
+            Ok(v) => v,
+            Err(e) => return Err(ExportError::WalkFailed(e)),
+        };
+        let path = path.trim_start_matches('/');
+
+        match &item {
+            Item::Directory(_) => write_header(out, &format!("{path}/"), &item, 0)?,
+            Item::File(f) => {
+                let len = f.len();
+
+                write_header(out, path, &item, len)?;
+
+                // Write the data.
+                let mut offset = 0;
+
+                while offset < len {
+                    let amount = buf.len().min((len - offset) as usize);
+                    let read = match f.read_at(offset, &mut buf[..amount]) {
+                        Ok(0) => {
+                            let e = Error::from(ErrorKind::UnexpectedEof);
+                            return Err(ExportError::ReadFileFailed(format!("/{path}"), e));
+                        }
+                        Ok(v) => v,
+                        Err(e) => return Err(ExportError::ReadFileFailed(format!("/{path}"), e)),
+                    };
+
+                    write(out, &buf[..read])?;
+                    offset += read as u64;
+                }
+
+                write_padding(out, len)?;
+            }
+        }
+    }
+
+    // The end of archive is marked by two zero blocks.
+    write(out, &[0; BLOCK_SIZE * 2])?;
+
+    out.flush().map_err(ExportError::WriteFailed)
+}
+
+fn write_header<P: DiskPartition, W: Write + ?Sized>(
+    out: &mut W,
+    path: &str,
+    item: &Item<P>,
+    size: u64,
+) -> Result<(), ExportError> {
+    // Split the path into prefix and name if it is too long.
+    let split = if path.len() <= 100 {
+        Some(("", path))
+    } else {
+        path.char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| (&path[..i], &path[(i + 1)..]))
+            .find(|(_, n)| !n.is_empty())
+    };
+
+    // Write the PAX extended header if required.
+    let mut records = String::new();
+
+    if split.is_none() {
+        records.push_str(&pax_record("path", path));
+    }
+
+    if size > MAX_SIZE {
+        records.push_str(&pax_record("size", &size.to_string()));
+    }
+
+    let mtime = item.timestamps().modified().unix_time().max(0) as u64;
+
+    if !records.is_empty() {
+        let header = ustar_header("", "PaxHeader", b'x', 0o644, records.len() as u64, mtime);
+
+        write(out, &header)?;
+        write(out, records.as_bytes())?;
+        write_padding(out, records.len() as u64)?;
+    }
+
+    // Write the ustar header.
+    let (prefix, name) = split.unwrap_or(("", path));
+    let mode = match item {
+        Item::Directory(_) => 0o755,
+        Item::File(_) if item.attributes().is_read_only() => 0o444,
+        Item::File(_) => 0o644,
+    };
+    let kind = match item {
+        Item::Directory(_) => b'5',
+        Item::File(_) => b'0',
+    };
+
+    write(
+        out,
+        &ustar_header(prefix, name, kind, mode, size.min(MAX_SIZE), mtime),
+    )
+}
+
+/// Builds a ustar header. The name and prefix will be truncated if they are too long.
+fn ustar_header(
+    prefix: &str,
+    name: &str,
+    kind: u8,
+    mode: u32,
+    size: u64,
+    mtime: u64,
+) -> [u8; BLOCK_SIZE] {
+    let mut h = [0u8; BLOCK_SIZE];
+    let name = &name.as_bytes()[..name.len().min(100)];
+    let prefix = &prefix.as_bytes()[..prefix.len().min(155)];
+
+    h[..name.len()].copy_from_slice(name);
+    octal(&mut h[100..108], mode.into());
+    octal(&mut h[108..116], 0); // uid
+    octal(&mut h[116..124], 0); // gid
+    octal(&mut h[124..136], size);
+    octal(&mut h[136..148], mtime.min(MAX_SIZE));
+    h[156] = kind;
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    h[345..(345 + prefix.len())].copy_from_slice(prefix);
+
+    // The checksum is calculated with the checksum field filled with spaces.
+    h[148..156].fill(b' ');
+
+    let sum: u32 = h.iter().map(|&b| u32::from(b)).sum();
+
+    octal(&mut h[148..155], sum.into());
+
+    h
+}
+
+/// Writes `v` as a zero-padded octal number terminated with NUL.
+fn octal(field: &mut [u8], v: u64) {
+    let digits = field.len() - 1;
+    let s = format!("{v:0digits$o}");
+
+    field[..digits].copy_from_slice(&s.as_bytes()[(s.len() - digits)..]);
+    field[digits] = 0;
+}
+
+/// Builds a PAX record, which is prefixed with its own length in decimal.
+fn pax_record(key: &str, value: &str) -> String {
+    let base = key.len() + value.len() + 3; // The space, the equals sign and the newline.
+    let mut len = base + 1;
+
+    while base + len.to_string().len() != len {
+        len += 1;
+    }
+
+    format!("{len} {key}={value}\n")
+}
+
+fn write_padding<W: Write + ?Sized>(out: &mut W, len: u64) -> Result<(), ExportError> {
+    let rem = (len % BLOCK_SIZE as u64) as usize;
+
+    if rem == 0 {
+        return Ok(());
+    }
+
+    write(out, &[0; BLOCK_SIZE][rem..])
+}
+
+fn write<W: Write + ?Sized>(out: &mut W, data: &[u8]) -> Result<(), ExportError> {
+    out.write_all(data).map_err(ExportError::WriteFailed)
+}
+
+/// Represents an error for [`export()`].
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("cannot walk the directory tree")]
+    WalkFailed(#[source] WalkError),
+
+    #[error("cannot read {0}")]
+    ReadFileFailed(String, #[source] Error),
+
+    #[error("cannot write the archive")]
+    WriteFailed(#[source] Error),
+}
@@ -0,0 +1,281 @@
+use byteorder::{ByteOrder, LE};
+
+/// Contains the timestamps of a file or a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Timestamps {
+    created: Timestamp,
+    modified: Timestamp,
+    accessed: Timestamp,
+}
+
+impl Timestamps {
+    pub fn new(created: Timestamp, modified: Timestamp, accessed: Timestamp) -> Self {
+        Self {
+            created,
+            modified,
+            accessed,
+        }
+    }
+
+    /// Loads the timestamps from the File Directory Entry.
+    pub(crate) fn load(data: &[u8; 32]) -> Self {
+        Self {
+            created: Timestamp::from_raw(LE::read_u32(&data[8..]), data[20], data[22]),
+            modified: Timestamp::from_raw(LE::read_u32(&data[12..]), data[21], data[23]),
+            accessed: Timestamp::from_raw(LE::read_u32(&data[16..]), 0, data[24]),
+        }
+    }
+
+    /// Writes the timestamps to the File Directory Entry. The LastAccessedTimestamp does not
+    /// have 10ms increment so the sub-second part of [`accessed()`][Self::accessed()] will be
+    /// truncated.
+    pub(crate) fn write(&self, data: &mut [u8; 32]) {
+        LE::write_u32(&mut data[8..], self.created.value);
+        LE::write_u32(&mut data[12..], self.modified.value);
+        LE::write_u32(&mut data[16..], self.accessed.value);
+        data[20] = self.created.increment;
+        data[21] = self.modified.increment;
+        data[22] = self.created.utc_offset;
+        data[23] = self.modified.utc_offset;
+        data[24] = self.accessed.utc_offset;
+    }
+
+    pub fn created(&self) -> Timestamp {
+        self.created
+    }
+
+    pub fn modified(&self) -> Timestamp {
+        self.modified
+    }
+
+    pub fn accessed(&self) -> Timestamp {
+        self.accessed
+    }
+
+    pub fn set_created(&mut self, v: Timestamp) {
+        self.created = v;
+    }
+
+    pub fn set_modified(&mut self, v: Timestamp) {
+        self.modified = v;
+    }
+
+    pub fn set_accessed(&mut self, v: Timestamp) {
+        self.accessed = v;
+    }
+}
+
+/// Represents a timestamp in the File Directory Entry.
+///
+/// The date and time are in the local time of the volume that write the timestamp. Use
+/// [`utc_offset()`][Self::utc_offset()] to convert it to UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    value: u32,
+    increment: u8, // in 10 milliseconds
+    utc_offset: u8,
+}
+
+impl Timestamp {
+    /// Creates a new [`Timestamp`] without UTC offset. Returns [`None`] if any of the values are
+    /// not valid or the year is not between 1980 and 2107.
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        if !(1980..=2107).contains(&year)
+            || !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return None;
+        }
+
+        let value = ((year - 1980) as u32) << 25
+            | (month as u32) << 21
+            | (day as u32) << 16
+            | (hour as u32) << 11
+            | (minute as u32) << 5
+            | (second / 2) as u32;
+
+        Some(Self::from_raw(value, (second % 2) * 100, 0))
+    }
+
+    /// Creates a new [`Timestamp`] in UTC from the number of seconds since 1970-01-01 00:00:00 UTC.
+    /// Returns [`None`] if the year is not between 1980 and 2107.
+    pub fn from_unix_time(secs: i64) -> Option<Self> {
+        // See civil_from_days() on http://howardhinnant.github.io/date_algorithms.html.
+        let days = secs.div_euclid(86400) + 719468;
+        let time = secs.rem_euclid(86400);
+        let era = days.div_euclid(146097);
+        let doe = days - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        let year = u16::try_from(year).ok()?;
+
+        Self::new(
+            year,
+            month as u8,
+            day as u8,
+            (time / 3600) as u8,
+            (time / 60 % 60) as u8,
+            (time % 60) as u8,
+        )?
+        .with_utc_offset(Some(0))
+    }
+
+    /// Creates a new [`Timestamp`] in UTC from [`SystemTime`][std::time::SystemTime]. Returns
+    /// [`None`] if the year is not between 1980 and 2107.
+    #[cfg(feature = "std")]
+    pub fn from_system_time(v: std::time::SystemTime) -> Option<Self> {
+        let v = v.duration_since(std::time::UNIX_EPOCH).ok()?;
+        let secs = i64::try_from(v.as_secs()).ok()?;
+
+        Self::from_unix_time(secs)?.with_millisecond(v.subsec_millis() as u16)
+    }
+
+    /// Returns the current time for the newly created items. This will be 1980-01-01 00:00:00
+    /// without `std` or on `wasm32-unknown-unknown` since there is no clock.
+    pub(crate) fn now() -> Self {
+        #[cfg(all(
+            feature = "std",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        if let Some(v) = Self::from_system_time(std::time::SystemTime::now()) {
+            return v;
+        }
+
+        Self::new(1980, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    /// Sets the millisecond of this timestamp. The value will be truncated to 10 milliseconds.
+    /// Returns [`None`] if `v` is 1000 or greater.
+    pub fn with_millisecond(mut self, v: u16) -> Option<Self> {
+        if v >= 1000 {
+            return None;
+        }
+
+        self.increment = (self.increment / 100) * 100 + (v / 10) as u8;
+
+        Some(self)
+    }
+
+    /// Sets the offset from UTC in minutes. The offset must be in 15 minutes increments and
+    /// between -16 and +15:45 hours, otherwise [`None`] will be returned.
+    pub fn with_utc_offset(mut self, v: Option<i16>) -> Option<Self> {
+        self.utc_offset = match v {
+            Some(v) if v % 15 != 0 || !(-960..=945).contains(&v) => return None,
+            Some(v) => 0x80 | ((v / 15) as u8 & 0x7f),
+            None => 0,
+        };
+
+        Some(self)
+    }
+
+    fn from_raw(value: u32, increment: u8, utc_offset: u8) -> Self {
+        Self {
+            value,
+            increment,
+            utc_offset,
+        }
+    }
+
+    pub fn year(self) -> u16 {
+        1980 + (self.value >> 25) as u16
+    }
+
+    pub fn month(self) -> u8 {
+        ((self.value >> 21) & 0xf) as u8
+    }
+
+    pub fn day(self) -> u8 {
+        ((self.value >> 16) & 0x1f) as u8
+    }
+
+    pub fn hour(self) -> u8 {
+        ((self.value >> 11) & 0x1f) as u8
+    }
+
+    pub fn minute(self) -> u8 {
+        ((self.value >> 5) & 0x3f) as u8
+    }
+
+    pub fn second(self) -> u8 {
+        ((self.value & 0x1f) * 2) as u8 + self.increment / 100
+    }
+
+    pub fn millisecond(self) -> u16 {
+        (self.increment % 100) as u16 * 10
+    }
+
+    /// Returns the offset from UTC in minutes or [`None`] if the offset is not available.
+    pub fn utc_offset(self) -> Option<i16> {
+        if (self.utc_offset & 0x80) == 0 {
+            return None;
+        }
+
+        // The offset is a 7-bit signed integer in 15 minutes increments.
+        let v = ((self.utc_offset << 1) as i8) >> 1;
+
+        Some(v as i16 * 15)
+    }
+
+    /// Returns the number of seconds since 1970-01-01 00:00:00 UTC. The timestamp is assumed to
+    /// be in UTC if [`utc_offset()`][Self::utc_offset()] is not available.
+    pub fn unix_time(self) -> i64 {
+        // See days_from_civil() on http://howardhinnant.github.io/date_algorithms.html.
+        let (m, d) = (self.month() as i64, self.day() as i64);
+        let y = self.year() as i64 - (m <= 2) as i64;
+        let era = y / 400;
+        let yoe = y - era * 400;
+        let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        let secs = days * 86400
+            + self.hour() as i64 * 3600
+            + self.minute() as i64 * 60
+            + self.second() as i64;
+
+        secs - self.utc_offset().unwrap_or(0) as i64 * 60
+    }
+
+    /// Converts this timestamp to [`SystemTime`][std::time::SystemTime]. See
+    /// [`unix_time()`][Self::unix_time()] for how the UTC offset is handled.
+    #[cfg(feature = "std")]
+    pub fn to_system_time(self) -> std::time::SystemTime {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let secs = self.unix_time();
+        let millis = Duration::from_millis(self.millisecond().into());
+
+        if secs < 0 {
+            UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + millis
+        } else {
+            UNIX_EPOCH + Duration::from_secs(secs as u64) + millis
+        }
+    }
+}
+
+/// Serializes the timestamp with its components instead of the raw value.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("Timestamp", 8)?;
+
+        s.serialize_field("year", &self.year())?;
+        s.serialize_field("month", &self.month())?;
+        s.serialize_field("day", &self.day())?;
+        s.serialize_field("hour", &self.hour())?;
+        s.serialize_field("minute", &self.minute())?;
+        s.serialize_field("second", &self.second())?;
+        s.serialize_field("millisecond", &self.millisecond())?;
+        s.serialize_field("utc_offset", &self.utc_offset())?;
+        s.end()
+    }
+}
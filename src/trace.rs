@@ -0,0 +1,32 @@
+//! Macros that forward to the [`tracing`](https://docs.rs/tracing) crate when the `tracing`
+//! feature is enabled.
+//!
+//! The spans are added with `#[cfg_attr(feature = "tracing", tracing::instrument(..))]` on the
+//! functions. The arguments of the events are still type-checked when the feature is disabled so
+//! the values that are used only for the events does not trigger the unused warnings.
+
+/// Emits an event about the progress of the expensive operations (e.g. opening a volume).
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+/// Emits an event about the individual I/O (e.g. the cache hits/misses).
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use {debug, trace};
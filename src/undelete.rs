@@ -0,0 +1,377 @@
+//! Finding the items that was deleted from a directory or lost with their directory.
+//!
+//! Deleting an item only clears the InUse bit of its directory entries and releases its clusters
+//! so the entries can be read until they are reused by a new item. The FAT chain of the deleted
+//! item is not preserved so only the first cluster and the length are known.
+use crate::check::{CheckError, Checker};
+use crate::cluster::{ClustersReader, NewError};
+use crate::disk::DiskPartition;
+use crate::entries::{checksum, EntriesReader, RawEntry, ReaderError};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::timestamp::Timestamps;
+use crate::{Cluster, ExFat, FileAttributes};
+use byteorder::{ByteOrder, LE};
+use core::error::Error;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+/// Scans the clusters that are not used by any item for the entry sets of the lost items.
+pub(crate) fn carve<P: DiskPartition>(
+    exfat: &Arc<ExFat<P>>,
+    cancel: Option<&AtomicBool>,
+) -> Result<Vec<DeletedItem>, CarveError> {
+    let mut checker = Checker::new(exfat);
+
+    if let Some(v) = cancel {
+        checker = checker.cancel(v);
+    }
+
+    match checker.check() {
+        Ok(_) => {}
+        Err(CheckError::Cancelled(_)) => return Err(CarveError::Cancelled(Vec::new())),
+        Err(e) => return Err(CarveError::CheckFailed(e)),
+    }
+
+    // Scan the clusters.
+    let params = &exfat.params;
+    let mut data = vec![0u8; params.cluster_size() as usize];
+    let mut items = Vec::new();
+
+    for cluster in (2..=u32::MAX).map(Cluster).take(params.cluster_count) {
+        if cancel.is_some_and(|v| v.load(Ordering::Relaxed)) {
+            return Err(CarveError::Cancelled(items));
+        } else if checker.is_used(cluster) {
+            continue;
+        }
+
+        let offset = match params.cluster_offset(cluster) {
+            Some(v) => v,
+            None => break,
+        };
+
+        if let Err(e) = exfat.partition.read_exact(offset, &mut data) {
+            return Err(CarveError::ReadClusterFailed(cluster, e));
+        }
+
+        let entries: Vec<[u8; 32]> = data
+            .chunks_exact(32)
+            .map(|v| v.try_into().unwrap())
+            .collect();
+        let mut i = 0;
+
+        while i < entries.len() {
+            match carve_set(&entries[i..]) {
+                Some((count, name)) => {
+                    let offset = offset + i as u64 * 32;
+                    let item = DeletedItem::new(exfat, &entries[i], &entries[i + 1], name, offset);
+
+                    items.push(item);
+                    i += count;
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+/// Returns the number of entries and the name if `entries` start with a valid entry set of a
+/// File Directory Entry. The entry set that cross the cluster boundary cannot be detected.
+fn carve_set(entries: &[[u8; 32]]) -> Option<(usize, Vec<u16>)> {
+    // Check the File Directory Entry. All entries must have the same InUse bit.
+    let file = &entries[0];
+    let in_use = file[0] & 0x80;
+    let count = file[1] as usize + 1;
+
+    if file[0] & 0x7f != 0x05 || !(3..=19).contains(&count) || count > entries.len() {
+        return None;
+    }
+
+    let set = &entries[..count];
+    let name_length = set[1][3] as usize;
+    let names = name_length.div_ceil(15);
+
+    if set[1][0] != (0x40 | in_use) || name_length == 0 || names > count - 2 {
+        return None;
+    }
+
+    if !set[2..(2 + names)].iter().all(|e| e[0] == (0x41 | in_use)) {
+        return None;
+    }
+
+    // The SetChecksum was calculated when the InUse bit was set.
+    let mut copy = set.to_vec();
+
+    for e in &mut copy {
+        e[0] |= 0x80;
+    }
+
+    if checksum(&copy) != LE::read_u16(&file[2..]) {
+        return None;
+    }
+
+    // Load the name.
+    let name = set[2..(2 + names)]
+        .iter()
+        .flat_map(|e| e[2..].chunks_exact(2).map(LE::read_u16))
+        .take(name_length)
+        .collect();
+
+    Some((count, name))
+}
+
+/// An iterator over the deleted items in a directory.
+pub struct DeletedItems<P: DiskPartition> {
+    reader: EntriesReader<P>,
+    pending: Option<RawEntry>,
+}
+
+impl<P: DiskPartition> DeletedItems<P> {
+    pub(crate) fn new(
+        exfat: Arc<ExFat<P>>,
+        first_cluster: Cluster,
+        data_length: Option<u64>,
+        no_fat_chain: Option<bool>,
+    ) -> Result<Self, ScanError> {
+        let reader = match ClustersReader::new(exfat, first_cluster, data_length, no_fat_chain) {
+            Ok(v) => EntriesReader::new(v),
+            Err(e) => return Err(ScanError::CreateClustersReaderFailed(e)),
+        };
+
+        Ok(Self {
+            reader,
+            pending: None,
+        })
+    }
+
+    fn read(&mut self) -> Result<Option<RawEntry>, ScanError> {
+        if let Some(v) = self.pending.take() {
+            return Ok(Some(v));
+        }
+
+        match self.reader.read() {
+            Ok(v) => Ok(v),
+            Err(e) => Err(ScanError::ReadEntryFailed(e)),
+        }
+    }
+
+    /// Loads the deleted entry set that start with `file`. Returns [`None`] if the stream
+    /// extension was reused.
+    fn load(&mut self, file: RawEntry) -> Result<Option<DeletedItem>, ScanError> {
+        // Load the stream extension.
+        let stream = match self.read()? {
+            Some(v) if v.data()[0] == 0x40 => v,
+            v => {
+                self.pending = v;
+                return Ok(None);
+            }
+        };
+
+        // Load the file names. The entries that was reused will make the name incomplete.
+        let data = stream.data();
+        let name_length = data[3] as usize;
+        let mut name = Vec::with_capacity(name_length);
+        let mut secondaries = file.data()[1].saturating_sub(1);
+
+        while name.len() < name_length && secondaries > 0 {
+            let entry = match self.read()? {
+                Some(v) if v.data()[0] == 0x41 => v,
+                v => {
+                    self.pending = v;
+                    break;
+                }
+            };
+
+            let n = (name_length - name.len()).min(15);
+
+            name.extend((0..n).map(|i| LE::read_u16(&entry.data()[(2 + i * 2)..])));
+            secondaries -= 1;
+        }
+
+        Ok(Some(DeletedItem::new(
+            self.reader.exfat(),
+            file.data(),
+            data,
+            name,
+            file.offset(),
+        )))
+    }
+}
+
+impl<P: DiskPartition> Iterator for DeletedItems<P> {
+    type Item = Result<DeletedItem, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Find the deleted File Directory Entry.
+            let entry = match self.read() {
+                Ok(Some(v)) => v,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if entry.data()[0] != 0x05 {
+                continue;
+            }
+
+            match self.load(entry) {
+                Ok(Some(v)) => return Some(Ok(v)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Represents a deleted item in a directory.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeletedItem {
+    name: String,
+    name_complete: bool,
+    in_use: bool,
+    attributes: FileAttributes,
+    timestamps: Timestamps,
+    no_fat_chain: bool,
+    valid_data_length: u64,
+    data_length: u64,
+    first_cluster: Cluster,
+    clusters: Range<usize>,
+    overwritten: bool,
+    offset: u64,
+}
+
+impl DeletedItem {
+    fn new<P: DiskPartition>(
+        exfat: &ExFat<P>,
+        file: &[u8; 32],
+        stream: &[u8; 32],
+        name: Vec<u16>,
+        offset: u64,
+    ) -> Self {
+        // Check if the clusters was reused.
+        let first_cluster = Cluster(LE::read_u32(&stream[20..]));
+        let data_length = LE::read_u64(&stream[24..]);
+        let count = data_length.div_ceil(exfat.params.cluster_size()) as usize;
+        let clusters = first_cluster.index()..first_cluster.index().saturating_add(count);
+        let alloc = exfat.alloc.lock().unwrap();
+        let overwritten = clusters.clone().any(|c| match Cluster::try_from(c) {
+            Ok(v) => !v.is_valid(exfat.params.cluster_count) || alloc.is_allocated(v),
+            Err(_) => true,
+        });
+
+        drop(alloc);
+
+        Self {
+            name: String::from_utf16_lossy(&name),
+            name_complete: name.len() == stream[3] as usize,
+            in_use: (file[0] & 0x80) != 0,
+            attributes: FileAttributes(LE::read_u16(&file[4..])),
+            timestamps: Timestamps::load(file),
+            no_fat_chain: (stream[1] & 2) != 0,
+            valid_data_length: LE::read_u64(&stream[8..]),
+            first_cluster,
+            clusters,
+            data_length,
+            overwritten,
+            offset,
+        }
+    }
+
+    /// Returns the name of the item. The characters that cannot be decoded will be replaced with
+    /// U+FFFD.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `false` if some of the file name entries was reused by another item, which mean
+    /// [`name()`][Self::name()] contains only the beginning of the name.
+    pub fn is_name_complete(&self) -> bool {
+        self.name_complete
+    }
+
+    /// Returns `true` if the InUse bit of the entry set is still set, which is possible only on the
+    /// items that was found by [`Root::carve()`][crate::Root::carve()].
+    pub fn is_in_use(&self) -> bool {
+        self.in_use
+    }
+
+    pub fn attributes(&self) -> FileAttributes {
+        self.attributes
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attributes.is_directory()
+    }
+
+    pub fn timestamps(&self) -> &Timestamps {
+        &self.timestamps
+    }
+
+    /// Returns `true` if the clusters of the item was contiguous, which mean
+    /// [`clusters()`][Self::clusters()] is exact.
+    pub fn no_fat_chain(&self) -> bool {
+        self.no_fat_chain
+    }
+
+    /// Returns the length of the item, which is DataLength.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.data_length
+    }
+
+    pub fn valid_data_length(&self) -> u64 {
+        self.valid_data_length
+    }
+
+    /// Returns the first cluster of the item or zero if the item was empty.
+    pub fn first_cluster(&self) -> Cluster {
+        self.first_cluster
+    }
+
+    /// Returns the clusters of the item assuming they are contiguous. This is the best guess when
+    /// [`no_fat_chain()`][Self::no_fat_chain()] is `false` since the FAT chain is not available.
+    pub fn clusters(&self) -> Range<usize> {
+        self.clusters.clone()
+    }
+
+    /// Returns `true` if any cluster in [`clusters()`][Self::clusters()] is allocated to another
+    /// item or not valid at the time of the scan, which mean the data was likely overwritten.
+    pub fn is_overwritten(&self) -> bool {
+        self.overwritten
+    }
+
+    /// Returns the offset of the File Directory Entry on the partition. For the items that was
+    /// found by [`Root::carve()`][crate::Root::carve()] this is the only way to know where they
+    /// are.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// Represents an error when scanning for the deleted items.
+#[derive(Debug, Error)]
+pub enum ScanError {
+    #[error("cannot create a clusters reader for the directory")]
+    CreateClustersReaderFailed(#[source] NewError),
+
+    #[error("cannot read an entry")]
+    ReadEntryFailed(#[source] ReaderError),
+}
+
+/// Represents an error for [`Root::carve()`][crate::Root::carve()].
+#[derive(Debug, Error)]
+pub enum CarveError {
+    #[error("cannot walk the directory tree")]
+    CheckFailed(#[source] CheckError),
+
+    #[error("cannot read cluster {0}")]
+    ReadClusterFailed(Cluster, #[source] Box<dyn Error + Send + Sync>),
+
+    /// The scan was cancelled. Contains the items that was found before the cancellation.
+    #[error("the scan was cancelled")]
+    Cancelled(Vec<DeletedItem>),
+}
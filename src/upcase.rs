@@ -0,0 +1,225 @@
+use crate::disk::DiskPartition;
+use crate::entries::ClusterAllocation;
+use crate::fat::Fat;
+use crate::param::Params;
+use crate::prelude::*;
+use crate::Cluster;
+use byteorder::{ByteOrder, LE};
+use core::fmt::Display;
+use core::ops::Range;
+
+/// Represents the decompressed Up-case Table of the volume.
+///
+/// Use [`Root::upcase_table()`][crate::Root::upcase_table()] to get the table of the opened volume.
+pub struct UpcaseTable {
+    map: Vec<u16>, // The compressed table if compressed is true.
+    compressed: bool,
+}
+
+impl UpcaseTable {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub(crate) fn load<P: DiskPartition>(
+        params: &Params,
+        partition: &P,
+        fat: &Fat,
+        alloc: &ClusterAllocation,
+        checksum: Option<u32>,
+        compressed: bool,
+    ) -> Result<Self, LoadError> {
+        // Check the size of the table.
+        let len = alloc.data_length();
+
+        if len == 0 || len > 0x20000 || !len.is_multiple_of(2) {
+            return Err(LoadError::InvalidDataLength);
+        }
+
+        // Read the table.
+        let cluster_size = params.cluster_size();
+        let mut data = vec![0u8; len as usize];
+        let mut clusters = fat.get_cluster_chain(partition, alloc.first_cluster());
+
+        for chunk in data.chunks_mut(cluster_size as usize) {
+            let cluster = match clusters.next() {
+                Some(v) => v,
+                None => match clusters.finish() {
+                    Ok(_) => return Err(LoadError::InvalidDataLength),
+                    Err(e) => return Err(LoadError::ReadChainFailed(e)),
+                },
+            };
+
+            let offset = match params.cluster_offset(cluster) {
+                Some(v) => v,
+                None => return Err(LoadError::InvalidCluster(cluster)),
+            };
+
+            if let Err(e) = partition.read_exact(offset, chunk) {
+                return Err(LoadError::ReadFailed(offset, e));
+            }
+        }
+
+        // Verify the checksum.
+        if let Some(checksum) = checksum {
+            let actual = data
+                .iter()
+                .fold(0u32, |c, &b| c.rotate_right(1).wrapping_add(b.into()));
+
+            if actual != checksum {
+                return Err(LoadError::ChecksumMismatch);
+            }
+        }
+
+        // Keep the table compressed if requested.
+        let mut table = vec![0u16; data.len() / 2];
+
+        LE::read_u16_into(&data, &mut table);
+
+        if compressed {
+            return Ok(Self {
+                map: table,
+                compressed,
+            });
+        }
+
+        // Decompress the table. The compressed table use 0xFFFF followed by the number of
+        // characters that map to itself.
+        let mut map: Vec<u16> = Vec::with_capacity(0x10000);
+        let mut i = 0;
+
+        while i < table.len() && map.len() < 0x10000 {
+            let v = table[i];
+
+            if v == 0xffff && i + 1 < table.len() {
+                let count = table[i + 1] as usize;
+                let start = map.len();
+                let end = (start + count).min(0x10000);
+
+                map.extend((start..end).map(|c| c as u16));
+                i += 2;
+            } else {
+                map.push(v);
+                i += 1;
+            }
+        }
+
+        // Characters that are not in the table map to itself.
+        let len = map.len();
+
+        map.extend((len..0x10000).map(|c| c as u16));
+
+        Ok(Self { map, compressed })
+    }
+
+    /// Converts `c` to upper case.
+    pub fn to_upper(&self, c: u16) -> u16 {
+        if !self.compressed {
+            return self.map[c as usize];
+        }
+
+        // Walk the compressed table until we reach the character.
+        let c = c as usize;
+        let mut start = 0;
+        let mut i = 0;
+
+        while i < self.map.len() {
+            let v = self.map[i];
+
+            if v == 0xffff && i + 1 < self.map.len() {
+                start += self.map[i + 1] as usize;
+                i += 2;
+
+                if c < start {
+                    break;
+                }
+            } else if c == start {
+                return v;
+            } else {
+                start += 1;
+                i += 1;
+            }
+        }
+
+        c as u16
+    }
+
+    /// Returns an iterator over the mapping of all 65536 characters. Each item is the character
+    /// and its upper case, in ascending order of the character.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            table: self,
+            chars: 0..0x10000,
+        }
+    }
+
+    /// Returns `true` if `a` and `b` are equal when compared case-insensitively.
+    pub(crate) fn eq(&self, a: &[u16], b: &[u16]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(&a, &b)| self.to_upper(a) == self.to_upper(b))
+    }
+}
+
+impl<'a> IntoIterator for &'a UpcaseTable {
+    type Item = (u16, u16);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the mapping of [`UpcaseTable`].
+pub struct Iter<'a> {
+    table: &'a UpcaseTable,
+    chars: Range<u32>,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (u16, u16);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = self.chars.next()? as u16;
+
+        Some((c, self.table.to_upper(c)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chars.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Represents an error for [`Root::open()`][crate::Root::open()] when the Up-case Table cannot be
+/// loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    InvalidDataLength,
+    InvalidCluster(Cluster),
+    ReadChainFailed(crate::fat::ChainError),
+    ChecksumMismatch,
+
+    ReadFailed(u64, Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidDataLength => f.write_str("invalid DataLength"),
+            Self::InvalidCluster(c) => write!(f, "cluster {c} is not valid"),
+            Self::ReadChainFailed(_) => f.write_str("cannot read the cluster chain"),
+            Self::ChecksumMismatch => f.write_str("TableChecksum does not match"),
+            Self::ReadFailed(offset, _) => write!(f, "cannot read the data at {offset:#018x}"),
+        }
+    }
+}
+
+impl core::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::ReadChainFailed(e) => Some(e),
+            Self::ReadFailed(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use crate::disk::DiskPartition;
+use io_uring::{opcode, squeue, types, EnterFlags, IoUring};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{IoSliceMut, Seek, SeekFrom};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// An implementation of [`DiskPartition`] that submits the I/O through io_uring.
+///
+/// Each call of [`DiskPartition`] methods waits for its own request to complete. Use
+/// [`read_batch()`][Self::read_batch()] to keep many reads in flight at the same time (e.g. when
+/// extracting a lot of clusters). The requests on the same [`UringPartition`] are serialized.
+pub struct UringPartition {
+    file: File,
+    ring: Mutex<Option<IoUring>>,
+    depth: u32,
+    len: u64,
+    writable: bool,
+}
+
+impl UringPartition {
+    /// Opens the file or device at `path` as read-only. `depth` is the maximum number of requests
+    /// to keep in flight.
+    pub fn open<T: AsRef<Path>>(path: T, depth: u32) -> Result<Self, OpenError> {
+        Self::open_with(path.as_ref(), depth, false)
+    }
+
+    /// Same as [`open()`][Self::open] but the resulting [`UringPartition`] is writable.
+    pub fn open_rw<T: AsRef<Path>>(path: T, depth: u32) -> Result<Self, OpenError> {
+        Self::open_with(path.as_ref(), depth, true)
+    }
+
+    /// Returns the maximum number of requests to keep in flight.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Reads all `requests` with up to [`depth()`][Self::depth()] requests in flight. `done` will
+    /// be called with the index of the request and its result as soon as it is completed, which
+    /// may not be in the same order as `requests`. The result is the number of bytes read, which
+    /// can be less than the size of the buffer.
+    pub fn read_batch<F>(
+        &self,
+        requests: &mut [ReadRequest<'_>],
+        done: F,
+    ) -> Result<(), SubmitError>
+    where
+        F: FnMut(usize, std::io::Result<u64>),
+    {
+        let fd = types::Fd(self.file.as_raw_fd());
+
+        self.submit(
+            requests.len(),
+            |i| {
+                let req = &mut requests[i];
+
+                opcode::Read::new(fd, req.buf.as_mut_ptr(), req.buf.len() as u32)
+                    .offset(req.offset)
+                    .build()
+            },
+            done,
+        )
+    }
+
+    fn open_with(path: &Path, depth: u32, writable: bool) -> Result<Self, OpenError> {
+        // Open the file.
+        let mut file = match OpenOptions::new().read(true).write(writable).open(path) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::OpenFailed(e)),
+        };
+
+        // Get the size. This work on both a regular file and a block device.
+        let len = match file.seek(SeekFrom::End(0)) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::GetLengthFailed(e)),
+        };
+
+        // Set up the ring.
+        let depth = depth.max(1);
+        let ring = match IoUring::new(depth) {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::SetupFailed(e)),
+        };
+
+        Ok(Self {
+            file,
+            ring: Mutex::new(Some(ring)),
+            depth,
+            len,
+            writable,
+        })
+    }
+
+    /// Submits `count` requests that are built by `entry` and waits for all of them to complete.
+    /// The buffers referenced by the requests must live until this method returns.
+    fn submit<E, F>(&self, count: usize, mut entry: E, mut done: F) -> Result<(), SubmitError>
+    where
+        E: FnMut(usize) -> squeue::Entry,
+        F: FnMut(usize, std::io::Result<u64>),
+    {
+        let mut lock = self.ring.lock().unwrap();
+        let ring = match lock.as_mut() {
+            Some(v) => v,
+            None => return Err(SubmitError::RingBroken),
+        };
+
+        let mut next = 0;
+        let mut inflight = 0;
+
+        loop {
+            // Fill the submission queue.
+            while next < count && inflight < self.depth as usize {
+                let entry = entry(next).user_data(next as u64);
+
+                // SAFETY: The buffer of the entry is valid until we return, which only happen
+                // after all requests that was consumed by the kernel were completed.
+                if unsafe { ring.submission().push(&entry) }.is_err() {
+                    break;
+                }
+
+                next += 1;
+                inflight += 1;
+            }
+
+            if inflight == 0 {
+                break;
+            }
+
+            // Wait for at least one request.
+            if let Err(e) = ring.submit_and_wait(1) {
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                // The kernel may still write to the buffers of the submitted requests so wait for
+                // them before returning. The entries that was not consumed by the kernel are
+                // discarded when the ring is destroyed.
+                let pending = inflight - ring.submission().len();
+
+                Self::drain(ring, pending, &mut done);
+                *lock = None;
+
+                return Err(SubmitError::SubmitFailed(e));
+            }
+
+            for cqe in ring.completion() {
+                let result = match cqe.result() {
+                    v if v < 0 => Err(std::io::Error::from_raw_os_error(-v)),
+                    v => Ok(v as u64),
+                };
+
+                done(cqe.user_data() as usize, result);
+                inflight -= 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reaps the completions until `pending` requests are completed without submitting the
+    /// remaining entries. This never give up since returning earlier would free the buffers that
+    /// are still in use by the kernel.
+    fn drain<F>(ring: &mut IoUring, mut pending: usize, done: &mut F)
+    where
+        F: FnMut(usize, std::io::Result<u64>),
+    {
+        while pending != 0 {
+            for cqe in ring.completion() {
+                let result = match cqe.result() {
+                    v if v < 0 => Err(std::io::Error::from_raw_os_error(-v)),
+                    v => Ok(v as u64),
+                };
+
+                done(cqe.user_data() as usize, result);
+                pending -= 1;
+            }
+
+            if pending == 0 {
+                break;
+            }
+
+            // SAFETY: No entries will be submitted and no argument is passed.
+            let flags = EnterFlags::GETEVENTS.bits();
+
+            if unsafe { ring.submitter().enter::<()>(0, 1, flags, None) }.is_err() {
+                // The completions are still posted by the kernel so keep polling.
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    /// Submits a single request and returns its result.
+    fn submit_one(&self, entry: squeue::Entry) -> Result<std::io::Result<u64>, SubmitError> {
+        let mut entry = Some(entry);
+        let mut result = None;
+
+        self.submit(1, |_| entry.take().unwrap(), |_, r| result = Some(r))?;
+
+        Ok(result.unwrap())
+    }
+}
+
+impl DiskPartition for UringPartition {
+    fn len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entry = opcode::Fsync::new(fd)
+            .flags(types::FsyncFlags::DATASYNC)
+            .build();
+
+        match self.submit_one(entry) {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(WriteError::FlushFailed(e).into()),
+            Err(e) => Err(WriteError::SubmitFailed(e).into()),
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), buf.len() as u32)
+            .offset(offset)
+            .build();
+
+        match self.submit_one(entry) {
+            Ok(Ok(v)) => Ok(v),
+            Ok(Err(e)) => Err(ReadError::ReadFailed(e).into()),
+            Err(e) => Err(ReadError::SubmitFailed(e).into()),
+        }
+    }
+
+    fn read_vectored_at(
+        &self,
+        offset: u64,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        // IoSliceMut is guaranteed to be ABI compatible with iovec on Unix.
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entry = opcode::Readv::new(fd, bufs.as_mut_ptr().cast(), bufs.len() as u32)
+            .offset(offset)
+            .build();
+
+        match self.submit_one(entry) {
+            Ok(Ok(v)) => Ok(v),
+            Ok(Err(e)) => Err(ReadError::ReadFailed(e).into()),
+            Err(e) => Err(ReadError::SubmitFailed(e).into()),
+        }
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if !self.writable {
+            return Err(WriteError::ReadOnly.into());
+        }
+
+        let fd = types::Fd(self.file.as_raw_fd());
+        let entry = opcode::Write::new(fd, buf.as_ptr(), buf.len() as u32)
+            .offset(offset)
+            .build();
+
+        match self.submit_one(entry) {
+            Ok(Ok(v)) => Ok(v),
+            Ok(Err(e)) => Err(WriteError::WriteFailed(e).into()),
+            Err(e) => Err(WriteError::SubmitFailed(e).into()),
+        }
+    }
+}
+
+/// A read for [`UringPartition::read_batch()`].
+pub struct ReadRequest<'a> {
+    pub offset: u64,
+    pub buf: &'a mut [u8],
+}
+
+/// Represents an error for [`UringPartition::open()`].
+#[derive(Debug, Error)]
+pub enum OpenError {
+    #[error("cannot open the file")]
+    OpenFailed(#[source] std::io::Error),
+
+    #[error("cannot get the size of the file")]
+    GetLengthFailed(#[source] std::io::Error),
+
+    #[error("cannot set up io_uring")]
+    SetupFailed(#[source] std::io::Error),
+}
+
+/// Represents an error for [`UringPartition::read_batch()`].
+#[derive(Debug, Error)]
+pub enum SubmitError {
+    #[error("cannot submit the requests")]
+    SubmitFailed(#[source] std::io::Error),
+
+    #[error("the ring was destroyed due to the previous failure")]
+    RingBroken,
+}
+
+/// Represents an error for [`UringPartition::read()`].
+#[derive(Debug, Error)]
+enum ReadError {
+    #[error("cannot submit the request")]
+    SubmitFailed(#[source] SubmitError),
+
+    #[error("cannot read the file")]
+    ReadFailed(#[source] std::io::Error),
+}
+
+/// Represents an error for [`UringPartition::write()`].
+#[derive(Debug, Error)]
+enum WriteError {
+    #[error("the file is not opened for writing")]
+    ReadOnly,
+
+    #[error("cannot submit the request")]
+    SubmitFailed(#[source] SubmitError),
+
+    #[error("cannot write the file")]
+    WriteFailed(#[source] std::io::Error),
+
+    #[error("cannot flush the file")]
+    FlushFailed(#[source] std::io::Error),
+}
@@ -0,0 +1,92 @@
+//! Computing how much space is used by a directory tree.
+use crate::directory::{Directory, Item, OpenError};
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+
+/// Contains the result of [`Directory::disk_usage()`][crate::directory::Directory::disk_usage()]
+/// and [`Root::disk_usage()`][crate::Root::disk_usage()].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DiskUsage {
+    files: u64,
+    directories: u64,
+    len: u64,
+    allocated: u64,
+}
+
+impl DiskUsage {
+    /// Returns the number of files in the tree.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Returns the number of directories in the tree, not including the directory where the
+    /// computation was started.
+    pub fn directories(&self) -> u64 {
+        self.directories
+    }
+
+    /// Returns the sum of the length of all files.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes of the clusters that was allocated to the files and the
+    /// directories.
+    pub fn allocated(&self) -> u64 {
+        self.allocated
+    }
+}
+
+/// Computes the usage of `items` and all of their descendants.
+pub(crate) fn disk_usage<'a, P, I>(
+    cluster_size: u64,
+    items: I,
+    mut pending: Vec<Directory<P>>,
+) -> Result<DiskUsage, OpenError>
+where
+    P: DiskPartition + 'a,
+    I: IntoIterator<Item = &'a Item<P>>,
+{
+    let mut usage = DiskUsage::default();
+
+    for item in items {
+        add(&mut usage, cluster_size, item, &mut pending);
+    }
+
+    // Descend into the directories. Each one is opened only after the previous one has been read
+    // so a single reader is alive at a time.
+    while let Some(dir) = pending.pop() {
+        for item in dir.items()? {
+            add(&mut usage, cluster_size, &item?, &mut pending);
+        }
+    }
+
+    Ok(usage)
+}
+
+fn add<P: DiskPartition>(
+    usage: &mut DiskUsage,
+    cluster_size: u64,
+    item: &Item<P>,
+    pending: &mut Vec<Directory<P>>,
+) {
+    let len = match item {
+        Item::Directory(d) => {
+            pending.push(d.handle());
+            usage.directories += 1;
+            d.allocation().data_length()
+        }
+        Item::File(f) => {
+            usage.files += 1;
+            usage.len += f.len();
+            f.len()
+        }
+    };
+
+    usage.allocated += len.div_ceil(cluster_size) * cluster_size;
+}
@@ -0,0 +1,156 @@
+use crate::directory::{Directory, Item, Items, OpenError};
+use crate::disk::DiskPartition;
+use crate::prelude::*;
+use alloc_crate::collections::VecDeque;
+use thiserror::Error;
+
+/// An iterator that walks the directory tree recursively, yielding the path of each item and the
+/// item itself. The path is always start with `/`.
+///
+/// The default order is depth-first, which yields the contents of a directory immediately after
+/// the directory itself.
+pub struct Walk<P: DiskPartition> {
+    sources: VecDeque<Source<P>>,
+    breadth_first: bool,
+    max_depth: Option<usize>,
+}
+
+impl<P: DiskPartition> Walk<P> {
+    pub(crate) fn new(items: Vec<Item<P>>) -> Self {
+        let mut sources = VecDeque::new();
+
+        sources.push_back(Source {
+            path: String::new(),
+            depth: 1,
+            items: Iter::Root(items.into_iter()),
+        });
+
+        Self {
+            sources,
+            breadth_first: false,
+            max_depth: None,
+        }
+    }
+
+    /// Yields all items in a directory before moving to the next level.
+    pub fn breadth_first(mut self) -> Self {
+        self.breadth_first = true;
+        self
+    }
+
+    /// Limits how deep the walker will descend. The items in the root have depth 1 so `1` means
+    /// only the items in the root will be yielded.
+    pub fn max_depth(mut self, v: usize) -> Self {
+        self.max_depth = Some(v);
+        self
+    }
+}
+
+impl<P: DiskPartition> Iterator for Walk<P> {
+    type Item = Result<(String, Item<P>), WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Get the current directory.
+            let source = if self.breadth_first {
+                self.sources.front_mut()?
+            } else {
+                self.sources.back_mut()?
+            };
+
+            // Open the directory when it is reached.
+            if let Iter::Pending(d) = &source.items {
+                match d.items() {
+                    Ok(v) => source.items = Iter::Directory(v),
+                    Err(e) => {
+                        let path = source.path.clone();
+
+                        if self.breadth_first {
+                            self.sources.pop_front();
+                        } else {
+                            self.sources.pop_back();
+                        }
+
+                        return Some(Err(WalkError::ReadDirectoryFailed(path, e)));
+                    }
+                }
+            }
+
+            // Get the next item.
+            let item = match source.items.next() {
+                Some(Ok(v)) => v,
+                Some(Err(e)) => {
+                    let path = if source.path.is_empty() {
+                        "/".into()
+                    } else {
+                        source.path.clone()
+                    };
+
+                    return Some(Err(WalkError::ReadDirectoryFailed(path, e)));
+                }
+                None => {
+                    if self.breadth_first {
+                        self.sources.pop_front();
+                    } else {
+                        self.sources.pop_back();
+                    }
+
+                    continue;
+                }
+            };
+
+            // Descend into the directory.
+            let depth = source.depth;
+            let path = match &item {
+                Item::Directory(d) => format!("{}/{}", source.path, d.name()),
+                Item::File(f) => format!("{}/{}", source.path, f.name()),
+            };
+
+            if let Item::Directory(d) = &item {
+                if self.max_depth.is_none_or(|v| depth < v) {
+                    self.sources.push_back(Source {
+                        path: path.clone(),
+                        depth: depth + 1,
+                        items: Iter::Pending(d.handle()),
+                    });
+                }
+            }
+
+            return Some(Ok((path, item)));
+        }
+    }
+}
+
+/// Contains the pending items of a directory.
+struct Source<P: DiskPartition> {
+    path: String,
+    depth: usize,
+    items: Iter<P>,
+}
+
+/// Encapsulates the items of either the root or a directory. A directory is [`Iter::Pending`]
+/// until it is reached so only the directories being read hold a reader.
+enum Iter<P: DiskPartition> {
+    Root(alloc_crate::vec::IntoIter<Item<P>>),
+    Pending(Directory<P>),
+    Directory(Items<P>),
+}
+
+impl<P: DiskPartition> Iterator for Iter<P> {
+    type Item = Result<Item<P>, OpenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Root(i) => i.next().map(Ok),
+            Self::Pending(_) => None,
+            Self::Directory(i) => i.next(),
+        }
+    }
+}
+
+/// Represents an error for [`Walk`].
+#[derive(Debug, Error)]
+pub enum WalkError {
+    #[error("cannot read {0}")]
+    ReadDirectoryFailed(String, #[source] OpenError),
+}
@@ -0,0 +1,300 @@
+use crate::bounce::Bounce;
+use crate::disk::DiskPartition;
+use std::cmp::min;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::mem::size_of;
+use std::os::windows::fs::{FileExt, OpenOptionsExt};
+use std::os::windows::io::AsRawHandle;
+use std::ptr::null_mut;
+use std::sync::RwLock;
+use thiserror::Error;
+use windows_sys::Win32::Storage::FileSystem::{FILE_SHARE_READ, FILE_SHARE_WRITE};
+use windows_sys::Win32::System::Ioctl::{
+    DISK_GEOMETRY, FSCTL_LOCK_VOLUME, GET_LENGTH_INFORMATION, IOCTL_DISK_GET_DRIVE_GEOMETRY,
+    IOCTL_DISK_GET_LENGTH_INFO,
+};
+use windows_sys::Win32::System::IO::DeviceIoControl;
+
+/// An implementation of [`DiskPartition`] on a raw volume on Windows like `\\.\E:`.
+///
+/// Windows requires the reads and writes on a volume handle to be aligned to the sector size of
+/// the device. The unaligned reads and writes will go through an aligned bounce buffer so the
+/// callers don't need to care about it. Opening a volume requires administrator privileges.
+pub struct RawVolume {
+    file: File,
+    sector_size: u64,
+    len: u64,
+    writable: bool,
+    rmw: RwLock<()>, // Write locked while updating a partial sector.
+}
+
+impl RawVolume {
+    /// Opens the volume at `path` (e.g. `\\.\E:`) as read-only.
+    pub fn open(path: &str) -> Result<Self, OpenError> {
+        Self::open_with(path, false)
+    }
+
+    /// Same as [`open()`][Self::open] but the resulting [`RawVolume`] is writable. The volume
+    /// will be locked so the other processes (including the file system driver) cannot access it
+    /// until the [`RawVolume`] is dropped.
+    pub fn open_rw(path: &str) -> Result<Self, OpenError> {
+        Self::open_with(path, true)
+    }
+
+    /// Returns the sector size of the device.
+    pub fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    /// Returns the size of the volume, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the volume has no data.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn open_with(path: &str, writable: bool) -> Result<Self, OpenError> {
+        // Open the volume.
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+            .open(path)
+        {
+            Ok(v) => v,
+            Err(e) => return Err(OpenError::OpenFailed(e)),
+        };
+
+        // Get the sector size.
+        let mut geometry: DISK_GEOMETRY = unsafe { std::mem::zeroed() };
+
+        if let Err(e) = Self::control(&file, IOCTL_DISK_GET_DRIVE_GEOMETRY, &mut geometry) {
+            return Err(OpenError::GetGeometryFailed(e));
+        }
+
+        let sector_size = match u64::from(geometry.BytesPerSector) {
+            v if v.is_power_of_two() => v,
+            v => return Err(OpenError::InvalidSectorSize(v)),
+        };
+
+        // Get the size of the volume.
+        let mut length: GET_LENGTH_INFORMATION = unsafe { std::mem::zeroed() };
+
+        if let Err(e) = Self::control(&file, IOCTL_DISK_GET_LENGTH_INFO, &mut length) {
+            return Err(OpenError::GetLengthFailed(e));
+        }
+
+        // Lock the volume.
+        if writable {
+            if let Err(e) = Self::control(&file, FSCTL_LOCK_VOLUME, &mut ()) {
+                return Err(OpenError::LockFailed(e));
+            }
+        }
+
+        Ok(Self {
+            file,
+            sector_size,
+            len: length.Length as u64,
+            writable,
+            rmw: RwLock::new(()),
+        })
+    }
+
+    /// Sends `code` to the device and put the result in `out`.
+    fn control<T>(file: &File, code: u32, out: &mut T) -> std::io::Result<()> {
+        let mut returned = 0;
+
+        // SAFETY: `out` is valid for writing and its size is passed along with it.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                code,
+                std::ptr::null(),
+                0,
+                (out as *mut T).cast(),
+                size_of::<T>() as u32,
+                &mut returned,
+                null_mut(),
+            )
+        };
+
+        if ok == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns `true` if the I/O on `buf` at `offset` can be done without a bounce buffer.
+    fn is_aligned(&self, offset: u64, buf: &[u8]) -> bool {
+        let align = self.sector_size;
+
+        offset.is_multiple_of(align)
+            && (buf.len() as u64).is_multiple_of(align)
+            && (buf.as_ptr() as u64).is_multiple_of(align)
+    }
+}
+
+impl DiskPartition for RawVolume {
+    fn len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn block_size(&self) -> u64 {
+        self.sector_size
+    }
+
+    fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.writable {
+            return Ok(());
+        }
+
+        match self.file.sync_all() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(WriteError::FlushFailed(e).into()),
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        // Windows does not treat reading beyond the end of the volume as the end of file.
+        if offset >= self.len {
+            return Ok(0);
+        } else if self.is_aligned(offset, buf) {
+            let len = min(buf.len() as u64, self.len - offset) as usize;
+
+            return match self.file.seek_read(&mut buf[..len], offset) {
+                Ok(v) => Ok(v as u64),
+                Err(e) => Err(ReadError::ReadFailed(e).into()),
+            };
+        }
+
+        // Read the sectors that cover the requested range.
+        let start = offset - offset % self.sector_size;
+        let head = (offset - start) as usize;
+        let len = min((head + buf.len()) as u64, self.len - start) as usize;
+        let mut bounce = Bounce::new(len, self.sector_size);
+        let read = match self.file.seek_read(bounce.as_mut(), start) {
+            Ok(v) => v,
+            Err(e) => return Err(ReadError::ReadFailed(e).into()),
+        };
+
+        if read <= head {
+            return Ok(0);
+        }
+
+        // Copy the data.
+        let amount = min(read - head, buf.len());
+
+        buf[..amount].copy_from_slice(&bounce.as_mut()[head..(head + amount)]);
+
+        Ok(amount as u64)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        if !self.writable {
+            return Err(WriteError::ReadOnly.into());
+        } else if offset >= self.len {
+            return Ok(0);
+        }
+
+        let buf = &buf[..(min(buf.len() as u64, self.len - offset) as usize)];
+
+        if self.is_aligned(offset, buf) {
+            let _lock = self.rmw.read().unwrap();
+
+            return match self.file.seek_write(buf, offset) {
+                Ok(v) => Ok(v as u64),
+                Err(e) => Err(WriteError::WriteFailed(e).into()),
+            };
+        }
+
+        // Read the sectors that cover the requested range then update it. The other writes must
+        // wait until the sectors are written back otherwise they would be overwritten with the old
+        // data.
+        let start = offset - offset % self.sector_size;
+        let head = (offset - start) as usize;
+        let mut bounce = Bounce::new(head + buf.len(), self.sector_size);
+        let data = bounce.as_mut();
+        let _lock = self.rmw.write().unwrap();
+        let mut read = 0;
+
+        while read < data.len() {
+            match self.file.seek_read(&mut data[read..], start + read as u64) {
+                Ok(0) => return Err(WriteError::EndOfVolume.into()),
+                Ok(v) => read += v,
+                Err(e) => return Err(WriteError::ReadFailed(e).into()),
+            }
+        }
+
+        data[head..(head + buf.len())].copy_from_slice(buf);
+
+        // Write the sectors.
+        let mut written = 0;
+
+        while written < data.len() {
+            match self
+                .file
+                .seek_write(&data[written..], start + written as u64)
+            {
+                Ok(0) => return Err(WriteError::EndOfVolume.into()),
+                Ok(v) => written += v,
+                Err(e) => return Err(WriteError::WriteFailed(e).into()),
+            }
+        }
+
+        Ok(buf.len() as u64)
+    }
+}
+
+/// Represents an error for [`RawVolume::open()`].
+#[derive(Debug, Error)]
+pub enum OpenError {
+    #[error("cannot open the volume")]
+    OpenFailed(#[source] std::io::Error),
+
+    #[error("cannot get the geometry of the device")]
+    GetGeometryFailed(#[source] std::io::Error),
+
+    #[error("sector size {0} is not valid")]
+    InvalidSectorSize(u64),
+
+    #[error("cannot get the size of the volume")]
+    GetLengthFailed(#[source] std::io::Error),
+
+    #[error("cannot lock the volume")]
+    LockFailed(#[source] std::io::Error),
+}
+
+/// Represents an error for [`RawVolume::read()`].
+#[derive(Debug, Error)]
+enum ReadError {
+    #[error("cannot read the volume")]
+    ReadFailed(#[source] std::io::Error),
+}
+
+/// Represents an error for [`RawVolume::write()`].
+#[derive(Debug, Error)]
+enum WriteError {
+    #[error("the volume is not opened for writing")]
+    ReadOnly,
+
+    #[error("cannot read the sectors to update")]
+    ReadFailed(#[source] std::io::Error),
+
+    #[error("cannot write the volume")]
+    WriteFailed(#[source] std::io::Error),
+
+    #[error("end of the volume has been reached")]
+    EndOfVolume,
+
+    #[error("cannot flush the volume")]
+    FlushFailed(#[source] std::io::Error),
+}
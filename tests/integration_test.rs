@@ -1,9 +1,31 @@
-use exfat::directory::Item;
-use exfat::image::Image;
-use exfat::Root;
+#![cfg(feature = "std")]
+// read_image() collects the items of Root with an explicit into_iter().
+#![allow(clippy::useless_conversion)]
+
+use exfat::cache::LruCache;
+use exfat::check::Problem;
+use exfat::clone::CloneOptions;
+use exfat::cluster;
+use exfat::diff::{Change, DiffOptions, Difference};
+use exfat::directory::{Item, RenameError};
+use exfat::disk::DiskPartition;
+use exfat::entries::FileEntryError;
+use exfat::fat::{self, FatEntry};
+use exfat::format::FormatOptions;
+use exfat::image::{Image, ImageBuilder};
+use exfat::repair::Fix;
+use exfat::sparse::Sparse;
+use exfat::timestamp::Timestamp;
+use exfat::walk::Walk;
+use exfat::{
+    BadClusterPolicy, Cluster, MediaFailurePolicy, OpenError, OpenOptions, OpenPathError,
+    ReadGranularity, Root, Sector, Warning,
+};
+use std::error::Error;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Cursor, IoSliceMut, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn read_image() {
@@ -65,3 +87,3562 @@ fn read_image() {
         }
     }
 }
+
+#[test]
+fn boot_region() {
+    let data = load_image();
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let boot = root.boot_region();
+
+    assert_eq!(None, root.volume_guid());
+    assert_eq!(8, boot.extended_boot_sectors().count());
+    assert_eq!(10, boot.oem_parameters().len());
+    assert!(boot.flash_parameters().is_none());
+    assert_eq!(root.total_bytes() - 7 * 4096, root.free_bytes());
+}
+
+#[test]
+fn read_cluster() {
+    let data = load_image();
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    // Read the cluster of file1.
+    let mut cluster = [0u8; 13];
+
+    assert_eq!(4096, root.cluster_size());
+    assert_eq!(250, root.cluster_count());
+    root.read_cluster(Cluster(7), &mut cluster)
+        .expect("cannot read cluster #7");
+    assert_eq!(b"Test file 1.\n", &cluster);
+    assert!(root.read_cluster(Cluster(252), &mut cluster).is_err());
+    assert!(root.read_cluster(Cluster(7), &mut [0u8; 4097]).is_err());
+}
+
+#[test]
+fn fat_entry() {
+    let data = load_image();
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert_eq!(
+        Some(FatEntry::Next(Cluster(4))),
+        root.fat_entry(Cluster(3)).unwrap()
+    );
+    assert_eq!(
+        Some(FatEntry::EndOfChain),
+        root.fat_entry(Cluster(4)).unwrap()
+    );
+    assert_eq!(Some(FatEntry::Free), root.fat_entry(Cluster(100)).unwrap());
+    assert_eq!(None, root.fat_entry(Cluster(1)).unwrap());
+    assert_eq!(
+        vec![Cluster(3), Cluster(4)],
+        root.cluster_chain(Cluster(3)).unwrap()
+    );
+}
+
+#[test]
+fn read_sector() {
+    let data = load_image();
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    // Read the main boot sector.
+    let mut sector = [0u8; 512];
+
+    assert_eq!(512, root.sector_size());
+    root.read_sector(Sector(0), &mut sector)
+        .expect("cannot read sector #0");
+    assert_eq!(b"EXFAT   ", &sector[3..11]);
+    assert!(root
+        .read_sector(Sector(root.sector_count()), &mut sector)
+        .is_err());
+}
+
+#[test]
+fn iter() {
+    let data = load_image();
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert_eq!(2, root.iter().count());
+    assert_eq!(2, (&root).into_iter().count());
+    assert_eq!(2, Vec::from_iter(root).len());
+}
+
+#[test]
+fn timestamps() {
+    let data = load_image();
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let file1 = root
+        .into_iter()
+        .find_map(|i| match i {
+            Item::File(f) if f.name() == "file1" => Some(f),
+            _ => None,
+        })
+        .expect("cannot find file1");
+    let modified = file1.timestamps().modified();
+
+    assert_eq!(2023, modified.year());
+    assert_eq!(3, modified.month());
+    assert_eq!(6, modified.day());
+    assert_eq!(13, modified.hour());
+    assert_eq!(3, modified.minute());
+    assert_eq!(6, modified.second());
+    assert_eq!(10, modified.millisecond());
+    assert_eq!(Some(0), modified.utc_offset());
+}
+
+#[test]
+fn large_no_fat_chain() {
+    // Load the image then set DataLength of file1 to the largest NoFatChain allocation that can
+    // start at cluster #7.
+    let mut data = load_image();
+    let len = (u64::from(u32::MAX) - 6) * 0x1000;
+
+    data[0x90f8..0x9100].copy_from_slice(&len.to_le_bytes());
+
+    // The clusters of the file must not be kept one by one.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().ignore_checksums(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let mut file1 = root
+        .into_iter()
+        .find_map(|i| match i {
+            Item::File(f) if f.name() == "file1" => Some(f),
+            _ => None,
+        })
+        .expect("cannot find file1");
+    let mut buf = [0xffu8; 16];
+
+    assert_eq!(len, file1.len());
+
+    file1.read_exact(&mut buf[..13]).expect("cannot read file1");
+
+    assert_eq!(b"Test file 1.\n", &buf[..13]);
+
+    file1.seek(SeekFrom::End(-16)).expect("cannot seek file1");
+    file1.read_exact(&mut buf).expect("cannot read file1");
+
+    assert_eq!([0u8; 16], buf);
+}
+
+#[test]
+fn volume_guid() {
+    // Load the image and append benign primary entries to the root.
+    let mut data = load_image();
+    let guid: [u8; 16] = std::array::from_fn(|i| i as u8 + 1);
+
+    data[0x9120] = 0xa0;
+    data[0x9126..0x9136].copy_from_slice(&guid);
+    data[0x9140] = 0xa1; // TexFAT Padding.
+    data[0x9160] = 0xb0; // Unknown benign primary entry.
+    data[0x9161] = 1;
+    data[0x9180] = 0xe0;
+
+    // Check the GUID.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert_eq!(Some(&guid), root.volume_guid());
+
+    // Unknown entries are not allowed in strict mode.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().strict(true);
+
+    assert!(matches!(
+        Root::open_with(image, &options),
+        Err(OpenError::UnknownEntry(11, Cluster(5)))
+    ));
+}
+
+#[test]
+fn vendor_entries() {
+    // Load the image and append a Vendor Extension entry, a Vendor Allocation entry with the
+    // cluster #9 and a contiguous Vendor Allocation entry with the cluster #10 and #11 to file1.
+    let mut data = load_image();
+    let guid: [u8; 16] = std::array::from_fn(|i| i as u8 + 1);
+
+    data[0x90c1] = 5;
+    data[0x9120] = 0xe0;
+    data[0x9122..0x9132].copy_from_slice(&guid);
+    data[0x9140] = 0xe1;
+    data[0x9141] = 1;
+    data[0x9142..0x9152].copy_from_slice(&guid);
+    data[0x9154..0x9158].copy_from_slice(&9u32.to_le_bytes());
+    data[0x9158..0x9160].copy_from_slice(&4096u64.to_le_bytes());
+    data[0x9160] = 0xe1;
+    data[0x9161] = 3;
+    data[0x9162..0x9172].copy_from_slice(&guid);
+    data[0x9174..0x9178].copy_from_slice(&10u32.to_le_bytes());
+    data[0x9178..0x9180].copy_from_slice(&8192u64.to_le_bytes());
+    data[0x6000] |= 0x80;
+    data[0x6001] |= 0x03;
+    data[0x4024..0x4028].copy_from_slice(&0xffffffffu32.to_le_bytes());
+
+    // Update SetChecksum.
+    let mut checksum = 0u16;
+
+    for (i, &b) in data[0x90c0..0x9180].iter().enumerate() {
+        if i != 2 && i != 3 {
+            checksum = checksum.rotate_right(1).wrapping_add(b.into());
+        }
+    }
+
+    data[0x90c2..0x90c4].copy_from_slice(&checksum.to_le_bytes());
+
+    // Check the entry.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    match root.open_path("file1") {
+        Ok(Item::File(f)) => {
+            let entries = f.vendor_entries();
+
+            assert_eq!(3, entries.len());
+            assert!(entries[0].is_vendor_extension());
+            assert_eq!(Some(&guid), entries[0].vendor_guid());
+            assert!(entries[1].is_vendor_allocation());
+            assert_eq!(Some((Cluster(9), 4096)), entries[1].allocation());
+            assert!(!entries[1].no_fat_chain());
+            assert_eq!(Some((Cluster(10), 8192)), entries[2].allocation());
+            assert!(entries[2].no_fat_chain());
+        }
+        _ => panic!("cannot open file1"),
+    };
+
+    // The entries must be preserved after renamed.
+    root.rename("file1", "file3").expect("cannot rename file1");
+
+    match root.open_path("file3") {
+        Ok(Item::File(f)) => assert_eq!(3, f.vendor_entries().len()),
+        _ => panic!("cannot open file3"),
+    };
+
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+
+    // The clusters of the Vendor Allocation entries must be released with the file.
+    let free = root.free_clusters();
+
+    root.remove("file3").expect("cannot remove file3");
+
+    assert_eq!(free + 4, root.free_clusters());
+
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+    assert_eq!(
+        Some(FatEntry::Free),
+        root.fat_entry(Cluster(9)).expect("cannot read the FAT")
+    );
+}
+
+#[test]
+fn flash_parameters() {
+    // Load the image and write the Flash Parameters.
+    let mut data = load_image();
+    let param = &mut data[(512 * 9)..(512 * 9 + 48)];
+
+    param[..16].copy_from_slice(&[
+        0x46, 0x7e, 0x0c, 0x0a, 0x99, 0x33, 0x21, 0x40, 0x90, 0xc8, 0xfa, 0x6d, 0x38, 0x9c, 0x4b,
+        0xa2,
+    ]);
+    param[16..20].copy_from_slice(&0x20000u32.to_le_bytes());
+    param[20..24].copy_from_slice(&0x1000u32.to_le_bytes());
+
+    // The boot checksum is no longer valid.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().lenient(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let flash = root
+        .boot_region()
+        .flash_parameters()
+        .expect("no Flash Parameters");
+
+    assert_eq!(0x20000, flash.erase_block_size());
+    assert_eq!(0x1000, flash.page_size());
+    assert!(!root.boot_region().oem_parameters()[0].is_unused());
+}
+
+#[test]
+fn create_file() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Create a file in the root and dir1.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let start = now();
+    let file = root.create_file("file3").expect("cannot create file3");
+    let created = file.timestamps().created().unix_time();
+
+    assert_eq!("file3", file.name());
+    assert!(file.is_empty());
+    assert!((start..=now()).contains(&created));
+    assert_eq!(created, file.timestamps().modified().unix_time());
+    assert_eq!(created / 2, file.timestamps().accessed().unix_time() / 2);
+    assert!(root.create_file("FILE1").is_err());
+    assert!(root.create_file(".").is_err());
+    assert!(root.create_file("..").is_err());
+    assert!(root.create_dir("..").is_err());
+
+    drop(file);
+
+    for i in root {
+        if let Item::Directory(mut d) = i {
+            for i in 0..200 {
+                d.create_file(&format!("file{i} with a long name"))
+                    .expect("cannot create a file in dir1");
+            }
+        }
+    }
+
+    // Check the created files.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let items = Vec::from_iter(root);
+
+    assert_eq!(3, items.len());
+
+    for i in items {
+        match i {
+            Item::Directory(d) => {
+                let mut items = d.items().expect("cannot open dir1");
+
+                assert_eq!("file2", item_name(&items.next().unwrap().unwrap()));
+                assert_eq!(200, items.filter(Result::is_ok).count());
+                assert_eq!(201, d.open().expect("cannot open dir1").len());
+            }
+            Item::File(f) => {
+                if f.name() == "file3" {
+                    assert!(f.is_empty());
+                    assert_eq!(created, f.timestamps().created().unix_time());
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn create_dir() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Create directories.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut dir = root.create_dir("dir2").expect("cannot create dir2");
+
+    assert_eq!("dir2", dir.name());
+    assert!(dir.open().expect("cannot open dir2").is_empty());
+    assert!(root.create_dir("DIR1").is_err());
+
+    dir.create_dir("dir3").expect("cannot create dir3");
+    dir.create_file("file3").expect("cannot create file3");
+
+    drop(dir);
+    drop(root);
+
+    // Check the created directories.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let dir = root
+        .into_iter()
+        .find_map(|i| match i {
+            Item::Directory(d) if d.name() == "dir2" => Some(d),
+            _ => None,
+        })
+        .expect("no dir2 in the root");
+
+    let items = dir.open().expect("cannot open dir2");
+
+    assert_eq!(2, items.len());
+
+    for i in items {
+        match i {
+            Item::Directory(d) => {
+                assert_eq!("dir3", d.name());
+                assert!(d.open().expect("cannot open dir3").is_empty());
+            }
+            Item::File(f) => assert_eq!("file3", f.name()),
+        }
+    }
+}
+
+#[test]
+fn remove() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Remove items.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut dir = root.create_dir("dir2").expect("cannot create dir2");
+
+    dir.create_dir("dir3").expect("cannot create dir3");
+    dir.create_file("file3").expect("cannot create file3");
+    dir.remove("dir3").expect("cannot remove dir3");
+
+    // The items after the removed entries must be still listed.
+    let items = dir.open().expect("cannot open dir2");
+
+    assert_eq!(1, items.len());
+    assert_eq!("file3", item_name(&items[0]));
+    assert!(root.remove("dir2").is_err());
+    assert!(root.remove("file2").is_err());
+
+    root.remove_all("DIR2").expect("cannot remove dir2");
+    root.remove("file1").expect("cannot remove file1");
+
+    drop(items);
+    drop(dir);
+    drop(root);
+
+    // Check the remaining items.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let items = Vec::from_iter(root);
+
+    assert_eq!(1, items.len());
+
+    match &items[0] {
+        Item::Directory(d) => assert_eq!("dir1", d.name()),
+        Item::File(_) => panic!("unexpected item in the root"),
+    }
+}
+
+#[test]
+fn remove_open_file() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Remove a file that has unwritten changes.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut file = root.create_file("file3").expect("cannot create file3");
+
+    file.write_all(b"data").expect("cannot write file3");
+    file.write_all(b"more").expect("cannot write file3");
+    root.remove("file3").expect("cannot remove file3");
+
+    assert_eq!(
+        std::io::ErrorKind::NotFound,
+        file.write(b"data").unwrap_err().kind()
+    );
+
+    drop(file);
+    drop(root);
+
+    // The removed file must not come back.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert!(root.iter().all(|i| item_name(i) != "file3"));
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+}
+
+#[test]
+fn remove_open_directory() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Update a directory that was removed.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut dir = root.create_dir("dir2").expect("cannot create dir2");
+
+    root.remove_all("dir2").expect("cannot remove dir2");
+
+    let mut attrs = dir.attributes();
+
+    attrs.set_hidden(true);
+
+    assert_eq!(
+        std::io::ErrorKind::NotFound,
+        dir.set_attributes(attrs).unwrap_err().kind()
+    );
+
+    assert_eq!(
+        std::io::ErrorKind::NotFound,
+        dir.set_times(*dir.timestamps()).unwrap_err().kind()
+    );
+
+    drop(dir);
+    drop(root);
+
+    // The removed directory must not come back.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert!(root.iter().all(|i| item_name(i) != "dir2"));
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+}
+
+#[test]
+fn rename() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Rename and move items.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut dir = root.create_dir("dir2").expect("cannot create dir2");
+    let mut sub = dir.create_dir("dir3").expect("cannot create dir3");
+
+    assert!(dir.rename("dir3", "dir4/").is_err());
+    assert!(root.rename("dir1", "file1").is_err());
+    assert!(root.move_to("dir2", &mut sub, "dir2").is_err());
+
+    let mut deep = sub.create_dir("dir5").expect("cannot create dir5");
+
+    assert!(matches!(
+        root.move_to("dir2", &mut deep, "dir2"),
+        Err(RenameError::MoveIntoItself)
+    ));
+
+    drop(deep);
+    sub.remove("dir5").expect("cannot remove dir5");
+
+    root.rename("dir1", "DIR1").expect("cannot rename dir1");
+    dir.create_file("file3").expect("cannot create file3");
+    dir.move_to_root("file3", &mut root, "file3")
+        .expect("cannot move file3");
+    root.rename("file3", "file3 with a name that need more entries")
+        .expect("cannot rename file3");
+    root.move_to(
+        "file3 with a name that need more entries",
+        &mut sub,
+        "file4",
+    )
+    .expect("cannot move file3");
+    sub.move_to("file4", &mut dir, "file5")
+        .expect("cannot move file4");
+
+    drop(sub);
+    drop(dir);
+    drop(root);
+
+    // Check the items.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut names = Vec::new();
+
+    for i in root {
+        match i {
+            Item::Directory(d) => {
+                if d.name() == "dir2" {
+                    let mut names: Vec<String> = d
+                        .open()
+                        .expect("cannot open dir2")
+                        .into_iter()
+                        .map(|i| match i {
+                            Item::Directory(d) => d.name().to_owned(),
+                            Item::File(f) => f.name().to_owned(),
+                        })
+                        .collect();
+
+                    names.sort();
+
+                    assert_eq!(vec!["dir3", "file5"], names);
+                }
+
+                names.push(d.name().to_owned());
+            }
+            Item::File(f) => names.push(f.name().to_owned()),
+        }
+    }
+
+    names.sort();
+
+    assert_eq!(vec!["DIR1", "dir2", "file1"], names);
+}
+
+#[test]
+fn rename_open_items() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Rename and move the items while they are open.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut file = root.create_file("a").expect("cannot create a");
+    let mut dir = root.create_dir("b").expect("cannot create b");
+    let mut dest = root.create_dir("c").expect("cannot create c");
+    let long = "a file with a name that need more entries";
+
+    file.write_all(b"data").expect("cannot write a");
+    root.rename("a", long).expect("cannot rename a");
+    file.write_all(b"more")
+        .expect("cannot write the renamed file");
+    root.move_to(long, &mut dest, "d")
+        .expect("cannot move the renamed file");
+    file.write_all(b"last")
+        .expect("cannot write the moved file");
+    root.move_to("b", &mut dest, "directory with a longer name")
+        .expect("cannot move b");
+
+    // Grow the moved directory so its entries are written.
+    for i in 0..50 {
+        dir.create_file(&format!("file{i}"))
+            .expect("cannot create a file in the moved directory");
+    }
+
+    let mut attrs = dir.attributes();
+
+    attrs.set_hidden(true);
+    dir.set_attributes(attrs)
+        .expect("cannot set attributes of the moved directory");
+
+    drop(file);
+    drop(dir);
+    drop(dest);
+    drop(root);
+
+    // Check the items.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert!(report.is_clean(), "{:?}", report.problems());
+    assert!(root
+        .iter()
+        .all(|i| !matches!(item_name(i), "a" | "b") && item_name(i) != long));
+
+    let mut file = match root.open_path("/c/d") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("/c/d is not a file"),
+    };
+
+    let mut content = Vec::new();
+
+    file.read_to_end(&mut content).expect("cannot read /c/d");
+
+    assert_eq!(b"datamorelast", content.as_slice());
+
+    match root.open_path("/c/directory with a longer name") {
+        Ok(Item::Directory(d)) => {
+            assert!(d.attributes().is_hidden());
+            assert_eq!(50, d.open().expect("cannot open the directory").len());
+        }
+        _ => panic!("the moved directory is not a directory"),
+    };
+}
+
+#[test]
+fn write_file() {
+    // Load the image.
+    let mut data = load_image();
+    let content: Vec<u8> = (0..10000u32).map(|v| v as u8).collect();
+
+    // Check if writing on a read-only volume is failed.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert!(matches!(
+        Root::open_rw(image),
+        Err(OpenError::PartitionNotWritable)
+    ));
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let mut root = Root::open(image).expect("cannot open the root directory");
+
+    assert!(root.create_file("file3").is_err());
+
+    drop(root);
+
+    // Write files.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut file3 = root.create_file("file3").expect("cannot create file3");
+    let mut file4 = root.create_file("file4").expect("cannot create file4");
+
+    file3
+        .write_all(&content[..5000])
+        .expect("cannot write file3");
+    file4
+        .write_all(b"Test file 4.\n")
+        .expect("cannot write file4");
+    file3
+        .write_all(&content[5000..])
+        .expect("cannot write file3");
+    file3.seek(SeekFrom::Start(4)).expect("cannot seek file3");
+    file3.write_all(b"overwrite").expect("cannot write file3");
+    file3.flush().expect("cannot flush file3");
+
+    drop(file3);
+    drop(file4);
+    drop(root);
+
+    // Check the files.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut found = 0;
+
+    for i in root {
+        if let Item::File(mut f) = i {
+            let mut c = Vec::new();
+
+            f.read_to_end(&mut c).expect("cannot read the file");
+
+            match f.name() {
+                "file3" => {
+                    assert_eq!(&content[..4], &c[..4]);
+                    assert_eq!(b"overwrite", &c[4..13]);
+                    assert_eq!(&content[13..], &c[13..]);
+                }
+                "file4" => assert_eq!(b"Test file 4.\n", c.as_slice()),
+                _ => continue,
+            }
+
+            found += 1;
+        }
+    }
+
+    assert_eq!(2, found);
+}
+
+#[test]
+fn set_len() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Resize files.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut file3 = root.create_file("file3").expect("cannot create file3");
+    let mut file4 = root.create_file("file4").expect("cannot create file4");
+
+    file3.set_len(5000).expect("cannot extend file3");
+
+    assert_eq!(0, file3.valid_data_length());
+
+    file4
+        .write_all(b"Test file 4.\n")
+        .expect("cannot write file4");
+    file3.set_len(10000).expect("cannot extend file3");
+    file3.set_len(9000).expect("cannot truncate file3");
+    file4.set_len(4).expect("cannot truncate file4");
+
+    assert_eq!(9000, file3.len());
+    assert_eq!(4, file4.len());
+
+    drop(file3);
+    drop(file4);
+
+    for i in root {
+        if let Item::File(mut f) = i {
+            if f.name() == "file1" {
+                f.set_len(0).expect("cannot truncate file1");
+            }
+        }
+    }
+
+    // Check the files.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    for i in root {
+        if let Item::File(mut f) = i {
+            let mut c = Vec::new();
+
+            f.read_to_end(&mut c).expect("cannot read the file");
+
+            match f.name() {
+                "file1" => assert!(c.is_empty()),
+                "file3" => {
+                    assert_eq!(vec![0u8; 9000], c);
+                    assert_eq!(0, f.valid_data_length());
+                }
+                "file4" => assert_eq!(b"Test", c.as_slice()),
+                _ => panic!("unexpected file in the root"),
+            }
+        }
+    }
+}
+
+#[test]
+fn set_volume_label() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Remove the label.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    assert!(root.set_volume_label(Some("Too long label")).is_err());
+
+    root.set_volume_label(None)
+        .expect("cannot remove the volume label");
+
+    assert_eq!(None, root.volume_label());
+
+    drop(root);
+
+    // Check if the label is removed without affecting the other entries.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert_eq!(None, root.volume_label());
+    assert_eq!(2, root.into_iter().count());
+
+    // Set a new label.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.set_volume_label(Some("New label"))
+        .expect("cannot set the volume label");
+
+    drop(root);
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert_eq!(Some("New label"), root.volume_label());
+}
+
+#[test]
+fn set_attributes_and_times() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Set attributes.
+    let image = open_rw(&mut data);
+    let root = Root::open_rw(image).expect("cannot open the root directory");
+
+    for i in root {
+        match i {
+            Item::Directory(mut d) => {
+                let mut attrs = d.attributes();
+
+                attrs.set_hidden(true);
+                d.set_attributes(attrs)
+                    .expect("cannot set attributes of dir1");
+            }
+            Item::File(mut f) => {
+                let mut attrs = f.attributes();
+
+                attrs.set_read_only(true);
+                attrs.set_archive(false);
+                f.set_attributes(attrs)
+                    .expect("cannot set attributes of file1");
+
+                // Set timestamps.
+                let mut times = *f.timestamps();
+                let modified = Timestamp::new(2024, 2, 29, 12, 34, 57)
+                    .and_then(|v| v.with_millisecond(120))
+                    .and_then(|v| v.with_utc_offset(Some(-570)))
+                    .unwrap();
+
+                times.set_modified(modified);
+                f.set_times(times).expect("cannot set timestamps of file1");
+            }
+        }
+    }
+
+    // Check the attributes.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    for i in root {
+        match i {
+            Item::Directory(d) => {
+                let attrs = d.attributes();
+
+                assert!(attrs.is_directory());
+                assert!(attrs.is_hidden());
+                assert!(!attrs.is_read_only());
+            }
+            Item::File(f) => {
+                let attrs = f.attributes();
+
+                assert!(attrs.is_read_only());
+                assert!(!attrs.is_archive());
+                assert!(!attrs.is_hidden());
+
+                let modified = f.timestamps().modified();
+
+                assert_eq!(2024, modified.year());
+                assert_eq!(2, modified.month());
+                assert_eq!(29, modified.day());
+                assert_eq!(12, modified.hour());
+                assert_eq!(34, modified.minute());
+                assert_eq!(57, modified.second());
+                assert_eq!(120, modified.millisecond());
+                assert_eq!(Some(-570), modified.utc_offset());
+                assert_eq!(1709244297, modified.unix_time());
+            }
+        }
+    }
+}
+
+#[test]
+fn format() {
+    // Format a new volume.
+    let mut data = vec![0u8; 8 * 1024 * 1024];
+    let image = open_rw(&mut data);
+    let options = FormatOptions::new().volume_label("New volume");
+
+    assert!(exfat::format::format(&image, 4096, &options).is_err());
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &options).expect("cannot format the volume");
+
+    // Write a file.
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    assert_eq!(Some("New volume"), root.volume_label());
+
+    let mut file = root.create_file("file1").expect("cannot create file1");
+
+    file.write_all(b"Test file 1.\n")
+        .expect("cannot write file1");
+
+    drop(file);
+    drop(root);
+
+    // Check the volume.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert!(report.is_clean(), "{:?}", report.problems());
+
+    let items = Vec::from_iter(root);
+
+    assert_eq!(1, items.len());
+
+    match items.into_iter().next() {
+        Some(Item::File(mut f)) => {
+            let mut c = String::new();
+
+            f.read_to_string(&mut c).expect("cannot read file1");
+
+            assert_eq!("file1", f.name());
+            assert_eq!("Test file 1.\n", c);
+        }
+        _ => panic!("unexpected item in the root"),
+    };
+}
+
+#[test]
+fn check() {
+    // Load the image.
+    let mut data = load_image();
+
+    // Check the clean image.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert!(report.is_clean(), "{:?}", report.problems());
+
+    drop(root);
+
+    // Mark cluster #12 as allocated and corrupt the SetChecksum of file1.
+    data[0x6001] |= 0x04;
+    data[0x90c2] ^= 0xff;
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert!(matches!(
+        Root::open(image),
+        Err(OpenError::LoadFileEntryFailed(
+            FileEntryError::ChecksumMismatch(6, Cluster(5))
+        ))
+    ));
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().lenient(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+    let problems = report.problems();
+
+    assert_eq!(
+        vec![Warning::ChecksumMismatch(6, Cluster(5))],
+        root.warnings()
+    );
+
+    assert_eq!(2, problems.len());
+    assert!(problems.contains(&Problem::LostCluster(Cluster(12))));
+    assert!(problems.contains(&Problem::InvalidChecksum("/file1".into())));
+    assert_eq!(
+        vec![Cluster(12)],
+        report.lost_clusters().collect::<Vec<_>>()
+    );
+
+    drop(root);
+
+    // Corrupt the NameHash of dir1.
+    data[0x9084] ^= 0xff;
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert!(report
+        .problems()
+        .contains(&Problem::InvalidNameHash("/dir1".into())));
+
+    drop(root);
+
+    // Corrupt the VolumeSerialNumber.
+    data[100] ^= 0xff;
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert!(matches!(
+        Root::open(image),
+        Err(OpenError::BootChecksumMismatch)
+    ));
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    assert!(root.warnings().contains(&Warning::BootChecksumMismatch));
+}
+
+#[test]
+fn chain_loop() {
+    // Load the image then make the root directory points to itself.
+    let mut data = load_image();
+
+    data[0x4014..0x4018].copy_from_slice(&5u32.to_le_bytes());
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert!(matches!(
+        Root::open(image),
+        Err(OpenError::CreateClustersReaderFailed(
+            cluster::NewError::ReadChainFailed(fat::ChainError::Loop(_))
+        ))
+    ));
+}
+
+#[test]
+fn compare_fats() {
+    // Load the image then add the second FAT and the second Allocation Bitmap.
+    let mut data = load_image();
+
+    data[110] = 2;
+    data.copy_within(0x4000..0x5000, 0x5000);
+    data[0x5030] = 1;
+    data.copy_within(0x9020..0x9040, 0x9120);
+    data[0x9121] = 1;
+
+    // Compare the FATs. The boot checksum is no longer valid.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().lenient(true).compare_fats(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    assert!(root
+        .warnings()
+        .contains(&Warning::FatMismatch(vec![Cluster(12)])));
+}
+
+#[test]
+fn mirror_fats() {
+    // Load the image then add the second FAT and the second Allocation Bitmap on cluster #20.
+    let mut data = load_image();
+
+    data[110] = 2;
+    data[0x6002] |= 0x04;
+    data[0x4050..0x4054].copy_from_slice(&0xffffffffu32.to_le_bytes());
+    data.copy_within(0x4000..0x5000, 0x5000);
+    data.copy_within(0x6000..0x6020, 0x18000);
+    data.copy_within(0x9020..0x9040, 0x9120);
+    data[0x9121] = 1;
+    data[0x9134..0x9138].copy_from_slice(&20u32.to_le_bytes());
+
+    // Write a file. The boot checksum is no longer valid.
+    let image = open_rw(&mut data);
+    let options = OpenOptions::new().writable(true).lenient(true);
+    let mut root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let mut file = root.create_file("file3").expect("cannot create file3");
+
+    file.write_all(&[1; 8192]).expect("cannot write file3");
+
+    drop(file);
+    drop(root);
+
+    // Both FATs and both Allocation Bitmaps must be the same.
+    assert_eq!(data[0x4000..0x5000], data[0x5000..0x6000]);
+    assert_eq!(data[0x6000..0x6020], data[0x18000..0x18020]);
+    assert_ne!(0x7f, data[0x6000]);
+}
+
+#[test]
+fn volume_dirty() {
+    // Load the image.
+    let mut data = load_image();
+
+    // VolumeDirty must be cleared once the volume is closed.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    assert!(!root.volume_dirty());
+
+    root.create_file("file3").expect("cannot create file3");
+
+    drop(root);
+
+    assert_eq!(0, data[106] & 2);
+
+    // VolumeDirty must be cleared after syncing and set again on the next change.
+    let mut copy = data.clone();
+    let image = Image::open_rw(Cursor::new(&mut copy)).expect("cannot open exFAT image");
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.create_file("file5").expect("cannot create file5");
+    root.sync_all().expect("cannot sync the volume");
+
+    assert!(!root.volume_dirty());
+
+    root.create_file("file6").expect("cannot create file6");
+
+    std::mem::forget(root);
+
+    assert_eq!(2, copy[106] & 2);
+
+    let mut copy = data.clone();
+    let image = Image::open_rw(Cursor::new(&mut copy)).expect("cannot open exFAT image");
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.create_file("file5").expect("cannot create file5");
+    root.sync_all().expect("cannot sync the volume");
+
+    // VolumeDirty must not be set by the operations that fail the validation.
+    assert!(root.create_file("file?").is_err());
+    assert!(root.create_dir("file5").is_err());
+    assert!(root.rename("file5", "").is_err());
+    assert!(root.rename("file5", "dir1").is_err());
+    assert!(root.remove("file7").is_err());
+    assert!(root.remove("dir1").is_err());
+
+    std::mem::forget(root);
+
+    assert_eq!(0, copy[106] & 2);
+
+    // Leave the volume without closing it.
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.create_file("file4").expect("cannot create file4");
+
+    std::mem::forget(root);
+
+    // Open the dirty volume.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert!(root.volume_dirty());
+    assert_eq!(vec![Warning::VolumeDirty], root.warnings());
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().reject_dirty(true);
+
+    assert!(matches!(
+        Root::open_with(image, &options),
+        Err(OpenError::VolumeDirty)
+    ));
+}
+
+#[test]
+fn media_failure() {
+    // Load the image then set MediaFailure.
+    let mut data = load_image();
+
+    data[106] |= 4;
+
+    // Check the policies.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert!(root.media_failure());
+    assert_eq!(vec![Warning::MediaFailure], root.warnings());
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().media_failure(MediaFailurePolicy::Ignore);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    assert!(root.warnings().is_empty());
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().media_failure(MediaFailurePolicy::Fail);
+
+    assert!(matches!(
+        Root::open_with(image, &options),
+        Err(OpenError::MediaFailure)
+    ));
+}
+
+#[test]
+fn bad_cluster() {
+    // Load the image then extend file1 to the cluster #9, which is marked as bad.
+    let mut data = load_image();
+
+    data[0x90e1] = 1;
+    data[0x90e8..0x90f0].copy_from_slice(&8192u64.to_le_bytes());
+    data[0x90f8..0x9100].copy_from_slice(&8192u64.to_le_bytes());
+    data[0x401c..0x4020].copy_from_slice(&9u32.to_le_bytes());
+    data[0x4024..0x4028].copy_from_slice(&0xfffffff7u32.to_le_bytes());
+
+    // Read file1 with each policy. SetChecksum is no longer valid.
+    let read = |policy| {
+        let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+        let options = OpenOptions::new().lenient(true).bad_cluster(policy);
+        let root = Root::open_with(image, &options).expect("cannot open the root directory");
+        let mut content = Vec::new();
+
+        match root.open_path("file1") {
+            Ok(Item::File(mut f)) => f.read_to_end(&mut content).map(|_| content),
+            _ => panic!("cannot open file1"),
+        }
+    };
+
+    let e = read(BadClusterPolicy::Fail).unwrap_err();
+    let e = e.get_ref().unwrap().downcast_ref::<cluster::BadCluster>();
+
+    assert!(matches!(
+        e,
+        Some(cluster::BadCluster {
+            first_cluster: Cluster(7),
+            cluster: Cluster(9),
+            offset: 4096
+        })
+    ));
+
+    let content = read(BadClusterPolicy::Zeros).unwrap();
+
+    assert_eq!(8192, content.len());
+    assert_eq!(b"Test file 1.\n", &content[..13]);
+    assert!(content[4096..].iter().all(|&b| b == 0));
+
+    assert_eq!(4096, read(BadClusterPolicy::Stop).unwrap().len());
+}
+
+#[test]
+fn first_cluster_overflow() {
+    // Load the image then make file1 a contiguous allocation that goes beyond the cluster #FFFFFFFF.
+    let mut data = load_image();
+
+    data[0x90e1] = 3;
+    data[0x90f4..0x90f8].copy_from_slice(&0xffffffffu32.to_le_bytes());
+    data[0x90f8..0x9100].copy_from_slice(&12288u64.to_le_bytes());
+
+    // The last cluster of file1 does not fit in 32 bits. SetChecksum is no longer valid.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().lenient(true);
+
+    assert!(matches!(
+        Root::open_with(image, &options),
+        Err(OpenError::CreateFileObjectFailed(
+            6,
+            Cluster(5),
+            exfat::file::NewError::CreateClustersReaderFailed(
+                Cluster(0xffffffff),
+                12288,
+                cluster::NewError::InvalidDataLength
+            )
+        ))
+    ));
+}
+
+#[test]
+fn skip_corrupt() {
+    // Load the image then make the file name of file1 invalid.
+    let mut data = load_image();
+
+    data[0x9101] = 1;
+
+    // The root directory cannot be opened without skipping the corrupted entry set.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert!(matches!(
+        Root::open(image),
+        Err(OpenError::LoadFileEntryFailed(
+            FileEntryError::InvalidFileName(8, Cluster(5))
+        ))
+    ));
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().skip_corrupt(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let warnings = root.warnings();
+
+    assert_eq!(1, warnings.len());
+    assert!(matches!(
+        warnings[0],
+        Warning::CorruptEntrySet(6, Cluster(5), _)
+    ));
+
+    let items = Vec::from_iter(root);
+
+    assert_eq!(1, items.len());
+    assert!(matches!(&items[0], Item::Directory(d) if d.name() == "dir1"));
+}
+
+#[test]
+fn ignore_checksums() {
+    // Load the image then break the boot checksum and SetChecksum of file1.
+    let mut data = load_image();
+
+    data[0x1400] ^= 1;
+    data[0x90c2] ^= 1;
+
+    // Open the image without verifying the checksums.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert!(matches!(
+        Root::open(image),
+        Err(OpenError::BootChecksumMismatch)
+    ));
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().ignore_checksums(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    assert!(root.warnings().is_empty());
+    assert_eq!(2, root.into_iter().count());
+}
+
+#[test]
+fn metadata_only() {
+    struct Partition {
+        data: Vec<u8>,
+        reads: Mutex<Vec<(u64, usize)>>,
+    }
+
+    impl DiskPartition for Partition {
+        fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            self.reads.lock().unwrap().push((offset, buf.len()));
+            self.data.as_slice().read(offset, buf)
+        }
+
+        fn write(&self, _: u64, _: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            Err("the partition is read-only".into())
+        }
+    }
+
+    // Open the image and count the bytes that was read from the FAT at 0x4000.
+    let data = load_image();
+    let open = |options: &OpenOptions| {
+        let partition = Arc::new(Partition {
+            data: data.clone(),
+            reads: Mutex::new(Vec::new()),
+        });
+
+        let root =
+            Root::open_with(partition.clone(), options).expect("cannot open the root directory");
+        let fat: usize = partition
+            .reads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&&(o, _)| (0x4000..0x5000).contains(&o))
+            .map(|&(_, l)| l)
+            .sum();
+
+        (root, fat)
+    };
+
+    let (_, fat) = open(&OpenOptions::new());
+
+    assert_eq!(1008, fat);
+
+    // Only the FAT entries of the Allocation Bitmap, the Up-case Table and the root directory must
+    // be read.
+    let (root, fat) = open(&OpenOptions::new().metadata_only(true));
+
+    assert_eq!(4 * 4, fat);
+    assert_eq!(Some("Test image"), root.volume_label());
+    assert_eq!(root.total_bytes() - 7 * 4096, root.free_bytes());
+    assert!(matches!(root.open_path("file1"), Ok(Item::File(_))));
+    assert_eq!(0, root.into_iter().count());
+}
+
+#[test]
+fn fat_window() {
+    // Read the files with the FAT entries loaded on demand.
+    let mut data = load_image();
+    let content: Vec<u8> = (0..10000u32).map(|v| v as u8).collect();
+    let image = open_rw(&mut data);
+    let options = OpenOptions::new().writable(true).fat_window(16);
+    let mut root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let mut file2 = match root.open_path("dir1/file2") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open dir1/file2"),
+    };
+    let mut buf = Vec::new();
+
+    file2.read_to_end(&mut buf).expect("cannot read dir1/file2");
+    assert_eq!(b"Test file 2.\n", buf.as_slice());
+    assert_eq!(
+        Some(FatEntry::EndOfChain),
+        root.fat_entry(Cluster(4)).unwrap()
+    );
+    assert_eq!(Some(FatEntry::Free), root.fat_entry(Cluster(200)).unwrap());
+
+    // Write a file that need a FAT chain.
+    let mut file3 = root.create_file("file3").expect("cannot create file3");
+
+    file3
+        .write_all(&content[..5000])
+        .expect("cannot write file3");
+    root.create_file("file4")
+        .expect("cannot create file4")
+        .write_all(b"Test file 4.\n")
+        .expect("cannot write file4");
+    file3
+        .write_all(&content[5000..])
+        .expect("cannot write file3");
+    file3.flush().expect("cannot flush file3");
+
+    drop(file2);
+    drop(file3);
+    drop(root);
+
+    // Check the file with the whole FAT loaded.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut file3 = match root.open_path("file3") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file3"),
+    };
+    let mut buf = Vec::new();
+
+    file3.read_to_end(&mut buf).expect("cannot read file3");
+    assert_eq!(content, buf);
+}
+
+#[test]
+fn minimal_memory() {
+    // Open the image with both modes.
+    let data = load_image();
+    let options = OpenOptions::new().minimal_memory(true);
+    let root = Root::open_with(data.as_slice(), &options).expect("cannot open the root directory");
+    let full = Root::open(data.as_slice()).expect("cannot open the root directory");
+
+    // Find the file case-insensitively.
+    let mut file2 = match root.open_path("DIR1/File2") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open dir1/file2"),
+    };
+    let mut buf = Vec::new();
+
+    file2.read_to_end(&mut buf).expect("cannot read dir1/file2");
+    assert_eq!(b"Test file 2.\n", buf.as_slice());
+    assert_eq!(file2.name(), "file2");
+    assert!(matches!(
+        root.open_path("dir1/file3"),
+        Err(OpenPathError::NotFound(_))
+    ));
+    assert_eq!(
+        Some(FatEntry::EndOfChain),
+        root.fat_entry(Cluster(4)).unwrap()
+    );
+
+    // The compressed Up-case Table must give the same mapping.
+    assert!(root.upcase_table().iter().eq(full.upcase_table().iter()));
+}
+
+#[test]
+fn cache_size() {
+    // Open the image with a cache smaller than the number of clusters being used.
+    let mut data = load_image();
+    let content: Vec<u8> = (0..10000u32).map(|v| v as u8).collect();
+    let image = open_rw(&mut data);
+    let options = OpenOptions::new().writable(true).cache_size(2);
+    let mut root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    for _ in 0..2 {
+        let mut file2 = match root.open_path("dir1/file2") {
+            Ok(Item::File(v)) => v,
+            _ => panic!("cannot open dir1/file2"),
+        };
+        let mut buf = Vec::new();
+
+        file2.read_to_end(&mut buf).expect("cannot read dir1/file2");
+        assert_eq!(b"Test file 2.\n", buf.as_slice());
+    }
+
+    // The cached clusters must reflect the writes.
+    let mut file3 = root.create_file("file3").expect("cannot create file3");
+
+    file3.write_all(&content).expect("cannot write file3");
+    file3.flush().expect("cannot flush file3");
+
+    drop(file3);
+
+    let mut file3 = match root.open_path("file3") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file3"),
+    };
+    let mut buf = Vec::new();
+
+    file3.read_to_end(&mut buf).expect("cannot read file3");
+    assert_eq!(content, buf);
+}
+
+#[test]
+fn read_ahead() {
+    // Write a file with multiple clusters.
+    let mut data = load_image();
+    let content: Vec<u8> = (0..10000u32).map(|v| (v % 251) as u8).collect();
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.create_file("file3")
+        .expect("cannot create file3")
+        .write_all(&content)
+        .expect("cannot write file3");
+
+    drop(root);
+
+    // Read the file in small chunks with read-ahead.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().read_ahead(1);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let mut file3 = match root.open_path("file3") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file3"),
+    };
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1000];
+
+    loop {
+        let n = file3.read(&mut chunk).expect("cannot read file3");
+
+        if n == 0 {
+            break;
+        }
+
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    assert_eq!(content, buf);
+
+    // Seek then read.
+    file3
+        .seek(SeekFrom::Start(4000))
+        .expect("cannot seek file3");
+    file3.read_exact(&mut chunk).expect("cannot read file3");
+    assert_eq!(&content[4000..5000], &chunk);
+}
+
+#[test]
+fn contiguous_reads() {
+    // Write a file with multiple clusters.
+    let mut data = load_image();
+    let content: Vec<u8> = (0..10000u32).map(|v| (v % 251) as u8).collect();
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.create_file("file3")
+        .expect("cannot create file3")
+        .write_all(&content)
+        .expect("cannot write file3");
+
+    drop(root);
+
+    // The contiguous clusters should be read at once.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut file3 = match root.open_path("file3") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file3"),
+    };
+    let mut buf = vec![0u8; content.len()];
+
+    assert_eq!(
+        content.len(),
+        file3.read(&mut buf).expect("cannot read file3")
+    );
+    assert_eq!(content, buf);
+
+    drop(file3);
+    drop(root);
+
+    // Limit the read to a single sector.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().read_granularity(ReadGranularity::Sector);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let mut file3 = match root.open_path("file3") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file3"),
+    };
+
+    assert_eq!(512, file3.read(&mut buf).expect("cannot read file3"));
+    assert_eq!(&content[..512], &buf[..512]);
+    buf.clear();
+    file3.read_to_end(&mut buf).expect("cannot read file3");
+    assert_eq!(&content[512..], buf.as_slice());
+}
+
+#[test]
+fn read_vectored() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut file1 = match root.open_path("file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file1"),
+    };
+    let mut first = [0u8; 5];
+    let mut second = [0u8; 20];
+    let mut bufs = [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)];
+
+    assert_eq!(
+        13,
+        file1.read_vectored(&mut bufs).expect("cannot read file1")
+    );
+    assert_eq!(b"Test ", &first);
+    assert_eq!(b"file 1.\n", &second[..8]);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap() {
+    use exfat::mmap::MmapPartition;
+
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = unsafe { MmapPartition::open(&image).expect("cannot map exfat.img") };
+
+    assert_eq!(b"EXFAT   ", &image.as_slice().unwrap()[3..11]);
+
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut file1 = match root.open_path("file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file1"),
+    };
+    let mut buf = Vec::new();
+
+    file1.read_to_end(&mut buf).expect("cannot read file1");
+    assert_eq!(b"Test file 1.\n", buf.as_slice());
+}
+
+#[test]
+fn in_memory() {
+    let data = load_image();
+
+    fn read_file1<P: DiskPartition>(partition: P) -> Vec<u8> {
+        let root = Root::open(partition).expect("cannot open the root directory");
+        let mut file1 = match root.open_path("file1") {
+            Ok(Item::File(v)) => v,
+            _ => panic!("cannot open file1"),
+        };
+        let mut buf = Vec::new();
+
+        file1.read_to_end(&mut buf).expect("cannot read file1");
+        buf
+    }
+
+    assert_eq!(b"Test file 1.\n", read_file1(data.as_slice()).as_slice());
+    assert_eq!(b"Test file 1.\n", read_file1(data.clone()).as_slice());
+    assert_eq!(
+        b"Test file 1.\n",
+        read_file1(Cursor::new(data.clone())).as_slice()
+    );
+
+    // Share a partition or use it as a trait object.
+    let shared = Arc::new(data);
+    let boxed: Box<dyn DiskPartition> = Box::new(shared.as_slice());
+
+    assert_eq!(b"Test file 1.\n", read_file1(shared.clone()).as_slice());
+    assert_eq!(b"Test file 1.\n", read_file1(&*shared).as_slice());
+    assert_eq!(b"Test file 1.\n", read_file1(boxed).as_slice());
+}
+
+#[cfg(all(feature = "blkdev", target_os = "linux"))]
+#[test]
+fn block_device() {
+    use exfat::blkdev::BlockDevice;
+
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+
+    for direct in [false, true] {
+        let device = BlockDevice::open(&image, direct).expect("cannot open exfat.img");
+
+        assert_eq!(512, device.block_size());
+
+        let root = Root::open(device).expect("cannot open the root directory");
+        let mut file1 = match root.open_path("file1") {
+            Ok(Item::File(v)) => v,
+            _ => panic!("cannot open file1"),
+        };
+        let mut buf = Vec::new();
+
+        file1.read_to_end(&mut buf).expect("cannot read file1");
+        assert_eq!(b"Test file 1.\n", buf.as_slice());
+    }
+}
+
+#[test]
+fn discard() {
+    struct Partition {
+        data: Mutex<Vec<u8>>,
+        discarded: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl DiskPartition for Partition {
+        fn is_writable(&self) -> bool {
+            true
+        }
+
+        fn discard(&self, offset: u64, len: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.discarded.lock().unwrap().push((offset, len));
+            Ok(())
+        }
+
+        fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            self.data.lock().unwrap().as_slice().read(offset, buf)
+        }
+
+        fn write(&self, offset: u64, buf: &[u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            let mut data = self.data.lock().unwrap();
+            let offset = offset as usize;
+
+            data[offset..(offset + buf.len())].copy_from_slice(buf);
+
+            Ok(buf.len() as u64)
+        }
+    }
+
+    // Load the image.
+    let data = load_image();
+
+    for discard in [false, true] {
+        let partition = Arc::new(Partition {
+            data: Mutex::new(data.clone()),
+            discarded: Mutex::new(Vec::new()),
+        });
+
+        // Remove file1 then shrink file3 from 3 clusters to 1 cluster.
+        let options = OpenOptions::new().writable(true).discard(discard);
+        let mut root =
+            Root::open_with(partition.clone(), &options).expect("cannot open the root directory");
+        let mut file3 = root.create_file("file3").expect("cannot create file3");
+
+        file3.write_all(&[1; 10000]).expect("cannot write file3");
+        file3.set_len(100).expect("cannot truncate file3");
+
+        drop(file3);
+
+        root.remove("file1").expect("cannot remove file1");
+
+        // Check the discarded ranges.
+        let discarded = partition.discarded.lock().unwrap();
+
+        if discard {
+            assert_eq!(2, discarded.len());
+            assert_eq!(0x2000, discarded[0].1);
+            assert_eq!((0xB000, 0x1000), discarded[1]);
+        } else {
+            assert!(discarded.is_empty());
+        }
+    }
+}
+
+#[cfg(all(feature = "uring", target_os = "linux"))]
+#[test]
+fn uring() {
+    use exfat::uring::{ReadRequest, UringPartition};
+
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let partition = UringPartition::open(&image, 4).expect("cannot open exfat.img");
+
+    // Read the clusters of file1 and file2 in a batch.
+    let mut file1 = [0u8; 13];
+    let mut file2 = [0u8; 13];
+    let mut tail = [0u8; 16];
+    let mut requests = [
+        ReadRequest {
+            offset: 0xB000,
+            buf: &mut file1,
+        },
+        ReadRequest {
+            offset: 0x100000 - 8,
+            buf: &mut tail,
+        },
+    ];
+    let mut results = [0; 2];
+
+    partition
+        .read_batch(&mut requests, |i, r| {
+            results[i] = r.expect("cannot read exfat.img")
+        })
+        .expect("cannot submit the reads");
+
+    assert_eq!(b"Test file 1.\n", &file1);
+    assert_eq!([13, 8], results);
+
+    // Read through the volume.
+    let root = Root::open(partition).expect("cannot open the root directory");
+    let mut file = match root.open_path("dir1/file2") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open dir1/file2"),
+    };
+
+    file.read_exact(&mut file2).expect("cannot read dir1/file2");
+    assert_eq!(b"Test file 2.\n", &file2);
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn http() {
+    use exfat::http::HttpPartition;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Serve the image with range requests.
+    let data = load_image();
+    let listener = TcpListener::bind("127.0.0.1:0").expect("cannot bind the server");
+    let url = format!("http://{}/exfat.img", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut range = None;
+
+            loop {
+                let mut line = String::new();
+
+                reader.read_line(&mut line).unwrap();
+
+                if let Some(v) = line.trim().strip_prefix("Range: bytes=") {
+                    let (start, end) = v.split_once('-').unwrap();
+                    range = Some((start.parse().unwrap(), end.parse::<usize>().unwrap()));
+                } else if line.trim().is_empty() {
+                    break;
+                }
+            }
+
+            let (start, end) = range.unwrap();
+            let body = &data[start..=end];
+
+            counter.fetch_add(1, Ordering::Relaxed);
+            write!(
+                stream,
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                start,
+                end,
+                data.len(),
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        }
+    });
+
+    // Read file1 twice. The second read must be served from the cache.
+    let partition = HttpPartition::open(&url, 0x10000, 16).expect("cannot open the image");
+
+    assert_eq!(Some(0x100000), partition.len());
+
+    let root = Root::open(partition).expect("cannot open the root directory");
+    let mut counts = Vec::new();
+
+    for _ in 0..2 {
+        let mut file1 = match root.open_path("file1") {
+            Ok(Item::File(v)) => v,
+            _ => panic!("cannot open file1"),
+        };
+        let mut buf = Vec::new();
+
+        file1.read_to_end(&mut buf).expect("cannot read file1");
+        assert_eq!(b"Test file 1.\n", buf.as_slice());
+
+        counts.push(requests.load(Ordering::Relaxed));
+    }
+
+    assert_eq!(counts[0], counts[1]);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn tokio() {
+    use exfat::nonblocking::{AsyncDiskPartition, AsyncItem, AsyncRoot, TokioExecutor};
+    use futures_core::Stream;
+    use std::future::poll_fn;
+    use std::pin::Pin;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    struct Partition(Vec<u8>);
+
+    impl AsyncDiskPartition for Partition {
+        async fn read(
+            &self,
+            offset: u64,
+            buf: &mut [u8],
+        ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            tokio::task::yield_now().await;
+            self.0.as_slice().read(offset, buf)
+        }
+    }
+
+    // Open the image.
+    let data = load_image();
+    let root = AsyncRoot::open(Partition(data), TokioExecutor::current())
+        .await
+        .expect("cannot open the root directory");
+    let names: Vec<&str> = root.iter().map(|i| i.name()).collect();
+
+    assert_eq!(vec!["dir1", "file1"], names);
+
+    // Read the files.
+    let mut file1 = match root.open_path("file1").await {
+        Ok(AsyncItem::File(v)) => v,
+        _ => panic!("cannot open file1"),
+    };
+    let mut buf = Vec::new();
+
+    file1
+        .read_to_end(&mut buf)
+        .await
+        .expect("cannot read file1");
+    assert_eq!(b"Test file 1.\n", buf.as_slice());
+
+    let dir1 = match root.find("DIR1").await {
+        Ok(Some(AsyncItem::Directory(v))) => v,
+        _ => panic!("cannot find dir1"),
+    };
+    let mut file2 = match dir1.find("file2").await {
+        Ok(Some(AsyncItem::File(v))) => v,
+        _ => panic!("cannot find file2"),
+    };
+    let mut buf = [0u8; 4];
+
+    file2.seek(SeekFrom::Start(5)).expect("cannot seek file2");
+    file2.read(&mut buf).await.expect("cannot read file2");
+    assert_eq!(b"file", &buf);
+    assert_eq!(1, dir1.open().await.expect("cannot open dir1").len());
+
+    // List the directory as a stream.
+    let mut items = dir1.items().await.expect("cannot open dir1");
+    let mut names = Vec::new();
+
+    while let Some(item) = poll_fn(|cx| Pin::new(&mut items).poll_next(cx)).await {
+        names.push(item.expect("cannot read dir1").name().to_owned());
+    }
+
+    assert_eq!(vec!["file2"], names);
+
+    // Use the file with Tokio I/O.
+    let mut buf = Vec::new();
+
+    file2.seek(SeekFrom::Start(0)).expect("cannot rewind file2");
+    tokio::io::copy(&mut file2, &mut buf)
+        .await
+        .expect("cannot copy file2");
+    assert_eq!(b"Test file 2.\n", buf.as_slice());
+
+    let mut buf = [0u8; 5];
+
+    AsyncSeekExt::seek(&mut file2, SeekFrom::End(-8))
+        .await
+        .expect("cannot seek file2");
+    AsyncReadExt::read_exact(&mut file2, &mut buf)
+        .await
+        .expect("cannot read file2");
+    assert_eq!(b"file ", &buf);
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn async_std() {
+    use exfat::nonblocking::{AsyncDiskPartition, AsyncItem, AsyncRoot, AsyncStdExecutor};
+    use futures_io::{AsyncRead, AsyncSeek};
+    use std::future::poll_fn;
+    use std::pin::Pin;
+
+    struct Partition(Vec<u8>);
+
+    impl AsyncDiskPartition for Partition {
+        async fn read(
+            &self,
+            offset: u64,
+            buf: &mut [u8],
+        ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            async_std::task::yield_now().await;
+            self.0.as_slice().read(offset, buf)
+        }
+    }
+
+    async_std::task::block_on(async {
+        // Open file1.
+        let data = load_image();
+        let root = AsyncRoot::open(Partition(data), AsyncStdExecutor)
+            .await
+            .expect("cannot open the root directory");
+        let mut file1 = match root.open_path("file1").await {
+            Ok(AsyncItem::File(v)) => v,
+            _ => panic!("cannot open file1"),
+        };
+
+        // Read the last word with futures-io.
+        let mut buf = [0u8; 16];
+        let pos = poll_fn(|cx| Pin::new(&mut file1).poll_seek(cx, SeekFrom::Start(10)))
+            .await
+            .expect("cannot seek file1");
+        let read = poll_fn(|cx| Pin::new(&mut file1).poll_read(cx, &mut buf))
+            .await
+            .expect("cannot read file1");
+
+        assert_eq!(10, pos);
+        assert_eq!(b"1.\n", &buf[..read]);
+    });
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn inline_executor() {
+    use exfat::nonblocking::{AsyncDiskPartition, AsyncItem, AsyncRoot, Executor};
+    use std::future::Future;
+    use std::pin::{pin, Pin};
+    use std::task::{Context, Poll, Waker};
+
+    struct Partition(Vec<u8>);
+
+    impl AsyncDiskPartition for Partition {
+        async fn read(
+            &self,
+            offset: u64,
+            buf: &mut [u8],
+        ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            self.0.as_slice().read(offset, buf)
+        }
+    }
+
+    // An executor without the blocking threads like the one on the embedded targets.
+    struct Inline;
+
+    impl Executor for Inline {
+        fn spawn_blocking(
+            &self,
+            task: Box<dyn FnOnce() + Send>,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async move { task() })
+        }
+
+        fn block_on(&self, mut future: Pin<&mut (dyn Future<Output = ()> + Send + '_)>) {
+            let mut cx = Context::from_waker(Waker::noop());
+
+            while future.as_mut().poll(&mut cx).is_pending() {
+                std::hint::spin_loop();
+            }
+        }
+    }
+
+    let data = load_image();
+    let mut task = pin!(async {
+        let root = AsyncRoot::open(Partition(data), Inline)
+            .await
+            .expect("cannot open the root directory");
+        let mut file1 = match root.open_path("file1").await {
+            Ok(AsyncItem::File(v)) => v,
+            _ => panic!("cannot open file1"),
+        };
+
+        let mut buf = Vec::new();
+
+        file1
+            .read_to_end(&mut buf)
+            .await
+            .expect("cannot read file1");
+
+        buf
+    });
+
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let buf = loop {
+        if let Poll::Ready(v) = task.as_mut().poll(&mut cx) {
+            break v;
+        }
+    };
+
+    assert_eq!(b"Test file 1.\n", buf.as_slice());
+}
+
+#[test]
+fn partition_too_small() {
+    let mut data = load_image();
+
+    data.truncate(512 * 1024);
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+
+    assert_eq!(Some(512 * 1024), image.len());
+    assert!(matches!(
+        Root::open(image),
+        Err(OpenError::PartitionTooSmall(0x100000, 0x80000))
+    ));
+
+    // The clusters within the partition are still readable in lenient mode.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().lenient(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    assert!(root
+        .warnings()
+        .contains(&Warning::PartitionTooSmall(0x100000, 0x80000)));
+    assert!(matches!(root.open_path("file1"), Ok(Item::File(_))));
+}
+
+#[test]
+fn valid_data_length() {
+    // Load the image then set ValidDataLength of file1 to 5.
+    let mut data = load_image();
+
+    data[0x90e8] = 5;
+
+    // The data after ValidDataLength must be read as zeros. SetChecksum is no longer valid.
+    let image = open_rw(&mut data);
+    let options = OpenOptions::new().writable(true).lenient(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let mut file = match root.open_path("file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file1"),
+    };
+
+    let mut content = Vec::new();
+
+    assert_eq!(13, file.len());
+    assert_eq!(5, file.valid_data_length());
+    assert_eq!(Some(Cluster(7)), file.first_cluster());
+    assert!(file.no_fat_chain());
+    file.read_to_end(&mut content).expect("cannot read file1");
+    assert_eq!(b"Test \0\0\0\0\0\0\0\0", content.as_slice());
+
+    // The gap must be filled with zeros when writing after ValidDataLength.
+    file.seek(SeekFrom::Start(10)).expect("cannot seek file1");
+    file.write_all(b"X").expect("cannot write file1");
+    file.rewind().expect("cannot rewind file1");
+    content.clear();
+    file.read_to_end(&mut content).expect("cannot read file1");
+    assert_eq!(b"Test \0\0\0\0\0X\0\0", content.as_slice());
+    drop(file);
+    drop(root);
+
+    assert_eq!(b"Test \0\0\0\0\0X", &data[0xb000..0xb00b]);
+}
+
+#[test]
+fn cross_links() {
+    // Load the image then point file1 to the cluster of file2.
+    let mut data = load_image();
+
+    data[0x90f4] = 8;
+
+    // Check the volume.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().lenient(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert_eq!(
+        vec![(Cluster(8), "/file1", "/dir1/file2")],
+        report.cross_links().collect::<Vec<_>>()
+    );
+    assert_eq!(vec![Cluster(7)], report.lost_clusters().collect::<Vec<_>>());
+}
+
+#[test]
+fn cross_linked_loop() {
+    // Load the image then point file1 to cluster #13 and file2 to cluster #12, which form a loop
+    // in the FAT. DataLength of both files is much larger than the volume.
+    let mut data = load_image();
+
+    data[0x4030..0x4034].copy_from_slice(&13u32.to_le_bytes());
+    data[0x4034..0x4038].copy_from_slice(&12u32.to_le_bytes());
+    data[0x6001] |= 0x0c;
+    data[0x90e1] = 0x01;
+    data[0x90f4] = 13;
+    data[0x90f8..0x9100].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    data[0xa021] = 0x01;
+    data[0xa034] = 12;
+    data[0xa038..0xa040].copy_from_slice(&(1u64 << 40).to_le_bytes());
+
+    // The walk of file2 must stop at the first cluster of file1.
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new()
+        .metadata_only(true)
+        .ignore_checksums(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert_eq!(
+        vec![(Cluster(12), "/file1", "/dir1/file2")],
+        report.cross_links().collect::<Vec<_>>()
+    );
+    assert!(report
+        .problems()
+        .contains(&Problem::CyclicChain("/file1".into())));
+}
+
+#[test]
+fn repair() {
+    // Load the image then mark cluster #12 as allocated and corrupt the SetChecksum of file1.
+    let mut data = load_image();
+
+    data[0x6001] |= 0x04;
+    data[0x90c2] ^= 0xff;
+
+    // Run dry-run.
+    let image = open_rw(&mut data);
+    let options = OpenOptions::new().writable(true).lenient(true);
+    let mut root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let fixes = root.repair(true).expect("cannot repair the volume");
+
+    assert_eq!(2, fixes.len());
+    assert!(fixes.contains(&Fix::FreeCluster(Cluster(12))));
+    assert!(fixes.contains(&Fix::RewriteChecksum("/file1".into())));
+    assert_eq!(
+        2,
+        root.check()
+            .expect("cannot check the volume")
+            .problems()
+            .len()
+    );
+
+    // Repair the volume.
+    assert_eq!(fixes, root.repair(false).expect("cannot repair the volume"));
+
+    drop(root);
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert!(report.is_clean(), "{:?}", report.problems());
+}
+
+#[test]
+fn repair_partial() {
+    // Load the image then change the type of the File Name entry of file1 so the entry set cannot
+    // be loaded.
+    let mut data = load_image();
+
+    data[0x9100] = 0xc5;
+
+    // The data of file1 must not be released.
+    let image = open_rw(&mut data);
+    let options = OpenOptions::new().writable(true).skip_corrupt(true);
+    let mut root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert_eq!([Problem::InvalidEntrySet("/".into(), 4)], report.problems());
+    assert!(report.is_partial());
+
+    assert!(root
+        .repair(false)
+        .expect("cannot repair the volume")
+        .is_empty());
+
+    drop(root);
+
+    let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+    let options = OpenOptions::new().skip_corrupt(true);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+    let report = root.check().expect("cannot check the volume");
+
+    assert!(report.is_partial());
+    assert_eq!(0, report.lost_clusters().count());
+    assert_eq!(0x7f, data[0x6000]);
+}
+
+fn load_image() -> Vec<u8> {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+
+    std::fs::read(image).expect("cannot read exfat.img")
+}
+
+fn open_rw(data: &mut Vec<u8>) -> Image<Cursor<&mut Vec<u8>>> {
+    Image::open_rw(Cursor::new(data)).expect("cannot open exFAT image")
+}
+
+fn now() -> i64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+
+    now.unwrap().as_secs() as i64
+}
+
+fn item_name<P: DiskPartition>(item: &Item<P>) -> &str {
+    match item {
+        Item::Directory(d) => d.name(),
+        Item::File(f) => f.name(),
+    }
+}
+
+#[test]
+fn open_path() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    // Read a part of file.
+    match root.open_path("file1").expect("cannot open file1") {
+        Item::File(mut f) => {
+            let mut buf = [0u8; 4];
+
+            f.seek(SeekFrom::End(-8)).expect("cannot seek file1");
+            f.read_exact(&mut buf).expect("cannot read file1");
+            assert_eq!(b"file", &buf);
+
+            f.seek(SeekFrom::Current(-4)).expect("cannot seek file1");
+            f.read_exact(&mut buf).expect("cannot read file1");
+            assert_eq!(b"file", &buf);
+
+            // Positioned reads do not move the position.
+            assert_eq!(4, f.read_at(5, &mut buf).expect("cannot read file1"));
+            assert_eq!(b"file", &buf);
+            assert_eq!(9, f.stream_position().unwrap());
+            assert_eq!(0, f.read_at(13, &mut buf).expect("cannot read file1"));
+
+            assert_eq!(13, f.seek(SeekFrom::Start(100)).expect("cannot seek file1"));
+            assert_eq!(0, f.read(&mut buf).expect("cannot read file1"));
+        }
+        Item::Directory(_) => panic!("file1 is not a file"),
+    }
+
+    // Find items.
+    let item = root.find("FILE1").unwrap().unwrap();
+
+    assert_eq!("file1", item.name());
+    assert_eq!(13, item.len());
+    assert!(!item.attributes().is_directory());
+
+    assert!(matches!(root.find("file2"), Ok(None)));
+
+    match root.find("Dir1") {
+        Ok(Some(Item::Directory(d))) => {
+            assert!(matches!(d.find("FILE2"), Ok(Some(Item::File(f))) if f.name() == "file2"));
+        }
+        _ => panic!("cannot find dir1"),
+    }
+
+    // Open existing items.
+    match root.open_path("/DIR1/file2").expect("cannot open file2") {
+        Item::File(mut f) => {
+            let mut c = String::new();
+
+            f.read_to_string(&mut c).expect("cannot read file2");
+
+            assert_eq!("Test file 2.\n", c);
+        }
+        Item::Directory(_) => panic!("file2 is not a file"),
+    }
+
+    match root.open_path("dir1/../dir1/") {
+        Ok(Item::Directory(d)) => assert_eq!("dir1", d.name()),
+        _ => panic!("cannot open dir1"),
+    }
+
+    // Open invalid paths.
+    assert!(matches!(
+        root.open_path("/"),
+        Err(OpenPathError::InvalidPath)
+    ));
+    assert!(matches!(
+        root.open_path("/dir1/file3"),
+        Err(OpenPathError::NotFound(p)) if p == "/dir1/file3"
+    ));
+    assert!(matches!(
+        root.open_path("/file1/file2"),
+        Err(OpenPathError::NotADirectory(_))
+    ));
+}
+
+#[test]
+fn case_insensitive() {
+    // Load the image.
+    let mut data = load_image();
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    // Create a file with non-ASCII name.
+    root.create_file("ärger").expect("cannot create ärger");
+
+    assert!(root.create_file("ÄRGER").is_err());
+
+    // Check the Up-case Table.
+    let table = root.upcase_table();
+
+    assert_eq!(0x41, table.to_upper(0x61));
+    assert_eq!(0x41, table.to_upper(0x41));
+    assert_eq!(0xc4, table.to_upper(0xe4));
+    assert_eq!(0x10000, table.iter().len());
+    assert!(table.iter().all(|(c, u)| u == table.to_upper(c)));
+
+    match root.open_path("/ÄRGER") {
+        Ok(Item::File(f)) => assert_eq!("ärger", f.name()),
+        _ => panic!("cannot open ärger"),
+    };
+}
+
+#[test]
+fn walk() {
+    // Load the image and create more items.
+    let mut data = load_image();
+    let image = open_rw(&mut data);
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut dir = root.create_dir("dir2").expect("cannot create dir2");
+    let mut sub = dir.create_dir("dir3").expect("cannot create dir3");
+
+    sub.create_file("file3").expect("cannot create file3");
+
+    drop(sub);
+    drop(dir);
+    drop(root);
+
+    // Walk the tree.
+    let walk = |f: fn(Walk<_>) -> Walk<_>| -> Vec<String> {
+        let image = Image::open(Cursor::new(&data)).expect("cannot open exFAT image");
+        let root = Root::open(image).expect("cannot open the root directory");
+
+        f(root.walk())
+            .map(|i| i.expect("cannot walk the tree").0)
+            .collect()
+    };
+
+    assert_eq!(
+        vec![
+            "/dir1",
+            "/dir1/file2",
+            "/file1",
+            "/dir2",
+            "/dir2/dir3",
+            "/dir2/dir3/file3"
+        ],
+        walk(|w| w)
+    );
+    assert_eq!(
+        vec![
+            "/dir1",
+            "/file1",
+            "/dir2",
+            "/dir1/file2",
+            "/dir2/dir3",
+            "/dir2/dir3/file3"
+        ],
+        walk(|w| w.breadth_first())
+    );
+    assert_eq!(
+        vec!["/dir1", "/dir1/file2", "/file1", "/dir2", "/dir2/dir3"],
+        walk(|w| w.max_depth(2))
+    );
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi() {
+    use exfat::ffi::*;
+    use std::ffi::CStr;
+    use std::ptr::null_mut;
+
+    unsafe {
+        // Open the volume.
+        let mut vol = null_mut();
+
+        assert_eq!(
+            EXFAT_EOPEN,
+            exfat_open(c"tests/not-exists.img".as_ptr(), &mut vol)
+        );
+        assert_eq!(EXFAT_OK, exfat_open(c"tests/exfat.img".as_ptr(), &mut vol));
+
+        // List the root directory.
+        let mut dir = null_mut();
+        let mut stat = std::mem::zeroed::<Stat>();
+        let mut names = Vec::new();
+
+        assert_eq!(EXFAT_OK, exfat_opendir(vol, c"/".as_ptr(), &mut dir));
+
+        while exfat_readdir(dir, &mut stat) == EXFAT_OK {
+            let name = CStr::from_ptr(stat.name.as_ptr()).to_str().unwrap();
+
+            names.push((name.to_owned(), stat.is_directory != 0));
+        }
+
+        exfat_closedir(dir);
+
+        assert_eq!(
+            vec![("dir1".to_owned(), true), ("file1".to_owned(), false)],
+            names
+        );
+
+        // Read the file.
+        let mut file = null_mut();
+        let mut buf = [0u8; 64];
+        let mut read = 0;
+
+        assert_eq!(
+            EXFAT_ENOTDIR,
+            exfat_opendir(vol, c"file1".as_ptr(), &mut dir)
+        );
+        assert_eq!(
+            EXFAT_EISDIR,
+            exfat_file_open(vol, c"dir1".as_ptr(), &mut file)
+        );
+        assert_eq!(
+            EXFAT_ENOENT,
+            exfat_stat(vol, c"dir1/file3".as_ptr(), &mut stat)
+        );
+        assert_eq!(EXFAT_OK, exfat_stat(vol, c"DIR1/FILE2".as_ptr(), &mut stat));
+        assert_eq!(13, stat.size);
+        assert_eq!(
+            EXFAT_OK,
+            exfat_file_open(vol, c"dir1/file2".as_ptr(), &mut file)
+        );
+
+        exfat_close(vol);
+
+        assert_eq!(
+            EXFAT_OK,
+            exfat_file_read(file, buf.as_mut_ptr(), buf.len(), &mut read)
+        );
+        assert_eq!(b"Test file 2.\n", &buf[..read]);
+
+        exfat_file_close(file);
+
+        let msg = CStr::from_ptr(exfat_strerror(EXFAT_ENOENT));
+
+        assert_eq!("no such file or directory", msg.to_str().unwrap());
+    }
+}
+
+#[test]
+fn cli() {
+    use std::process::Command;
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_exfat-cli"))
+            .args(args)
+            .output()
+            .expect("cannot run exfat-cli");
+
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+        )
+    };
+
+    // List the directory.
+    let (status, out) = run(&["ls", "tests/exfat.img", "dir1"]);
+
+    assert_eq!(Some(0), status);
+    assert!(out
+        .trim_end()
+        .ends_with(" 13 2023-03-06 13:03:18 +00:00 file2"));
+
+    // Read the file.
+    assert_eq!(
+        (Some(0), "Test file 2.\n".to_owned()),
+        run(&["cat", "tests/exfat.img", "/DIR1/file2"])
+    );
+    assert_eq!(Some(1), run(&["cat", "tests/exfat.img", "dir1"]).0);
+    assert_eq!(Some(2), run(&["cat", "tests/exfat.img"]).0);
+
+    // Check the volume.
+    assert_eq!(
+        (Some(0), "Test image\n".to_owned()),
+        run(&["label", "tests/exfat.img"])
+    );
+    assert_eq!(Some(0), run(&["check", "tests/exfat.img"]).0);
+}
+
+#[test]
+fn extract() {
+    use exfat::extract::{ErrorPolicy, ExtractOptions};
+    use std::time::UNIX_EPOCH;
+
+    // Open the image.
+    let image = File::open("tests/exfat.img").expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let dest = std::env::temp_dir().join(format!("exfat-extract-{}", std::process::id()));
+    let mut files = Vec::new();
+    let options = ExtractOptions::new()
+        .on_error(ErrorPolicy::Skip)
+        .progress(|p, n| files.push((p.to_owned(), n)));
+
+    // Extract.
+    let skipped = root
+        .extract_with(&dest, options)
+        .expect("cannot extract the volume");
+
+    assert!(skipped.is_empty());
+    assert_eq!(
+        vec![("/dir1/file2".to_owned(), 13), ("/file1".to_owned(), 13)],
+        files
+    );
+    assert_eq!(
+        "Test file 2.\n",
+        std::fs::read_to_string(dest.join("dir1").join("file2")).unwrap()
+    );
+
+    // Check the timestamps.
+    let file1 = match root.find("file1").unwrap() {
+        Some(Item::File(v)) => v,
+        _ => unreachable!(),
+    };
+    let modified = std::fs::metadata(dest.join("file1"))
+        .and_then(|m| m.modified())
+        .unwrap();
+
+    assert_eq!(
+        file1.timestamps().modified().unix_time() as u64,
+        modified.duration_since(UNIX_EPOCH).unwrap().as_secs()
+    );
+
+    // Extract into the same directory again.
+    assert!(root.extract_to(&dest).is_err());
+
+    std::fs::remove_dir_all(&dest).unwrap();
+}
+
+#[test]
+fn create_image() {
+    let path = std::env::temp_dir().join(format!("exfat-create-{}.img", std::process::id()));
+    let options = FormatOptions::new().volume_label("Created");
+    let builder = ImageBuilder::new(8 * 1024 * 1024).format(options);
+    let image = builder.create(&path).expect("cannot create the image");
+
+    // The existing file should not be replaced by default.
+    assert!(builder.create(&path).is_err());
+
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    assert_eq!(Some("Created"), root.volume_label());
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+    assert_eq!(8 * 1024 * 1024, std::fs::metadata(&path).unwrap().len());
+
+    drop(root);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sparse() {
+    // Fill the destination with non-zero so we can see which blocks were written.
+    let mut data = vec![0xffu8; 4096];
+    let image = open_rw(&mut data);
+    let mut buf = vec![0u8; 2048];
+
+    buf[600] = 1;
+
+    Sparse::new(image)
+        .write_exact(256, &buf)
+        .expect("cannot write the data");
+
+    assert!(data[..512].iter().all(|&b| b == 0xff));
+    assert!(data[512..1024].iter().all(|&b| b == 0 || b == 1));
+    assert!(data[1024..].iter().all(|&b| b == 0xff));
+    assert_eq!(1, data[856]);
+    assert!(exfat::sparse::is_zero(&[0; 100]));
+
+    // Create a fully allocated image.
+    let path = std::env::temp_dir().join(format!("exfat-sparse-{}.img", std::process::id()));
+    let image = ImageBuilder::new(4 * 1024 * 1024)
+        .sparse(false)
+        .format(FormatOptions::new())
+        .create(&path)
+        .expect("cannot create the image");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+
+    drop(root);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn clone() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+
+    // Clone the volume.
+    let mut data = vec![0u8; 1024 * 1024];
+    let dest = open_rw(&mut data);
+    let options = CloneOptions::new().sparse(true);
+    let copied = root
+        .clone_with(dest, &options)
+        .expect("cannot clone the volume");
+
+    assert!(copied < 1024 * 1024);
+
+    // Check the clone.
+    let clone = Root::open(data.as_slice()).expect("cannot open the clone");
+    let file = match clone.open_path("/dir1/file2") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("file2 is not a file"),
+    };
+    let mut buf = vec![0u8; 13];
+
+    file.read_at(0, &mut buf).expect("cannot read file2");
+
+    assert_eq!(b"Test file 2.\n", buf.as_slice());
+    assert_eq!(Some("Test image"), clone.volume_label());
+    assert!(clone.check().expect("cannot check the clone").is_clean());
+}
+
+#[test]
+fn diff() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let mut data = std::fs::read(&image).expect("cannot read exfat.img");
+
+    // Modify a copy of the volume.
+    let copy = open_rw(&mut data);
+    let mut root = Root::open_rw(copy).expect("cannot open the root directory");
+    let mut file = match root.open_path("/file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("file1 is not a file"),
+    };
+
+    file.write_all(b"Test file X.\n")
+        .expect("cannot write file1");
+
+    drop(file);
+
+    root.create_file("file3").expect("cannot create file3");
+    root.sync_all().expect("cannot sync the volume");
+
+    drop(root);
+
+    // Compare.
+    let old = File::open(image).expect("cannot open exfat.img");
+    let old = Image::open(old).expect("cannot open exFAT image from exfat.img");
+    let old = Root::open(old).expect("cannot open the root directory");
+    let new = Root::open(data.as_slice()).expect("cannot open the root directory");
+    let options = DiffOptions::new().timestamps(false);
+    let changes = exfat::diff::diff(old.walk(), new.walk(), &options).expect("cannot diff");
+
+    assert_eq!(
+        vec![
+            Change::Changed("/file1".into(), vec![Difference::Contents]),
+            Change::Added("/file3".into()),
+        ],
+        changes
+    );
+}
+
+#[test]
+fn deleted_items() {
+    let mut data = vec![0u8; 8 * 1024 * 1024];
+    let image = open_rw(&mut data);
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &FormatOptions::new())
+        .expect("cannot format the volume");
+
+    // Create and delete a file with a name that spans multiple entries.
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let name = "A file with a long name to be deleted";
+    let mut file = root.create_file(name).expect("cannot create the file");
+
+    file.write_all(&[0xaa; 10000])
+        .expect("cannot write the file");
+    file.flush().expect("cannot flush the file");
+
+    drop(file);
+
+    root.create_file("kept").expect("cannot create the file");
+    root.remove(name).expect("cannot remove the file");
+
+    // Find the deleted file.
+    let deleted: Vec<_> = root
+        .deleted_items()
+        .expect("cannot scan the root directory")
+        .collect::<Result<_, _>>()
+        .expect("cannot read the deleted items");
+
+    assert_eq!(1, deleted.len());
+
+    let item = &deleted[0];
+
+    assert_eq!(name, item.name());
+    assert!(item.is_name_complete());
+    assert!(!item.is_directory());
+    assert_eq!(10000, item.len());
+    assert_eq!(3, item.clusters().len());
+    assert!(!item.is_overwritten());
+
+    // Read the data of the deleted file.
+    let mut file = root
+        .open_clusters(item.first_cluster(), item.len(), true)
+        .expect("cannot open the clusters");
+    let mut read = Vec::new();
+
+    file.read_to_end(&mut read).expect("cannot read the data");
+
+    assert_eq!(vec![0xaa; 10000], read);
+    assert!(file.write_all(b"data").is_err());
+}
+
+#[test]
+fn carve() {
+    let mut data = vec![0u8; 8 * 1024 * 1024];
+    let image = open_rw(&mut data);
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &FormatOptions::new())
+        .expect("cannot format the volume");
+
+    // Remove a directory with its contents.
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let mut dir = root
+        .create_dir("lost")
+        .expect("cannot create the directory");
+    let mut file = dir.create_file("orphan").expect("cannot create the file");
+
+    file.write_all(b"Orphan file.\n")
+        .expect("cannot write the file");
+    file.flush().expect("cannot flush the file");
+
+    drop(file);
+    drop(dir);
+
+    root.create_file("kept").expect("cannot create the file");
+    root.remove_all("lost")
+        .expect("cannot remove the directory");
+
+    // Find the file in the released cluster of the directory.
+    let items = root.carve().expect("cannot carve the volume");
+
+    assert_eq!(1, items.len());
+
+    let item = &items[0];
+
+    assert_eq!("orphan", item.name());
+    assert!(!item.is_in_use());
+    assert_eq!(13, item.len());
+    assert!(!item.is_overwritten());
+}
+
+#[test]
+fn raw_entries() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let entries: Vec<_> = root
+        .raw_entries()
+        .expect("cannot open the root directory")
+        .collect::<Result<_, _>>()
+        .expect("cannot read the entries");
+
+    // The root directory has a single cluster.
+    assert_eq!(root.cluster_size() as usize / 32, entries.len());
+    assert!(entries.iter().any(|e| e.entry_type() == 0x83));
+    assert!(entries.iter().any(|e| e.entry_type() == 0x81));
+    assert_eq!(2, entries.iter().filter(|e| e.entry_type() == 0x85).count());
+    assert!(!entries.last().unwrap().is_in_use());
+
+    for (i, e) in entries.iter().enumerate() {
+        assert_eq!(i, e.index());
+        assert_eq!(entries[0].offset() + i as u64 * 32, e.offset());
+    }
+}
+
+#[test]
+fn defragment() {
+    let mut data = vec![0u8; 8 * 1024 * 1024];
+    let image = open_rw(&mut data);
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &FormatOptions::new())
+        .expect("cannot format the volume");
+
+    // Interleave the clusters of two files.
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let size = root.cluster_size() as usize;
+    let mut a = root.create_file("a").expect("cannot create a");
+    let mut b = root.create_file("b").expect("cannot create b");
+
+    for i in 0..4u8 {
+        a.write_all(&vec![i; size]).expect("cannot write a");
+        a.flush().expect("cannot flush a");
+        b.write_all(&vec![0xff; size]).expect("cannot write b");
+        b.flush().expect("cannot flush b");
+    }
+
+    let frag = a.fragmentation();
+
+    assert_eq!(4, frag.extents());
+    assert_eq!(size as u64, frag.largest_extent());
+    assert!(!frag.is_contiguous());
+
+    // The files must be closed before defragmenting.
+    assert!(matches!(
+        root.defragment(),
+        Err(exfat::defrag::DefragError::InUse)
+    ));
+
+    drop(a);
+    drop(b);
+
+    let report = root.defragment().expect("cannot defragment the volume");
+
+    assert_eq!(["/a", "/b"], report.relocated());
+    assert!(report.skipped().is_empty());
+
+    // Check the reloaded items.
+    let a = match root.iter().find(|i| item_name(i) == "a") {
+        Some(Item::File(v)) => v,
+        _ => panic!("a is not a file"),
+    };
+    let mut read = vec![0u8; 4 * size];
+
+    for (i, c) in read.chunks_mut(size).enumerate() {
+        a.read_at((i * size) as u64, c).expect("cannot read a");
+    }
+
+    assert!(a.fragmentation().is_contiguous());
+    assert!(read
+        .chunks(size)
+        .enumerate()
+        .all(|(i, c)| c.iter().all(|&b| b == i as u8)));
+
+    root.sync_all().expect("cannot sync the volume");
+    drop(root);
+
+    // Check the volume.
+    let root = Root::open(data.as_slice()).expect("cannot open the root directory");
+    let mut a = match root.open_path("/a") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("a is not a file"),
+    };
+    let mut read = Vec::new();
+
+    a.read_to_end(&mut read).expect("cannot read a");
+
+    assert!(a.fragmentation().is_contiguous());
+    assert_eq!(4 * size as u64, a.fragmentation().largest_extent());
+
+    assert!(read
+        .chunks(size)
+        .enumerate()
+        .all(|(i, c)| c.iter().all(|&b| b == i as u8)));
+    let report = root.check().expect("cannot check the volume");
+    assert!(report.is_clean(), "{:?}", report.problems());
+}
+
+#[test]
+fn import() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Create a directory tree on the host.
+    let src = std::env::temp_dir().join(format!("exfat-import-{}", std::process::id()));
+    let data: Vec<u8> = (0..10000u32).map(|v| v as u8).collect();
+
+    std::fs::create_dir_all(src.join("dir1")).unwrap();
+    std::fs::write(src.join("dir1").join("file2"), "Test file 2.\n").unwrap();
+
+    {
+        let mut file = File::create(src.join("file1")).unwrap();
+
+        file.write_all(&data).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1577934245))
+            .unwrap();
+    }
+
+    // The link to the parent must not be followed.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("..", src.join("dir1").join("loop")).unwrap();
+
+    // Import.
+    let mut data2 = vec![0u8; 8 * 1024 * 1024];
+    let image = Image::open_rw(Cursor::new(&mut data2)).expect("cannot open exFAT image");
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &FormatOptions::new())
+        .expect("cannot format the volume");
+
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    root.import_from(&src).expect("cannot import the directory");
+    root.sync_all().expect("cannot sync the volume");
+
+    std::fs::remove_dir_all(&src).unwrap();
+
+    // Check the items.
+    let mut file1 = match root.open_path("/file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("file1 is not a file"),
+    };
+    let mut read = Vec::new();
+
+    file1.read_to_end(&mut read).unwrap();
+
+    assert_eq!(data, read);
+    assert_eq!(1577934245, file1.timestamps().modified().unix_time());
+    assert_eq!(Some(0), file1.timestamps().modified().utc_offset());
+
+    let mut file2 = match root.open_path("/dir1/file2") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("dir1/file2 is not a file"),
+    };
+    let mut read = String::new();
+
+    file2.read_to_string(&mut read).unwrap();
+
+    assert_eq!("Test file 2.\n", read);
+    assert!(matches!(
+        root.open_path("/dir1/loop"),
+        Err(OpenPathError::NotFound(_))
+    ));
+    assert!(root.check().unwrap().is_clean());
+}
+
+#[test]
+fn tar() {
+    // Export the volume.
+    let image = File::open("tests/exfat.img").expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let mut tar = Vec::new();
+
+    exfat::tar::export(root.walk(), &mut tar).expect("cannot export the volume");
+
+    // Check the headers.
+    let name = |h: &[u8]| {
+        let end = h.iter().position(|&b| b == 0).unwrap();
+        std::str::from_utf8(&h[..end]).unwrap().to_owned()
+    };
+
+    assert_eq!(512 * 7, tar.len());
+    assert_eq!("dir1/", name(&tar[..100]));
+    assert_eq!(b'5', tar[156]);
+    assert_eq!("dir1/file2", name(&tar[512..612]));
+    assert_eq!(b"00000000015\0", &tar[(512 + 124)..(512 + 136)]);
+    assert_eq!(b"ustar\0", &tar[(512 + 257)..(512 + 263)]);
+    assert_eq!(b"Test file 2.\n", &tar[1024..1037]);
+    assert_eq!("file1", name(&tar[1536..1636]));
+    assert!(tar[2560..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn partition_table() {
+    use exfat::partition_table::{PartitionType, ReadError, Scheme};
+
+    // Build a disk with the volume in a logical partition.
+    let image = std::fs::read("tests/exfat.img").unwrap();
+    let sectors = image.len() as u32 / 512;
+    let mut disk = vec![0u8; 4096 * 512 + image.len()];
+    let mut entry = |sector: usize, index: usize, kind: u8, start: u32, len: u32| {
+        let e = &mut disk[(sector * 512 + 446 + index * 16)..];
+
+        e[4] = kind;
+        e[8..12].copy_from_slice(&start.to_le_bytes());
+        e[12..16].copy_from_slice(&len.to_le_bytes());
+        disk[(sector * 512 + 510)..(sector * 512 + 512)].copy_from_slice(&[0x55, 0xaa]);
+    };
+
+    entry(0, 0, 0x83, 1, 1023);
+    entry(0, 1, 0x0f, 1024, 2048 + sectors);
+    entry(1024, 0, 0x83, 1, 1023);
+    entry(1024, 1, 0x05, 1024, 1024 + sectors);
+    entry(2048, 0, 0x07, 2048, sectors);
+    disk[(4096 * 512)..].copy_from_slice(&image);
+
+    // Read the table.
+    let table = exfat::partition_table::read(&disk).expect("cannot read the partition table");
+    let offsets: Vec<_> = table
+        .partitions()
+        .iter()
+        .map(|p| (p.number(), p.offset(), p.kind()))
+        .collect();
+
+    assert_eq!(Scheme::Mbr, table.scheme());
+    assert_eq!(
+        vec![
+            (1, 512, PartitionType::Mbr(0x83)),
+            (5, 1024 * 512 + 512, PartitionType::Mbr(0x83)),
+            (6, 4096 * 512, PartitionType::Mbr(0x07)),
+        ],
+        offsets
+    );
+
+    // Open the volume.
+    let part = exfat::partition_table::find_exfat(disk)
+        .expect("cannot read the partition table")
+        .expect("cannot find the exFAT partition");
+    let root = Root::open(part).expect("cannot open the root directory");
+
+    assert_eq!(2, root.iter().count());
+    assert!(matches!(
+        exfat::partition_table::read(&image),
+        Err(ReadError::NoPartitionTable)
+    ));
+}
+
+#[test]
+fn malformed_gpt() {
+    use exfat::partition_table::{PartitionType, ReadError, Scheme};
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+
+        for &b in data {
+            crc ^= u32::from(b);
+
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+            }
+        }
+
+        !crc
+    }
+
+    // Build a disk with a GPT that has a single entry with valid checksums.
+    let gpt = |size: u32, count: u32, start: u64, end: u64| {
+        let mut disk = vec![0u8; 64 * 512];
+
+        disk[446 + 4] = 0xee;
+        disk[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+        let entries = &mut disk[1024..(1024 + 16 * 512)];
+
+        entries[..16].fill(0xaa);
+        entries[32..40].copy_from_slice(&start.to_le_bytes());
+        entries[40..48].copy_from_slice(&end.to_le_bytes());
+
+        let len = (size as usize * count as usize).min(entries.len());
+        let crc = crc32(&entries[..len]);
+        let header = &mut disk[512..1024];
+
+        header[..8].copy_from_slice(b"EFI PART");
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&count.to_le_bytes());
+        header[84..88].copy_from_slice(&size.to_le_bytes());
+        header[88..92].copy_from_slice(&crc.to_le_bytes());
+
+        let crc = crc32(&header[..92]);
+
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+
+        disk
+    };
+
+    let disk = gpt(128, 4, 34, 63);
+    let table = exfat::partition_table::read(&disk).expect("cannot read the partition table");
+
+    assert_eq!(Scheme::Gpt, table.scheme());
+    assert_eq!(1, table.partitions().len());
+    assert_eq!(34 * 512, table.partitions()[0].offset());
+    assert_eq!(30 * 512, table.partitions()[0].len());
+    assert_eq!(PartitionType::Gpt([0xaa; 16]), table.partitions()[0].kind());
+
+    // The size of the entry is too large, not a power of two or the entries overflow.
+    for disk in [
+        gpt(0x80000000, 4, 34, 63),
+        gpt(1024, 4, 34, 63),
+        gpt(129, 4, 34, 63),
+        gpt(128, 4, u64::MAX / 2, u64::MAX / 2),
+        gpt(128, 4, 0, u64::MAX),
+    ] {
+        assert!(matches!(
+            exfat::partition_table::read(&disk),
+            Err(ReadError::InvalidGptEntries)
+        ));
+    }
+}
+
+#[test]
+fn disk_usage() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let cluster_size = root.cluster_size();
+    let usage = root
+        .disk_usage()
+        .expect("cannot compute the usage of the root");
+
+    assert_eq!(2, usage.files());
+    assert_eq!(1, usage.directories());
+    assert_eq!(26, usage.len());
+    assert_eq!(cluster_size * 3, usage.allocated());
+
+    // Check the sub-directory.
+    let dir = match root.iter().find(|i| i.name() == "dir1").unwrap() {
+        Item::Directory(v) => v,
+        _ => panic!("dir1 is not a directory"),
+    };
+
+    let usage = dir.disk_usage().expect("cannot compute the usage of dir1");
+
+    assert_eq!(1, usage.files());
+    assert_eq!(0, usage.directories());
+    assert_eq!(13, usage.len());
+    assert_eq!(cluster_size, usage.allocated());
+}
+
+#[test]
+fn percent_in_use() {
+    let mut data = vec![0u8; 8 * 1024 * 1024];
+    let image = open_rw(&mut data);
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &FormatOptions::new())
+        .expect("cannot format the volume");
+
+    // Fill half of the volume.
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+    let before = root
+        .percent_in_use()
+        .expect("PercentInUse is not available");
+    let mut file = root.create_file("file").expect("cannot create file");
+
+    file.write_all(&vec![0xaa; 4 * 1024 * 1024])
+        .expect("cannot write file");
+    file.flush().expect("cannot flush file");
+    drop(file);
+
+    let used = root.cluster_count() - root.free_clusters();
+    let expected = (used * 100 / root.cluster_count()) as u8;
+
+    assert_eq!(before, root.percent_in_use().unwrap());
+    assert_eq!(
+        expected,
+        root.update_percent_in_use()
+            .expect("cannot update PercentInUse")
+    );
+    assert_eq!(Some(expected), root.percent_in_use());
+
+    root.sync_all().expect("cannot sync the volume");
+    drop(root);
+
+    // Check the volume.
+    assert_eq!(expected, data[112]);
+    assert_eq!(expected, data[12 * 512 + 112]);
+    assert_eq!(0, data[106] & 0x02);
+
+    let root = Root::open(data.as_slice()).expect("cannot open the root directory");
+
+    assert!(expected >= 50);
+    assert_eq!(Some(expected), root.percent_in_use());
+}
+
+#[test]
+fn volume_info() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let info = root.info();
+
+    assert_eq!(Some("Test image"), info.label());
+    assert_eq!(root.cluster_size(), info.cluster_size());
+    assert_eq!(root.cluster_count(), info.cluster_count());
+    assert_eq!(root.free_clusters(), info.free_clusters());
+    assert_eq!(root.percent_in_use(), info.percent_in_use());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize() {
+    fn check<T: serde::Serialize>() {}
+
+    check::<exfat::VolumeInfo>();
+    check::<exfat::FileAttributes>();
+    check::<exfat::timestamp::Timestamps>();
+    check::<exfat::file::Fragmentation>();
+    check::<exfat::check::CheckReport>();
+    check::<exfat::usage::DiskUsage>();
+    check::<exfat::undelete::DeletedItem>();
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing() {
+    use tracing::field::{Field, Visit};
+    use tracing::span::Attributes;
+    use tracing::{Event, Id, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    #[derive(Default, Clone)]
+    struct Recorder(Arc<Mutex<(Vec<String>, Vec<String>)>>);
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes, _: &Id, _: Context<S>) {
+            let name = attrs.metadata().name().to_owned();
+
+            self.0.lock().unwrap().0.push(name);
+        }
+
+        fn on_event(&self, event: &Event, _: Context<S>) {
+            struct Message<'a>(&'a mut Vec<String>);
+
+            impl Visit for Message<'_> {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{value:?}"));
+                    }
+                }
+            }
+
+            event.record(&mut Message(&mut self.0.lock().unwrap().1));
+        }
+    }
+
+    // Open the image with the cache enabled.
+    let recorder = Recorder::default();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+        let image = File::open(image).expect("cannot open exfat.img");
+        let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+        let options = OpenOptions::new().cache_size(4);
+        let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+        drop(root);
+    });
+
+    let (spans, events) = recorder.0.lock().unwrap().clone();
+
+    for name in ["open_with", "load", "root_directory", "read"] {
+        assert!(spans.iter().any(|s| s == name), "no {name} span");
+    }
+
+    assert!(events.iter().any(|m| m.starts_with("cache miss on block")));
+    assert!(events.iter().any(|m| m == "volume opened"));
+}
+
+#[test]
+fn io_stats() {
+    let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+    let image = File::open(image).expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let options = OpenOptions::new().cache_size(4);
+    let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+    root.reset_io_stats();
+
+    assert_eq!(0, root.io_stats().reads());
+
+    // Read the file twice so the second read is served from the cache.
+    let file = match root.open_path("/file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file1"),
+    };
+
+    let mut buf = [0u8; 13];
+
+    for _ in 0..2 {
+        assert_eq!(13, file.read_at(0, &mut buf).expect("cannot read file1"));
+        assert_eq!(b"Test file 1.\n", &buf);
+    }
+
+    let stats = root.io_stats();
+
+    assert_eq!(1, stats.reads());
+    assert_eq!(root.cluster_size(), stats.bytes_read());
+    assert_eq!(1, stats.clusters_read());
+    assert_ne!(0, stats.cache_hits());
+}
+
+#[test]
+fn cluster_cache() {
+    let cache = Arc::new(LruCache::new(64));
+    let mut roots = Vec::new();
+
+    // Open the same image twice with a shared cache.
+    for _ in 0..2 {
+        let image: PathBuf = ["tests", "exfat.img"].iter().collect();
+        let image = File::open(image).expect("cannot open exfat.img");
+        let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+        let options = OpenOptions::new().cluster_cache(cache.clone());
+        let root = Root::open_with(image, &options).expect("cannot open the root directory");
+
+        roots.push(root);
+    }
+
+    let before = cache.len();
+
+    for root in &roots {
+        let file = match root.open_path("/file1") {
+            Ok(Item::File(v)) => v,
+            _ => panic!("cannot open file1"),
+        };
+
+        let mut buf = [0u8; 13];
+
+        file.read_at(0, &mut buf).expect("cannot read file1");
+        file.read_at(0, &mut buf).expect("cannot read file1");
+
+        assert_eq!(b"Test file 1.\n", &buf);
+        assert_ne!(0, root.io_stats().cache_hits());
+    }
+
+    // Each volume has its own blocks.
+    assert_eq!(before + 2, cache.len());
+}
+
+#[test]
+fn cancel() {
+    use exfat::check::CheckError;
+    use exfat::extract::{ExtractError, ExtractOptions};
+    use exfat::undelete::CarveError;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let image = File::open("tests/exfat.img").expect("cannot open exfat.img");
+    let image = Image::open(image).expect("cannot open exFAT image from exfat.img");
+    let root = Root::open(image).expect("cannot open the root directory");
+    let cancel = AtomicBool::new(false);
+
+    assert!(root.check_cancellable(&cancel).is_ok());
+
+    // Cancel the extraction after the first file.
+    let dest = std::env::temp_dir().join(format!("exfat-cancel-{}", std::process::id()));
+    let options = ExtractOptions::new()
+        .progress(|_, _| cancel.store(true, Ordering::Relaxed))
+        .cancel(&cancel);
+
+    match root.extract_with(&dest, options) {
+        Err(ExtractError::Cancelled(v)) => assert!(v.is_empty()),
+        _ => panic!("the extraction was not cancelled"),
+    }
+
+    assert!(dest.join("dir1").join("file2").exists());
+    assert!(!dest.join("file1").exists());
+
+    std::fs::remove_dir_all(&dest).unwrap();
+
+    // Cancel the scans.
+    match root.check_cancellable(&cancel) {
+        Err(CheckError::Cancelled(r)) => assert!(r.is_clean()),
+        _ => panic!("the check was not cancelled"),
+    }
+
+    match root.carve_cancellable(&cancel) {
+        Err(CarveError::Cancelled(v)) => assert!(v.is_empty()),
+        _ => panic!("the scan was not cancelled"),
+    }
+}
+
+#[test]
+fn retry() {
+    use exfat::retry::{Retry, RetryPolicy};
+    use std::time::Duration;
+
+    // A partition that fails every other read and all reads that are larger than a sector.
+    struct Partition {
+        data: Vec<u8>,
+        fail: Mutex<bool>,
+    }
+
+    impl DiskPartition for Partition {
+        fn read(&self, offset: u64, buf: &mut [u8]) -> Result<u64, Box<dyn Error + Send + Sync>> {
+            let mut fail = self.fail.lock().unwrap();
+
+            *fail = !*fail;
+
+            if *fail || buf.len() > 512 {
+                return Err("transient error".into());
+            }
+
+            self.data.as_slice().read(offset, buf)
+        }
+    }
+
+    let data = load_image();
+    let partition = Partition {
+        data,
+        fail: Mutex::new(false),
+    };
+
+    assert!(Root::open(&partition).is_err());
+
+    // Open with the retry.
+    let policy = RetryPolicy::new().backoff(Duration::ZERO);
+    let root = Root::open(Retry::new(&partition, policy)).expect("cannot open the root directory");
+    let mut file1 = match root.open_path("/file1") {
+        Ok(Item::File(v)) => v,
+        _ => panic!("cannot open file1"),
+    };
+
+    let mut buf = Vec::new();
+
+    file1.read_to_end(&mut buf).expect("cannot read file1");
+    assert_eq!(b"Test file 1.\n", buf.as_slice());
+
+    // Disable the fallback.
+    let policy = RetryPolicy::new().backoff(Duration::ZERO).split(false);
+
+    assert!(Root::open(Retry::new(&partition, policy)).is_err());
+}
+
+#[test]
+fn extract_unsafe_name() {
+    use exfat::extract::{ErrorPolicy, ExtractError, ExtractOptions};
+
+    let mut data = vec![0u8; 8 * 1024 * 1024];
+    let image = open_rw(&mut data);
+
+    exfat::format::format(&image, 8 * 1024 * 1024, &FormatOptions::new())
+        .expect("cannot format the volume");
+
+    // Create the files with the same length as the names to inject.
+    let outside = std::env::temp_dir().join(format!("exfat-unsafe-{}-x", std::process::id()));
+    let absolute = outside.to_str().unwrap().to_owned();
+    let placeholder = "a".repeat(absolute.len());
+    let mut root = Root::open_rw(image).expect("cannot open the root directory");
+
+    for name in ["safe", "dd_x", &placeholder] {
+        let mut file = root.create_file(name).expect("cannot create a file");
+
+        file.write_all(b"data").expect("cannot write the file");
+        file.flush().expect("cannot flush the file");
+    }
+
+    drop(root);
+
+    // Rename the files on the disk. Each File Name entry contains up to 15 characters.
+    for (from, to) in [("dd_x", "../x"), (placeholder.as_str(), absolute.as_str())] {
+        let from: Vec<u16> = from.encode_utf16().collect();
+        let to: Vec<u16> = to.encode_utf16().collect();
+        let mut start = 0;
+
+        for (from, to) in from.chunks(15).zip(to.chunks(15)) {
+            let from: Vec<u8> = from.iter().flat_map(|c| c.to_le_bytes()).collect();
+            let to: Vec<u8> = to.iter().flat_map(|c| c.to_le_bytes()).collect();
+            let i = start
+                + data[start..]
+                    .windows(from.len())
+                    .position(|w| w == from)
+                    .expect("cannot find the name");
+
+            data[i..(i + to.len())].copy_from_slice(&to);
+            start = i + to.len();
+        }
+    }
+
+    // Extract.
+    let options = OpenOptions::new().ignore_checksums(true);
+    let root = Root::open_with(data.as_slice(), &options).expect("cannot open the root directory");
+    let parent = std::env::temp_dir().join(format!("exfat-unsafe-{}", std::process::id()));
+    let dest = parent.join("dest");
+    let skipped = root
+        .extract_with(&dest, ExtractOptions::new().on_error(ErrorPolicy::Skip))
+        .expect("cannot extract the volume");
+
+    assert_eq!(2, skipped.len());
+    assert!(skipped
+        .iter()
+        .all(|e| matches!(e, ExtractError::UnsafeName(_))));
+    assert!(dest.join("safe").exists());
+    assert!(!parent.join("x").exists());
+    assert!(!outside.exists());
+    assert_eq!(1, std::fs::read_dir(&parent).unwrap().count());
+    assert_eq!(1, std::fs::read_dir(&dest).unwrap().count());
+
+    std::fs::remove_dir_all(&parent).unwrap();
+}
@@ -0,0 +1,42 @@
+//! Tests that use only the API that is available without `std` feature.
+use exfat::directory::Item;
+use exfat::io::Read;
+use exfat::Root;
+
+static IMAGE: &[u8] = include_bytes!("exfat.img");
+
+#[test]
+fn open_slice() {
+    let root = Root::open(IMAGE).expect("cannot open the root directory");
+
+    assert_eq!(Some("Test image"), root.volume_label());
+
+    for item in root {
+        let mut content = String::new();
+
+        match item {
+            Item::Directory(d) => {
+                let mut items = d.items().expect("cannot open dir1");
+                let mut file = match items.next() {
+                    Some(Ok(Item::File(v))) => v,
+                    _ => panic!("dir1 does not contains file2"),
+                };
+
+                assert_eq!("dir1", d.name());
+                assert_eq!("file2", file.name());
+
+                file.read_to_string(&mut content)
+                    .expect("cannot read file2");
+
+                assert_eq!("Test file 2.\n", content);
+            }
+            Item::File(mut f) => {
+                assert_eq!("file1", f.name());
+
+                f.read_to_string(&mut content).expect("cannot read file1");
+
+                assert_eq!("Test file 1.\n", content);
+            }
+        }
+    }
+}